@@ -0,0 +1,646 @@
+//! Alarms: what raises them, acknowledging and silencing, and the buzzer or LED they sound.
+//!
+//! [`AlarmManager`] checks the conditions in [`AlarmInputs`] every cycle against the `[alarm]`
+//! thresholds. A condition has to hold for its delay before the alarm is raised, and an alarm on
+//! a measured value only clears once the value is back under the threshold by the hysteresis.
+//! Raised and cleared alarms go to the console, the log and the alarm file.
+//!
+//! Acknowledged alarms stop sounding but stay listed until their condition clears; event alarms
+//! such as a waypoint arrival have nothing to clear and go once acknowledged. Silencing mutes the
+//! alarms sounding at the time for `alarm.silence_secs`; an alarm raised meanwhile still sounds.
+//!
+//! [`AlarmOutput`] clones share the pin, so the panic hook and the watchdog can sound it too. The
+//! pin is left as it is when the program exits, so a crash keeps it sounding.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+
+// Use rppal in production
+#[cfg(not(test))]
+use rppal::gpio::{Gpio, OutputPin};
+
+#[cfg(test)]
+// This is only used in testing, not compiled in release.
+use crate::mocks::mock_gpio::{Gpio, OutputPin};
+
+use crate::config::AlarmSettings;
+use crate::motor::MotorFault;
+use crate::rudder::RudderFault;
+
+/// Alarm buzzer or LED, or nothing if there is none.
+#[derive(Clone, Default)]
+pub struct AlarmOutput {
+    pin: Option<Arc<Mutex<OutputPin>>>,
+}
+
+impl AlarmOutput {
+    /// Open the alarm pin, off, if `settings.output_enabled`.
+    pub fn new(settings: &AlarmSettings) -> Result<Self, Box<dyn Error>> {
+        if !settings.output_enabled {
+            return Ok(Self::default());
+        }
+        let mut pin = Gpio::new()?.get(settings.output_pin)?.into_output_low();
+        pin.set_reset_on_drop(false);
+        Ok(Self {
+            pin: Some(Arc::new(Mutex::new(pin))),
+        })
+    }
+
+    /// Sound the alarm, or silence it.
+    pub fn set(&self, on: bool) {
+        let Some(pin) = &self.pin else {
+            return;
+        };
+        // never wait: the panic hook may run while the pin is held
+        let Ok(mut pin) = pin.try_lock() else {
+            return;
+        };
+        if on {
+            pin.set_high();
+        } else {
+            pin.set_low();
+        }
+    }
+}
+
+/// What an alarm is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Alarm {
+    /// The failsafe has tripped for lack of a heading.
+    NoHeading,
+    OffCourse,
+    GpsFixLost,
+    HdopHigh,
+    CompassDisturbed,
+    CrossTrack,
+    RudderAtLimit,
+    /// The rudder feedback is faulty, or the rudder jammed.
+    RudderFault,
+    /// The rudder motor is stopped, stalled or without feedback.
+    MotorFault,
+    WaypointArrival,
+    /// The anchor watch has seen the boat outside the swing radius.
+    AnchorDragging,
+    /// No GPS fix on anchor watch.
+    AnchorFixLost,
+    /// The MOB button was pressed.
+    ManOverboard,
+}
+
+impl Alarm {
+    /// Whether this is an event rather than a condition: it has nothing to clear and goes once
+    /// acknowledged.
+    pub fn is_event(self) -> bool {
+        matches!(self, Alarm::WaypointArrival | Alarm::ManOverboard)
+    }
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Alarm::NoHeading => "no heading",
+            Alarm::OffCourse => "off course",
+            Alarm::GpsFixLost => "GPS fix lost",
+            Alarm::HdopHigh => "HDOP high",
+            Alarm::CompassDisturbed => "compass disturbed",
+            Alarm::CrossTrack => "cross-track error",
+            Alarm::RudderAtLimit => "rudder at limit",
+            Alarm::RudderFault => "rudder fault",
+            Alarm::MotorFault => "rudder motor fault",
+            Alarm::WaypointArrival => "waypoint arrival",
+            Alarm::AnchorDragging => "anchor dragging",
+            Alarm::AnchorFixLost => "anchor watch fix lost",
+            Alarm::ManOverboard => "man overboard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The values the alarm conditions are checked against, gathered each cycle. `None` means the
+/// value doesn't apply right now, e.g. no cross-track error outside TRACK mode, and counts as
+/// clear.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AlarmInputs {
+    /// The failsafe has tripped.
+    pub no_heading: bool,
+    /// Heading minus target heading while engaged (degrees).
+    pub heading_error: Option<f64>,
+    /// Time since the last GPS fix.
+    pub fix_age: Option<Duration>,
+    pub hdop: Option<f64>,
+    pub compass_disturbed: bool,
+    /// Distance off the route leg in TRACK mode (meters, either side).
+    pub cross_track_m: Option<f64>,
+    /// The rudder is at its limit while engaged.
+    pub rudder_at_limit: bool,
+    /// Fault in the rudder feedback while engaged, or the rudder jammed.
+    pub rudder_fault: Option<RudderFault>,
+    /// Why the rudder motor is stopped while engaged.
+    pub motor_fault: Option<MotorFault>,
+    /// Distance from the anchor while the anchor watch reports dragging (meters).
+    pub anchor_dragging: Option<f64>,
+    /// The anchor watch has lost the GPS fix.
+    pub anchor_fix_lost: bool,
+}
+
+/// A raised alarm.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveAlarm {
+    pub alarm: Alarm,
+    pub message: String,
+    pub since: Instant,
+    pub acknowledged: bool,
+    /// Muted until the silence is over.
+    pub silenced: bool,
+}
+
+impl ActiveAlarm {
+    pub fn is_sounding(&self) -> bool {
+        !self.acknowledged && !self.silenced
+    }
+}
+
+impl fmt::Display for ActiveAlarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if self.acknowledged {
+            write!(f, " (acknowledged)")?;
+        } else if self.silenced {
+            write!(f, " (silenced)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Raises, clears and sounds the alarms.
+pub struct AlarmManager {
+    settings: AlarmSettings,
+    output: AlarmOutput,
+    /// When each condition was first seen, for alarms waiting out their delay.
+    pending: BTreeMap<Alarm, Instant>,
+    active: Vec<ActiveAlarm>,
+    silenced_until: Option<Instant>,
+}
+
+impl AlarmManager {
+    pub fn new(settings: &AlarmSettings, output: AlarmOutput) -> Self {
+        Self {
+            settings: settings.clone(),
+            output,
+            pending: BTreeMap::new(),
+            active: Vec::new(),
+            silenced_until: None,
+        }
+    }
+
+    pub fn configure(&mut self, settings: &AlarmSettings) {
+        self.settings = settings.clone();
+    }
+
+    /// Raised alarms, oldest first.
+    pub fn active(&self) -> &[ActiveAlarm] {
+        &self.active
+    }
+
+    /// Whether any alarm is sounding: raised, not acknowledged and not silenced.
+    pub fn is_sounding(&self) -> bool {
+        self.active.iter().any(ActiveAlarm::is_sounding)
+    }
+
+    /// Check the conditions at `now`, raise and clear alarms, and drive the output.
+    pub fn update(&mut self, inputs: &AlarmInputs, now: Instant) {
+        let settings = &self.settings;
+        let gps_lost = Duration::from_secs_f64(settings.gps_lost_secs);
+        let conditions = [
+            (
+                Alarm::NoHeading,
+                inputs
+                    .no_heading
+                    .then(|| "No heading to steer by".to_string()),
+                0.0,
+            ),
+            (
+                Alarm::OffCourse,
+                inputs
+                    .heading_error
+                    .filter(|error| {
+                        self.exceeds(Alarm::OffCourse, error.abs(), settings.off_course_deg)
+                    })
+                    .map(|error| format!("Off course by {:+.0}°", error)),
+                settings.off_course_secs,
+            ),
+            (
+                Alarm::GpsFixLost,
+                inputs
+                    .fix_age
+                    .filter(|age| !gps_lost.is_zero() && *age >= gps_lost)
+                    .map(|age| format!("No GPS fix for {:.0}s", age.as_secs_f64())),
+                0.0,
+            ),
+            (
+                Alarm::HdopHigh,
+                inputs
+                    .hdop
+                    .filter(|hdop| self.exceeds(Alarm::HdopHigh, *hdop, settings.hdop_max))
+                    .map(|hdop| format!("GPS HDOP {:.1} (limit {:.1})", hdop, settings.hdop_max)),
+                settings.hdop_secs,
+            ),
+            (
+                Alarm::CompassDisturbed,
+                (inputs.compass_disturbed && settings.compass_disturbed_secs > 0.0)
+                    .then(|| "Compass disturbed by magnetic interference".to_string()),
+                settings.compass_disturbed_secs,
+            ),
+            (
+                Alarm::CrossTrack,
+                inputs
+                    .cross_track_m
+                    .filter(|xte| {
+                        self.exceeds(Alarm::CrossTrack, xte.abs(), settings.cross_track_m)
+                    })
+                    .map(|xte| format!("Cross-track error {:.0} m", xte.abs())),
+                settings.cross_track_secs,
+            ),
+            (
+                Alarm::RudderAtLimit,
+                (inputs.rudder_at_limit && settings.rudder_limit_secs > 0.0)
+                    .then(|| "Rudder at its limit".to_string()),
+                settings.rudder_limit_secs,
+            ),
+            (
+                Alarm::RudderFault,
+                inputs.rudder_fault.map(|fault| format!("Rudder {}", fault)),
+                0.0,
+            ),
+            (
+                Alarm::MotorFault,
+                inputs
+                    .motor_fault
+                    .map(|fault| format!("Rudder motor {}", fault)),
+                0.0,
+            ),
+            (
+                Alarm::AnchorDragging,
+                inputs
+                    .anchor_dragging
+                    .map(|distance| format!("Anchor dragging: {:.0} m from the anchor", distance)),
+                0.0,
+            ),
+            (
+                Alarm::AnchorFixLost,
+                inputs
+                    .anchor_fix_lost
+                    .then(|| "No GPS fix on anchor watch".to_string()),
+                0.0,
+            ),
+        ];
+        for (alarm, condition, delay_secs) in conditions {
+            self.check(alarm, condition, delay_secs, now);
+        }
+
+        if self.silenced_until.is_some_and(|until| now >= until) {
+            self.silenced_until = None;
+            for active in &mut self.active {
+                active.silenced = false;
+            }
+            if self.is_sounding() {
+                crate::warn!("  ⚠ ALARM: silence over");
+            }
+        }
+        self.output.set(self.is_sounding());
+    }
+
+    /// Note that a route waypoint was reached, if `alarm.waypoint_arrival`.
+    pub fn waypoint_arrived(&mut self, name: &str, now: Instant) {
+        if self.settings.waypoint_arrival {
+            self.raise(
+                Alarm::WaypointArrival,
+                format!("Waypoint {} reached", name),
+                now,
+            );
+            self.output.set(self.is_sounding());
+        }
+    }
+
+    /// Sound the man-overboard alarm, described by `message`.
+    pub fn man_overboard(&mut self, message: String, now: Instant) {
+        self.raise(Alarm::ManOverboard, message, now);
+        self.output.set(self.is_sounding());
+    }
+
+    /// Stop the raised alarms sounding. Events are done with; conditions stay listed until they
+    /// clear. Returns how many were acknowledged.
+    pub fn acknowledge(&mut self) -> usize {
+        let mut count = 0;
+        self.active.retain_mut(|active| {
+            if active.acknowledged {
+                return true;
+            }
+            count += 1;
+            active.acknowledged = true;
+            !active.alarm.is_event()
+        });
+        if count > 0 {
+            crate::info!("  ✓ {} alarm(s) acknowledged", count);
+            self.record(&format!("ACKNOWLEDGED {} alarm(s)", count));
+        }
+        self.output.set(self.is_sounding());
+        count
+    }
+
+    /// Mute the alarms sounding now for `alarm.silence_secs`. Returns how many were silenced.
+    pub fn silence(&mut self, now: Instant) -> usize {
+        let mut count = 0;
+        for active in self.active.iter_mut().filter(|active| active.is_sounding()) {
+            active.silenced = true;
+            count += 1;
+        }
+        if count > 0 {
+            let secs = self.settings.silence_secs;
+            self.silenced_until = Some(now + Duration::from_secs_f64(secs));
+            crate::info!("  ✓ {} alarm(s) silenced for {:.0}s", count, secs);
+            self.record(&format!("SILENCED {} alarm(s) for {:.0}s", count, secs));
+        }
+        self.output.set(self.is_sounding());
+        count
+    }
+
+    /// Whether `value` is over `threshold` (0 = no alarm), or for a raised alarm still over it
+    /// less the hysteresis.
+    fn exceeds(&self, alarm: Alarm, value: f64, threshold: f64) -> bool {
+        if threshold <= 0.0 {
+            return false;
+        }
+        let raised = self.active.iter().any(|active| active.alarm == alarm);
+        let limit = if raised {
+            threshold * (1.0 - self.settings.hysteresis)
+        } else {
+            threshold
+        };
+        value > limit
+    }
+
+    /// Raise `alarm` once its condition has held for `delay_secs`, or clear it.
+    fn check(&mut self, alarm: Alarm, condition: Option<String>, delay_secs: f64, now: Instant) {
+        let index = self.active.iter().position(|active| active.alarm == alarm);
+        match (condition, index) {
+            (Some(message), Some(index)) => self.active[index].message = message,
+            (Some(message), None) => {
+                let since = *self.pending.entry(alarm).or_insert(now);
+                if now.duration_since(since).as_secs_f64() >= delay_secs {
+                    self.pending.remove(&alarm);
+                    self.raise(alarm, message, now);
+                }
+            }
+            (None, Some(index)) => {
+                let cleared = self.active.remove(index);
+                crate::info!("  ✓ Alarm cleared: {}", cleared.alarm);
+                self.record(&format!("CLEARED {}", cleared.alarm));
+            }
+            (None, None) => {
+                self.pending.remove(&alarm);
+            }
+        }
+    }
+
+    /// Raise `alarm`, or sound it again if it is an event raised before.
+    fn raise(&mut self, alarm: Alarm, message: String, now: Instant) {
+        crate::warn!("  ⚠ ALARM: {}", message);
+        self.record(&format!("ALARM {}", message));
+
+        let raised = ActiveAlarm {
+            alarm,
+            message,
+            since: now,
+            acknowledged: false,
+            silenced: false,
+        };
+        match self.active.iter_mut().find(|active| active.alarm == alarm) {
+            Some(active) => *active = raised,
+            None => self.active.push(raised),
+        }
+    }
+
+    /// Append a timestamped line to the alarm file, if there is one.
+    fn record(&self, line: &str) {
+        let path = &self.settings.log_file;
+        if path.as_os_str().is_empty() {
+            return;
+        }
+        let result = path
+            .parent()
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| OpenOptions::new().create(true).append(true).open(path))
+            .and_then(|mut file| {
+                let time = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+                writeln!(file, "{} {}", time, line)
+            });
+        if let Err(e) = result {
+            crate::warn!("Alarm file {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ALARM_OUTPUT_PIN;
+    use crate::mocks::mock_gpio::{self, Level};
+    use std::path::PathBuf;
+
+    fn manager() -> AlarmManager {
+        mock_gpio::reset_mock_pins();
+        let settings = AlarmSettings {
+            output_enabled: true,
+            log_file: PathBuf::new(),
+            ..AlarmSettings::default()
+        };
+        let output = AlarmOutput::new(&settings).unwrap();
+        AlarmManager::new(&settings, output)
+    }
+
+    fn sounding() -> bool {
+        mock_gpio::get_mock_pin_level(ALARM_OUTPUT_PIN) == Level::High
+    }
+
+    fn names(manager: &AlarmManager) -> Vec<Alarm> {
+        manager.active().iter().map(|active| active.alarm).collect()
+    }
+
+    #[test]
+    fn test_alarm_output() {
+        mock_gpio::reset_mock_pins();
+        let settings = AlarmSettings {
+            output_enabled: true,
+            output_pin: 22,
+            ..AlarmSettings::default()
+        };
+        let alarm = AlarmOutput::new(&settings).unwrap();
+        assert_eq!(mock_gpio::get_mock_pin_level(22), Level::Low);
+
+        // clones drive the same pin
+        alarm.clone().set(true);
+        assert_eq!(mock_gpio::get_mock_pin_level(22), Level::High);
+        alarm.set(false);
+        assert_eq!(mock_gpio::get_mock_pin_level(22), Level::Low);
+
+        // disabled: nothing to drive
+        mock_gpio::reset_mock_pins();
+        let none = AlarmOutput::new(&AlarmSettings::default()).unwrap();
+        none.set(true);
+        assert_eq!(mock_gpio::get_mock_pin_level(ALARM_OUTPUT_PIN), Level::Low);
+    }
+
+    #[test]
+    fn test_delay_and_hysteresis() {
+        let mut alarms = manager();
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let off_course = |error: f64| AlarmInputs {
+            heading_error: Some(error),
+            ..AlarmInputs::default()
+        };
+
+        // 20° for 30 s
+        alarms.update(&off_course(25.0), at(0.0));
+        alarms.update(&off_course(-25.0), at(29.0));
+        assert!(alarms.active().is_empty());
+        assert!(!sounding());
+        alarms.update(&off_course(-25.0), at(30.0));
+        assert_eq!(names(&alarms), [Alarm::OffCourse]);
+        assert_eq!(alarms.active()[0].message, "Off course by -25°");
+        assert!(sounding());
+
+        // clears below 16° only
+        alarms.update(&off_course(18.0), at(31.0));
+        assert_eq!(names(&alarms), [Alarm::OffCourse]);
+        alarms.update(&off_course(15.0), at(32.0));
+        assert!(alarms.active().is_empty());
+        assert!(!sounding());
+
+        // a dip under the threshold restarts the delay
+        alarms.update(&off_course(25.0), at(40.0));
+        alarms.update(&off_course(18.0), at(50.0));
+        alarms.update(&off_course(25.0), at(60.0));
+        alarms.update(&off_course(25.0), at(89.0));
+        assert!(alarms.active().is_empty());
+
+        // not applicable counts as clear, 0 turns the alarm off
+        alarms.update(&AlarmInputs::default(), at(100.0));
+        alarms.configure(&AlarmSettings {
+            off_course_deg: 0.0,
+            log_file: PathBuf::new(),
+            ..AlarmSettings::default()
+        });
+        alarms.update(&off_course(90.0), at(200.0));
+        alarms.update(&off_course(90.0), at(300.0));
+        assert!(alarms.active().is_empty());
+
+        // the fix age is its own delay
+        let lost = |secs: u64| AlarmInputs {
+            fix_age: Some(Duration::from_secs(secs)),
+            ..AlarmInputs::default()
+        };
+        alarms.update(&lost(9), at(400.0));
+        assert!(alarms.active().is_empty());
+        alarms.update(&lost(10), at(401.0));
+        assert_eq!(names(&alarms), [Alarm::GpsFixLost]);
+        alarms.update(&lost(0), at(402.0));
+        assert!(alarms.active().is_empty());
+
+        // steering faults are raised at once
+        let faults = AlarmInputs {
+            rudder_fault: Some(RudderFault::Jammed),
+            motor_fault: Some(MotorFault::Stalled),
+            ..AlarmInputs::default()
+        };
+        alarms.update(&faults, at(500.0));
+        assert_eq!(names(&alarms), [Alarm::RudderFault, Alarm::MotorFault]);
+        assert_eq!(alarms.active()[0].message, "Rudder jammed");
+        assert_eq!(alarms.active()[1].message, "Rudder motor stalled");
+        alarms.update(&AlarmInputs::default(), at(501.0));
+        assert!(alarms.active().is_empty());
+    }
+
+    #[test]
+    fn test_acknowledge_and_silence() {
+        let mut alarms = manager();
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let failed = AlarmInputs {
+            no_heading: true,
+            ..AlarmInputs::default()
+        };
+
+        alarms.update(&failed, at(0.0));
+        alarms.waypoint_arrived("WP1", at(0.0));
+        assert_eq!(names(&alarms), [Alarm::NoHeading, Alarm::WaypointArrival]);
+        assert!(sounding());
+
+        // acknowledged: quiet, the condition stays listed and the event goes
+        assert_eq!(alarms.acknowledge(), 2);
+        assert_eq!(names(&alarms), [Alarm::NoHeading]);
+        assert!(alarms.active()[0].acknowledged);
+        alarms.update(&failed, at(1.0));
+        assert!(!sounding());
+        assert_eq!(alarms.acknowledge(), 0);
+
+        // once cleared it sounds again next time
+        alarms.update(&AlarmInputs::default(), at(2.0));
+        assert!(alarms.active().is_empty());
+        alarms.update(&failed, at(3.0));
+        assert!(sounding());
+
+        // silenced for 120 s
+        assert_eq!(alarms.silence(at(3.0)), 1);
+        assert!(!sounding());
+        alarms.update(&failed, at(122.0));
+        assert!(!sounding());
+        assert_eq!(
+            alarms.active()[0].to_string(),
+            "No heading to steer by (silenced)"
+        );
+        alarms.update(&failed, at(123.0));
+        assert!(sounding());
+
+        // a new alarm sounds during a silence
+        assert_eq!(alarms.silence(at(130.0)), 1);
+        alarms.waypoint_arrived("WP2", at(131.0));
+        assert!(sounding());
+        assert_eq!(alarms.acknowledge(), 2);
+        assert!(!sounding());
+    }
+
+    #[test]
+    fn test_alarm_file() {
+        let path = std::env::temp_dir().join(format!("geo-rs-alarms-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let settings = AlarmSettings {
+            log_file: path.clone(),
+            ..AlarmSettings::default()
+        };
+        let mut alarms = AlarmManager::new(&settings, AlarmOutput::default());
+        let now = Instant::now();
+        alarms.waypoint_arrived("WP1", now);
+        alarms.acknowledge();
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[0].ends_with(" ALARM Waypoint WP1 reached"),
+            "{}",
+            text
+        );
+        assert!(lines[1].ends_with(" ACKNOWLEDGED 1 alarm(s)"), "{}", text);
+        fs::remove_file(&path).unwrap();
+    }
+}
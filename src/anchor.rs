@@ -0,0 +1,377 @@
+//! Anchor watch.
+//!
+//! The anchor is marked at the boat's position, or `rode` meters away on a bearing when the
+//! anchor was dropped further off. The boat's position is smoothed with a first-order filter, in
+//! meters north and east of the anchor, so a single jumpy fix doesn't sound the alarm; the watch
+//! reports dragging once the filtered position has stayed outside the swing radius for
+//! `anchor.drag_secs`. Losing the GPS fix while watching is reported separately.
+//!
+//! The filtered position is recorded every `anchor.record_interval_secs`, in memory and as CSV in
+//! `anchor.directory`:
+//!
+//! ```text
+//! time,latitude,longitude,distance_m,bearing
+//! 2024-06-01T12:00:00Z,48.056597,-123.119772,31.4,212.0
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::config::AnchorSettings;
+use crate::position::Position;
+
+/// Swing pattern points kept in memory: a day at the default interval.
+pub const MAX_SWING_POINTS: usize = 8640;
+
+/// Where the anchor lies from the boat when it is marked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rode {
+    pub length_m: f64,
+    /// Bearing from the boat to the anchor (degrees).
+    pub bearing: f64,
+}
+
+/// One recorded point of the swing pattern.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwingPoint {
+    pub time: DateTime<Utc>,
+    /// Filtered boat position.
+    pub position: Position,
+    /// Distance from the anchor (meters).
+    pub distance_m: f64,
+    /// Bearing from the anchor (degrees).
+    pub bearing: f64,
+}
+
+/// A running anchor watch.
+pub struct AnchorWatch {
+    settings: AnchorSettings,
+    anchor: Position,
+    radius_m: f64,
+    /// Filtered boat position, meters north and east of the anchor.
+    filtered: (f64, f64),
+    last_update: Instant,
+    outside_since: Option<Instant>,
+    dragging: bool,
+    fix_lost: bool,
+    max_distance_m: f64,
+    swing: VecDeque<SwingPoint>,
+    last_record: Option<Instant>,
+    file: Option<(PathBuf, File)>,
+}
+
+impl AnchorWatch {
+    /// Mark the anchor from the boat at `boat` and start watching. The radius defaults to
+    /// `anchor.radius_m`.
+    pub fn start(
+        settings: &AnchorSettings,
+        boat: Position,
+        radius_m: Option<f64>,
+        rode: Option<Rode>,
+        now: Instant,
+    ) -> Self {
+        let anchor = rode.map_or(boat, |rode| boat.project(rode.bearing, rode.length_m));
+        let file = if settings.directory.as_os_str().is_empty() {
+            None
+        } else {
+            match create_swing_file(&settings.directory) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    crate::warn!("Anchor swing pattern not saved: {}", e);
+                    None
+                }
+            }
+        };
+
+        let mut watch = Self {
+            settings: settings.clone(),
+            anchor,
+            radius_m: radius_m.unwrap_or(settings.radius_m),
+            filtered: offset(&anchor, &boat),
+            last_update: now,
+            outside_since: None,
+            dragging: false,
+            fix_lost: false,
+            max_distance_m: 0.0,
+            swing: VecDeque::new(),
+            last_record: None,
+            file,
+        };
+        watch.check_radius(now);
+        watch.record(now);
+        watch
+    }
+
+    pub fn configure(&mut self, settings: &AnchorSettings) {
+        self.settings = settings.clone();
+    }
+
+    pub fn anchor(&self) -> Position {
+        self.anchor
+    }
+
+    pub fn radius_m(&self) -> f64 {
+        self.radius_m
+    }
+
+    /// Filtered boat position.
+    pub fn position(&self) -> Position {
+        self.anchor.project(self.bearing(), self.distance_m())
+    }
+
+    /// Filtered distance from the anchor (meters).
+    pub fn distance_m(&self) -> f64 {
+        self.filtered.0.hypot(self.filtered.1)
+    }
+
+    /// Bearing of the filtered position from the anchor (degrees).
+    pub fn bearing(&self) -> f64 {
+        self.filtered
+            .1
+            .atan2(self.filtered.0)
+            .to_degrees()
+            .rem_euclid(360.0)
+    }
+
+    /// Furthest the filtered position has been from the anchor (meters).
+    pub fn max_distance_m(&self) -> f64 {
+        self.max_distance_m
+    }
+
+    /// Whether the filtered position has been outside the radius for `anchor.drag_secs`.
+    pub fn is_dragging(&self) -> bool {
+        self.dragging
+    }
+
+    /// Whether there has been no GPS fix for `anchor.fix_lost_secs`.
+    pub fn is_fix_lost(&self) -> bool {
+        self.fix_lost
+    }
+
+    /// Recorded swing pattern, oldest first.
+    pub fn swing(&self) -> &VecDeque<SwingPoint> {
+        &self.swing
+    }
+
+    /// File the swing pattern is written to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Take the boat's position and the age of the fix it came from at `now`. Without a recent
+    /// fix the position is ignored and the watch holds its state.
+    pub fn update(&mut self, boat: Option<Position>, fix_age: Option<Duration>, now: Instant) {
+        let fix_lost_after = Duration::from_secs_f64(self.settings.fix_lost_secs);
+        self.fix_lost = fix_age.is_none_or(|age| age >= fix_lost_after);
+        let Some(boat) = boat.filter(|_| !self.fix_lost) else {
+            self.last_update = now;
+            return;
+        };
+
+        let (north, east) = offset(&self.anchor, &boat);
+        let dt = now.duration_since(self.last_update).as_secs_f64();
+        let alpha = if self.settings.filter_secs > 0.0 {
+            dt / (self.settings.filter_secs + dt)
+        } else {
+            1.0
+        };
+        self.filtered.0 += alpha * (north - self.filtered.0);
+        self.filtered.1 += alpha * (east - self.filtered.1);
+        self.last_update = now;
+
+        self.check_radius(now);
+        let interval = Duration::from_secs_f64(self.settings.record_interval_secs);
+        if self
+            .last_record
+            .is_none_or(|last| now.duration_since(last) >= interval)
+        {
+            self.record(now);
+        }
+    }
+
+    fn check_radius(&mut self, now: Instant) {
+        let distance_m = self.distance_m();
+        self.max_distance_m = self.max_distance_m.max(distance_m);
+        if distance_m <= self.radius_m {
+            self.outside_since = None;
+            self.dragging = false;
+            return;
+        }
+        let since = *self.outside_since.get_or_insert(now);
+        if now.duration_since(since).as_secs_f64() >= self.settings.drag_secs {
+            self.dragging = true;
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        let point = SwingPoint {
+            time: Utc::now(),
+            position: self.position(),
+            distance_m: self.distance_m(),
+            bearing: self.bearing(),
+        };
+        if self.swing.len() == MAX_SWING_POINTS {
+            self.swing.pop_front();
+        }
+        self.swing.push_back(point);
+        self.last_record = Some(now);
+
+        if let Some((path, file)) = &mut self.file
+            && let Err(e) = writeln!(
+                file,
+                "{},{:.6},{:.6},{:.1},{:.1}",
+                point.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                point.position.latitude,
+                point.position.longitude,
+                point.distance_m,
+                point.bearing
+            )
+        {
+            crate::warn!("Anchor swing pattern {}: {}", path.display(), e);
+            self.file = None;
+        }
+    }
+}
+
+impl fmt::Display for AnchorWatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} m at {:03.0}° from the anchor (radius {:.0} m, max {:.0} m)",
+            self.distance_m(),
+            self.bearing(),
+            self.radius_m,
+            self.max_distance_m
+        )
+    }
+}
+
+/// Meters north and east of `anchor`.
+fn offset(anchor: &Position, boat: &Position) -> (f64, f64) {
+    let distance = anchor.distance_to(boat);
+    let bearing = anchor.heading_to(boat).to_radians();
+    (distance * bearing.cos(), distance * bearing.sin())
+}
+
+/// Create a new timestamped swing pattern file (`anchor-YYYYMMDD-HHMMSS.csv`) in `directory`.
+fn create_swing_file(directory: &Path) -> io::Result<(PathBuf, File)> {
+    fs::create_dir_all(directory)?;
+    let path = directory.join(format!("anchor-{}.csv", Utc::now().format("%Y%m%d-%H%M%S")));
+    let mut file = File::create(&path)?;
+    writeln!(file, "time,latitude,longitude,distance_m,bearing")?;
+    Ok((path, file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> AnchorSettings {
+        AnchorSettings {
+            directory: PathBuf::new(),
+            ..AnchorSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_rode_offset() {
+        let boat = Position::new(48.0, -123.0);
+        let rode = Rode {
+            length_m: 40.0,
+            bearing: 180.0,
+        };
+        let watch = AnchorWatch::start(&settings(), boat, Some(30.0), Some(rode), Instant::now());
+
+        assert!((watch.anchor().distance_to(&boat) - 40.0).abs() < 0.01);
+        assert!(watch.anchor().latitude < boat.latitude);
+        // the boat lies north of the anchor, outside the radius but not for long yet
+        assert!((watch.distance_m() - 40.0).abs() < 0.01);
+        assert!(watch.bearing() < 0.1 || watch.bearing() > 359.9);
+        assert!(!watch.is_dragging());
+        assert_eq!(watch.radius_m(), 30.0);
+        assert_eq!(watch.swing().len(), 1);
+    }
+
+    #[test]
+    fn test_dragging() {
+        let anchor = Position::new(48.0, -123.0);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let fix = Some(Duration::ZERO);
+        let mut watch = AnchorWatch::start(&settings(), anchor, None, None, start);
+
+        // a single fix far out is filtered down
+        watch.update(Some(anchor.project(90.0, 500.0)), fix, at(1));
+        assert!(watch.distance_m() < 50.0, "{}", watch.distance_m());
+        watch.update(Some(anchor.project(90.0, 10.0)), fix, at(2));
+
+        // drifting off east: outside after a while, dragging 30 s later
+        let away = Some(anchor.project(90.0, 80.0));
+        let mut secs = 2;
+        while watch.distance_m() <= 50.0 {
+            secs += 1;
+            watch.update(away, fix, at(secs));
+        }
+        assert!(!watch.is_dragging());
+        watch.update(away, fix, at(secs + 29));
+        assert!(!watch.is_dragging());
+        watch.update(away, fix, at(secs + 30));
+        assert!(watch.is_dragging());
+        assert!((watch.bearing() - 90.0).abs() < 0.5);
+        assert!(watch.position().distance_to(&anchor) > 50.0);
+
+        // no fix: the position is ignored and the state held
+        watch.update(Some(anchor), Some(Duration::from_secs(10)), at(secs + 100));
+        assert!(watch.is_fix_lost());
+        assert!(watch.is_dragging());
+        watch.update(None, None, at(secs + 101));
+        assert!(watch.is_fix_lost());
+
+        // back inside
+        for step in 1..=60 {
+            watch.update(Some(anchor), fix, at(secs + 101 + step));
+        }
+        assert!(!watch.is_fix_lost());
+        assert!(!watch.is_dragging());
+        assert!(watch.max_distance_m() > 70.0);
+
+        // recorded every 10 s while there was a fix: at the start, once outside and on the
+        // way back
+        assert_eq!(watch.swing().len(), 8);
+    }
+
+    #[test]
+    fn test_swing_file() {
+        let directory = std::env::temp_dir().join(format!("geo-rs-anchor-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let settings = AnchorSettings {
+            directory: directory.clone(),
+            ..AnchorSettings::default()
+        };
+        let anchor = Position::new(48.0, -123.0);
+        let start = Instant::now();
+        let mut watch = AnchorWatch::start(&settings, anchor, None, None, start);
+        let fix = Some(Duration::ZERO);
+        watch.update(Some(anchor), fix, start + Duration::from_secs(5));
+        watch.update(Some(anchor), fix, start + Duration::from_secs(10));
+
+        let path = watch.path().unwrap().to_path_buf();
+        drop(watch);
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", text);
+        assert_eq!(lines[0], "time,latitude,longitude,distance_m,bearing");
+        assert!(
+            lines[1].ends_with(",48.000000,-123.000000,0.0,0.0"),
+            "{}",
+            text
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,528 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::autotune::{AutotuneStep, RelayAutotune};
+use crate::compass::heading_to_direction_8point;
+use crate::config::{AutotuneSettings, SteeringSettings};
+use crate::pwm::ServoController;
+
+/// Autopilot operating modes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Not steering, rudder centered.
+    Standby,
+    /// Heading hold: steer to a fixed target heading.
+    Auto,
+    /// Follow the active route.
+    Track,
+    /// Hold a constant apparent wind angle.
+    Wind,
+    /// Relay autotune experiment around the Auto target heading.
+    Autotune,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Mode::Standby => "STANDBY",
+            Mode::Auto => "AUTO",
+            Mode::Track => "TRACK",
+            Mode::Wind => "WIND",
+            Mode::Autotune => "AUTOTUNE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "STANDBY" => Ok(Mode::Standby),
+            "AUTO" => Ok(Mode::Auto),
+            "TRACK" => Ok(Mode::Track),
+            "WIND" => Ok(Mode::Wind),
+            "AUTOTUNE" => Ok(Mode::Autotune),
+            _ => Err(format!("unknown mode '{}'", s)),
+        }
+    }
+}
+
+impl Mode {
+    /// Whether the autopilot is steering in this mode.
+    pub fn is_engaged(&self) -> bool {
+        *self != Mode::Standby
+    }
+
+    /// Allowed transitions. Every mode can drop to standby; the steering modes can be
+    /// reached from standby, and Auto acts as the hub between Track and Wind. Autotune starts
+    /// from and returns to Auto.
+    fn can_transition_to(&self, to: Mode) -> bool {
+        matches!(
+            (self, to),
+            (_, Mode::Standby)
+                | (Mode::Standby, Mode::Auto)
+                | (Mode::Standby, Mode::Track)
+                | (Mode::Standby, Mode::Wind)
+                | (Mode::Auto, Mode::Track)
+                | (Mode::Auto, Mode::Wind)
+                | (Mode::Track, Mode::Auto)
+                | (Mode::Wind, Mode::Auto)
+                | (Mode::Auto, Mode::Autotune)
+                | (Mode::Autotune, Mode::Auto)
+        )
+    }
+}
+
+/// Snapshot of the autopilot state for display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutopilotStatus {
+    pub mode: Mode,
+    pub target_heading: Option<f64>,
+    pub target_wind_angle: Option<f64>,
+    /// Time spent in the current mode.
+    pub mode_duration: Duration,
+}
+
+impl fmt::Display for AutopilotStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mode)?;
+
+        match self.mode {
+            Mode::Standby => {}
+            Mode::Wind => {
+                if let Some(angle) = self.target_wind_angle {
+                    write!(f, " | AWA: {:+.0}°", angle)?;
+                }
+            }
+            Mode::Auto | Mode::Track | Mode::Autotune => {
+                if let Some(heading) = self.target_heading {
+                    let (direction, _) = heading_to_direction_8point(heading);
+                    write!(f, " | Target: {:.1}° ({})", heading, direction)?;
+                }
+            }
+        }
+
+        write!(f, " | for {}s", self.mode_duration.as_secs())
+    }
+}
+
+/// Autopilot state machine.
+///
+/// Engaging runs the entry actions of the new mode (capture heading, reset the controller),
+/// dropping to standby runs the exit action (center the rudder). Guards reject engaging
+/// without the inputs the mode needs.
+pub struct Autopilot {
+    mode: Mode,
+    target_heading: Option<f64>,
+    target_wind_angle: Option<f64>,
+    mode_since: Instant,
+    steering: SteeringSettings,
+    autotune: Option<RelayAutotune>,
+}
+
+impl Default for Autopilot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Autopilot {
+    /// Create an autopilot in standby.
+    pub fn new() -> Self {
+        Self::with_settings(&SteeringSettings::default())
+    }
+
+    /// Create an autopilot in standby with the vessel's steering defaults.
+    pub fn with_settings(steering: &SteeringSettings) -> Self {
+        Self {
+            mode: Mode::Standby,
+            target_heading: None,
+            target_wind_angle: None,
+            mode_since: Instant::now(),
+            steering: steering.clone(),
+            autotune: None,
+        }
+    }
+
+    /// Use new steering defaults. They apply from the next engage.
+    pub fn configure(&mut self, steering: &SteeringSettings) {
+        self.steering = steering.clone();
+    }
+
+    /// Engage mode and heading source in use.
+    pub fn steering(&self) -> &SteeringSettings {
+        &self.steering
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn is_engaged(&self) -> bool {
+        self.mode.is_engaged()
+    }
+
+    pub fn status(&self) -> AutopilotStatus {
+        AutopilotStatus {
+            mode: self.mode,
+            target_heading: self.target_heading,
+            target_wind_angle: self.target_wind_angle,
+            mode_duration: self.mode_since.elapsed(),
+        }
+    }
+
+    /// Target heading in Auto, Track and Autotune (degrees).
+    pub fn target_heading(&self) -> Option<f64> {
+        match self.mode {
+            Mode::Auto | Mode::Track | Mode::Autotune => self.target_heading,
+            _ => None,
+        }
+    }
+
+    /// How far `heading` is off the target heading (degrees, -180..180, positive = to
+    /// starboard of it).
+    pub fn heading_error(&self, heading: f64) -> Option<f64> {
+        self.target_heading()
+            .map(|target| normalize_relative(heading - target))
+    }
+
+    /// Engage heading hold on the current heading.
+    pub fn engage_auto(
+        &mut self,
+        current_heading: Option<f64>,
+        servo: Option<&mut ServoController>,
+    ) -> Result<(), Box<dyn Error>> {
+        let heading = current_heading.ok_or("No heading source, cannot engage AUTO")?;
+        self.transition(Mode::Auto, servo)?;
+
+        // entry action: hold the heading we're on now
+        self.target_heading = Some(normalize(heading));
+        Ok(())
+    }
+
+    /// Engage route following. `route_active` is whether a route with a next waypoint is loaded.
+    pub fn engage_track(
+        &mut self,
+        current_heading: Option<f64>,
+        route_active: bool,
+        servo: Option<&mut ServoController>,
+    ) -> Result<(), Box<dyn Error>> {
+        if current_heading.is_none() {
+            return Err("No heading source, cannot engage TRACK".into());
+        }
+        if !route_active {
+            return Err("No active route, cannot engage TRACK".into());
+        }
+        self.transition(Mode::Track, servo)?;
+
+        // the route supplies the target on the next cycle
+        self.target_heading = None;
+        Ok(())
+    }
+
+    /// Engage wind steering on the current apparent wind angle.
+    pub fn engage_wind(
+        &mut self,
+        current_heading: Option<f64>,
+        wind_angle: Option<f64>,
+        servo: Option<&mut ServoController>,
+    ) -> Result<(), Box<dyn Error>> {
+        if current_heading.is_none() {
+            return Err("No heading source, cannot engage WIND".into());
+        }
+        let wind_angle = wind_angle.ok_or("No wind source, cannot engage WIND")?;
+        self.transition(Mode::Wind, servo)?;
+
+        // entry action: hold the wind angle we're sailing now
+        self.target_wind_angle = Some(normalize_relative(wind_angle));
+        Ok(())
+    }
+
+    /// Start a relay autotune around the heading held in Auto. `max_angle` is the servo range.
+    pub fn start_autotune(
+        &mut self,
+        settings: &AutotuneSettings,
+        max_angle: f64,
+        servo: Option<&mut ServoController>,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.mode != Mode::Auto {
+            return Err(format!("Autotune starts from AUTO, not {}", self.mode).into());
+        }
+        let target = self
+            .target_heading
+            .ok_or("No target heading, cannot start AUTOTUNE")?;
+        self.transition(Mode::Autotune, servo)?;
+
+        // entry action: the experiment swings around the heading being held
+        self.autotune = Some(RelayAutotune::new(
+            settings,
+            target,
+            max_angle,
+            Instant::now(),
+        ));
+        Ok(())
+    }
+
+    /// Advance the autotune with the current heading at `now`. Without a heading the test only
+    /// checks its time limit. `None` unless in Autotune, or if there is nothing to do. The caller
+    /// returns to Auto with [`Autopilot::stop_autotune`] once it is done or aborted.
+    pub fn autotune_step(
+        &mut self,
+        current_heading: Option<f64>,
+        now: Instant,
+    ) -> Option<AutotuneStep> {
+        if self.mode != Mode::Autotune {
+            return None;
+        }
+        let autotune = self.autotune.as_mut()?;
+        match current_heading {
+            Some(heading) => Some(autotune.update(heading, now)),
+            None => autotune.timed_out(now),
+        }
+    }
+
+    /// Leave Autotune and hold the target heading again in Auto.
+    pub fn stop_autotune(
+        &mut self,
+        servo: Option<&mut ServoController>,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.mode != Mode::Autotune {
+            return Ok(());
+        }
+        self.transition(Mode::Auto, servo)
+    }
+
+    /// Disengage and center the rudder.
+    pub fn standby(&mut self, servo: Option<&mut ServoController>) -> Result<(), Box<dyn Error>> {
+        if self.mode == Mode::Standby {
+            return Ok(());
+        }
+        self.transition(Mode::Standby, servo)
+    }
+
+    /// Adjust the target by the given degrees (heading in Auto, wind angle in Wind).
+    /// Returns false if the current mode has no adjustable target.
+    pub fn adjust_target(&mut self, degrees: f64) -> bool {
+        match self.mode {
+            Mode::Auto => {
+                self.target_heading = self.target_heading.map(|h| normalize(h + degrees));
+                true
+            }
+            Mode::Wind => {
+                self.target_wind_angle = self
+                    .target_wind_angle
+                    .map(|a| normalize_relative(a + degrees));
+                true
+            }
+            Mode::Standby | Mode::Track | Mode::Autotune => false,
+        }
+    }
+
+    /// Replace the target heading (toggle adjustments in Auto, the route in Track).
+    /// Ignored in the other modes.
+    pub fn set_target_heading(&mut self, heading: f64) {
+        if matches!(self.mode, Mode::Auto | Mode::Track) {
+            self.target_heading = Some(normalize(heading));
+        }
+    }
+
+    /// Heading to steer in Wind mode: turn by the difference between the measured and target
+    /// apparent wind angle (positive = wind on starboard).
+    pub fn wind_steering_heading(&self, current_heading: f64, wind_angle: f64) -> Option<f64> {
+        match (self.mode, self.target_wind_angle) {
+            (Mode::Wind, Some(target)) => Some(normalize(current_heading + wind_angle - target)),
+            _ => None,
+        }
+    }
+
+    fn transition(
+        &mut self,
+        to: Mode,
+        servo: Option<&mut ServoController>,
+    ) -> Result<(), Box<dyn Error>> {
+        let from = self.mode;
+        if !from.can_transition_to(to) {
+            return Err(format!("Invalid mode change: {} → {}", from, to).into());
+        }
+
+        if let Some(servo) = servo {
+            if to == Mode::Standby {
+                // exit action: leave the rudder centered
+                servo.center()?;
+            } else {
+                // entry action: don't carry controller state between modes
+                servo.reset();
+            }
+        }
+
+        if to == Mode::Standby {
+            self.target_heading = None;
+        }
+        if to != Mode::Wind {
+            self.target_wind_angle = None;
+        }
+        if to != Mode::Autotune {
+            self.autotune = None;
+        }
+
+        self.mode = to;
+        self.mode_since = Instant::now();
+        crate::info!("  ✈ Autopilot: {} → {}", from, to);
+        Ok(())
+    }
+}
+
+/// Normalize a heading to 0-360.
+fn normalize(heading: f64) -> f64 {
+    heading.rem_euclid(360.0)
+}
+
+/// Normalize a relative angle to -180..180.
+fn normalize_relative(angle: f64) -> f64 {
+    let a = normalize(angle);
+    if a > 180.0 { a - 360.0 } else { a }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_starts_in_standby() {
+        let autopilot = Autopilot::new();
+        assert_eq!(autopilot.mode(), Mode::Standby);
+        assert!(!autopilot.is_engaged());
+        assert_eq!(autopilot.target_heading(), None);
+    }
+
+    #[test]
+    fn test_engage_auto_captures_heading() {
+        let mut autopilot = Autopilot::new();
+        autopilot.engage_auto(Some(123.4), None).unwrap();
+
+        assert_eq!(autopilot.mode(), Mode::Auto);
+        assert_eq!(autopilot.target_heading(), Some(123.4));
+        assert!((autopilot.heading_error(133.4).unwrap() - 10.0).abs() < 1e-9);
+        assert!((autopilot.heading_error(310.0).unwrap() + 173.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_engage_guards() {
+        let mut autopilot = Autopilot::new();
+
+        assert!(autopilot.engage_auto(None, None).is_err());
+        assert!(autopilot.engage_track(Some(90.0), false, None).is_err());
+        assert!(autopilot.engage_wind(Some(90.0), None, None).is_err());
+        assert_eq!(autopilot.mode(), Mode::Standby);
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        let mut autopilot = Autopilot::new();
+        autopilot.engage_auto(Some(90.0), None).unwrap();
+
+        // no self transition
+        assert!(autopilot.engage_auto(Some(90.0), None).is_err());
+
+        // Track → Wind has to go through Auto
+        autopilot.engage_track(Some(90.0), true, None).unwrap();
+        assert!(autopilot.engage_wind(Some(90.0), Some(45.0), None).is_err());
+        assert_eq!(autopilot.mode(), Mode::Track);
+    }
+
+    #[test]
+    fn test_standby_centers_rudder() {
+        let config = Config::default();
+        let mut servo = ServoController::new(&config).unwrap();
+        let mut autopilot = Autopilot::new();
+
+        autopilot.engage_auto(Some(90.0), Some(&mut servo)).unwrap();
+        servo.set_angle(20.0).unwrap();
+
+        autopilot.standby(Some(&mut servo)).unwrap();
+        assert_eq!(autopilot.mode(), Mode::Standby);
+        assert_eq!(servo.get_angle(), 0.0);
+        assert_eq!(autopilot.target_heading(), None);
+    }
+
+    #[test]
+    fn test_adjust_target() {
+        let mut autopilot = Autopilot::new();
+        assert!(!autopilot.adjust_target(5.0));
+
+        autopilot.engage_auto(Some(358.0), None).unwrap();
+        assert!(autopilot.adjust_target(5.0));
+        assert_eq!(autopilot.target_heading(), Some(3.0));
+
+        autopilot.set_target_heading(-10.0);
+        assert_eq!(autopilot.target_heading(), Some(350.0));
+
+        autopilot.standby(None).unwrap();
+        autopilot.set_target_heading(10.0);
+        assert_eq!(autopilot.target_heading(), None);
+    }
+
+    #[test]
+    fn test_wind_mode() {
+        let mut autopilot = Autopilot::new();
+        autopilot.engage_wind(Some(90.0), Some(45.0), None).unwrap();
+        assert_eq!(autopilot.status().target_wind_angle, Some(45.0));
+
+        // apparent wind moved forward to 35°: bear away to port to bring it back to 45°
+        let heading = autopilot.wind_steering_heading(90.0, 35.0).unwrap();
+        assert_eq!(heading, 80.0);
+    }
+
+    #[test]
+    fn test_autotune_mode() {
+        let mut autopilot = Autopilot::new();
+        let settings = AutotuneSettings::default();
+
+        // only from Auto
+        assert!(autopilot.start_autotune(&settings, 30.0, None).is_err());
+        assert_eq!(autopilot.autotune_step(Some(90.0), Instant::now()), None);
+
+        autopilot.engage_auto(Some(90.0), None).unwrap();
+        autopilot.start_autotune(&settings, 30.0, None).unwrap();
+        assert_eq!(autopilot.mode(), Mode::Autotune);
+        assert_eq!(autopilot.target_heading(), Some(90.0));
+        // toggles don't move the target of the experiment
+        assert!(!autopilot.adjust_target(5.0));
+        let now = Instant::now();
+        assert_eq!(
+            autopilot.autotune_step(Some(92.0), now),
+            Some(AutotuneStep::Rudder(settings.relay_amplitude_deg))
+        );
+        // the heading is lost: nothing to do until the time is up
+        assert_eq!(autopilot.autotune_step(None, now), None);
+        let late = now + Duration::from_secs_f64(settings.max_duration_secs + 1.0);
+        assert!(matches!(
+            autopilot.autotune_step(None, late),
+            Some(AutotuneStep::Aborted(_))
+        ));
+
+        autopilot.stop_autotune(None).unwrap();
+        assert_eq!(autopilot.mode(), Mode::Auto);
+        assert_eq!(autopilot.target_heading(), Some(90.0));
+        assert_eq!(autopilot.autotune_step(Some(92.0), Instant::now()), None);
+    }
+
+    #[test]
+    fn test_status_display() {
+        let mut autopilot = Autopilot::new();
+        assert!(autopilot.status().to_string().starts_with("STANDBY"));
+
+        autopilot.engage_auto(Some(90.0), None).unwrap();
+        assert!(
+            autopilot
+                .status()
+                .to_string()
+                .starts_with("AUTO | Target: 90.0° (E)")
+        );
+    }
+}
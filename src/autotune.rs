@@ -0,0 +1,310 @@
+//! Relay (bang-bang) autotuning of the heading PID.
+//!
+//! The rudder is put hard to one side by `relay_amplitude_deg` until the heading crosses the
+//! target, then to the other, which makes the boat oscillate around the target heading at the
+//! loop's ultimate period `Tu`. From the relay amplitude `d` and the heading amplitude `a` the
+//! ultimate gain is `Ku = 4d / (π·√(a² − h²))`, `h` being the switching hysteresis. The PID
+//! gains follow from a tuning rule.
+//!
+//! The test is stopped if the heading strays more than `max_deviation_deg` from the target or
+//! no steady oscillation appears within `max_duration_secs` of wall-clock time, headings or not.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::Instant;
+
+use crate::config::{AutotuneSettings, TuningRule};
+use crate::pid::Gains;
+
+/// What the autopilot should do after an update.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AutotuneStep {
+    /// Keep going with this rudder angle (degrees, positive to starboard).
+    Rudder(f64),
+    /// The oscillation was measured.
+    Done(AutotuneResult),
+    /// The test was stopped for safety; the reason is given.
+    Aborted(String),
+}
+
+/// Measured oscillation and the gains proposed from it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutotuneResult {
+    /// Ultimate gain (degrees of rudder per degree of heading).
+    pub ku: f64,
+    /// Ultimate period (seconds).
+    pub tu: f64,
+    /// Heading amplitude of the oscillation (degrees).
+    pub amplitude: f64,
+    pub rule: TuningRule,
+    pub gains: Gains,
+}
+
+impl fmt::Display for AutotuneResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Ku {:.2}, Tu {:.1}s (±{:.1}°) → {} ({})",
+            self.ku, self.tu, self.amplitude, self.gains, self.rule
+        )
+    }
+}
+
+impl fmt::Display for TuningRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TuningRule::ZieglerNichols => "Ziegler–Nichols",
+            TuningRule::TyreusLuyben => "Tyreus–Luyben",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// PID gains for the ultimate gain and period.
+pub fn tuning_gains(rule: TuningRule, ku: f64, tu: f64) -> Gains {
+    // proportional gain, integral and derivative times
+    let (kp, ti, td) = match rule {
+        TuningRule::ZieglerNichols => (0.6 * ku, tu / 2.0, tu / 8.0),
+        // less aggressive, with more margin against the lag of GPS heading
+        TuningRule::TyreusLuyben => (ku / 2.2, 2.2 * tu, tu / 6.3),
+    };
+    Gains {
+        kp,
+        ki: kp / ti,
+        kd: kp * td,
+    }
+}
+
+/// Relay experiment around a target heading, fed one heading at a time.
+pub struct RelayAutotune {
+    settings: AutotuneSettings,
+    target: f64,
+    /// Relay output, limited to the servo range (degrees).
+    amplitude: f64,
+    /// +1 or -1: the side the rudder is on.
+    relay: f64,
+    started: Instant,
+    /// Time from the start to the last update (seconds).
+    elapsed: f64,
+    /// Times the relay switched to positive (seconds since the start).
+    rising: Vec<f64>,
+    /// Largest heading error of each half cycle (degrees).
+    peaks: Vec<f64>,
+    /// Largest heading error of the current half cycle.
+    peak: f64,
+}
+
+impl RelayAutotune {
+    /// Start a test around `target_heading` at `now`. `max_angle` is the servo range.
+    pub fn new(
+        settings: &AutotuneSettings,
+        target_heading: f64,
+        max_angle: f64,
+        now: Instant,
+    ) -> Self {
+        Self {
+            settings: settings.clone(),
+            target: target_heading,
+            amplitude: settings.relay_amplitude_deg.min(max_angle),
+            relay: 0.0,
+            started: now,
+            elapsed: 0.0,
+            rising: Vec::new(),
+            peaks: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    pub fn target_heading(&self) -> f64 {
+        self.target
+    }
+
+    /// Time since the start (seconds).
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Half cycles completed.
+    pub fn half_cycles(&self) -> usize {
+        self.peaks.len()
+    }
+
+    /// The test stopped for running out of time at `now`, whether headings arrive or not.
+    pub fn timed_out(&self, now: Instant) -> Option<AutotuneStep> {
+        let elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+        (elapsed > self.settings.max_duration_secs).then(|| {
+            AutotuneStep::Aborted(format!(
+                "no steady oscillation within {:.0}s",
+                self.settings.max_duration_secs
+            ))
+        })
+    }
+
+    /// Update with the current heading at `now`.
+    pub fn update(&mut self, heading: f64, now: Instant) -> AutotuneStep {
+        self.elapsed = now.saturating_duration_since(self.started).as_secs_f64();
+
+        // same sense as the steering: heading right of target takes right (positive) rudder
+        let error = (heading - self.target + 180.0).rem_euclid(360.0) - 180.0;
+        if error.abs() > self.settings.max_deviation_deg {
+            return AutotuneStep::Aborted(format!(
+                "heading {:.0}° off target, more than the {:.0}° allowed",
+                error.abs(),
+                self.settings.max_deviation_deg
+            ));
+        }
+        if let Some(aborted) = self.timed_out(now) {
+            return aborted;
+        }
+
+        let hysteresis = self.settings.hysteresis_deg;
+        if self.relay == 0.0 {
+            self.relay = if error >= 0.0 { 1.0 } else { -1.0 };
+        } else if (self.relay > 0.0 && error < -hysteresis)
+            || (self.relay < 0.0 && error > hysteresis)
+        {
+            self.relay = -self.relay;
+            self.peaks.push(self.peak);
+            self.peak = 0.0;
+            if self.relay > 0.0 {
+                self.rising.push(self.elapsed);
+            }
+            if let Some(result) = self.result() {
+                return AutotuneStep::Done(result);
+            }
+        }
+        self.peak = self.peak.max(error.abs());
+
+        AutotuneStep::Rudder(self.relay * self.amplitude)
+    }
+
+    /// The result once the last `cycles` periods (the first one is skipped, it starts from
+    /// steady steering) agree within 20%.
+    fn result(&self) -> Option<AutotuneResult> {
+        let cycles = self.settings.cycles;
+        if self.rising.len() < cycles + 2 {
+            return None;
+        }
+
+        let times = &self.rising[self.rising.len() - cycles - 1..];
+        let periods: Vec<f64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        let peaks = &self.peaks[self.peaks.len() - 2 * cycles..];
+
+        let tu = mean(&periods);
+        let amplitude = mean(peaks);
+        let steady = |values: &[f64], mean: f64| {
+            values
+                .iter()
+                .all(|value| (value - mean).abs() <= 0.2 * mean)
+        };
+        if !steady(&periods, tu) || !steady(peaks, amplitude) {
+            return None;
+        }
+
+        let hysteresis = self.settings.hysteresis_deg.min(0.9 * amplitude);
+        let ku = 4.0 * self.amplitude / (PI * (amplitude.powi(2) - hysteresis.powi(2)).sqrt());
+        Some(AutotuneResult {
+            ku,
+            tu,
+            amplitude,
+            rule: self.settings.rule,
+            gains: tuning_gains(self.settings.rule, ku, tu),
+        })
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// First order yaw response with a dead time: the heading turns at a rate that follows the
+    /// rudder after `delay` seconds. Returns the relay result.
+    fn simulate(settings: &AutotuneSettings, delay: f64) -> AutotuneStep {
+        let dt = 0.1;
+        let delay_steps = (delay / dt) as usize;
+        let mut now = Instant::now();
+        let mut tune = RelayAutotune::new(settings, 90.0, 30.0, now);
+        let mut heading: f64 = 91.0;
+        // the heading responds one step after the rudder is applied, the rest is the queue
+        let mut rudder = vec![0.0; delay_steps - 1];
+
+        loop {
+            now += Duration::from_secs_f64(dt);
+            match tune.update(heading, now) {
+                AutotuneStep::Rudder(angle) => rudder.push(angle),
+                other => return other,
+            }
+            // positive rudder turns the bow to port at 0.5°/s per degree
+            let applied = rudder.remove(0);
+            heading = (heading - 0.5 * applied * dt).rem_euclid(360.0);
+        }
+    }
+
+    #[test]
+    fn test_measures_oscillation() {
+        let settings = AutotuneSettings::default();
+        let AutotuneStep::Done(result) = simulate(&settings, 1.0) else {
+            panic!("no result");
+        };
+
+        // 5°/s of turn and 1 s dead time: each half cycle is 2 × 1 s of dead time plus 2 × 1°
+        // of hysteresis at 5°/s, and the heading overshoots the hysteresis by 5°
+        assert!((result.tu - 4.8).abs() < 0.2, "{}", result);
+        assert!((result.amplitude - 6.0).abs() < 0.3, "{}", result);
+        let expected_ku = 4.0 * 10.0 / (PI * (36.0f64 - 1.0).sqrt());
+        assert!((result.ku - expected_ku).abs() < 0.1, "{}", result);
+        assert_eq!(result.rule, settings.rule);
+        assert_eq!(
+            result.gains,
+            tuning_gains(settings.rule, result.ku, result.tu)
+        );
+    }
+
+    #[test]
+    fn test_safety_limits() {
+        // a slow boat swings further than allowed
+        let settings = AutotuneSettings {
+            max_deviation_deg: 5.0,
+            ..AutotuneSettings::default()
+        };
+        assert!(matches!(
+            simulate(&settings, 3.0),
+            AutotuneStep::Aborted(reason) if reason.contains("off target")
+        ));
+
+        let settings = AutotuneSettings {
+            max_duration_secs: 5.0,
+            ..AutotuneSettings::default()
+        };
+        assert!(matches!(
+            simulate(&settings, 1.0),
+            AutotuneStep::Aborted(reason) if reason.contains("within 5s")
+        ));
+
+        // times out without any heading to update with
+        let start = Instant::now();
+        let tune = RelayAutotune::new(&settings, 90.0, 30.0, start);
+        assert_eq!(tune.timed_out(start + Duration::from_secs(5)), None);
+        assert!(matches!(
+            tune.timed_out(start + Duration::from_secs(6)),
+            Some(AutotuneStep::Aborted(reason)) if reason.contains("within 5s")
+        ));
+    }
+
+    #[test]
+    fn test_tuning_rules() {
+        let zn = tuning_gains(TuningRule::ZieglerNichols, 2.0, 10.0);
+        assert!((zn.kp - 1.2).abs() < 1e-9);
+        assert!((zn.ki - 0.24).abs() < 1e-9);
+        assert!((zn.kd - 1.5).abs() < 1e-9);
+
+        // Tyreus–Luyben is gentler on every term
+        let tl = tuning_gains(TuningRule::TyreusLuyben, 2.0, 10.0);
+        assert!(tl.kp < zn.kp && tl.ki < zn.ki && tl.kd < zn.kd);
+    }
+}
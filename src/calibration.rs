@@ -0,0 +1,640 @@
+//! Magnetometer hard-iron calibration and field baseline, rudder sensor end stops and servo
+//! pulse range, and writing them (and autotune results) into the configuration file.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::SIGINT;
+
+use crate::autotune::AutotuneResult;
+use crate::compass_sensor::{CompassSensor, FieldBaseline};
+use crate::config::{
+    Actuator, CompassSettings, Config, RudderSettings, ServoSettings, update_section,
+};
+use crate::gpio_input::{SwitchPosition, UserInterface};
+use crate::gyro::Accelerometer;
+use crate::pwm::{ServoController, pulse_for_angle};
+use crate::rudder::RudderSensor;
+
+/// Pulse width change per step while a toggle is held, and how often it steps: 100 µs/s.
+const SERVO_JOG_STEP_US: f64 = 5.0;
+const SERVO_JOG_INTERVAL: Duration = Duration::from_millis(50);
+/// Pulse widths jogging is kept within, wider than any servo's travel (microseconds).
+const SERVO_JOG_MIN_US: f64 = 500.0;
+const SERVO_JOG_MAX_US: f64 = 2500.0;
+
+/// Readings averaged for each rudder calibration point.
+const RUDDER_SAMPLES: u32 = 20;
+const RUDDER_SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+/// Fewer counts than this between the end stops leaves too little resolution (about 0.1° at
+/// ±35°): the pot is geared too low or barely turns.
+const RUDDER_MIN_SPAN: f64 = 700.0;
+
+/// Running min/max of the raw readings on each axis. The hard-iron offset is the center of the
+/// range the readings sweep while the sensor is rotated.
+#[derive(Clone, Debug)]
+pub struct MagnetometerCalibration {
+    min: [f64; 3],
+    max: [f64; 3],
+    samples: usize,
+}
+
+impl Default for MagnetometerCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MagnetometerCalibration {
+    pub fn new() -> Self {
+        Self {
+            min: [f64::MAX; 3],
+            max: [f64::MIN; 3],
+            samples: 0,
+        }
+    }
+
+    pub fn add_sample(&mut self, x: f64, y: f64, z: f64) {
+        for (axis, value) in [x, y, z].into_iter().enumerate() {
+            self.min[axis] = self.min[axis].min(value);
+            self.max[axis] = self.max[axis].max(value);
+        }
+        self.samples += 1;
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Min and max seen on each axis (X, Y, Z).
+    pub fn ranges(&self) -> [(f64, f64); 3] {
+        [0, 1, 2].map(|axis| (self.min[axis], self.max[axis]))
+    }
+
+    /// X, Y, Z offsets in raw counts, `None` before the first sample.
+    pub fn offsets(&self) -> Option<(f64, f64, f64)> {
+        if self.samples == 0 {
+            return None;
+        }
+        let [x, y, z] = [0, 1, 2].map(|axis| (self.min[axis] + self.max[axis]) / 2.0);
+        Some((x, y, z))
+    }
+}
+
+/// Interactive calibration: sample the magnetometer while the user rotates the board and print
+/// the offsets as they converge, until `duration` has passed or until Ctrl+C if `None`. Then
+/// measure the undisturbed field with the board back in place, the baseline for interference
+/// detection, and save both to the `[compass]` section of the configuration file at `path`.
+pub fn run_magnetometer_calibration(
+    config: &Config,
+    path: &Path,
+    start_delay: Duration,
+    duration: Option<Duration>,
+) -> Result<CompassSettings, Box<dyn Error>> {
+    let settings = &config.compass;
+    println!("╔══════════════════════════════════════════════════════╗");
+    println!("║     Magnetometer Calibration Tool                    ║");
+    println!("╚══════════════════════════════════════════════════════╝\n");
+
+    println!("Instructions:");
+    println!("1. Keep the board LEVEL (horizontal)");
+    println!("2. Slowly rotate the board through a FULL 360° circle");
+    println!("3. Take at least 30 seconds to complete the rotation");
+    println!("4. Try to rotate smoothly at constant speed");
+    println!("   (Z offset: also tilt the board nose up/down and roll it over)");
+    match duration {
+        Some(duration) => println!("5. Sampling stops after {}s\n", duration.as_secs()),
+        None => println!("5. Press Ctrl+C when done\n"),
+    }
+
+    println!("Starting in {} seconds...\n", start_delay.as_secs());
+    thread::sleep(start_delay);
+
+    let mut compass = CompassSensor::new(settings)?;
+    match Accelerometer::new() {
+        Ok(accelerometer) => compass.set_accelerometer(Some(accelerometer)),
+        Err(e) => println!(
+            "Accelerometer not available: {} - baseline measured unleveled\n",
+            e
+        ),
+    }
+    let mut calibration = MagnetometerCalibration::new();
+    let start = Instant::now();
+    // the first Ctrl+C ends the sampling, a second one exits
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 1, Arc::clone(&stop))?;
+    signal_hook::flag::register(SIGINT, Arc::clone(&stop))?;
+
+    println!("Collecting samples... (ROTATE NOW!)");
+    println!(
+        "\n{:^8} | {:^20} | {:^20} | {:^20} | {:^30}",
+        "Sample", "X Range", "Y Range", "Z Range", "Calculated Offsets"
+    );
+    println!(
+        "{:-<8}-+-{:-<20}-+-{:-<20}-+-{:-<20}-+-{:-<30}",
+        "", "", "", "", ""
+    );
+
+    while !stop.load(Ordering::Relaxed)
+        && duration.is_none_or(|duration| start.elapsed() < duration)
+    {
+        if let Ok((x, y, z)) = compass.read_raw_magnetometer() {
+            calibration.add_sample(x, y, z);
+
+            // print update every 10 samples
+            if calibration.samples().is_multiple_of(10)
+                && let Some((x_offset, y_offset, z_offset)) = calibration.offsets()
+            {
+                let [(x_min, x_max), (y_min, y_max), (z_min, z_max)] = calibration.ranges();
+                println!(
+                    "{:^8} | {:>7.0} to {:>7.0} | {:>7.0} to {:>7.0} | {:>7.0} to {:>7.0} | X: {:>7.0}  Y: {:>7.0}  Z: {:>7.0}",
+                    calibration.samples(),
+                    x_min,
+                    x_max,
+                    y_min,
+                    y_max,
+                    z_min,
+                    z_max,
+                    x_offset,
+                    y_offset,
+                    z_offset
+                );
+            }
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let (x, y, z) = calibration
+        .offsets()
+        .ok_or("no magnetometer readings, nothing calibrated")?;
+    println!(
+        "\nOffsets from {} samples: X {:.0}, Y {:.0}, Z {:.0}",
+        calibration.samples(),
+        x,
+        y,
+        z
+    );
+    let mut calibrated = CompassSettings {
+        x_offset: x.round(),
+        y_offset: y.round(),
+        z_offset: z.round(),
+        ..settings.clone()
+    };
+    compass.set_calibration(&calibrated);
+
+    // interference is a change from this field, so it has to be measured without any
+    println!("\nPut the board back level in its mounting place, with the engine, autopilot");
+    println!("motor and winches stopped and nothing magnetic nearby, and press Enter");
+    io::stdin().lock().read_line(&mut String::new())?;
+    let mut fields = Vec::new();
+    for _ in 0..settings.field_baseline_samples {
+        fields.push(compass.read_leveled_field()?);
+        thread::sleep(Duration::from_millis(100));
+    }
+    let baseline = FieldBaseline::average(&fields).ok_or("no field readings for the baseline")?;
+    calibrated.field_magnitude = baseline.magnitude;
+    calibrated.field_inclination_deg = baseline.inclination;
+    println!(
+        "Field baseline: {:.3} gauss, inclination {:.1}°",
+        baseline.magnitude, baseline.inclination
+    );
+
+    save_compass_calibration(path, config.profile.as_deref(), &calibrated)?;
+    println!("\nCalibration complete, saved to {}", path.display());
+
+    Ok(calibrated)
+}
+
+/// Rudder settings with the readings taken at the port end stop, center and starboard end
+/// stop. Fails if the readings can't be from a pot turned by the rudder.
+pub fn rudder_calibration(
+    settings: &RudderSettings,
+    port_raw: f64,
+    center_raw: f64,
+    starboard_raw: f64,
+) -> Result<RudderSettings, Box<dyn Error>> {
+    let span = (starboard_raw - port_raw).abs();
+    if span < RUDDER_MIN_SPAN {
+        return Err(format!(
+            "only {:.0} counts between the end stops, at least {:.0} needed - check the pot is turned by the rudder",
+            span, RUDDER_MIN_SPAN
+        )
+        .into());
+    }
+    if (center_raw - port_raw) * (starboard_raw - center_raw) <= 0.0 {
+        return Err(format!(
+            "center ({:.0}) is not between the end stops ({:.0} and {:.0}) - is the pot slipping?",
+            center_raw, port_raw, starboard_raw
+        )
+        .into());
+    }
+
+    Ok(RudderSettings {
+        enabled: true,
+        port_raw,
+        center_raw,
+        starboard_raw,
+        ..settings.clone()
+    })
+}
+
+/// Interactive calibration: the user puts the rudder against each end stop and amidships,
+/// pressing Enter at each, and the averaged readings are printed as a `[rudder]` section.
+pub fn run_rudder_calibration(
+    settings: &RudderSettings,
+    max_angle: f64,
+) -> Result<RudderSettings, Box<dyn Error>> {
+    println!("╔══════════════════════════════════════════════════════╗");
+    println!("║     Rudder Sensor Calibration                        ║");
+    println!("╚══════════════════════════════════════════════════════╝\n");
+    println!("Move the rudder by hand or with the helm; the end stops are taken as");
+    println!("±{:.0}° (servo.max_angle).\n", max_angle);
+
+    let mut sensor = RudderSensor::new(settings)?;
+    let mut input = io::stdin().lock();
+    let mut readings = [0.0; 3];
+    for (reading, position) in readings.iter_mut().zip([
+        "hard to PORT, against the end stop",
+        "AMIDSHIPS",
+        "hard to STARBOARD, against the end stop",
+    ]) {
+        println!("Put the rudder {} and press Enter", position);
+        input.read_line(&mut String::new())?;
+
+        let mut total = 0.0;
+        for _ in 0..RUDDER_SAMPLES {
+            total += f64::from(sensor.read_raw()?);
+            thread::sleep(RUDDER_SAMPLE_INTERVAL);
+        }
+        *reading = total / RUDDER_SAMPLES as f64;
+        println!("  reading {:.0}\n", reading);
+    }
+
+    let [port, center, starboard] = readings;
+    let calibrated = rudder_calibration(settings, port, center, starboard)?;
+
+    println!("Calibration complete.");
+    println!("\nAdd to the configuration file:\n");
+    println!("[rudder]");
+    println!("enabled = true");
+    println!("port_raw = {:.0}", calibrated.port_raw);
+    println!("center_raw = {:.0}", calibrated.center_raw);
+    println!("starboard_raw = {:.0}", calibrated.starboard_raw);
+
+    Ok(calibrated)
+}
+
+/// Servo settings with the pulse widths that put the rudder amidships and at each end. The
+/// end pulses become the pulse range, `reversed` if port takes the longer pulse.
+pub fn servo_calibration(
+    settings: &ServoSettings,
+    center_us: f64,
+    port_us: f64,
+    starboard_us: f64,
+) -> Result<ServoSettings, Box<dyn Error>> {
+    if (center_us - port_us) * (starboard_us - center_us) <= 0.0 {
+        return Err(format!(
+            "center ({:.0}μs) is not between the ends ({:.0}μs and {:.0}μs)",
+            center_us, port_us, starboard_us
+        )
+        .into());
+    }
+
+    Ok(ServoSettings {
+        min_pulse_us: port_us.min(starboard_us),
+        center_pulse_us: center_us,
+        max_pulse_us: port_us.max(starboard_us),
+        reversed: port_us > starboard_us,
+        ..settings.clone()
+    })
+}
+
+/// Interactive calibration: the servo is jogged with the toggles to amidships and to each end,
+/// the engage button accepting each position, and the pulses are saved to the configuration
+/// file at `path`.
+pub fn run_servo_calibration(
+    config: &Config,
+    path: &Path,
+) -> Result<ServoSettings, Box<dyn Error>> {
+    if config.servo.actuator != Actuator::Servo {
+        return Err("servo calibration needs servo.actuator = \"servo\"".into());
+    }
+
+    println!("╔══════════════════════════════════════════════════════╗");
+    println!("║     Servo Calibration                                ║");
+    println!("╚══════════════════════════════════════════════════════╝\n");
+    println!("Left toggle: shorter pulse, right toggle: longer pulse (hold to keep moving).");
+    println!("Press engage to accept each position. The ends are the rudder at");
+    println!(
+        "±{:.0}° (servo.max_angle), short of the end stops.\n",
+        config.servo.max_angle
+    );
+
+    let jog_config = Config {
+        servo: ServoSettings {
+            min_pulse_us: SERVO_JOG_MIN_US,
+            max_pulse_us: SERVO_JOG_MAX_US,
+            ..config.servo.clone()
+        },
+        ..config.clone()
+    };
+    let mut servo = ServoController::new(&jog_config)?;
+    let mut ui = UserInterface::new(&config.gpio, config.servo.max_angle)?;
+
+    let max_angle = config.servo.max_angle;
+    let mut pulses = [0.0; 3];
+    for (pulse, (position, start)) in pulses.iter_mut().zip([
+        ("AMIDSHIPS", config.servo.center_pulse_us),
+        ("to PORT", pulse_for_angle(&config.servo, -max_angle)),
+        ("to STARBOARD", pulse_for_angle(&config.servo, max_angle)),
+    ]) {
+        println!("Jog the rudder {} and press engage", position);
+        *pulse = jog(&mut servo, &mut ui, start)?;
+        println!("\r  {:.0}μs accepted\n", pulse);
+    }
+
+    let [center, port, starboard] = pulses;
+    servo.set_pulse_width_us(center)?;
+    let calibrated = servo_calibration(&config.servo, center, port, starboard)?;
+
+    println!("Calibration complete:");
+    println!(
+        "  min {:.0}μs, center {:.0}μs, max {:.0}μs{}",
+        calibrated.min_pulse_us,
+        calibrated.center_pulse_us,
+        calibrated.max_pulse_us,
+        if calibrated.reversed {
+            ", reversed"
+        } else {
+            ""
+        }
+    );
+    save_servo_calibration(path, config.profile.as_deref(), &calibrated)?;
+    println!("\nSaved to {}", path.display());
+
+    Ok(calibrated)
+}
+
+/// Move the servo while a toggle is held, from `start`, until engage is pressed. Returns the
+/// pulse width (microseconds).
+fn jog(
+    servo: &mut ServoController,
+    ui: &mut UserInterface,
+    start: f64,
+) -> Result<f64, Box<dyn Error>> {
+    let mut pulse = start.clamp(SERVO_JOG_MIN_US, SERVO_JOG_MAX_US);
+    servo.set_pulse_width_us(pulse)?;
+
+    loop {
+        thread::sleep(SERVO_JOG_INTERVAL);
+        if ui.engage_pressed() {
+            return Ok(pulse);
+        }
+        let step = match ui.read_toggle_switch() {
+            SwitchPosition::Left => -SERVO_JOG_STEP_US,
+            SwitchPosition::Right => SERVO_JOG_STEP_US,
+            SwitchPosition::Neutral => continue,
+        };
+        pulse = (pulse + step).clamp(SERVO_JOG_MIN_US, SERVO_JOG_MAX_US);
+        servo.set_pulse_width_us(pulse)?;
+        print!("\r  {:.0}μs ", pulse);
+        io::stdout().flush().ok();
+    }
+}
+
+/// Write calibrated pulses into the configuration file, into the vessel profile's servo section
+/// if one is selected. The file is left alone if it wouldn't load afterwards.
+pub fn save_servo_calibration(
+    path: &Path,
+    profile: Option<&str>,
+    settings: &ServoSettings,
+) -> Result<(), Box<dyn Error>> {
+    save_calibration(
+        path,
+        profile,
+        "servo",
+        &[
+            ("min_pulse_us", format!("{:.1}", settings.min_pulse_us)),
+            (
+                "center_pulse_us",
+                format!("{:.1}", settings.center_pulse_us),
+            ),
+            ("max_pulse_us", format!("{:.1}", settings.max_pulse_us)),
+            ("reversed", settings.reversed.to_string()),
+        ],
+    )
+}
+
+/// Write the hard-iron offsets and the field baseline into the configuration file, into the
+/// vessel profile's compass section if one is selected. The file is left alone if it wouldn't
+/// load afterwards.
+pub fn save_compass_calibration(
+    path: &Path,
+    profile: Option<&str>,
+    settings: &CompassSettings,
+) -> Result<(), Box<dyn Error>> {
+    save_calibration(
+        path,
+        profile,
+        "compass",
+        &[
+            ("x_offset", format!("{:.1}", settings.x_offset)),
+            ("y_offset", format!("{:.1}", settings.y_offset)),
+            ("z_offset", format!("{:.1}", settings.z_offset)),
+            (
+                "field_magnitude",
+                format!("{:.4}", settings.field_magnitude),
+            ),
+            (
+                "field_inclination_deg",
+                format!("{:.1}", settings.field_inclination_deg),
+            ),
+        ],
+    )
+}
+
+/// Write an autotune result into the configuration file's `[autotune.result]`, or the vessel
+/// profile's, for review. The `[pid]` gains are left alone.
+pub fn save_autotune_result(
+    path: &Path,
+    profile: Option<&str>,
+    result: &AutotuneResult,
+) -> Result<(), Box<dyn Error>> {
+    save_calibration(
+        path,
+        profile,
+        "autotune.result",
+        &[
+            ("kp", format!("{:.3}", result.gains.kp)),
+            ("ki", format!("{:.3}", result.gains.ki)),
+            ("kd", format!("{:.3}", result.gains.kd)),
+            ("ultimate_gain", format!("{:.3}", result.ku)),
+            ("ultimate_period_secs", format!("{:.1}", result.tu)),
+        ],
+    )
+}
+
+/// Set `values` in `section` of the configuration file, or of the vessel profile's section.
+fn save_calibration(
+    path: &Path,
+    profile: Option<&str>,
+    section: &str,
+    values: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let text = if path.exists() {
+        fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else {
+        String::new()
+    };
+    let section = match profile {
+        Some(name) => format!("profiles.{}.{}", name, section),
+        None => section.to_string(),
+    };
+    let updated = update_section(&text, &section, values);
+
+    Config::from_toml_profile(&updated, profile)
+        .and_then(|config| config.validate())
+        .map_err(|e| format!("{} not updated: {}", path.display(), e))?;
+    fs::write(path, updated).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_are_range_centers() {
+        let mut calibration = MagnetometerCalibration::new();
+        assert_eq!(calibration.offsets(), None);
+
+        calibration.add_sample(-1000.0, 500.0, 10.0);
+        calibration.add_sample(3000.0, -1500.0, 30.0);
+        calibration.add_sample(1000.0, 0.0, 20.0);
+
+        assert_eq!(calibration.samples(), 3);
+        assert_eq!(calibration.offsets(), Some((1000.0, -500.0, 20.0)));
+        assert_eq!(calibration.ranges()[0], (-1000.0, 3000.0));
+    }
+
+    #[test]
+    fn test_rudder_calibration() {
+        let settings = RudderSettings::default();
+        let calibrated = rudder_calibration(&settings, 16000.0, 13000.0, 9000.0).unwrap();
+        assert!(calibrated.enabled);
+        assert_eq!(
+            (
+                calibrated.port_raw,
+                calibrated.center_raw,
+                calibrated.starboard_raw
+            ),
+            (16000.0, 13000.0, 9000.0)
+        );
+        assert_eq!(calibrated.position_kp, settings.position_kp);
+
+        // pot not turning, or center outside the end stops
+        assert!(rudder_calibration(&settings, 13000.0, 13100.0, 13200.0).is_err());
+        assert!(rudder_calibration(&settings, 9000.0, 17000.0, 16000.0).is_err());
+    }
+
+    #[test]
+    fn test_servo_calibration() {
+        let settings = ServoSettings::default();
+        // mounted reversed: port takes the long pulses
+        let calibrated = servo_calibration(&settings, 1520.0, 1880.0, 1150.0).unwrap();
+        assert_eq!(calibrated.min_pulse_us, 1150.0);
+        assert_eq!(calibrated.center_pulse_us, 1520.0);
+        assert_eq!(calibrated.max_pulse_us, 1880.0);
+        assert!(calibrated.reversed);
+        assert_eq!(pulse_for_angle(&calibrated, -settings.max_angle), 1880.0);
+
+        assert!(servo_calibration(&settings, 1900.0, 1100.0, 1800.0).is_err());
+
+        // saved into the selected profile, the rest of the file kept
+        let path = std::env::temp_dir().join(format!("geo-rs-servo-{}.toml", std::process::id()));
+        fs::write(&path, "# my boat\n[profiles.ketch]\n").unwrap();
+        save_servo_calibration(&path, Some("ketch"), &calibrated).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# my boat\n"), "{}", text);
+        let config = Config::from_toml_profile(&text, Some("ketch")).unwrap();
+        assert_eq!(config.servo.max_pulse_us, 1880.0);
+        assert!(config.servo.reversed);
+        assert_eq!(
+            Config::from_toml(&text).unwrap().servo,
+            ServoSettings::default()
+        );
+
+        // a calibration the file can't take is not saved
+        let bad = ServoSettings {
+            max_pulse_us: 30000.0,
+            ..calibrated
+        };
+        assert!(save_servo_calibration(&path, Some("ketch"), &bad).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_compass_calibration() {
+        let path = std::env::temp_dir().join(format!("geo-rs-compass-{}.toml", std::process::id()));
+        fs::write(&path, "[compass]\nheading_offset = 12.0 # here\n").unwrap();
+        let calibrated = CompassSettings {
+            x_offset: -2800.0,
+            y_offset: 2500.0,
+            z_offset: 150.0,
+            field_magnitude: 0.4832,
+            field_inclination_deg: 67.3,
+            ..CompassSettings::default()
+        };
+        save_compass_calibration(&path, None, &calibrated).unwrap();
+
+        let compass = Config::from_toml(&fs::read_to_string(&path).unwrap())
+            .unwrap()
+            .compass;
+        assert_eq!(
+            compass,
+            CompassSettings {
+                heading_offset: 12.0,
+                ..calibrated
+            }
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_autotune_result() {
+        let path =
+            std::env::temp_dir().join(format!("geo-rs-autotune-{}.toml", std::process::id()));
+        fs::write(&path, "[pid]\nkp = 2.0\n").unwrap();
+        let result = AutotuneResult {
+            ku: 2.62,
+            tu: 18.4,
+            amplitude: 4.9,
+            rule: crate::config::TuningRule::TyreusLuyben,
+            gains: crate::pid::Gains {
+                kp: 1.18,
+                ki: 0.052,
+                kd: 3.05,
+            },
+        };
+        save_autotune_result(&path, None, &result).unwrap();
+
+        let config = Config::from_toml(&fs::read_to_string(&path).unwrap()).unwrap();
+        // for review only: the gains in use are unchanged
+        assert_eq!(config.pid.kp, 2.0);
+        let saved = &config.autotune.result;
+        assert_eq!((saved.kp, saved.ki, saved.kd), (1.18, 0.052, 3.05));
+        assert_eq!(
+            (saved.ultimate_gain, saved.ultimate_period_secs),
+            (2.62, 18.4)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,119 +1,1039 @@
-use rppal::i2c::I2c;
 use std::error::Error;
 
-use crate::config::{HEADING_OFFSET, X_OFFSET, Y_OFFSET};
+// Use rppal in production
+#[cfg(not(test))]
+use rppal::i2c::I2c;
+
+#[cfg(test)]
+// This is only used in testing, not compiled in release.
+use crate::mocks::mock_i2c::I2c;
+
+use crate::config::CompassSettings;
+use crate::gyro::Accelerometer;
 
 const LIS3MDL_ADDR: u16 = 0x1C;
+/// Expected WHO_AM_I response for the LIS3MDL.
+const LIS3MDL_ID: u8 = 0x3D;
 
 // LIS3MDL register addresses.
 const WHO_AM_I: u8 = 0x0F;
-/// Temperature enabled, Ultra-high performance mode (X,Y), ODR = 80 Hz.
+/// Temperature enable, X/Y operating mode, output data rate.
 const CTRL_REG1: u8 = 0x20;
-/// Full scale ±4 gauss.
+/// Full scale selection.
 const CTRL_REG2: u8 = 0x21;
-/// Continuous conversion mode.
+/// Conversion mode (continuous, single, power-down).
 const CTRL_REG3: u8 = 0x22;
-/// Ultra-high performance mode (Z-axis), little endian.
+/// Z-axis operating mode, endianness.
 const CTRL_REG4: u8 = 0x23;
-/// Block data update enabled.
+/// Block data update.
 const CTRL_REG5: u8 = 0x24;
 
 const STATUS_REG: u8 = 0x27;
 const OUT_X_L: u8 = 0x28;
+const TEMP_OUT_L: u8 = 0x2E;
+const INT_CFG: u8 = 0x30;
+const INT_SRC: u8 = 0x31;
+const INT_THS_L: u8 = 0x32;
+const INT_THS_H: u8 = 0x33;
+
+/// Setting the MSB of the register address enables auto-increment for multi-byte reads.
+const AUTO_INCREMENT: u8 = 0x80;
+/// STATUS_REG: new X, Y and Z data available.
+const STATUS_ZYXDA: u8 = 0x08;
+/// CTRL_REG1: temperature sensor enable.
+const TEMP_EN: u8 = 0x80;
+/// CTRL_REG1: enables data rates above 80 Hz.
+const FAST_ODR: u8 = 0x02;
+/// CTRL_REG5: output registers are not updated until both bytes have been read.
+const BDU: u8 = 0x40;
+/// INT_CFG: bit 3 must always be written as 1.
+const INT_CFG_RESERVED: u8 = 0x08;
+
+/// The on-die temperature sensor reports 8 LSB per °C relative to 25 °C.
+const TEMP_LSB_PER_DEGREE: f64 = 8.0;
+const TEMP_REFERENCE_C: f64 = 25.0;
+
+/// Magnetometer full scale range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FullScale {
+    Gauss4,
+    Gauss8,
+    Gauss12,
+    Gauss16,
+}
+
+impl FullScale {
+    /// CTRL_REG2 FS bits.
+    fn bits(self) -> u8 {
+        match self {
+            FullScale::Gauss4 => 0x00,
+            FullScale::Gauss8 => 0x20,
+            FullScale::Gauss12 => 0x40,
+            FullScale::Gauss16 => 0x60,
+        }
+    }
+
+    /// Sensitivity from the datasheet (LSB per gauss).
+    pub fn lsb_per_gauss(self) -> f64 {
+        match self {
+            FullScale::Gauss4 => 6842.0,
+            FullScale::Gauss8 => 3421.0,
+            FullScale::Gauss12 => 2281.0,
+            FullScale::Gauss16 => 1711.0,
+        }
+    }
+}
+
+/// Output data rate. `Fast` selects the FAST_ODR rate of the current operating mode
+/// (1000/560/300/155 Hz for low-power/medium/high/ultra-high performance).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataRate {
+    Hz0_625,
+    Hz1_25,
+    Hz2_5,
+    Hz5,
+    Hz10,
+    Hz20,
+    Hz40,
+    Hz80,
+    Fast,
+}
+
+impl DataRate {
+    /// CTRL_REG1 DO and FAST_ODR bits.
+    fn bits(self) -> u8 {
+        match self {
+            DataRate::Hz0_625 => 0x00,
+            DataRate::Hz1_25 => 0x04,
+            DataRate::Hz2_5 => 0x08,
+            DataRate::Hz5 => 0x0C,
+            DataRate::Hz10 => 0x10,
+            DataRate::Hz20 => 0x14,
+            DataRate::Hz40 => 0x18,
+            DataRate::Hz80 => 0x1C,
+            DataRate::Fast => FAST_ODR,
+        }
+    }
+}
+
+/// Operating mode, trading power for noise. Applied to all three axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperatingMode {
+    LowPower,
+    MediumPerformance,
+    HighPerformance,
+    UltraHighPerformance,
+}
+
+impl OperatingMode {
+    /// OM bits (CTRL_REG1 bits 6:5); the Z-axis OMZ bits in CTRL_REG4 use the same values
+    /// shifted to bits 3:2.
+    fn bits(self) -> u8 {
+        match self {
+            OperatingMode::LowPower => 0x00,
+            OperatingMode::MediumPerformance => 0x01,
+            OperatingMode::HighPerformance => 0x02,
+            OperatingMode::UltraHighPerformance => 0x03,
+        }
+    }
+}
+
+/// Measurement settings applied when the sensor is initialized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SensorConfig {
+    pub full_scale: FullScale,
+    pub data_rate: DataRate,
+    pub mode: OperatingMode,
+    pub temperature_enabled: bool,
+}
+
+impl Default for SensorConfig {
+    /// ±4 gauss, 80 Hz, ultra-high performance, temperature enabled.
+    fn default() -> Self {
+        Self {
+            full_scale: FullScale::Gauss4,
+            data_rate: DataRate::Hz80,
+            mode: OperatingMode::UltraHighPerformance,
+            temperature_enabled: true,
+        }
+    }
+}
+
+/// Magnetic field strength on each axis (gauss).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MagneticField {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl MagneticField {
+    /// Total field strength (gauss).
+    pub fn magnitude(&self) -> f64 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    /// Inclination (dip) of the field below the board plane (degrees).
+    pub fn inclination(&self) -> f64 {
+        self.z.atan2(self.x.hypot(self.y)).to_degrees()
+    }
+
+    /// The field in a level frame, undoing the board's roll and pitch given the `up` direction
+    /// measured by the accelerometer in the same axes. Its inclination is then the dip below the
+    /// horizon, whatever the heel.
+    pub fn leveled(&self, up: [f64; 3]) -> MagneticField {
+        let [up_x, up_y, up_z] = up;
+        let roll = up_y.atan2(up_z);
+        let pitch = (-up_x).atan2(up_y.hypot(up_z));
+
+        let y = self.y * roll.cos() - self.z * roll.sin();
+        let z = self.y * roll.sin() + self.z * roll.cos();
+        MagneticField {
+            x: self.x * pitch.cos() + z * pitch.sin(),
+            y,
+            z: -self.x * pitch.sin() + z * pitch.cos(),
+        }
+    }
+}
+
+/// Undisturbed field used as the reference for interference detection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldBaseline {
+    /// Total field strength (gauss).
+    pub magnitude: f64,
+    /// Field inclination (degrees).
+    pub inclination: f64,
+}
+
+impl FieldBaseline {
+    /// The baseline measured by the calibration tool, `None` if there isn't one.
+    pub fn configured(settings: &CompassSettings) -> Option<Self> {
+        (settings.field_magnitude > 0.0).then_some(Self {
+            magnitude: settings.field_magnitude,
+            inclination: settings.field_inclination_deg,
+        })
+    }
+
+    /// Average of the readings, `None` without any.
+    pub fn average(fields: &[MagneticField]) -> Option<Self> {
+        if fields.is_empty() {
+            return None;
+        }
+        let count = fields.len() as f64;
+        Some(Self {
+            magnitude: fields.iter().map(MagneticField::magnitude).sum::<f64>() / count,
+            inclination: fields.iter().map(MagneticField::inclination).sum::<f64>() / count,
+        })
+    }
+}
+
+/// Result of comparing a reading against the field baseline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldStatus {
+    /// Still collecting samples for the baseline.
+    Learning,
+    /// Field matches the baseline, heading can be trusted.
+    Normal,
+    /// Field deviates from the baseline, heading should not be used for steering.
+    Disturbed {
+        /// Relative magnitude deviation (0.2 = 20% stronger, -0.2 = 20% weaker).
+        magnitude_deviation: f64,
+        /// Inclination deviation (degrees).
+        inclination_deviation: f64,
+    },
+}
+
+impl FieldStatus {
+    pub fn is_disturbed(&self) -> bool {
+        matches!(self, FieldStatus::Disturbed { .. })
+    }
+}
+
+/// Watches the total field magnitude and inclination for nearby magnets, ferrous objects or
+/// running motors. The earth's field is constant at a given location, so a change in either
+/// means the heading is being pulled by something on board.
+pub struct InterferenceDetector {
+    baseline: Option<FieldBaseline>,
+    magnitude_sum: f64,
+    inclination_sum: f64,
+    sample_count: usize,
+    disturbed: bool,
+    baseline_samples: usize,
+    magnitude_tolerance: f64,
+    inclination_tolerance: f64,
+    /// Whether the fields checked are leveled; heel changes the inclination otherwise.
+    leveled: bool,
+}
+
+impl Default for InterferenceDetector {
+    fn default() -> Self {
+        Self::new(&CompassSettings::default())
+    }
+}
+
+impl InterferenceDetector {
+    /// Create a detector with the baseline measured by the calibration tool, or one that learns
+    /// it from the first readings if there is none.
+    pub fn new(settings: &CompassSettings) -> Self {
+        Self {
+            baseline: FieldBaseline::configured(settings),
+            magnitude_sum: 0.0,
+            inclination_sum: 0.0,
+            sample_count: 0,
+            disturbed: false,
+            baseline_samples: settings.field_baseline_samples,
+            magnitude_tolerance: settings.field_magnitude_tolerance,
+            inclination_tolerance: settings.field_inclination_tolerance_deg,
+            leveled: true,
+        }
+    }
+
+    /// Create a detector with a known baseline (e.g. recorded during calibration).
+    pub fn with_baseline(settings: &CompassSettings, baseline: FieldBaseline) -> Self {
+        Self {
+            baseline: Some(baseline),
+            ..Self::new(settings)
+        }
+    }
+
+    pub fn baseline(&self) -> Option<FieldBaseline> {
+        self.baseline
+    }
+
+    /// Use new tolerances and baseline sample count, and the calibrated baseline if there is
+    /// one. A learned baseline is kept otherwise.
+    pub fn configure(&mut self, settings: &CompassSettings) {
+        if let Some(baseline) = FieldBaseline::configured(settings) {
+            self.baseline = Some(baseline);
+        }
+        self.baseline_samples = settings.field_baseline_samples;
+        self.magnitude_tolerance = settings.field_magnitude_tolerance;
+        self.inclination_tolerance = settings.field_inclination_tolerance_deg;
+    }
+
+    /// Whether the fields checked are leveled (the default). Without leveling only the field
+    /// strength is checked, as heeling changes the inclination in the board frame.
+    pub fn set_leveled(&mut self, leveled: bool) {
+        self.leveled = leveled;
+    }
+
+    /// Discard the baseline and learn it again from the next readings.
+    pub fn relearn(&mut self) {
+        self.baseline = None;
+        self.magnitude_sum = 0.0;
+        self.inclination_sum = 0.0;
+        self.sample_count = 0;
+        self.disturbed = false;
+    }
+
+    /// Compare a calibrated reading against the baseline.
+    pub fn check(&mut self, field: &MagneticField) -> FieldStatus {
+        let magnitude = field.magnitude();
+        let inclination = field.inclination();
+
+        let Some(baseline) = self.baseline else {
+            self.magnitude_sum += magnitude;
+            self.inclination_sum += inclination;
+            self.sample_count += 1;
+
+            if self.sample_count >= self.baseline_samples {
+                let count = self.sample_count as f64;
+                self.baseline = Some(FieldBaseline {
+                    magnitude: self.magnitude_sum / count,
+                    inclination: self.inclination_sum / count,
+                });
+            }
+            return FieldStatus::Learning;
+        };
+
+        if baseline.magnitude <= 0.0 {
+            return FieldStatus::Normal;
+        }
+
+        let magnitude_deviation = (magnitude - baseline.magnitude) / baseline.magnitude;
+        let inclination_deviation = inclination - baseline.inclination;
+
+        // once disturbed, require the field to settle well inside the tolerance before
+        // trusting it again, so a reading hovering at the limit doesn't flap
+        let scale = if self.disturbed { 0.8 } else { 1.0 };
+        self.disturbed = magnitude_deviation.abs() > self.magnitude_tolerance * scale
+            || (self.leveled && inclination_deviation.abs() > self.inclination_tolerance * scale);
+
+        if self.disturbed {
+            FieldStatus::Disturbed {
+                magnitude_deviation,
+                inclination_deviation,
+            }
+        } else {
+            FieldStatus::Normal
+        }
+    }
+}
+
+/// A heading reading together with the interference check of the field it was computed from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompassReading {
+    pub heading: f64,
+    pub field: MagneticField,
+    pub status: FieldStatus,
+}
+
+/// Threshold interrupt settings. The interrupt fires when the absolute value of an enabled
+/// axis exceeds `threshold_gauss`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterruptConfig {
+    pub x_enabled: bool,
+    pub y_enabled: bool,
+    pub z_enabled: bool,
+    /// INT pin polarity (true = active high).
+    pub active_high: bool,
+    /// Latch the interrupt until INT_SRC is read.
+    pub latched: bool,
+    pub threshold_gauss: f64,
+}
+
+/// Decoded INT_SRC register.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InterruptSource {
+    pub positive_x: bool,
+    pub positive_y: bool,
+    pub positive_z: bool,
+    pub negative_x: bool,
+    pub negative_y: bool,
+    pub negative_z: bool,
+    /// Internal measurement range overflow.
+    pub overflow: bool,
+    pub active: bool,
+}
+
+impl From<u8> for InterruptSource {
+    fn from(bits: u8) -> Self {
+        Self {
+            positive_x: bits & 0x80 != 0,
+            positive_y: bits & 0x40 != 0,
+            positive_z: bits & 0x20 != 0,
+            negative_x: bits & 0x10 != 0,
+            negative_y: bits & 0x08 != 0,
+            negative_z: bits & 0x04 != 0,
+            overflow: bits & 0x02 != 0,
+            active: bits & 0x01 != 0,
+        }
+    }
+}
 
 pub struct CompassSensor {
     i2c: I2c,
+    config: SensorConfig,
+    calibration: CompassSettings,
+    detector: InterferenceDetector,
+    accelerometer: Option<Accelerometer>,
 }
 
 impl CompassSensor {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    /// Initialize the LIS3MDL with the default settings (±4 gauss, 80 Hz, ultra-high performance).
+    pub fn new(calibration: &CompassSettings) -> Result<Self, Box<dyn Error>> {
+        Self::with_config(calibration, SensorConfig::default())
+    }
+
+    /// Initialize the LIS3MDL with custom measurement settings.
+    pub fn with_config(
+        calibration: &CompassSettings,
+        config: SensorConfig,
+    ) -> Result<Self, Box<dyn Error>> {
         let mut i2c = I2c::new()?;
         i2c.set_slave_address(LIS3MDL_ADDR)?;
 
         // verify device ID (should be 0x3D for LIS3MDL)
         let who_am_i = i2c.smbus_read_byte(WHO_AM_I)?;
-        if who_am_i != 0x3D {
-            return Err(format!("Wrong device ID: 0x{:02X}, expected 0x3D", who_am_i).into());
+        if who_am_i != LIS3MDL_ID {
+            return Err(format!(
+                "Wrong device ID: 0x{:02X}, expected 0x{:02X}",
+                who_am_i, LIS3MDL_ID
+            )
+            .into());
         }
 
-        // initialize LIS3MDL magnetometer
-        // CTRL_REG1: temperature enabled, Ultra-high performance mode (X,Y), ODR = 80 Hz
-        i2c.smbus_write_byte(CTRL_REG1, 0xFC)?;
+        if FieldBaseline::configured(calibration).is_none() {
+            crate::warn!(
+                "Compass: no field baseline from calibration - learning it now, keep magnets and motors away"
+            );
+        }
+        let mut sensor = Self {
+            i2c,
+            config,
+            calibration: calibration.clone(),
+            detector: InterferenceDetector::new(calibration),
+            accelerometer: None,
+        };
+        sensor.detector.set_leveled(false);
 
-        // CTRL_REG2: full scale ±4 gauss
-        i2c.smbus_write_byte(CTRL_REG2, 0x00)?;
+        sensor.write_ctrl_reg1()?;
+        sensor.set_full_scale(config.full_scale)?;
 
         // CTRL_REG3: continuous conversion mode
-        i2c.smbus_write_byte(CTRL_REG3, 0x00)?;
+        sensor.i2c.smbus_write_byte(CTRL_REG3, 0x00)?;
 
-        // CTRL_REG4: ultra-high performance mode (Z-axis), little endian
-        i2c.smbus_write_byte(CTRL_REG4, 0x0C)?;
+        // CTRL_REG4: Z-axis operating mode, little endian
+        sensor.set_operating_mode(config.mode)?;
 
         // CTRL_REG5: block data update enabled
-        i2c.smbus_write_byte(CTRL_REG5, 0x40)?;
+        sensor.i2c.smbus_write_byte(CTRL_REG5, BDU)?;
 
         std::thread::sleep(std::time::Duration::from_millis(100));
 
         // check if data is available
-        let status = i2c.smbus_read_byte(STATUS_REG)?;
+        let status = sensor.i2c.smbus_read_byte(STATUS_REG)?;
         if status == 0 {
             return Err("Magnetometer hardware not responding (no data ready)".into());
         }
 
-        println!("✓ Compass (LIS3MDL) initialized");
-        Ok(Self { i2c })
+        crate::info!("✓ Compass (LIS3MDL) initialized");
+        Ok(sensor)
     }
 
-    /// Read from the device, and return the calibrated heading.
-    pub fn read_heading(&mut self) -> Result<f64, Box<dyn Error>> {
+    /// Current measurement settings.
+    pub fn config(&self) -> SensorConfig {
+        self.config
+    }
+
+    /// Select the full scale range. Subsequent readings are scaled accordingly.
+    pub fn set_full_scale(&mut self, full_scale: FullScale) -> Result<(), Box<dyn Error>> {
+        self.i2c.smbus_write_byte(CTRL_REG2, full_scale.bits())?;
+        self.config.full_scale = full_scale;
+        Ok(())
+    }
+
+    /// Select the output data rate.
+    pub fn set_data_rate(&mut self, data_rate: DataRate) -> Result<(), Box<dyn Error>> {
+        self.config.data_rate = data_rate;
+        self.write_ctrl_reg1()
+    }
+
+    /// Select the operating mode for all three axes.
+    pub fn set_operating_mode(&mut self, mode: OperatingMode) -> Result<(), Box<dyn Error>> {
+        self.config.mode = mode;
+        self.write_ctrl_reg1()?;
+        self.i2c.smbus_write_byte(CTRL_REG4, mode.bits() << 2)?;
+        Ok(())
+    }
+
+    /// Enable or disable the on-die temperature sensor.
+    pub fn set_temperature_enabled(&mut self, enabled: bool) -> Result<(), Box<dyn Error>> {
+        self.config.temperature_enabled = enabled;
+        self.write_ctrl_reg1()
+    }
+
+    /// CTRL_REG1 combines the temperature enable, X/Y operating mode and data rate.
+    fn write_ctrl_reg1(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut value = (self.config.mode.bits() << 5) | self.config.data_rate.bits();
+        if self.config.temperature_enabled {
+            value |= TEMP_EN;
+        }
+        self.i2c.smbus_write_byte(CTRL_REG1, value)?;
+        Ok(())
+    }
+
+    /// Read X, Y and Z output registers in a single burst transaction.
+    pub fn read_raw(&mut self) -> Result<[i16; 3], Box<dyn Error>> {
         // wait for data to be ready
         let status = self.i2c.smbus_read_byte(STATUS_REG)?;
-        if status & 0x08 == 0 {
+        if status & STATUS_ZYXDA == 0 {
             return Err("Magnetometer data not ready".into());
         }
 
-        // read 6 bytes starting from OUT_X_L (auto-increment enabled)
         let mut data = [0u8; 6];
-        for (i, item) in data.iter_mut().enumerate() {
-            *item = self.i2c.smbus_read_byte(OUT_X_L + i as u8)?;
-        }
+        self.i2c
+            .write_read(&[OUT_X_L | AUTO_INCREMENT], &mut data)?;
 
         // convert to signed 16-bit values (little endian)
-        let x_raw = i16::from_le_bytes([data[0], data[1]]) as f64;
-        let y_raw = i16::from_le_bytes([data[2], data[3]]) as f64;
+        Ok([
+            i16::from_le_bytes([data[0], data[1]]),
+            i16::from_le_bytes([data[2], data[3]]),
+            i16::from_le_bytes([data[4], data[5]]),
+        ])
+    }
 
-        // apply hard iron calibration (center the readings)
-        let x = x_raw - X_OFFSET;
-        let y = y_raw - Y_OFFSET;
+    /// Read the magnetic field on all three axes, scaled to gauss.
+    pub fn read_field(&mut self) -> Result<MagneticField, Box<dyn Error>> {
+        let [x, y, z] = self.read_raw()?;
+        let lsb_per_gauss = self.config.full_scale.lsb_per_gauss();
+
+        Ok(MagneticField {
+            x: x as f64 / lsb_per_gauss,
+            y: y as f64 / lsb_per_gauss,
+            z: z as f64 / lsb_per_gauss,
+        })
+    }
 
-        // calculate heading using calibrated values
-        let raw_heading = y.atan2(x).to_degrees();
+    /// Read the magnetic field with the hard iron offsets removed (gauss).
+    /// The offsets are configured in raw counts at ±4 gauss, as reported by the calibration tool.
+    pub fn read_calibrated_field(&mut self) -> Result<MagneticField, Box<dyn Error>> {
+        let field = self.read_field()?;
+        let calibration_lsb = FullScale::Gauss4.lsb_per_gauss();
 
-        // apply final heading offset for location
-        let calibrated_heading = raw_heading + HEADING_OFFSET;
+        Ok(MagneticField {
+            x: field.x - self.calibration.x_offset / calibration_lsb,
+            y: field.y - self.calibration.y_offset / calibration_lsb,
+            z: field.z - self.calibration.z_offset / calibration_lsb,
+        })
+    }
 
-        // normalize to 0-360 range
-        Ok((calibrated_heading + 360.0) % 360.0)
+    /// Read from the device, and return the calibrated heading.
+    pub fn read_heading(&mut self) -> Result<f64, Box<dyn Error>> {
+        let field = self.read_calibrated_field()?;
+        Ok(heading_from_field(&field, self.calibration.heading_offset))
+    }
+
+    /// Level the field with `accelerometer` for the interference check, so heeling isn't taken
+    /// for interference; its axes must match the magnetometer's. Without one the inclination is
+    /// not checked.
+    pub fn set_accelerometer(&mut self, accelerometer: Option<Accelerometer>) {
+        self.detector.set_leveled(accelerometer.is_some());
+        self.accelerometer = accelerometer;
+    }
+
+    /// Read the calibrated field, leveled if there is an accelerometer (gauss).
+    pub fn read_leveled_field(&mut self) -> Result<MagneticField, Box<dyn Error>> {
+        let field = self.read_calibrated_field()?;
+        self.level(field)
+    }
+
+    /// Read the calibrated heading and check the leveled field for magnetic interference.
+    pub fn read(&mut self) -> Result<CompassReading, Box<dyn Error>> {
+        let field = self.read_calibrated_field()?;
+        let leveled = self.level(field)?;
+        let status = self.detector.check(&leveled);
+
+        Ok(CompassReading {
+            heading: heading_from_field(&field, self.calibration.heading_offset),
+            field,
+            status,
+        })
+    }
+
+    fn level(&mut self, field: MagneticField) -> Result<MagneticField, Box<dyn Error>> {
+        match &mut self.accelerometer {
+            Some(accelerometer) => Ok(field.leveled(accelerometer.read_up()?)),
+            None => Ok(field),
+        }
+    }
+
+    /// Use new calibration offsets and interference tolerances for the following readings.
+    pub fn set_calibration(&mut self, calibration: &CompassSettings) {
+        self.calibration = calibration.clone();
+        self.detector.configure(calibration);
+    }
+
+    /// Interference detector, to inspect or reset the field baseline.
+    pub fn interference_detector(&mut self) -> &mut InterferenceDetector {
+        &mut self.detector
     }
 
     /// Read raw magnetometer X, Y, Z values (for calibration).
-    pub fn read_raw_magnetometer(&mut self) -> Result<(f64, f64), Box<dyn Error>> {
-        // wait for data to be ready
-        let status = self.i2c.smbus_read_byte(STATUS_REG)?;
-        if status & 0x08 == 0 {
-            return Err("Magnetometer data not ready".into());
+    pub fn read_raw_magnetometer(&mut self) -> Result<(f64, f64, f64), Box<dyn Error>> {
+        let [x, y, z] = self.read_raw()?;
+        Ok((x as f64, y as f64, z as f64))
+    }
+
+    /// Read the on-die temperature sensor (°C). Only meaningful relative to other readings,
+    /// the sensor is not factory calibrated.
+    pub fn read_temperature(&mut self) -> Result<f64, Box<dyn Error>> {
+        if !self.config.temperature_enabled {
+            return Err("Temperature sensor is disabled".into());
         }
 
-        // read 6 bytes starting from OUT_X_L (auto-increment enabled)
-        let mut data = [0u8; 6];
-        for (i, item) in data.iter_mut().enumerate() {
-            *item = self.i2c.smbus_read_byte(OUT_X_L + i as u8)?;
+        let mut data = [0u8; 2];
+        self.i2c
+            .write_read(&[TEMP_OUT_L | AUTO_INCREMENT], &mut data)?;
+        let raw = i16::from_le_bytes(data) as f64;
+
+        Ok(TEMP_REFERENCE_C + raw / TEMP_LSB_PER_DEGREE)
+    }
+
+    /// Configure and enable the threshold interrupt on the INT pin.
+    pub fn configure_interrupt(&mut self, config: InterruptConfig) -> Result<(), Box<dyn Error>> {
+        // threshold is an unsigned 15-bit value in LSB at the current full scale
+        let threshold = (config.threshold_gauss.abs() * self.config.full_scale.lsb_per_gauss())
+            .round()
+            .min(i16::MAX as f64) as u16;
+        let [low, high] = threshold.to_le_bytes();
+        self.i2c.smbus_write_byte(INT_THS_L, low)?;
+        self.i2c.smbus_write_byte(INT_THS_H, high)?;
+
+        let mut value = INT_CFG_RESERVED | 0x01; // IEN
+        if config.x_enabled {
+            value |= 0x80;
+        }
+        if config.y_enabled {
+            value |= 0x40;
+        }
+        if config.z_enabled {
+            value |= 0x20;
         }
+        if config.active_high {
+            value |= 0x04;
+        }
+        if config.latched {
+            value |= 0x02;
+        }
+        self.i2c.smbus_write_byte(INT_CFG, value)?;
 
-        // convert to signed 16-bit values (little endian)
-        let x = i16::from_le_bytes([data[0], data[1]]) as f64;
-        let y = i16::from_le_bytes([data[2], data[3]]) as f64;
+        Ok(())
+    }
+
+    /// Disable the threshold interrupt.
+    pub fn disable_interrupt(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.smbus_write_byte(INT_CFG, INT_CFG_RESERVED)?;
+        Ok(())
+    }
+
+    /// Read (and, when latched, clear) the interrupt source register.
+    pub fn read_interrupt_source(&mut self) -> Result<InterruptSource, Box<dyn Error>> {
+        Ok(InterruptSource::from(self.i2c.smbus_read_byte(INT_SRC)?))
+    }
+}
+
+/// Calculate the heading from a calibrated field, including the location heading offset.
+/// Returns degrees in the 0-360 range.
+pub fn heading_from_field(field: &MagneticField, heading_offset: f64) -> f64 {
+    let raw_heading = field.y.atan2(field.x).to_degrees();
+
+    // apply final heading offset for location
+    let calibrated_heading = raw_heading + heading_offset;
+
+    // normalize to 0-360 range
+    ((calibrated_heading % 360.0) + 360.0) % 360.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FIELD_BASELINE_SAMPLES, HEADING_OFFSET, X_OFFSET, Y_OFFSET, Z_OFFSET};
+    use crate::mocks::mock_i2c;
+
+    fn mock_sensor() -> CompassSensor {
+        mock_i2c::reset_mock_registers();
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, WHO_AM_I, LIS3MDL_ID);
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, STATUS_REG, STATUS_ZYXDA);
+        CompassSensor::new(&CompassSettings::default()).unwrap()
+    }
+
+    fn set_raw_sample(x: i16, y: i16, z: i16) {
+        for (i, value) in [x, y, z].iter().enumerate() {
+            let [low, high] = value.to_le_bytes();
+            mock_i2c::set_mock_register(LIS3MDL_ADDR, OUT_X_L + 2 * i as u8, low);
+            mock_i2c::set_mock_register(LIS3MDL_ADDR, OUT_X_L + 2 * i as u8 + 1, high);
+        }
+    }
+
+    #[test]
+    fn test_wrong_device_id() {
+        mock_i2c::reset_mock_registers();
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, WHO_AM_I, 0x42);
+        assert!(CompassSensor::new(&CompassSettings::default()).is_err());
+    }
+
+    #[test]
+    fn test_default_configuration_registers() {
+        let _sensor = mock_sensor();
+
+        // matches the previous hardcoded setup: temp enabled, UHP, 80 Hz, ±4 gauss
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG1), 0xFC);
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG2), 0x00);
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG4), 0x0C);
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG5), 0x40);
+    }
+
+    #[test]
+    fn test_range_and_rate_selection() {
+        let mut sensor = mock_sensor();
+
+        sensor.set_full_scale(FullScale::Gauss16).unwrap();
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG2), 0x60);
+
+        sensor.set_operating_mode(OperatingMode::LowPower).unwrap();
+        sensor.set_data_rate(DataRate::Fast).unwrap();
+        // temp enabled, LP, FAST_ODR
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG1), 0x82);
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, CTRL_REG4), 0x00);
+    }
+
+    #[test]
+    fn test_burst_read_scales_all_axes() {
+        let mut sensor = mock_sensor();
+        set_raw_sample(6842, -3421, 1711);
+
+        let field = sensor.read_field().unwrap();
+        assert!((field.x - 1.0).abs() < 1e-9);
+        assert!((field.y + 0.5).abs() < 1e-9);
+        assert!((field.z - 0.25).abs() < 1e-3);
+
+        sensor.set_full_scale(FullScale::Gauss16).unwrap();
+        let field = sensor.read_field().unwrap();
+        assert!((field.z - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_data_not_ready() {
+        let mut sensor = mock_sensor();
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, STATUS_REG, 0x00);
+        assert!(sensor.read_raw().is_err());
+    }
+
+    #[test]
+    fn test_temperature() {
+        let mut sensor = mock_sensor();
+
+        // +40 LSB = +5 °C above the 25 °C reference
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, TEMP_OUT_L, 40);
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, TEMP_OUT_L + 1, 0);
+        assert!((sensor.read_temperature().unwrap() - 30.0).abs() < 1e-9);
+
+        sensor.set_temperature_enabled(false).unwrap();
+        assert!(sensor.read_temperature().is_err());
+    }
+
+    #[test]
+    fn test_interrupt_threshold() {
+        let mut sensor = mock_sensor();
+
+        sensor
+            .configure_interrupt(InterruptConfig {
+                x_enabled: true,
+                y_enabled: false,
+                z_enabled: true,
+                active_high: true,
+                latched: true,
+                threshold_gauss: 1.0,
+            })
+            .unwrap();
+
+        // 1 gauss at ±4 gauss = 6842 LSB = 0x1ABA
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, INT_THS_L), 0xBA);
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, INT_THS_H), 0x1A);
+        assert_eq!(mock_i2c::get_mock_register(LIS3MDL_ADDR, INT_CFG), 0xAF);
+
+        mock_i2c::set_mock_register(LIS3MDL_ADDR, INT_SRC, 0x81);
+        let source = sensor.read_interrupt_source().unwrap();
+        assert!(source.positive_x && source.active);
+        assert!(!source.negative_x);
+    }
+
+    fn field(magnitude: f64, inclination: f64) -> MagneticField {
+        let horizontal = magnitude * inclination.to_radians().cos();
+        MagneticField {
+            x: horizontal,
+            y: 0.0,
+            z: magnitude * inclination.to_radians().sin(),
+        }
+    }
+
+    #[test]
+    fn test_field_magnitude_and_inclination() {
+        let f = field(0.5, 60.0);
+        assert!((f.magnitude() - 0.5).abs() < 1e-9);
+        assert!((f.inclination() - 60.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interference_baseline_learning() {
+        let mut detector = InterferenceDetector::default();
+
+        for _ in 0..FIELD_BASELINE_SAMPLES - 1 {
+            assert_eq!(detector.check(&field(0.5, 60.0)), FieldStatus::Learning);
+        }
+        detector.check(&field(0.5, 60.0));
+
+        let baseline = detector.baseline().unwrap();
+        assert!((baseline.magnitude - 0.5).abs() < 1e-9);
+        assert!((baseline.inclination - 60.0).abs() < 1e-9);
+        assert_eq!(detector.check(&field(0.52, 61.0)), FieldStatus::Normal);
+    }
+
+    #[test]
+    fn test_calibrated_baseline() {
+        let settings = CompassSettings {
+            field_magnitude: 0.5,
+            field_inclination_deg: 60.0,
+            ..CompassSettings::default()
+        };
+        // no learning: the first reading is already checked, even next to the engine
+        let mut detector = InterferenceDetector::new(&settings);
+        assert!(detector.check(&field(0.8, 60.0)).is_disturbed());
+
+        let mut detector = InterferenceDetector::default();
+        detector.check(&field(0.8, 60.0));
+        detector.configure(&settings);
+        assert_eq!(
+            detector.baseline(),
+            Some(FieldBaseline {
+                magnitude: 0.5,
+                inclination: 60.0
+            })
+        );
+
+        let baseline = FieldBaseline::average(&[field(0.4, 58.0), field(0.6, 62.0)]).unwrap();
+        assert!((baseline.magnitude - 0.5).abs() < 1e-9);
+        assert!((baseline.inclination - 60.0).abs() < 1e-9);
+        assert_eq!(FieldBaseline::average(&[]), None);
+    }
+
+    /// `field` as the board measures it heeled by `roll` and pitched by `pitch` (degrees).
+    fn tilted(field: MagneticField, roll: f64, pitch: f64) -> MagneticField {
+        let (roll, pitch) = (roll.to_radians(), pitch.to_radians());
+        let x = field.x * pitch.cos() - field.z * pitch.sin();
+        let z = field.x * pitch.sin() + field.z * pitch.cos();
+        MagneticField {
+            x,
+            y: field.y * roll.cos() + z * roll.sin(),
+            z: -field.y * roll.sin() + z * roll.cos(),
+        }
+    }
+
+    fn up(roll: f64, pitch: f64) -> [f64; 3] {
+        let up = tilted(
+            MagneticField {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            },
+            roll,
+            pitch,
+        );
+        [up.x, up.y, up.z]
+    }
+
+    #[test]
+    fn test_leveled_field() {
+        let level = MagneticField {
+            x: 0.2,
+            y: -0.1,
+            z: 0.45,
+        };
+        let heeled = tilted(level, 25.0, -10.0);
+        assert!((heeled.inclination() - level.inclination()).abs() > 5.0);
+
+        let leveled = heeled.leveled(up(25.0, -10.0));
+        assert!((leveled.x - level.x).abs() < 1e-9);
+        assert!((leveled.y - level.y).abs() < 1e-9);
+        assert!((leveled.z - level.z).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interference_detected() {
+        let baseline = FieldBaseline {
+            magnitude: 0.5,
+            inclination: 60.0,
+        };
+        let mut detector =
+            InterferenceDetector::with_baseline(&CompassSettings::default(), baseline);
+
+        // a magnet doubles the field strength
+        assert!(detector.check(&field(1.0, 60.0)).is_disturbed());
+
+        // a steel object bends the field without changing its strength much
+        let mut detector =
+            InterferenceDetector::with_baseline(&CompassSettings::default(), baseline);
+        assert!(detector.check(&field(0.5, 66.0)).is_disturbed());
+
+        // heeling 20° tilts the field in the board frame, leveled that's no interference
+        let heeled = tilted(field(0.5, 60.0), 20.0, 0.0);
+        let mut detector =
+            InterferenceDetector::with_baseline(&CompassSettings::default(), baseline);
+        assert!(
+            !detector
+                .check(&heeled.leveled(up(20.0, 0.0)))
+                .is_disturbed()
+        );
+
+        // unleveled, only the strength is checked
+        detector.set_leveled(false);
+        assert!(!detector.check(&heeled).is_disturbed());
+        assert!(detector.check(&field(1.0, 60.0)).is_disturbed());
+    }
+
+    #[test]
+    fn test_interference_hysteresis() {
+        let mut detector = InterferenceDetector::with_baseline(
+            &CompassSettings::default(),
+            FieldBaseline {
+                magnitude: 0.5,
+                inclination: 60.0,
+            },
+        );
+
+        assert!(detector.check(&field(0.6, 60.0)).is_disturbed());
+        // 14% is inside the tolerance, but not far enough to clear a disturbance
+        assert!(detector.check(&field(0.57, 60.0)).is_disturbed());
+        assert_eq!(detector.check(&field(0.51, 60.0)), FieldStatus::Normal);
+        // and from normal, 14% stays normal
+        assert_eq!(detector.check(&field(0.57, 60.0)), FieldStatus::Normal);
+    }
+
+    #[test]
+    fn test_read_flags_disturbed_reading() {
+        let mut sensor = mock_sensor();
+        *sensor.interference_detector() = InterferenceDetector::with_baseline(
+            &CompassSettings::default(),
+            FieldBaseline {
+                magnitude: 0.5,
+                inclination: 0.0,
+            },
+        );
+
+        // calibrated field of 0.5 gauss along X
+        let x = (0.5 * 6842.0 + X_OFFSET) as i16;
+        set_raw_sample(x, Y_OFFSET as i16, Z_OFFSET as i16);
+        assert_eq!(sensor.read().unwrap().status, FieldStatus::Normal);
+
+        let x = (1.5 * 6842.0 + X_OFFSET) as i16;
+        set_raw_sample(x, Y_OFFSET as i16, Z_OFFSET as i16);
+        assert!(sensor.read().unwrap().status.is_disturbed());
+    }
+
+    #[test]
+    fn test_read_levels_with_accelerometer() {
+        let settings = CompassSettings {
+            field_magnitude: 0.5,
+            field_inclination_deg: 0.0,
+            ..CompassSettings::default()
+        };
+        let set_field = |field: MagneticField| {
+            set_raw_sample(
+                (field.x * 6842.0 + X_OFFSET).round() as i16,
+                (field.y * 6842.0 + Y_OFFSET).round() as i16,
+                (field.z * 6842.0 + Z_OFFSET).round() as i16,
+            )
+        };
+        // 0.5 gauss along X, the bow pitched up 20°
+        let pitched = tilted(field(0.5, 0.0), 0.0, 20.0);
+
+        let mut sensor = mock_sensor();
+        sensor.set_calibration(&settings);
+        sensor.set_accelerometer(Some(crate::gyro::tests::mock_accelerometer(up(0.0, 20.0))));
+        set_field(pitched);
+        assert_eq!(sensor.read().unwrap().status, FieldStatus::Normal);
+        let leveled = sensor.read_leveled_field().unwrap();
+        assert!(leveled.inclination().abs() < 0.1);
+
+        // the same field with the board level: bent by something magnetic
+        sensor.set_accelerometer(Some(crate::gyro::tests::mock_accelerometer(up(0.0, 0.0))));
+        assert!(sensor.read().unwrap().status.is_disturbed());
+
+        // without an accelerometer the inclination isn't checked
+        let mut sensor = mock_sensor();
+        sensor.set_calibration(&settings);
+        set_field(pitched);
+        assert_eq!(sensor.read().unwrap().status, FieldStatus::Normal);
+    }
 
-        Ok((x, y))
+    #[test]
+    fn test_heading_from_field() {
+        let north = MagneticField {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        assert!((heading_from_field(&north, HEADING_OFFSET) - HEADING_OFFSET).abs() < 1e-9);
     }
 }
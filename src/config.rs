@@ -1,22 +1,74 @@
+//! Settings. The constants below are the defaults; at runtime they are read into a [`Config`],
+//! which a TOML file and `GEO_RS_*` environment variables can override, and which is passed to
+//! each module.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
 // ** CALIBRATION CONFIGURATION ** //
 /// Magnetometer calibration offsets obtained from calibration: rotate board 360° and record min/max X,Y values and add them here.
 pub const X_OFFSET: f64 = -2776.0; // (X_min + X_max) / 2
 /// Obtained from calibration: rotate board 360° and record min/max X,Y values.
 pub const Y_OFFSET: f64 = 2556.0; // (Y_min + Y_max) / 2
+/// Obtained from calibration: only needed for field magnitude and inclination, not heading.
+pub const Z_OFFSET: f64 = 0.0; // (Z_min + Z_max) / 2
 /// Overall heading correction for this location.
 pub const HEADING_OFFSET: f64 = 88.0;
 
+// ** MAGNETIC INTERFERENCE CONFIGURATION ** //
+/// Undisturbed field strength measured by the calibration tool (gauss, 0 = not measured: the
+/// baseline is learned from the first readings after startup instead).
+pub const FIELD_MAGNITUDE: f64 = 0.0;
+/// Undisturbed field inclination measured by the calibration tool (degrees).
+pub const FIELD_INCLINATION_DEG: f64 = 0.0;
+/// Number of readings averaged for the undisturbed field baseline, by the calibration tool or
+/// at startup.
+pub const FIELD_BASELINE_SAMPLES: usize = 50;
+/// Allowed deviation of the total field magnitude from the baseline (fraction, 0.15 = 15%).
+pub const FIELD_MAGNITUDE_TOLERANCE: f64 = 0.15;
+/// Allowed deviation of the field inclination (dip angle) from the baseline (degrees). The field
+/// is leveled with the accelerometer first, so heeling doesn't count; without an accelerometer
+/// the inclination is not checked.
+pub const FIELD_INCLINATION_TOLERANCE_DEG: f64 = 5.0;
+
+// ** GPS CONFIGURATION ** //
+/// Serial device the GPS receiver is connected to.
+pub const GPS_SERIAL_DEVICE: &str = "/dev/serial0";
+/// How long past fixes are kept, e.g. to back-date a man-overboard mark (seconds, at least
+/// `MOB_BACKDATE_SECS`).
+pub const FIX_HISTORY_SECS: f64 = 60.0;
+/// A GPS course or speed older than this is not used: the receiver has stopped or lost its fix
+/// (seconds). A lost course trips the failsafe this long plus `FAILSAFE_HEADING_TIMEOUT_SECS`
+/// after the last one, so it is at most that timeout.
+pub const GPS_MAX_AGE_SECS: f64 = 3.0;
+
 // ** GPIO CONFIGURATION ** //
 /// GPIO Pin assignments for left button.
 pub const GPIO_TOGGLE_LEFT: u8 = 23;
 /// GPIO Pin assignments for right button.
 pub const GPIO_TOGGLE_RIGHT: u8 = 24;
+/// GPIO Pin assignment for the autopilot engage/standby button.
+pub const GPIO_ENGAGE_BUTTON: u8 = 25;
+/// Dedicated man-overboard button (active low, like the others).
+pub const GPIO_MOB_BUTTON_ENABLED: bool = false;
+pub const GPIO_MOB_BUTTON: u8 = 26;
+/// Holding the engage button this long marks a man overboard instead (seconds, 0 = off). While
+/// on, engage/standby happens when the button is released.
+pub const GPIO_MOB_LONG_PRESS_SECS: f64 = 3.0;
 /// Each button press modifies the heading in -5.0 degree increments.
 pub const ADJUST_LEFT_DEGREES: f64 = -5.0;
 /// Each button press modifies the heading in +5.0 degree increments.
 pub const ADJUST_RIGHT_DEGREES: f64 = 5.0;
 
 //  ** SERVO CONFIGURATION ** //
+/// What moves the rudder: a hobby servo positioned by pulse width, or a DC motor through an
+/// H-bridge positioned with the rudder sensor.
+pub const ACTUATOR: Actuator = Actuator::Servo;
 /// GPIO PWM channel for servo control: GPIO 18 (PWM0) - Most commonly used.
 pub const SERVO_PWM_PIN: u8 = 18;
 /// Standard servo pulse width range (microseconds). Most servos use 1000-2000μs, with 1500μs as center.
@@ -29,6 +81,11 @@ pub const SERVO_CENTER_PULSE_US: f64 = 1500.0;
 pub const SERVO_FREQUENCY_HZ: f64 = 50.0;
 /// Maximum servo angle range (degrees). Typical servos have 180° or 90° range.
 pub const SERVO_MAX_ANGLE: f64 = 90.0;
+/// Servo mounted the other way round: port rudder takes the longer pulses.
+pub const SERVO_REVERSED: bool = false;
+/// Measured rudder angle to pulse width points for a linkage that isn't linear. Empty maps
+/// port and starboard linearly onto the pulse range either side of center.
+pub const SERVO_CURVE: &[CurvePoint] = &[];
 /// PID controller gains: Proportional gain.
 pub const KP: f64 = 1.0;
 /// PID controller gains: Integral gain (disabled for now).
@@ -37,13 +94,2210 @@ pub const KI: f64 = 0.0;
 pub const KD: f64 = 0.0;
 /// Maximum heading error before applying correction (degrees).
 pub const HEADING_ERROR_DEADBAND: f64 = 2.0;
+/// Largest rudder angle the integral term may contribute (degrees).
+pub const PID_INTEGRAL_LIMIT: f64 = 20.0;
+/// How the integral is kept from winding up while the rudder is at its limit.
+pub const PID_ANTI_WINDUP: AntiWindup = AntiWindup::BackCalculation;
+/// Time constant of the low-pass filter on the derivative term (seconds). GPS heading is noisy
+/// at low speed, so the derivative is smoothed over about one fix.
+pub const PID_DERIVATIVE_FILTER_SECS: f64 = 1.0;
+/// Share of a setpoint change (a toggle press, a new leg) the proportional term acts on at once,
+/// 0 to 1. Below 1 the rudder doesn't jump when the target heading does and the integral turns
+/// the boat for the rest, so it needs `KI` above 0.
+pub const PID_SETPOINT_WEIGHT: f64 = 1.0;
+/// PID gains by speed over ground, e.g. softer at hull speed than at 2 knots. Empty uses the
+/// gains above at every speed; they are also used while there is no speed.
+pub const GAIN_SCHEDULE: &[GainPoint] = &[];
 /// Maximum servo movement rate (degrees per second). This prevents violent rudder movements.
 pub const MAX_SERVO_RATE: f64 = 40.0;
 /// Maximum servo movement rate (degrees per second). 0.1 = 10Hz.
 pub const SERVO_UPDATE_INTERVAL_SECS: f64 = 0.1;
 
+// ** RUDDER SENSOR CONFIGURATION ** //
+/// Read the rudder angle from a potentiometer on an ADS1115 ADC and close the position loop on it.
+pub const RUDDER_SENSOR_ENABLED: bool = false;
+/// I2C address of the ADS1115 (ADDR pin to GND).
+pub const RUDDER_ADC_ADDRESS: u16 = 0x48;
+/// ADS1115 input the potentiometer wiper is on (AIN0-AIN3).
+pub const RUDDER_ADC_CHANNEL: u8 = 0;
+/// Raw readings at the port end stop, center and starboard end stop, from `geo-rs
+/// calibrate-rudder`. The end stops are `servo.max_angle` either side. These suit a pot across
+/// 3.3 V (26400 counts) turned about 40° each way; calibrate before use.
+pub const RUDDER_PORT_RAW: f64 = 9900.0;
+pub const RUDDER_CENTER_RAW: f64 = 13200.0;
+pub const RUDDER_STARBOARD_RAW: f64 = 16500.0;
+/// Position loop: extra command per degree the rudder is off the commanded angle, and per
+/// degree-second, to take out linkage slop and load (degrees).
+pub const RUDDER_POSITION_KP: f64 = 0.5;
+pub const RUDDER_POSITION_KI: f64 = 0.5;
+/// Most the position loop may add to the command (degrees).
+pub const RUDDER_MAX_TRIM: f64 = 10.0;
+/// Rudder errors below this are left alone (degrees).
+pub const RUDDER_DEADBAND: f64 = 0.5;
+/// The rudder counts as jammed when it stays further than `RUDDER_JAM_TOLERANCE` from the
+/// commanded angle for `RUDDER_JAM_TIMEOUT_SECS` (degrees, seconds).
+pub const RUDDER_JAM_TOLERANCE: f64 = 5.0;
+pub const RUDDER_JAM_TIMEOUT_SECS: f64 = 3.0;
+/// Readings further outside the calibrated range than this fraction of it mean a broken wire
+/// or a loose pot: the wiper floats to a rail.
+pub const RUDDER_DISCONNECT_MARGIN: f64 = 0.1;
+
+// ** MOTOR DRIVE CONFIGURATION ** //
+/// H-bridge direction input: high drives the rudder to starboard. The speed is PWM on
+/// `SERVO_PWM_PIN`.
+pub const MOTOR_DIRECTION_PIN: u8 = 27;
+/// PWM frequency for the H-bridge (Hz).
+pub const MOTOR_FREQUENCY_HZ: f64 = 1000.0;
+/// Duty cycle range: below the minimum the motor doesn't turn, the maximum limits its speed.
+pub const MOTOR_MIN_DUTY: f64 = 0.2;
+pub const MOTOR_MAX_DUTY: f64 = 1.0;
+/// Duty cycle per degree of rudder error.
+pub const MOTOR_KP: f64 = 0.1;
+/// Rudder errors below this stop the motor (degrees).
+pub const MOTOR_DEADBAND: f64 = 1.0;
+/// Time to ramp the duty cycle from stopped to full, on starting, stopping and reversing
+/// (seconds). Spares the gearbox and the supply.
+pub const MOTOR_RAMP_SECS: f64 = 0.5;
+/// The motor counts as stalled when the rudder moves slower than `MOTOR_STALL_RATE` while
+/// driven for `MOTOR_STALL_TIMEOUT_SECS`; it is then stopped for `MOTOR_STALL_RETRY_SECS`
+/// (degrees per second, seconds).
+pub const MOTOR_STALL_RATE: f64 = 1.0;
+pub const MOTOR_STALL_TIMEOUT_SECS: f64 = 1.0;
+pub const MOTOR_STALL_RETRY_SECS: f64 = 5.0;
+
+// ** FAILSAFE CONFIGURATION ** //
+/// What the rudder does when no valid heading has arrived for `FAILSAFE_HEADING_TIMEOUT_SECS`
+/// while engaged. Steering resumes when the heading is back.
+pub const FAILSAFE_ACTION: FailsafeAction = FailsafeAction::Center;
+pub const FAILSAFE_HEADING_TIMEOUT_SECS: f64 = 5.0;
+/// Where the rudder is left when the program stops: on shutdown, Ctrl+C, SIGTERM or a crash.
+/// Holding is not possible once the program is gone.
+pub const FAILSAFE_SHUTDOWN_ACTION: FailsafeAction = FailsafeAction::Center;
+
+// ** WATCHDOG CONFIGURATION ** //
+/// What watches the control loop: a thread that puts the rudder in its shutdown state if the
+/// loop stops completing cycles with fresh sensor data, and with `Hardware` also stops petting
+/// the hardware watchdog so the system resets.
+pub const WATCHDOG_MODE: WatchdogMode = WatchdogMode::Software;
+/// Hardware watchdog device. Its own timeout (15 s on a Raspberry Pi) must be longer than
+/// `WATCHDOG_TIMEOUT_SECS`.
+pub const WATCHDOG_DEVICE: &str = "/dev/watchdog";
+/// The watchdog trips when no cycle has completed with fresh sensor data (see
+/// `WATCHDOG_SENSOR_TIMEOUT_SECS`) for this long (seconds).
+pub const WATCHDOG_TIMEOUT_SECS: f64 = 3.0;
+/// Cycles longer than this are logged with the stage that took longest (seconds).
+pub const WATCHDOG_CYCLE_DEADLINE_SECS: f64 = 0.5;
+/// While steering, a cycle has fresh sensor data if it had a heading, from the GPS or the
+/// compass, no older than this (seconds). Otherwise any completed cycle counts.
+pub const WATCHDOG_SENSOR_TIMEOUT_SECS: f64 = 5.0;
+
+// ** ALARM CONFIGURATION ** //
+/// Drive a buzzer or LED on `ALARM_OUTPUT_PIN` (high = sounding) while an alarm is sounding. It
+/// stays on if the program crashes.
+pub const ALARM_OUTPUT_ENABLED: bool = false;
+pub const ALARM_OUTPUT_PIN: u8 = 22;
+/// Alarms are appended to this file as well as logged (empty = no alarm file).
+pub const ALARM_LOG_FILE: &str = "logs/alarms.log";
+/// An alarm on a measured value clears once the value is back below its threshold by this
+/// fraction of the threshold, so it doesn't flap around the limit.
+pub const ALARM_HYSTERESIS: f64 = 0.2;
+/// How long `alarm silence` mutes the alarms sounding at the time (seconds).
+pub const ALARM_SILENCE_SECS: f64 = 120.0;
+/// Heading more than this far off the target while engaged (degrees, 0 = no alarm)...
+pub const ALARM_OFF_COURSE_DEG: f64 = 20.0;
+/// ...for this long (seconds).
+pub const ALARM_OFF_COURSE_SECS: f64 = 30.0;
+/// No GPS fix for this long (seconds, 0 = no alarm).
+pub const ALARM_GPS_LOST_SECS: f64 = 10.0;
+/// HDOP above this (0 = no alarm)...
+pub const ALARM_HDOP_MAX: f64 = 5.0;
+/// ...for this long (seconds).
+pub const ALARM_HDOP_SECS: f64 = 30.0;
+/// Compass disturbed by magnetic interference for this long (seconds, 0 = no alarm).
+pub const ALARM_COMPASS_DISTURBED_SECS: f64 = 10.0;
+/// Cross-track error beyond this in TRACK mode (meters, 0 = no alarm)...
+pub const ALARM_CROSS_TRACK_M: f64 = 100.0;
+/// ...for this long (seconds).
+pub const ALARM_CROSS_TRACK_SECS: f64 = 30.0;
+/// Rudder at its limit for this long while engaged (seconds, 0 = no alarm).
+pub const ALARM_RUDDER_LIMIT_SECS: f64 = 30.0;
+/// Sound the alarm when a route waypoint is reached, until acknowledged.
+pub const ALARM_WAYPOINT_ARRIVAL: bool = true;
+
+// ** AUTOTUNE CONFIGURATION ** //
+/// Rudder angle the relay test swings between (degrees either side).
+pub const AUTOTUNE_RELAY_AMPLITUDE: f64 = 10.0;
+/// Heading error the relay switches at, so GPS heading noise doesn't chatter it (degrees).
+pub const AUTOTUNE_HYSTERESIS: f64 = 1.0;
+/// The test stops if the heading strays further from the target than this (degrees).
+pub const AUTOTUNE_MAX_DEVIATION: f64 = 20.0;
+/// The test stops if no steady oscillation appears within this (seconds).
+pub const AUTOTUNE_MAX_DURATION_SECS: f64 = 180.0;
+/// Oscillation periods that must agree before the result is accepted.
+pub const AUTOTUNE_CYCLES: usize = 3;
+/// Rule turning the measured oscillation into PID gains.
+pub const AUTOTUNE_RULE: TuningRule = TuningRule::TyreusLuyben;
+
 // ** MAIN CONFIGURATION ** //
 /// Determins the length of the "vector" to follow (in meters).
 pub const LOOKAHEAD_DISTANCE_M: f64 = 100.0;
+/// Largest angle at which the autopilot steers back towards the route leg (degrees).
+pub const MAX_INTERCEPT_ANGLE_DEG: f64 = 30.0;
+/// Radius around a waypoint that counts as arrived (meters).
+pub const ARRIVAL_RADIUS_M: f64 = 50.0;
+/// Route loaded at startup if present: one `name,latitude,longitude` waypoint per line.
+pub const ROUTE_FILE: &str = "route.csv";
+/// GPX route loaded at startup if present, in preference to `ROUTE_FILE`.
+pub const ROUTE_GPX_FILE: &str = "route.gpx";
 /// How often to post Status Updates to the console.
 pub const STATUS_UPDATE_INTERVAL_SECS: u64 = 1;
+
+// ** ANCHOR WATCH CONFIGURATION ** //
+/// Swing radius around the anchor unless one is given when the anchor is dropped (meters).
+pub const ANCHOR_RADIUS_M: f64 = 50.0;
+/// Time constant of the filter smoothing the boat's position (seconds, 0 = unfiltered).
+pub const ANCHOR_FILTER_SECS: f64 = 10.0;
+/// The anchor alarm sounds once the filtered position has been outside the radius this long
+/// (seconds).
+pub const ANCHOR_DRAG_SECS: f64 = 30.0;
+/// The fix-lost alarm sounds after this long without a GPS fix while watching (seconds).
+pub const ANCHOR_FIX_LOST_SECS: f64 = 10.0;
+/// Record the swing pattern this often (seconds).
+pub const ANCHOR_RECORD_INTERVAL_SECS: f64 = 10.0;
+/// Directory the swing pattern files are written to (empty = keep it in memory only).
+pub const ANCHOR_DIR: &str = "logs";
+
+// ** MAN OVERBOARD CONFIGURATION ** //
+/// The MOB position is where the boat was this long before the button was pressed (seconds, at
+/// most `FIX_HISTORY_SECS`).
+pub const MOB_BACKDATE_SECS: f64 = 5.0;
+/// Make the MOB position the active waypoint, replacing the route. In TRACK the autopilot steers
+/// back to it.
+pub const MOB_SET_WAYPOINT: bool = true;
+
+// ** TRACK LOG CONFIGURATION ** //
+/// Directory the track log files are written to.
+pub const TRACK_LOG_DIR: &str = "logs";
+/// Log a record at least this often (seconds, 0 = only by distance).
+pub const TRACK_LOG_INTERVAL_SECS: f64 = 5.0;
+/// Log a record whenever the boat has moved this far since the last one (meters, 0 = only by time).
+pub const TRACK_LOG_MIN_DISTANCE_M: f64 = 10.0;
+/// Start a new log file once the current one reaches this size (bytes, 0 = no size limit).
+pub const TRACK_LOG_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+/// Start a new log file at midnight UTC.
+pub const TRACK_LOG_ROTATE_DAILY: bool = true;
+/// How often the log file is synced to disk (seconds). Every record is flushed to the OS
+/// immediately; this bounds what a power loss can take.
+pub const TRACK_LOG_SYNC_INTERVAL_SECS: u64 = 10;
+
+// ** NMEA CAPTURE CONFIGURATION ** //
+/// Directory raw NMEA captures are written to.
+pub const NMEA_CAPTURE_DIR: &str = "logs";
+/// Capture every raw line from the GPS receiver from startup.
+pub const NMEA_CAPTURE_ON_START: bool = false;
+
+// ** SIMULATOR CONFIGURATION ** //
+/// Where the simulated boat starts (`--simulate`).
+pub const SIMULATOR_START_LATITUDE: f64 = 48.056597;
+pub const SIMULATOR_START_LONGITUDE: f64 = -123.119772;
+/// Course (degrees) and speed (knots) of the simulated boat.
+pub const SIMULATOR_COURSE: f64 = 245.0;
+pub const SIMULATOR_SPEED_KNOTS: f64 = 5.0;
+
+// ** STEERING CONFIGURATION ** //
+/// What the engage button starts: `Track` follows the route if one is loaded, `Auto` always
+/// holds the current heading.
+pub const ENGAGE_MODE: EngageMode = EngageMode::Track;
+/// Heading the autopilot steers by; the other source is the fallback.
+pub const HEADING_SOURCE: HeadingSource = HeadingSource::Gps;
+/// How heading error becomes rudder: a PID on the heading error, or the cascaded rate-of-turn
+/// controller.
+pub const STEERING_ALGORITHM: SteeringAlgorithm = SteeringAlgorithm::Pid;
+
+// ** RATE OF TURN CONTROLLER ** //
+/// Fastest turn the outer loop asks for (degrees per second).
+pub const MAX_RATE_OF_TURN: f64 = 3.0;
+/// Outer loop: rate of turn asked for per degree of heading error (1/s).
+pub const ROT_HEADING_GAIN: f64 = 0.3;
+/// Inner loop gains: rudder degrees per °/s of rate error.
+pub const ROT_KP: f64 = 4.0;
+pub const ROT_KI: f64 = 0.5;
+pub const ROT_KD: f64 = 0.0;
+/// Where the rate of turn comes from. Without a gyro it is taken from the heading.
+pub const ROT_SOURCE: RateSource = RateSource::Gyro;
+/// Time constant of the low-pass filter on the rate of turn taken from the heading (seconds).
+/// GPS heading only updates once a second, so this smooths over a few fixes.
+pub const ROT_HEADING_FILTER_SECS: f64 = 2.0;
+
+// ** CONTROL CONFIGURATION ** //
+/// Local socket for reading and changing settings while running (`geo-rs param`).
+pub const CONTROL_SOCKET: &str = "geo-rs.sock";
+/// Reload the configuration file when it changes.
+pub const WATCH_CONFIG_FILE: bool = true;
+
+// ** RUNTIME CONFIGURATION ** //
+/// Configuration file read at startup if present and no `--config` is given.
+pub const CONFIG_FILE: &str = "geo-rs.toml";
+/// Prefix of the environment variables that override single settings, e.g.
+/// `GEO_RS_SERVO_MAX_PULSE_US=1900`.
+pub const ENV_PREFIX: &str = "GEO_RS_";
+/// Environment variable selecting the vessel profile, like `--profile`.
+pub const PROFILE_ENV: &str = "GEO_RS_PROFILE";
+
+/// Top-level keys of the file that select and define vessel profiles, and the key a profile
+/// names its base profile with.
+const PROFILE_KEY: &str = "profile";
+const PROFILES_KEY: &str = "profiles";
+const INHERITS_KEY: &str = "inherits";
+
+/// Runtime configuration. Every section and setting is optional in the file; anything left out
+/// keeps the default from the constants above.
+///
+/// A file can also define vessel profiles, `[profiles.<name>]` tables holding any of the
+/// sections below plus an optional `inherits = "<base profile>"`. The selected profile and the
+/// profiles it inherits from are layered over the top-level settings, base first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Vessel profile the settings came from.
+    #[serde(skip)]
+    pub profile: Option<String>,
+    pub compass: CompassSettings,
+    pub gps: GpsSettings,
+    pub gpio: GpioSettings,
+    pub servo: ServoSettings,
+    pub motor: MotorSettings,
+    pub rudder: RudderSettings,
+    pub failsafe: FailsafeSettings,
+    pub watchdog: WatchdogSettings,
+    pub alarm: AlarmSettings,
+    pub pid: PidSettings,
+    pub gain_schedule: GainScheduleSettings,
+    pub autotune: AutotuneSettings,
+    pub navigation: NavigationSettings,
+    pub anchor: AnchorSettings,
+    pub mob: MobSettings,
+    pub track_log: TrackLogSettings,
+    pub nmea_capture: CaptureSettings,
+    pub simulator: SimulatorSettings,
+    pub steering: SteeringSettings,
+    pub rate_of_turn: RateOfTurnSettings,
+    pub control: ControlSettings,
+}
+
+/// Magnetometer calibration and interference detection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompassSettings {
+    /// Hard iron offsets in raw counts at ±4 gauss, as printed by the calibration tool.
+    pub x_offset: f64,
+    pub y_offset: f64,
+    pub z_offset: f64,
+    pub heading_offset: f64,
+    pub field_magnitude: f64,
+    pub field_inclination_deg: f64,
+    pub field_baseline_samples: usize,
+    pub field_magnitude_tolerance: f64,
+    pub field_inclination_tolerance_deg: f64,
+}
+
+impl Default for CompassSettings {
+    fn default() -> Self {
+        Self {
+            x_offset: X_OFFSET,
+            y_offset: Y_OFFSET,
+            z_offset: Z_OFFSET,
+            heading_offset: HEADING_OFFSET,
+            field_magnitude: FIELD_MAGNITUDE,
+            field_inclination_deg: FIELD_INCLINATION_DEG,
+            field_baseline_samples: FIELD_BASELINE_SAMPLES,
+            field_magnitude_tolerance: FIELD_MAGNITUDE_TOLERANCE,
+            field_inclination_tolerance_deg: FIELD_INCLINATION_TOLERANCE_DEG,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpsSettings {
+    pub serial_device: PathBuf,
+    pub max_age_secs: f64,
+    pub fix_history_secs: f64,
+}
+
+impl Default for GpsSettings {
+    fn default() -> Self {
+        Self {
+            serial_device: PathBuf::from(GPS_SERIAL_DEVICE),
+            max_age_secs: GPS_MAX_AGE_SECS,
+            fix_history_secs: FIX_HISTORY_SECS,
+        }
+    }
+}
+
+/// Toggle switch and engage button wiring.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpioSettings {
+    pub toggle_left: u8,
+    pub toggle_right: u8,
+    pub engage_button: u8,
+    pub mob_button_enabled: bool,
+    pub mob_button: u8,
+    pub mob_long_press_secs: f64,
+    pub adjust_left_degrees: f64,
+    pub adjust_right_degrees: f64,
+}
+
+impl Default for GpioSettings {
+    fn default() -> Self {
+        Self {
+            toggle_left: GPIO_TOGGLE_LEFT,
+            toggle_right: GPIO_TOGGLE_RIGHT,
+            engage_button: GPIO_ENGAGE_BUTTON,
+            mob_button_enabled: GPIO_MOB_BUTTON_ENABLED,
+            mob_button: GPIO_MOB_BUTTON,
+            mob_long_press_secs: GPIO_MOB_LONG_PRESS_SECS,
+            adjust_left_degrees: ADJUST_LEFT_DEGREES,
+            adjust_right_degrees: ADJUST_RIGHT_DEGREES,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServoSettings {
+    pub actuator: Actuator,
+    pub pwm_pin: u8,
+    pub min_pulse_us: f64,
+    pub max_pulse_us: f64,
+    pub center_pulse_us: f64,
+    pub frequency_hz: f64,
+    pub max_angle: f64,
+    /// Degrees per second.
+    pub max_rate: f64,
+    pub update_interval_secs: f64,
+    pub reversed: bool,
+    pub curve: Vec<CurvePoint>,
+}
+
+/// Pulse width that puts the rudder at an angle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurvePoint {
+    pub angle_deg: f64,
+    pub pulse_us: f64,
+}
+
+impl Default for ServoSettings {
+    fn default() -> Self {
+        Self {
+            actuator: ACTUATOR,
+            pwm_pin: SERVO_PWM_PIN,
+            min_pulse_us: SERVO_MIN_PULSE_US,
+            max_pulse_us: SERVO_MAX_PULSE_US,
+            center_pulse_us: SERVO_CENTER_PULSE_US,
+            frequency_hz: SERVO_FREQUENCY_HZ,
+            max_angle: SERVO_MAX_ANGLE,
+            max_rate: MAX_SERVO_RATE,
+            update_interval_secs: SERVO_UPDATE_INTERVAL_SECS,
+            reversed: SERVO_REVERSED,
+            curve: SERVO_CURVE.to_vec(),
+        }
+    }
+}
+
+/// What moves the rudder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Actuator {
+    /// Hobby servo, positioned by pulse width.
+    Servo,
+    /// DC motor through an H-bridge (PWM speed and a direction pin), positioned with the rudder
+    /// sensor.
+    Motor,
+}
+
+/// H-bridge motor drive, used with `servo.actuator = "motor"`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotorSettings {
+    pub direction_pin: u8,
+    pub frequency_hz: f64,
+    pub min_duty: f64,
+    pub max_duty: f64,
+    pub kp: f64,
+    pub deadband_deg: f64,
+    pub ramp_secs: f64,
+    pub stall_rate_dps: f64,
+    pub stall_timeout_secs: f64,
+    pub stall_retry_secs: f64,
+}
+
+impl Default for MotorSettings {
+    fn default() -> Self {
+        Self {
+            direction_pin: MOTOR_DIRECTION_PIN,
+            frequency_hz: MOTOR_FREQUENCY_HZ,
+            min_duty: MOTOR_MIN_DUTY,
+            max_duty: MOTOR_MAX_DUTY,
+            kp: MOTOR_KP,
+            deadband_deg: MOTOR_DEADBAND,
+            ramp_secs: MOTOR_RAMP_SECS,
+            stall_rate_dps: MOTOR_STALL_RATE,
+            stall_timeout_secs: MOTOR_STALL_TIMEOUT_SECS,
+            stall_retry_secs: MOTOR_STALL_RETRY_SECS,
+        }
+    }
+}
+
+/// What the rudder does when steering can't go on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailsafeAction {
+    /// Leave the rudder where it is.
+    Hold,
+    /// Bring the rudder amidships.
+    Center,
+    /// Stop driving the rudder: no servo pulses, motor stopped.
+    Release,
+}
+
+impl fmt::Display for FailsafeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            FailsafeAction::Hold => "holding rudder",
+            FailsafeAction::Center => "centering rudder",
+            FailsafeAction::Release => "releasing rudder",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Failsafe on heading loss and on shutdown.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailsafeSettings {
+    pub action: FailsafeAction,
+    pub heading_timeout_secs: f64,
+    pub shutdown_action: FailsafeAction,
+}
+
+impl Default for FailsafeSettings {
+    fn default() -> Self {
+        Self {
+            action: FAILSAFE_ACTION,
+            heading_timeout_secs: FAILSAFE_HEADING_TIMEOUT_SECS,
+            shutdown_action: FAILSAFE_SHUTDOWN_ACTION,
+        }
+    }
+}
+
+/// What watches the control loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogMode {
+    Off,
+    /// A thread puts the rudder in its shutdown state when the loop is stuck.
+    Software,
+    /// As `Software`, and the hardware watchdog resets the system.
+    Hardware,
+}
+
+/// Control loop watchdog.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogSettings {
+    pub mode: WatchdogMode,
+    pub device: PathBuf,
+    pub timeout_secs: f64,
+    pub cycle_deadline_secs: f64,
+    pub sensor_timeout_secs: f64,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            mode: WATCHDOG_MODE,
+            device: PathBuf::from(WATCHDOG_DEVICE),
+            timeout_secs: WATCHDOG_TIMEOUT_SECS,
+            cycle_deadline_secs: WATCHDOG_CYCLE_DEADLINE_SECS,
+            sensor_timeout_secs: WATCHDOG_SENSOR_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// Alarm thresholds and the buzzer or LED output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlarmSettings {
+    pub output_enabled: bool,
+    pub output_pin: u8,
+    pub log_file: PathBuf,
+    pub hysteresis: f64,
+    pub silence_secs: f64,
+    pub off_course_deg: f64,
+    pub off_course_secs: f64,
+    pub gps_lost_secs: f64,
+    pub hdop_max: f64,
+    pub hdop_secs: f64,
+    pub compass_disturbed_secs: f64,
+    pub cross_track_m: f64,
+    pub cross_track_secs: f64,
+    pub rudder_limit_secs: f64,
+    pub waypoint_arrival: bool,
+}
+
+impl Default for AlarmSettings {
+    fn default() -> Self {
+        Self {
+            output_enabled: ALARM_OUTPUT_ENABLED,
+            output_pin: ALARM_OUTPUT_PIN,
+            log_file: PathBuf::from(ALARM_LOG_FILE),
+            hysteresis: ALARM_HYSTERESIS,
+            silence_secs: ALARM_SILENCE_SECS,
+            off_course_deg: ALARM_OFF_COURSE_DEG,
+            off_course_secs: ALARM_OFF_COURSE_SECS,
+            gps_lost_secs: ALARM_GPS_LOST_SECS,
+            hdop_max: ALARM_HDOP_MAX,
+            hdop_secs: ALARM_HDOP_SECS,
+            compass_disturbed_secs: ALARM_COMPASS_DISTURBED_SECS,
+            cross_track_m: ALARM_CROSS_TRACK_M,
+            cross_track_secs: ALARM_CROSS_TRACK_SECS,
+            rudder_limit_secs: ALARM_RUDDER_LIMIT_SECS,
+            waypoint_arrival: ALARM_WAYPOINT_ARRIVAL,
+        }
+    }
+}
+
+/// Rudder angle sensor and the position loop closed on it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RudderSettings {
+    pub enabled: bool,
+    pub i2c_address: u16,
+    pub channel: u8,
+    pub port_raw: f64,
+    pub center_raw: f64,
+    pub starboard_raw: f64,
+    pub position_kp: f64,
+    pub position_ki: f64,
+    pub max_trim_deg: f64,
+    pub deadband_deg: f64,
+    pub jam_tolerance_deg: f64,
+    pub jam_timeout_secs: f64,
+    pub disconnect_margin: f64,
+}
+
+impl Default for RudderSettings {
+    fn default() -> Self {
+        Self {
+            enabled: RUDDER_SENSOR_ENABLED,
+            i2c_address: RUDDER_ADC_ADDRESS,
+            channel: RUDDER_ADC_CHANNEL,
+            port_raw: RUDDER_PORT_RAW,
+            center_raw: RUDDER_CENTER_RAW,
+            starboard_raw: RUDDER_STARBOARD_RAW,
+            position_kp: RUDDER_POSITION_KP,
+            position_ki: RUDDER_POSITION_KI,
+            max_trim_deg: RUDDER_MAX_TRIM,
+            deadband_deg: RUDDER_DEADBAND,
+            jam_tolerance_deg: RUDDER_JAM_TOLERANCE,
+            jam_timeout_secs: RUDDER_JAM_TIMEOUT_SECS,
+            disconnect_margin: RUDDER_DISCONNECT_MARGIN,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PidSettings {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    /// Heading error (degrees) below which no correction is applied.
+    pub deadband: f64,
+    pub integral_limit: f64,
+    pub anti_windup: AntiWindup,
+    pub derivative_filter_secs: f64,
+    pub setpoint_weight: f64,
+}
+
+impl Default for PidSettings {
+    fn default() -> Self {
+        Self {
+            kp: KP,
+            ki: KI,
+            kd: KD,
+            deadband: HEADING_ERROR_DEADBAND,
+            integral_limit: PID_INTEGRAL_LIMIT,
+            anti_windup: PID_ANTI_WINDUP,
+            derivative_filter_secs: PID_DERIVATIVE_FILTER_SECS,
+            setpoint_weight: PID_SETPOINT_WEIGHT,
+        }
+    }
+}
+
+/// Integrator anti-windup strategy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AntiWindup {
+    /// Stop integrating while the output is saturated in the direction of the error.
+    Clamp,
+    /// Bleed the integral off by the amount the output exceeds its limit.
+    BackCalculation,
+}
+
+/// PID gains to use at a speed over ground.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GainPoint {
+    pub speed_knots: f64,
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+}
+
+/// Speed-scheduled PID gains. Between points the gains are interpolated, beyond the ends the
+/// nearest point's gains are held.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GainScheduleSettings {
+    pub points: Vec<GainPoint>,
+}
+
+impl Default for GainScheduleSettings {
+    fn default() -> Self {
+        Self {
+            points: GAIN_SCHEDULE.to_vec(),
+        }
+    }
+}
+
+/// PID tuning rule for the relay autotune.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TuningRule {
+    ZieglerNichols,
+    TyreusLuyben,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutotuneSettings {
+    pub relay_amplitude_deg: f64,
+    pub hysteresis_deg: f64,
+    pub max_deviation_deg: f64,
+    pub max_duration_secs: f64,
+    pub cycles: usize,
+    pub rule: TuningRule,
+    /// Written by the autopilot when a run completes, for review.
+    pub result: AutotuneResultSettings,
+}
+
+impl Default for AutotuneSettings {
+    fn default() -> Self {
+        Self {
+            relay_amplitude_deg: AUTOTUNE_RELAY_AMPLITUDE,
+            hysteresis_deg: AUTOTUNE_HYSTERESIS,
+            max_deviation_deg: AUTOTUNE_MAX_DEVIATION,
+            max_duration_secs: AUTOTUNE_MAX_DURATION_SECS,
+            cycles: AUTOTUNE_CYCLES,
+            rule: AUTOTUNE_RULE,
+            result: AutotuneResultSettings::default(),
+        }
+    }
+}
+
+/// Gains proposed by the last autotune run and the oscillation they came from. They are not
+/// used until copied into `[pid]`; all zero until a run completes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutotuneResultSettings {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub ultimate_gain: f64,
+    pub ultimate_period_secs: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NavigationSettings {
+    pub lookahead_distance_m: f64,
+    pub max_intercept_angle_deg: f64,
+    pub arrival_radius_m: f64,
+    pub route_file: PathBuf,
+    pub route_gpx_file: PathBuf,
+    pub status_update_interval_secs: u64,
+}
+
+impl Default for NavigationSettings {
+    fn default() -> Self {
+        Self {
+            lookahead_distance_m: LOOKAHEAD_DISTANCE_M,
+            max_intercept_angle_deg: MAX_INTERCEPT_ANGLE_DEG,
+            arrival_radius_m: ARRIVAL_RADIUS_M,
+            route_file: PathBuf::from(ROUTE_FILE),
+            route_gpx_file: PathBuf::from(ROUTE_GPX_FILE),
+            status_update_interval_secs: STATUS_UPDATE_INTERVAL_SECS,
+        }
+    }
+}
+
+/// Anchor watch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnchorSettings {
+    pub radius_m: f64,
+    pub filter_secs: f64,
+    pub drag_secs: f64,
+    pub fix_lost_secs: f64,
+    pub record_interval_secs: f64,
+    pub directory: PathBuf,
+}
+
+impl Default for AnchorSettings {
+    fn default() -> Self {
+        Self {
+            radius_m: ANCHOR_RADIUS_M,
+            filter_secs: ANCHOR_FILTER_SECS,
+            drag_secs: ANCHOR_DRAG_SECS,
+            fix_lost_secs: ANCHOR_FIX_LOST_SECS,
+            record_interval_secs: ANCHOR_RECORD_INTERVAL_SECS,
+            directory: PathBuf::from(ANCHOR_DIR),
+        }
+    }
+}
+
+/// Man overboard mark.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MobSettings {
+    pub backdate_secs: f64,
+    pub set_waypoint: bool,
+}
+
+impl Default for MobSettings {
+    fn default() -> Self {
+        Self {
+            backdate_secs: MOB_BACKDATE_SECS,
+            set_waypoint: MOB_SET_WAYPOINT,
+        }
+    }
+}
+
+/// Track log thresholds; 0 disables the interval, distance or size limit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackLogSettings {
+    pub directory: PathBuf,
+    pub interval_secs: f64,
+    pub min_distance_m: f64,
+    pub max_file_bytes: u64,
+    pub rotate_daily: bool,
+    pub sync_interval_secs: u64,
+}
+
+impl Default for TrackLogSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from(TRACK_LOG_DIR),
+            interval_secs: TRACK_LOG_INTERVAL_SECS,
+            min_distance_m: TRACK_LOG_MIN_DISTANCE_M,
+            max_file_bytes: TRACK_LOG_MAX_FILE_BYTES,
+            rotate_daily: TRACK_LOG_ROTATE_DAILY,
+            sync_interval_secs: TRACK_LOG_SYNC_INTERVAL_SECS,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureSettings {
+    pub directory: PathBuf,
+    pub on_start: bool,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from(NMEA_CAPTURE_DIR),
+            on_start: NMEA_CAPTURE_ON_START,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulatorSettings {
+    pub start_latitude: f64,
+    pub start_longitude: f64,
+    pub course: f64,
+    pub speed_knots: f64,
+}
+
+impl Default for SimulatorSettings {
+    fn default() -> Self {
+        Self {
+            start_latitude: SIMULATOR_START_LATITUDE,
+            start_longitude: SIMULATOR_START_LONGITUDE,
+            course: SIMULATOR_COURSE,
+            speed_knots: SIMULATOR_SPEED_KNOTS,
+        }
+    }
+}
+
+/// Mode the engage button starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EngageMode {
+    Auto,
+    Track,
+}
+
+/// Which heading the autopilot steers by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadingSource {
+    /// GPS course over ground, the compass when there is none (e.g. too slow).
+    Gps,
+    /// Compass, GPS when the compass is unavailable or disturbed.
+    Compass,
+}
+
+/// Controller turning heading error into rudder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SteeringAlgorithm {
+    /// PID on the heading error (`[pid]`).
+    Pid,
+    /// Heading error to a rate of turn, rate error to rudder (`[rate_of_turn]`).
+    RateOfTurn,
+}
+
+/// Where the rate-of-turn controller gets the rate of turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateSource {
+    /// The IMU gyroscope, falling back to the heading if there is none.
+    Gyro,
+    /// Differentiated, filtered heading.
+    Heading,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SteeringSettings {
+    pub engage_mode: EngageMode,
+    pub heading_source: HeadingSource,
+    pub algorithm: SteeringAlgorithm,
+}
+
+impl Default for SteeringSettings {
+    fn default() -> Self {
+        Self {
+            engage_mode: ENGAGE_MODE,
+            heading_source: HEADING_SOURCE,
+            algorithm: STEERING_ALGORITHM,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateOfTurnSettings {
+    pub max_rate_dps: f64,
+    pub heading_gain: f64,
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub source: RateSource,
+    pub heading_filter_secs: f64,
+}
+
+impl Default for RateOfTurnSettings {
+    fn default() -> Self {
+        Self {
+            max_rate_dps: MAX_RATE_OF_TURN,
+            heading_gain: ROT_HEADING_GAIN,
+            kp: ROT_KP,
+            ki: ROT_KI,
+            kd: ROT_KD,
+            source: ROT_SOURCE,
+            heading_filter_secs: ROT_HEADING_FILTER_SECS,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlSettings {
+    pub socket: PathBuf,
+    pub watch_config: bool,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            socket: PathBuf::from(CONTROL_SOCKET),
+            watch_config: WATCH_CONFIG_FILE,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration: the file if given with the vessel profile applied, then the
+    /// environment overrides. The result is validated.
+    ///
+    /// The profile is `profile`, else `GEO_RS_PROFILE`, else the file's own `profile` setting.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let profile = profile
+            .map(str::to_string)
+            .or_else(|| env::var(PROFILE_ENV).ok().filter(|name| !name.is_empty()));
+
+        let mut config = match path {
+            Some(path) => {
+                let text =
+                    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Self::from_toml_profile(&text, profile.as_deref())
+                    .map_err(|e| format!("{}: {}", path.display(), e))?
+            }
+            None => match profile {
+                Some(name) => {
+                    return Err(
+                        format!("vessel profile '{}' needs a configuration file", name).into(),
+                    );
+                }
+                None => Self::default(),
+            },
+        };
+
+        config.apply_overrides(env::vars())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse a configuration file with its default profile, if it names one. Not validated.
+    pub fn from_toml(text: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_toml_profile(text, None)
+    }
+
+    /// Parse a configuration file with the given vessel profile (or the file's default profile
+    /// if `None`) layered over the top-level settings. Not validated.
+    pub fn from_toml_profile(text: &str, profile: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let mut table: toml::Table = toml::from_str(text)?;
+        let default_profile = match table.remove(PROFILE_KEY) {
+            Some(toml::Value::String(name)) => Some(name),
+            Some(_) => return Err("profile must be the name of a profile".into()),
+            None => None,
+        };
+        let profiles = take_profiles(&mut table)?;
+
+        let profile = profile.map(str::to_string).or(default_profile);
+        if let Some(name) = &profile {
+            for layer in profile_chain(&profiles, name)? {
+                merge_tables(&mut table, layer);
+            }
+        }
+
+        let mut config: Config = table.try_into()?;
+        config.profile = profile;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Apply `GEO_RS_<SECTION>_<SETTING>` overrides, e.g. `GEO_RS_PID_KP=1.5`. Variables without
+    /// the prefix are ignored; a prefixed variable that doesn't name a setting is an error, so a
+    /// typo doesn't go unnoticed.
+    pub fn apply_overrides(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let keys: Vec<String> = self.settings().into_iter().map(|(key, _)| key).collect();
+        let mut overrides = Vec::new();
+
+        for (name, value) in vars {
+            if !name.starts_with(ENV_PREFIX) || name == PROFILE_ENV {
+                continue;
+            }
+            let key = keys
+                .iter()
+                .find(|key| env_name(key) == name)
+                .ok_or_else(|| format!("{}: no such setting", name))?;
+            overrides.push((name, key.clone(), value));
+        }
+
+        if !overrides.is_empty() {
+            self.set_values(&overrides)
+                .map_err(|e| format!("environment override: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Every setting as a `section.setting` name and its value.
+    pub fn settings(&self) -> Vec<(String, toml::Value)> {
+        let table = toml::Table::try_from(self).unwrap_or_default();
+        table
+            .into_iter()
+            .filter_map(|(section_name, section)| match section {
+                toml::Value::Table(section) => Some((section_name, section)),
+                _ => None,
+            })
+            .flat_map(|(section_name, section)| {
+                section
+                    .into_iter()
+                    .map(move |(key, value)| (format!("{}.{}", section_name, key), value))
+            })
+            .collect()
+    }
+
+    /// Value of a `section.setting`.
+    pub fn get(&self, key: &str) -> Option<toml::Value> {
+        self.settings()
+            .into_iter()
+            .find_map(|(name, value)| (name == key).then_some(value))
+    }
+
+    /// Set a `section.setting` from its text form, e.g. `set("pid.kp", "1.5")`. The value is
+    /// parsed as the setting's type; the result is not validated.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        self.set_values(&[(key.to_string(), key.to_string(), value.to_string())])
+    }
+
+    /// Apply `(name for errors, section.setting, value)` triples all at once.
+    fn set_values(&mut self, values: &[(String, String, String)]) -> Result<(), Box<dyn Error>> {
+        let mut table = toml::Table::try_from(&*self)?;
+
+        for (name, key, value) in values {
+            let setting = key.split_once('.').and_then(|(section, setting)| {
+                table.get_mut(section)?.as_table_mut()?.get_mut(setting)
+            });
+            let Some(setting) = setting else {
+                return Err(format!("{}: no such setting", name).into());
+            };
+
+            let invalid =
+                |expected: &str| format!("{}: expected {}, got '{}'", name, expected, value);
+            *setting = match setting {
+                toml::Value::Integer(_) => {
+                    toml::Value::Integer(value.trim().parse().map_err(|_| invalid("an integer"))?)
+                }
+                toml::Value::Float(_) => {
+                    toml::Value::Float(value.trim().parse().map_err(|_| invalid("a number"))?)
+                }
+                toml::Value::Boolean(_) => match value.trim().to_ascii_lowercase().as_str() {
+                    "true" | "1" | "yes" | "on" => toml::Value::Boolean(true),
+                    "false" | "0" | "no" | "off" => toml::Value::Boolean(false),
+                    _ => return Err(invalid("true or false").into()),
+                },
+                // inline TOML, e.g. `[{ speed_knots = 2.0, kp = 1.5, ki = 0.0, kd = 0.0 }]`
+                toml::Value::Array(_) => format!("value = {}", value)
+                    .parse::<toml::Table>()
+                    .ok()
+                    .and_then(|mut table| table.remove("value"))
+                    .filter(toml::Value::is_array)
+                    .ok_or_else(|| invalid("an array"))?,
+                _ => toml::Value::String(value.clone()),
+            };
+        }
+
+        let mut config: Config = table.try_into()?;
+        config.profile = self.profile.take();
+        *self = config;
+        Ok(())
+    }
+
+    /// Settings that differ in `new`, in name order.
+    pub fn changes(&self, new: &Config) -> Vec<Change> {
+        self.settings()
+            .into_iter()
+            .zip(new.settings())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((key, old), (_, new))| Change { key, old, new })
+            .collect()
+    }
+
+    /// Check the settings are usable together. All problems are reported at once, one per line.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, message: String| {
+            if !ok {
+                errors.push(message);
+            }
+        };
+
+        let compass = &self.compass;
+        for (name, value) in [
+            ("x_offset", compass.x_offset),
+            ("y_offset", compass.y_offset),
+            ("z_offset", compass.z_offset),
+            ("heading_offset", compass.heading_offset),
+        ] {
+            check(
+                value.is_finite(),
+                format!("compass.{} ({}) must be a finite number", name, value),
+            );
+        }
+        check(
+            compass.field_magnitude >= 0.0,
+            format!(
+                "compass.field_magnitude ({}) must not be negative",
+                compass.field_magnitude
+            ),
+        );
+        check(
+            (-90.0..=90.0).contains(&compass.field_inclination_deg),
+            format!(
+                "compass.field_inclination_deg ({}) must be between -90 and 90",
+                compass.field_inclination_deg
+            ),
+        );
+        check(
+            compass.field_baseline_samples > 0,
+            "compass.field_baseline_samples must be at least 1".to_string(),
+        );
+        check(
+            compass.field_magnitude_tolerance > 0.0,
+            format!(
+                "compass.field_magnitude_tolerance ({}) must be greater than 0",
+                compass.field_magnitude_tolerance
+            ),
+        );
+        check(
+            compass.field_inclination_tolerance_deg > 0.0,
+            format!(
+                "compass.field_inclination_tolerance_deg ({}) must be greater than 0",
+                compass.field_inclination_tolerance_deg
+            ),
+        );
+
+        let gps = &self.gps;
+        check(
+            gps.max_age_secs > 0.0 && gps.max_age_secs <= self.failsafe.heading_timeout_secs,
+            format!(
+                "gps.max_age_secs ({}) must be greater than 0 and at most failsafe.heading_timeout_secs ({})",
+                gps.max_age_secs, self.failsafe.heading_timeout_secs
+            ),
+        );
+
+        let gpio = &self.gpio;
+        check(
+            gpio.toggle_left != gpio.toggle_right
+                && gpio.toggle_left != gpio.engage_button
+                && gpio.toggle_right != gpio.engage_button,
+            format!(
+                "gpio.toggle_left ({}), gpio.toggle_right ({}) and gpio.engage_button ({}) must be different pins",
+                gpio.toggle_left, gpio.toggle_right, gpio.engage_button
+            ),
+        );
+        check(
+            gpio.mob_long_press_secs >= 0.0,
+            format!(
+                "gpio.mob_long_press_secs ({}) must not be negative",
+                gpio.mob_long_press_secs
+            ),
+        );
+        check(
+            gpio.adjust_left_degrees < 0.0,
+            format!(
+                "gpio.adjust_left_degrees ({}) must be negative",
+                gpio.adjust_left_degrees
+            ),
+        );
+        check(
+            gpio.adjust_right_degrees > 0.0,
+            format!(
+                "gpio.adjust_right_degrees ({}) must be positive",
+                gpio.adjust_right_degrees
+            ),
+        );
+
+        let servo = &self.servo;
+        check(
+            matches!(servo.pwm_pin, 12 | 13 | 18 | 19),
+            format!(
+                "servo.pwm_pin ({}) must be a hardware PWM pin: 12, 13, 18 or 19",
+                servo.pwm_pin
+            ),
+        );
+        check(
+            ![gpio.toggle_left, gpio.toggle_right, gpio.engage_button].contains(&servo.pwm_pin),
+            format!(
+                "servo.pwm_pin ({}) is already used by a gpio input",
+                servo.pwm_pin
+            ),
+        );
+        check(
+            servo.min_pulse_us > 0.0,
+            format!(
+                "servo.min_pulse_us ({}) must be greater than 0",
+                servo.min_pulse_us
+            ),
+        );
+        check(
+            servo.min_pulse_us < servo.max_pulse_us,
+            format!(
+                "servo.min_pulse_us ({}) must be less than servo.max_pulse_us ({})",
+                servo.min_pulse_us, servo.max_pulse_us
+            ),
+        );
+        check(
+            servo.min_pulse_us < servo.center_pulse_us
+                && servo.center_pulse_us < servo.max_pulse_us,
+            format!(
+                "servo.center_pulse_us ({}) must be between servo.min_pulse_us ({}) and servo.max_pulse_us ({})",
+                servo.center_pulse_us, servo.min_pulse_us, servo.max_pulse_us
+            ),
+        );
+        if servo.frequency_hz > 0.0 {
+            let period_us = 1_000_000.0 / servo.frequency_hz;
+            check(
+                servo.max_pulse_us < period_us,
+                format!(
+                    "servo.max_pulse_us ({}) must be shorter than the PWM period ({:.0}μs at {} Hz)",
+                    servo.max_pulse_us, period_us, servo.frequency_hz
+                ),
+            );
+        } else {
+            check(
+                false,
+                format!(
+                    "servo.frequency_hz ({}) must be greater than 0",
+                    servo.frequency_hz
+                ),
+            );
+        }
+        for (name, value) in [
+            ("max_angle", servo.max_angle),
+            ("max_rate", servo.max_rate),
+            ("update_interval_secs", servo.update_interval_secs),
+        ] {
+            check(
+                value > 0.0,
+                format!("servo.{} ({}) must be greater than 0", name, value),
+            );
+        }
+        let curve = &servo.curve;
+        check(
+            curve.len() != 1,
+            "servo.curve needs at least two points".to_string(),
+        );
+        for (i, point) in curve.iter().enumerate() {
+            check(
+                point.pulse_us >= servo.min_pulse_us && point.pulse_us <= servo.max_pulse_us,
+                format!(
+                    "servo.curve[{}].pulse_us ({}) must be between servo.min_pulse_us ({}) and servo.max_pulse_us ({})",
+                    i, point.pulse_us, servo.min_pulse_us, servo.max_pulse_us
+                ),
+            );
+        }
+        for (i, pair) in curve.windows(2).enumerate() {
+            check(
+                pair[0].angle_deg < pair[1].angle_deg,
+                format!(
+                    "servo.curve must be in order of increasing angle ({}° then {}° at {})",
+                    pair[0].angle_deg,
+                    pair[1].angle_deg,
+                    i + 1
+                ),
+            );
+        }
+        let rising = curve
+            .windows(2)
+            .all(|pair| pair[0].pulse_us < pair[1].pulse_us);
+        let falling = curve
+            .windows(2)
+            .all(|pair| pair[0].pulse_us > pair[1].pulse_us);
+        check(
+            rising || falling,
+            "servo.curve pulses must all increase or all decrease with the angle".to_string(),
+        );
+
+        let motor = &self.motor;
+        if servo.actuator == Actuator::Motor {
+            check(
+                self.rudder.enabled,
+                "servo.actuator = \"motor\" needs the rudder sensor (rudder.enabled)".to_string(),
+            );
+            check(
+                ![
+                    gpio.toggle_left,
+                    gpio.toggle_right,
+                    gpio.engage_button,
+                    servo.pwm_pin,
+                ]
+                .contains(&motor.direction_pin),
+                format!(
+                    "motor.direction_pin ({}) is already used by a gpio input or servo.pwm_pin",
+                    motor.direction_pin
+                ),
+            );
+        }
+        check(
+            motor.min_duty >= 0.0 && motor.min_duty <= motor.max_duty && motor.max_duty <= 1.0,
+            format!(
+                "motor.min_duty ({}) and motor.max_duty ({}) must be in order between 0 and 1",
+                motor.min_duty, motor.max_duty
+            ),
+        );
+        for (name, value) in [
+            ("frequency_hz", motor.frequency_hz),
+            ("kp", motor.kp),
+            ("stall_rate_dps", motor.stall_rate_dps),
+            ("stall_timeout_secs", motor.stall_timeout_secs),
+        ] {
+            check(
+                value > 0.0,
+                format!("motor.{} ({}) must be greater than 0", name, value),
+            );
+        }
+        for (name, value) in [
+            ("deadband_deg", motor.deadband_deg),
+            ("ramp_secs", motor.ramp_secs),
+            ("stall_retry_secs", motor.stall_retry_secs),
+        ] {
+            check(
+                value >= 0.0,
+                format!("motor.{} ({}) must not be negative", name, value),
+            );
+        }
+
+        let rudder = &self.rudder;
+        check(
+            matches!(rudder.i2c_address, 0x48..=0x4B),
+            format!(
+                "rudder.i2c_address (0x{:02X}) must be an ADS1115 address, 0x48 to 0x4B",
+                rudder.i2c_address
+            ),
+        );
+        check(
+            rudder.channel <= 3,
+            format!("rudder.channel ({}) must be 0 to 3", rudder.channel),
+        );
+        check(
+            (rudder.port_raw < rudder.center_raw && rudder.center_raw < rudder.starboard_raw)
+                || (rudder.port_raw > rudder.center_raw
+                    && rudder.center_raw > rudder.starboard_raw),
+            format!(
+                "rudder.center_raw ({}) must be between rudder.port_raw ({}) and rudder.starboard_raw ({})",
+                rudder.center_raw, rudder.port_raw, rudder.starboard_raw
+            ),
+        );
+        for (name, value) in [
+            ("position_kp", rudder.position_kp),
+            ("position_ki", rudder.position_ki),
+            ("max_trim_deg", rudder.max_trim_deg),
+            ("deadband_deg", rudder.deadband_deg),
+            ("disconnect_margin", rudder.disconnect_margin),
+        ] {
+            check(
+                value >= 0.0,
+                format!("rudder.{} ({}) must not be negative", name, value),
+            );
+        }
+        for (name, value) in [
+            ("jam_tolerance_deg", rudder.jam_tolerance_deg),
+            ("jam_timeout_secs", rudder.jam_timeout_secs),
+        ] {
+            check(
+                value > 0.0,
+                format!("rudder.{} ({}) must be greater than 0", name, value),
+            );
+        }
+
+        let failsafe = &self.failsafe;
+        check(
+            failsafe.heading_timeout_secs > 0.0,
+            format!(
+                "failsafe.heading_timeout_secs ({}) must be greater than 0",
+                failsafe.heading_timeout_secs
+            ),
+        );
+        check(
+            failsafe.shutdown_action != FailsafeAction::Hold,
+            "failsafe.shutdown_action must be \"center\" or \"release\"".to_string(),
+        );
+
+        let watchdog = &self.watchdog;
+        for (name, value) in [
+            ("timeout_secs", watchdog.timeout_secs),
+            ("cycle_deadline_secs", watchdog.cycle_deadline_secs),
+            ("sensor_timeout_secs", watchdog.sensor_timeout_secs),
+        ] {
+            check(
+                value > 0.0,
+                format!("watchdog.{} ({}) must be greater than 0", name, value),
+            );
+        }
+
+        let alarm = &self.alarm;
+        check(
+            (0.0..1.0).contains(&alarm.hysteresis),
+            format!(
+                "alarm.hysteresis ({}) must be at least 0 and less than 1",
+                alarm.hysteresis
+            ),
+        );
+        check(
+            alarm.silence_secs > 0.0,
+            format!(
+                "alarm.silence_secs ({}) must be greater than 0",
+                alarm.silence_secs
+            ),
+        );
+        for (name, value) in [
+            ("off_course_deg", alarm.off_course_deg),
+            ("off_course_secs", alarm.off_course_secs),
+            ("gps_lost_secs", alarm.gps_lost_secs),
+            ("hdop_max", alarm.hdop_max),
+            ("hdop_secs", alarm.hdop_secs),
+            ("compass_disturbed_secs", alarm.compass_disturbed_secs),
+            ("cross_track_m", alarm.cross_track_m),
+            ("cross_track_secs", alarm.cross_track_secs),
+            ("rudder_limit_secs", alarm.rudder_limit_secs),
+        ] {
+            check(
+                value >= 0.0,
+                format!("alarm.{} ({}) must not be negative", name, value),
+            );
+        }
+        if alarm.output_enabled {
+            let mut used = vec![
+                gpio.toggle_left,
+                gpio.toggle_right,
+                gpio.engage_button,
+                servo.pwm_pin,
+            ];
+            if servo.actuator == Actuator::Motor {
+                used.push(motor.direction_pin);
+            }
+            check(
+                !used.contains(&alarm.output_pin),
+                format!(
+                    "alarm.output_pin ({}) is already used by a gpio input, the servo or the motor",
+                    alarm.output_pin
+                ),
+            );
+        }
+        if gpio.mob_button_enabled {
+            let mut used = vec![
+                gpio.toggle_left,
+                gpio.toggle_right,
+                gpio.engage_button,
+                servo.pwm_pin,
+            ];
+            if servo.actuator == Actuator::Motor {
+                used.push(motor.direction_pin);
+            }
+            if alarm.output_enabled {
+                used.push(alarm.output_pin);
+            }
+            check(
+                !used.contains(&gpio.mob_button),
+                format!(
+                    "gpio.mob_button ({}) is already used by another gpio input, the servo, the motor or the alarm",
+                    gpio.mob_button
+                ),
+            );
+        }
+
+        let pid = &self.pid;
+        for (name, value) in [
+            ("kp", pid.kp),
+            ("ki", pid.ki),
+            ("kd", pid.kd),
+            ("deadband", pid.deadband),
+            ("integral_limit", pid.integral_limit),
+            ("derivative_filter_secs", pid.derivative_filter_secs),
+        ] {
+            check(
+                value >= 0.0,
+                format!("pid.{} ({}) must not be negative", name, value),
+            );
+        }
+        check(
+            (0.0..=1.0).contains(&pid.setpoint_weight),
+            format!(
+                "pid.setpoint_weight ({}) must be between 0 and 1",
+                pid.setpoint_weight
+            ),
+        );
+        // only the integral takes up the rest of a setpoint change
+        let no_integral = pid.ki == 0.0 || self.gain_schedule.points.iter().any(|p| p.ki == 0.0);
+        check(
+            pid.setpoint_weight == 1.0 || !no_integral,
+            format!(
+                "pid.setpoint_weight ({}) below 1 needs pid.ki, and every gain_schedule point's ki, above 0",
+                pid.setpoint_weight
+            ),
+        );
+
+        let points = &self.gain_schedule.points;
+        for (i, point) in points.iter().enumerate() {
+            check(
+                point.speed_knots >= 0.0,
+                format!(
+                    "gain_schedule.points[{}].speed_knots ({}) must not be negative",
+                    i, point.speed_knots
+                ),
+            );
+            for (name, value) in [("kp", point.kp), ("ki", point.ki), ("kd", point.kd)] {
+                check(
+                    value >= 0.0,
+                    format!(
+                        "gain_schedule.points[{}].{} ({}) must not be negative",
+                        i, name, value
+                    ),
+                );
+            }
+        }
+        for (i, pair) in points.windows(2).enumerate() {
+            check(
+                pair[0].speed_knots < pair[1].speed_knots,
+                format!(
+                    "gain_schedule.points must be in order of increasing speed ({} kn then {} kn at {})",
+                    pair[0].speed_knots,
+                    pair[1].speed_knots,
+                    i + 1
+                ),
+            );
+        }
+
+        let autotune = &self.autotune;
+        for (name, value) in [
+            ("relay_amplitude_deg", autotune.relay_amplitude_deg),
+            ("max_duration_secs", autotune.max_duration_secs),
+        ] {
+            check(
+                value > 0.0,
+                format!("autotune.{} ({}) must be greater than 0", name, value),
+            );
+        }
+        check(
+            autotune.hysteresis_deg >= 0.0 && autotune.hysteresis_deg < autotune.max_deviation_deg,
+            format!(
+                "autotune.hysteresis_deg ({}) must be between 0 and autotune.max_deviation_deg ({})",
+                autotune.hysteresis_deg, autotune.max_deviation_deg
+            ),
+        );
+        check(
+            autotune.cycles >= 1,
+            "autotune.cycles must be at least 1".to_string(),
+        );
+
+        let rate_of_turn = &self.rate_of_turn;
+        for (name, value) in [
+            ("max_rate_dps", rate_of_turn.max_rate_dps),
+            ("heading_gain", rate_of_turn.heading_gain),
+        ] {
+            check(
+                value > 0.0,
+                format!("rate_of_turn.{} ({}) must be greater than 0", name, value),
+            );
+        }
+        for (name, value) in [
+            ("kp", rate_of_turn.kp),
+            ("ki", rate_of_turn.ki),
+            ("kd", rate_of_turn.kd),
+            ("heading_filter_secs", rate_of_turn.heading_filter_secs),
+        ] {
+            check(
+                value >= 0.0,
+                format!("rate_of_turn.{} ({}) must not be negative", name, value),
+            );
+        }
+
+        let navigation = &self.navigation;
+        check(
+            navigation.lookahead_distance_m > 0.0,
+            format!(
+                "navigation.lookahead_distance_m ({}) must be greater than 0",
+                navigation.lookahead_distance_m
+            ),
+        );
+        check(
+            navigation.max_intercept_angle_deg > 0.0 && navigation.max_intercept_angle_deg <= 90.0,
+            format!(
+                "navigation.max_intercept_angle_deg ({}) must be between 0 and 90",
+                navigation.max_intercept_angle_deg
+            ),
+        );
+        check(
+            navigation.arrival_radius_m > 0.0,
+            format!(
+                "navigation.arrival_radius_m ({}) must be greater than 0",
+                navigation.arrival_radius_m
+            ),
+        );
+        check(
+            navigation.status_update_interval_secs > 0,
+            "navigation.status_update_interval_secs must be at least 1".to_string(),
+        );
+
+        let mob = &self.mob;
+        check(
+            (0.0..=self.gps.fix_history_secs).contains(&mob.backdate_secs),
+            format!(
+                "mob.backdate_secs ({}) must be between 0 and gps.fix_history_secs ({})",
+                mob.backdate_secs, self.gps.fix_history_secs
+            ),
+        );
+
+        let anchor = &self.anchor;
+        for (name, value) in [
+            ("radius_m", anchor.radius_m),
+            ("fix_lost_secs", anchor.fix_lost_secs),
+            ("record_interval_secs", anchor.record_interval_secs),
+        ] {
+            check(
+                value > 0.0,
+                format!("anchor.{} ({}) must be greater than 0", name, value),
+            );
+        }
+        for (name, value) in [
+            ("filter_secs", anchor.filter_secs),
+            ("drag_secs", anchor.drag_secs),
+        ] {
+            check(
+                value >= 0.0,
+                format!("anchor.{} ({}) must not be negative", name, value),
+            );
+        }
+
+        let track_log = &self.track_log;
+        check(
+            track_log.interval_secs >= 0.0,
+            format!(
+                "track_log.interval_secs ({}) must not be negative",
+                track_log.interval_secs
+            ),
+        );
+        check(
+            track_log.min_distance_m >= 0.0,
+            format!(
+                "track_log.min_distance_m ({}) must not be negative",
+                track_log.min_distance_m
+            ),
+        );
+        check(
+            track_log.interval_secs > 0.0 || track_log.min_distance_m > 0.0,
+            "track_log.interval_secs and track_log.min_distance_m can't both be 0".to_string(),
+        );
+        check(
+            track_log.sync_interval_secs > 0,
+            "track_log.sync_interval_secs must be at least 1".to_string(),
+        );
+
+        let simulator = &self.simulator;
+        check(
+            (-90.0..=90.0).contains(&simulator.start_latitude),
+            format!(
+                "simulator.start_latitude ({}) must be between -90 and 90",
+                simulator.start_latitude
+            ),
+        );
+        check(
+            (-180.0..=180.0).contains(&simulator.start_longitude),
+            format!(
+                "simulator.start_longitude ({}) must be between -180 and 180",
+                simulator.start_longitude
+            ),
+        );
+        check(
+            simulator.speed_knots >= 0.0,
+            format!(
+                "simulator.speed_knots ({}) must not be negative",
+                simulator.speed_knots
+            ),
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid configuration:\n  {}", errors.join("\n  ")).into())
+        }
+    }
+}
+
+/// A setting that changed, for logging.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub key: String,
+    pub old: toml::Value,
+    pub new: toml::Value,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} → {}", self.key, self.old, self.new)
+    }
+}
+
+/// A vessel profile defined in a configuration file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Profile it is based on.
+    pub inherits: Option<String>,
+}
+
+/// Vessel profiles defined in a configuration file, in name order.
+pub fn list_profiles(text: &str) -> Result<Vec<Profile>, Box<dyn Error>> {
+    let mut table: toml::Table = toml::from_str(text)?;
+    let profiles = take_profiles(&mut table)?;
+
+    Ok(profiles
+        .into_iter()
+        .map(|(name, profile)| {
+            let inherits = profile
+                .get(INHERITS_KEY)
+                .and_then(toml::Value::as_str)
+                .map(str::to_string);
+            Profile { name, inherits }
+        })
+        .collect())
+}
+
+/// Set `key = value` lines in the `[section]` table of a configuration file, keeping the rest
+/// of the file as written, comments included. Keys missing from the section are added to it,
+/// a missing section is added at the end. Values are TOML.
+pub fn update_section(text: &str, section: &str, values: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let is_header = |line: &str| line.trim_start().starts_with('[');
+    let header = format!("[{}]", section);
+    let start = lines
+        .iter()
+        .position(|line| line.split('#').next().unwrap_or("").trim() == header);
+
+    let Some(start) = start else {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(header);
+        lines.extend(
+            values
+                .iter()
+                .map(|(key, value)| format!("{} = {}", key, value)),
+        );
+        return lines.join("\n") + "\n";
+    };
+
+    let mut end = lines[start + 1..]
+        .iter()
+        .position(|line| is_header(line))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    for (key, value) in values {
+        let setting = format!("{} = {}", key, value);
+        let existing = (start + 1..end).find(|&i| {
+            let line = &lines[i];
+            !line.trim_start().starts_with('#')
+                && line
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name.trim() == *key)
+        });
+        match existing {
+            Some(i) => lines[i] = setting,
+            None => {
+                // after the last setting, before any blank lines leading to the next section
+                let last = (start..end)
+                    .rev()
+                    .find(|&i| !lines[i].trim().is_empty())
+                    .unwrap_or(start);
+                lines.insert(last + 1, setting);
+                end += 1;
+            }
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Remove the `[profiles]` table from a parsed file.
+fn take_profiles(table: &mut toml::Table) -> Result<toml::Table, Box<dyn Error>> {
+    match table.remove(PROFILES_KEY) {
+        Some(toml::Value::Table(profiles)) => Ok(profiles),
+        Some(_) => Err("profiles must be a table of [profiles.<name>] sections".into()),
+        None => Ok(toml::Table::new()),
+    }
+}
+
+/// Settings of profile `name` and the profiles it inherits from, base first.
+fn profile_chain(profiles: &toml::Table, name: &str) -> Result<Vec<toml::Table>, Box<dyn Error>> {
+    let mut chain = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut next = Some(name.to_string());
+
+    while let Some(name) = next {
+        if names.contains(&name) {
+            names.push(name);
+            return Err(format!("profile inheritance loop: {}", names.join(" → ")).into());
+        }
+
+        let Some(toml::Value::Table(profile)) = profiles.get(&name) else {
+            return Err(match names.last() {
+                Some(child) => format!("profile '{}' inherits unknown profile '{}'", child, name),
+                None => {
+                    let available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                    format!(
+                        "unknown vessel profile '{}' (defined: {})",
+                        name,
+                        if available.is_empty() {
+                            "none".to_string()
+                        } else {
+                            available.join(", ")
+                        }
+                    )
+                }
+            }
+            .into());
+        };
+
+        let mut profile = profile.clone();
+        next = match profile.remove(INHERITS_KEY) {
+            Some(toml::Value::String(base)) => Some(base),
+            Some(_) => {
+                return Err(format!("profile '{}': inherits must be a profile name", name).into());
+            }
+            None => None,
+        };
+
+        // check each profile on its own, so a typo is reported against the right one
+        toml::Value::Table(profile.clone())
+            .try_into::<Config>()
+            .map_err(|e| format!("profile '{}': {}", name, e))?;
+
+        chain.push(profile);
+        names.push(name);
+    }
+
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Layer `overlay` over `base`, section by section.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Environment variable for a `section.setting`.
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_")).to_ascii_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_defaults_match_constants() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.servo.min_pulse_us, SERVO_MIN_PULSE_US);
+        assert_eq!(config.pid.deadband, HEADING_ERROR_DEADBAND);
+        assert_eq!(config.gps.serial_device, Path::new(GPS_SERIAL_DEVICE));
+
+        // an empty file is the defaults, and the defaults survive a round trip
+        assert_eq!(Config::from_toml("").unwrap(), config);
+        assert_eq!(
+            Config::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_partial_file() {
+        let config = Config::from_toml(
+            "[servo]\nmax_pulse_us = 1900\n\n[pid]\nkp = 1.5\n\n[gps]\nserial_device = \"/dev/ttyUSB0\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.servo.max_pulse_us, 1900.0);
+        assert_eq!(config.servo.min_pulse_us, SERVO_MIN_PULSE_US);
+        assert_eq!(config.pid.kp, 1.5);
+        assert_eq!(config.gps.serial_device, Path::new("/dev/ttyUSB0"));
+
+        // typos are reported rather than silently ignored
+        assert!(Config::from_toml("[servo]\nmax_pulse = 1900\n").is_err());
+        assert!(Config::from_toml("[sevro]\nmax_pulse_us = 1900\n").is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let mut config = Config::default();
+        config
+            .apply_overrides(vars(&[
+                ("PATH", "/usr/bin"),
+                ("GEO_RS_SERVO_MAX_PULSE_US", "1900"),
+                ("GEO_RS_GPIO_TOGGLE_LEFT", "5"),
+                ("GEO_RS_TRACK_LOG_ROTATE_DAILY", "off"),
+                ("GEO_RS_GPS_SERIAL_DEVICE", "/dev/ttyUSB0"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.servo.max_pulse_us, 1900.0);
+        assert_eq!(config.gpio.toggle_left, 5);
+        assert!(!config.track_log.rotate_daily);
+        assert_eq!(config.gps.serial_device, Path::new("/dev/ttyUSB0"));
+
+        let error = Config::default()
+            .apply_overrides(vars(&[("GEO_RS_SERVO_MAX_PULSE", "1900")]))
+            .unwrap_err();
+        assert!(error.to_string().contains("no such setting"), "{}", error);
+
+        let error = Config::default()
+            .apply_overrides(vars(&[("GEO_RS_PID_KP", "fast")]))
+            .unwrap_err();
+        assert!(error.to_string().contains("expected a number"), "{}", error);
+
+        assert!(
+            Config::default()
+                .apply_overrides(vars(&[("GEO_RS_GPIO_ENGAGE_BUTTON", "300")]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_gain_schedule() {
+        let mut config = Config::from_toml(
+            "[[gain_schedule.points]]\nspeed_knots = 2.0\nkp = 2.0\nki = 0.1\nkd = 1.0\n",
+        )
+        .unwrap();
+        assert_eq!(config.gain_schedule.points.len(), 1);
+
+        // the whole table can be set at once, as inline TOML
+        config
+            .set(
+                "gain_schedule.points",
+                "[{ speed_knots = 6.0, kp = 1.0, ki = 0.0, kd = 0.5 }, \
+                 { speed_knots = 3.0, kp = 2.0, ki = 0.0, kd = 0.5 }]",
+            )
+            .unwrap();
+        assert_eq!(config.gain_schedule.points[1].speed_knots, 3.0);
+        assert!(config.set("gain_schedule.points", "fast").is_err());
+
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("increasing speed"), "{}", error);
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let mut config = Config::default();
+        config.servo.min_pulse_us = 2100.0;
+        config.pid.kp = -1.0;
+
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("servo.min_pulse_us (2100) must be less than servo.max_pulse_us (2000)"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("pid.kp (-1) must not be negative"),
+            "{}",
+            error
+        );
+
+        let mut config = Config::default();
+        config.gpio.engage_button = config.gpio.toggle_left;
+        config.servo.pwm_pin = 17;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("must be different pins"), "{}", error);
+        assert!(error.contains("servo.pwm_pin (17)"), "{}", error);
+
+        // a GPS course is not held past the failsafe timeout
+        let mut config = Config::default();
+        config.gps.max_age_secs = 6.0;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("gps.max_age_secs (6)"), "{}", error);
+        config.failsafe.heading_timeout_secs = 10.0;
+        config.validate().unwrap();
+
+        // a weighted setpoint leaves the rest of the change to the integral
+        let mut config = Config::default();
+        config.pid.setpoint_weight = 0.5;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error.contains("pid.setpoint_weight (0.5) below 1"),
+            "{}",
+            error
+        );
+        config.pid.ki = 0.05;
+        config.validate().unwrap();
+        config.pid.setpoint_weight = 1.5;
+        config.validate().unwrap_err();
+        config.pid.setpoint_weight = 0.0;
+        config.validate().unwrap();
+
+        // a MOB mark is taken from the fixes kept
+        config.mob.backdate_secs = 90.0;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(
+            error
+                .contains("mob.backdate_secs (90) must be between 0 and gps.fix_history_secs (60)"),
+            "{}",
+            error
+        );
+        config.gps.fix_history_secs = 120.0;
+        config.validate().unwrap();
+
+        // a motor is positioned with the rudder sensor
+        let mut config = Config::from_toml("[servo]\nactuator = \"motor\"\n").unwrap();
+        assert_eq!(config.servo.actuator, Actuator::Motor);
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("rudder.enabled"), "{}", error);
+        config.rudder.enabled = true;
+        config.validate().unwrap();
+
+        // the alarm output needs a pin of its own, and holding is no shutdown state
+        let mut config = Config::from_toml(
+            "[alarm]\noutput_enabled = true\noutput_pin = 27\n[failsafe]\nshutdown_action = \"hold\"\n",
+        )
+        .unwrap();
+        config.validate().unwrap_err();
+        config.failsafe.shutdown_action = FailsafeAction::Release;
+        config.validate().unwrap();
+        config.servo.actuator = Actuator::Motor;
+        config.rudder.enabled = true;
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("alarm.output_pin (27)"), "{}", error);
+    }
+
+    #[test]
+    fn test_update_section() {
+        let text =
+            "# boat\n[servo]\nmin_pulse_us = 1000.0 # stock\nmax_angle = 30.0\n\n[pid]\nkp = 2.0\n";
+        let updated = update_section(
+            text,
+            "servo",
+            &[
+                ("min_pulse_us", "1080".to_string()),
+                ("reversed", "true".to_string()),
+            ],
+        );
+        assert_eq!(
+            updated,
+            "# boat\n[servo]\nmin_pulse_us = 1080\nmax_angle = 30.0\nreversed = true\n\n[pid]\nkp = 2.0\n"
+        );
+        let config = Config::from_toml(&updated).unwrap();
+        assert_eq!(config.servo.min_pulse_us, 1080.0);
+        assert!(config.servo.reversed);
+        assert_eq!(config.pid.kp, 2.0);
+
+        // a section the file doesn't have yet
+        let updated = update_section(
+            text,
+            "profiles.ketch.servo",
+            &[("reversed", "true".to_string())],
+        );
+        assert!(updated.ends_with("kp = 2.0\n\n[profiles.ketch.servo]\nreversed = true\n"));
+        assert!(
+            Config::from_toml_profile(&updated, Some("ketch"))
+                .unwrap()
+                .servo
+                .reversed
+        );
+    }
+
+    const PROFILES: &str = r#"
+profile = "sloop"
+
+[pid]
+kp = 1.0
+
+[profiles.base.servo]
+max_angle = 25.0
+
+[profiles.base.pid]
+kp = 1.2
+ki = 0.05
+
+[profiles.sloop]
+inherits = "base"
+pid = { kp = 0.8 }
+steering = { heading_source = "compass" }
+
+[profiles.trawler]
+inherits = "base"
+servo = { max_angle = 35.0 }
+steering = { engage_mode = "auto" }
+"#;
+
+    #[test]
+    fn test_profile_inheritance() {
+        // the file's default profile
+        let sloop = Config::from_toml(PROFILES).unwrap();
+        assert_eq!(sloop.profile.as_deref(), Some("sloop"));
+        assert_eq!(sloop.pid.kp, 0.8);
+        assert_eq!(sloop.pid.ki, 0.05); // from base
+        assert_eq!(sloop.servo.max_angle, 25.0); // from base
+        assert_eq!(sloop.steering.heading_source, HeadingSource::Compass);
+        assert_eq!(sloop.steering.engage_mode, ENGAGE_MODE);
+
+        let trawler = Config::from_toml_profile(PROFILES, Some("trawler")).unwrap();
+        assert_eq!(trawler.pid.kp, 1.2);
+        assert_eq!(trawler.servo.max_angle, 35.0);
+        assert_eq!(trawler.steering.engage_mode, EngageMode::Auto);
+
+        // the profile survives changing a setting
+        let mut config = trawler.clone();
+        config.set("pid.kd", "0.5").unwrap();
+        assert_eq!(config.profile.as_deref(), Some("trawler"));
+
+        let profiles = list_profiles(PROFILES).unwrap();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["base", "sloop", "trawler"]);
+        assert_eq!(profiles[0].inherits, None);
+        assert_eq!(profiles[2].inherits.as_deref(), Some("base"));
+    }
+
+    #[test]
+    fn test_profile_errors() {
+        let error = Config::from_toml_profile(PROFILES, Some("ketch"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("unknown vessel profile 'ketch'"),
+            "{}",
+            error
+        );
+        assert!(error.contains("base, sloop, trawler"), "{}", error);
+
+        let looped = "[profiles.a]\ninherits = \"b\"\n[profiles.b]\ninherits = \"a\"\n";
+        // unused profiles are not resolved
+        assert!(Config::from_toml(looped).is_ok());
+        let error = Config::from_toml_profile(looped, Some("a"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("a → b → a"), "{}", error);
+
+        let error = Config::from_toml_profile("[profiles.a]\ninherits = \"b\"\n", Some("a"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("inherits unknown profile 'b'"), "{}", error);
+
+        // typos are reported against the profile they are in
+        let error = Config::from_toml_profile("[profiles.a.servo]\nmax_angel = 30.0\n", Some("a"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("profile 'a'"), "{}", error);
+    }
+}
@@ -0,0 +1,420 @@
+//! Local command channel for reading and changing parameters while running.
+//!
+//! A Unix socket takes one command per connection and answers with text, the first line
+//! starting with `error:` if the command failed:
+//!
+//! ```text
+//! list                      every setting
+//! get <section.setting>
+//! set <section.setting> <value>
+//! reload                    read the configuration file again
+//! autotune [stop]           start or stop a relay autotune of the heading PID
+//! alarm ack|silence         acknowledge the alarms, or silence them for a while
+//! anchor drop [radius <m>] [rode <m> <bearing>]
+//!                           start the anchor watch, the anchor off the boat by the rode
+//! anchor up                 stop the anchor watch
+//! mob [clear]               mark a man overboard where the boat was, or clear the mark
+//! status                    mode, target and time in mode of the autopilot
+//! ```
+//!
+//! Changes are staged like any other and applied by the control loop between cycles. Requests
+//! for the autopilot itself are queued for the control loop the same way, and the control loop
+//! publishes the autopilot status every cycle for `status`.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::anchor::Rode;
+use crate::autopilot::AutopilotStatus;
+use crate::params::{Parameters, needs_restart};
+
+/// How long a connection may take to send its command and read the reply. Connections are
+/// served one at a time, so a client that connects and goes quiet holds up the others this long.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Something for the control loop to do, sent over the control socket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    /// Start a relay autotune around the heading held in AUTO.
+    Autotune,
+    /// Stop the autotune and go back to AUTO.
+    StopAutotune,
+    /// Acknowledge the raised alarms.
+    AcknowledgeAlarms,
+    /// Silence the sounding alarms for `alarm.silence_secs`.
+    SilenceAlarms,
+    /// Mark the anchor and start the anchor watch.
+    DropAnchor {
+        radius_m: Option<f64>,
+        rode: Option<Rode>,
+    },
+    /// Stop the anchor watch.
+    RaiseAnchor,
+    /// Mark a man overboard.
+    ManOverboard,
+    /// Clear the MOB mark.
+    ClearMob,
+}
+
+/// The autopilot status as the control loop last published it. Clones share it.
+#[derive(Clone, Debug, Default)]
+pub struct SharedStatus {
+    status: Arc<Mutex<Option<AutopilotStatus>>>,
+}
+
+impl SharedStatus {
+    /// Replace the status, once a cycle.
+    pub fn publish(&self, status: AutopilotStatus) {
+        if let Ok(mut current) = self.status.lock() {
+            *current = Some(status);
+        }
+    }
+
+    /// Latest status, `None` until the control loop has published one.
+    pub fn latest(&self) -> Option<AutopilotStatus> {
+        self.status.lock().ok().and_then(|current| *current)
+    }
+}
+
+/// Listen on `socket` and serve commands on a background thread. A stale socket file left by a
+/// previous run is replaced. Requests for the autopilot are sent to `requests`, and `status`
+/// answers from `status`.
+pub fn start_control_server(
+    socket: &Path,
+    parameters: Parameters,
+    requests: Sender<Request>,
+    status: SharedStatus,
+) -> io::Result<()> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another instance", socket.display()),
+            ));
+        }
+        fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream, &parameters, &requests, &status) {
+                        crate::debug!("Control connection failed: {}", e);
+                    }
+                }
+                Err(e) => crate::warn!("Control socket: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn serve(
+    stream: UnixStream,
+    parameters: &Parameters,
+    requests: &Sender<Request>,
+    status: &SharedStatus,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let reply = match execute(line.trim(), parameters, requests, status) {
+        Ok(reply) => reply,
+        Err(e) => format!("error: {}\n", e),
+    };
+    (&stream).write_all(reply.as_bytes())
+}
+
+/// Run one command against the parameters and return the reply text.
+pub fn execute(
+    command: &str,
+    parameters: &Parameters,
+    requests: &Sender<Request>,
+    status: &SharedStatus,
+) -> Result<String, Box<dyn Error>> {
+    let mut words = command.split_whitespace();
+    let mut reply = String::new();
+
+    match (words.next(), words.next()) {
+        (Some("list"), None) => {
+            for (key, value) in parameters.current().settings() {
+                writeln!(reply, "{} = {}", key, value)?;
+            }
+        }
+        (Some("get"), Some(key)) => {
+            let value = parameters
+                .current()
+                .get(key)
+                .ok_or_else(|| format!("{}: no such setting", key))?;
+            writeln!(reply, "{} = {}", key, value)?;
+        }
+        (Some("set"), Some(key)) => {
+            let value = words.collect::<Vec<_>>().join(" ");
+            if value.is_empty() {
+                return Err("usage: set <section.setting> <value>".into());
+            }
+            crate::info!("  ⚙ Control socket: set {} {}", key, value);
+            let change = parameters.set(key, &value)?;
+            writeln!(reply, "{}", change)?;
+            if needs_restart(key) {
+                writeln!(reply, "takes effect after a restart")?;
+            }
+        }
+        (Some("reload"), None) => {
+            let changes = parameters.reload()?;
+            if changes.is_empty() {
+                writeln!(reply, "no changes")?;
+            }
+            for change in changes {
+                writeln!(reply, "{}", change)?;
+            }
+        }
+        (Some("autotune"), stop) => {
+            let request = match stop {
+                None => Request::Autotune,
+                Some("stop") => Request::StopAutotune,
+                Some(_) => return Err("usage: autotune [stop]".into()),
+            };
+            crate::info!("  ⚙ Control socket: {}", command);
+            requests
+                .send(request)
+                .map_err(|_| "the autopilot is not running")?;
+            writeln!(reply, "requested, see the autopilot log for the outcome")?;
+        }
+        (Some("alarm"), action) => {
+            let request = match action {
+                Some("ack") => Request::AcknowledgeAlarms,
+                Some("silence") => Request::SilenceAlarms,
+                _ => return Err("usage: alarm ack|silence".into()),
+            };
+            crate::info!("  ⚙ Control socket: {}", command);
+            requests
+                .send(request)
+                .map_err(|_| "the autopilot is not running")?;
+            writeln!(reply, "requested, see the autopilot log for the outcome")?;
+        }
+        (Some("anchor"), action) => {
+            let request = match action {
+                Some("drop") => parse_anchor_drop(&words.collect::<Vec<_>>())?,
+                Some("up") => Request::RaiseAnchor,
+                _ => return Err(ANCHOR_USAGE.into()),
+            };
+            crate::info!("  ⚙ Control socket: {}", command);
+            requests
+                .send(request)
+                .map_err(|_| "the autopilot is not running")?;
+            writeln!(reply, "requested, see the autopilot log for the outcome")?;
+        }
+        (Some("mob"), clear) => {
+            let request = match clear {
+                None => Request::ManOverboard,
+                Some("clear") => Request::ClearMob,
+                Some(_) => return Err("usage: mob [clear]".into()),
+            };
+            crate::info!("  ⚙ Control socket: {}", command);
+            requests
+                .send(request)
+                .map_err(|_| "the autopilot is not running")?;
+            writeln!(reply, "requested, see the autopilot log for the outcome")?;
+        }
+        (Some("status"), None) => {
+            let status = status.latest().ok_or("the autopilot is not running")?;
+            writeln!(reply, "{}", status)?;
+        }
+        _ => {
+            return Err(format!(
+                "unknown command '{}' (list, get, set, reload, autotune, alarm, anchor, mob, status)",
+                command
+            )
+            .into());
+        }
+    }
+    Ok(reply)
+}
+
+const ANCHOR_USAGE: &str = "usage: anchor drop [radius <m>] [rode <m> <bearing>] | anchor up";
+
+/// Read the options of `anchor drop`.
+fn parse_anchor_drop(words: &[&str]) -> Result<Request, Box<dyn Error>> {
+    let number = |word: Option<&&str>| -> Result<f64, Box<dyn Error>> {
+        let word = word.ok_or(ANCHOR_USAGE)?;
+        word.parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .ok_or_else(|| format!("{}: not a distance or bearing", word).into())
+    };
+
+    let mut radius_m = None;
+    let mut rode = None;
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        match *word {
+            "radius" => {
+                let radius = number(words.next())?;
+                if radius == 0.0 {
+                    return Err("the radius must be greater than 0".into());
+                }
+                radius_m = Some(radius);
+            }
+            "rode" => {
+                rode = Some(Rode {
+                    length_m: number(words.next())?,
+                    bearing: number(words.next())?,
+                });
+            }
+            _ => return Err(ANCHOR_USAGE.into()),
+        }
+    }
+    Ok(Request::DropAnchor { radius_m, rode })
+}
+
+/// Send a command to a running instance and return its reply. A reply starting with `error:`
+/// is returned as an error.
+pub fn send_command(socket: &Path, command: &str) -> Result<String, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket).map_err(|e| {
+        format!(
+            "{}: {} (is geo-rs running in this directory?)",
+            socket.display(),
+            e
+        )
+    })?;
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    match reply.strip_prefix("error: ") {
+        Some(message) => Err(message.trim_end().into()),
+        None => Ok(reply),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autopilot::Mode;
+    use crate::config::Config;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_commands_over_socket() {
+        let dir = std::env::temp_dir().join(format!("geo-rs-control-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("geo-rs.sock");
+
+        let parameters = Parameters::new(Config::default(), None);
+        let (requests, received) = mpsc::channel();
+        let status = SharedStatus::default();
+        start_control_server(
+            &socket,
+            parameters.clone(),
+            requests.clone(),
+            status.clone(),
+        )
+        .unwrap();
+        // a second instance must not take over the socket
+        assert!(
+            start_control_server(&socket, parameters.clone(), requests, status.clone()).is_err()
+        );
+
+        let reply = send_command(&socket, "set servo.max_rate 25").unwrap();
+        assert_eq!(reply, "servo.max_rate: 40.0 → 25.0\n");
+        // applied by the control loop, not by the command
+        assert_eq!(
+            send_command(&socket, "get servo.max_rate").unwrap(),
+            "servo.max_rate = 40.0\n"
+        );
+        parameters.apply_pending();
+        assert_eq!(
+            send_command(&socket, "get servo.max_rate").unwrap(),
+            "servo.max_rate = 25.0\n"
+        );
+
+        let error = send_command(&socket, "set servo.min_pulse_us 3000").unwrap_err();
+        assert!(
+            error.to_string().contains("servo.min_pulse_us"),
+            "{}",
+            error
+        );
+        assert!(send_command(&socket, "bogus").is_err());
+        assert!(
+            send_command(&socket, "list")
+                .unwrap()
+                .contains("pid.kp = 1.0\n")
+        );
+
+        // a client that never sends its command doesn't hold up the others
+        let _quiet = UnixStream::connect(&socket).unwrap();
+        assert!(send_command(&socket, "get pid.kp").is_ok());
+
+        send_command(&socket, "autotune").unwrap();
+        send_command(&socket, "autotune stop").unwrap();
+        assert!(send_command(&socket, "autotune now").is_err());
+        send_command(&socket, "alarm ack").unwrap();
+        send_command(&socket, "alarm silence").unwrap();
+        assert!(send_command(&socket, "alarm").is_err());
+        send_command(&socket, "anchor drop").unwrap();
+        send_command(&socket, "anchor drop rode 30 215 radius 45").unwrap();
+        send_command(&socket, "anchor up").unwrap();
+        assert!(send_command(&socket, "anchor drop radius").is_err());
+        assert!(send_command(&socket, "anchor drop rode 30").is_err());
+        assert!(send_command(&socket, "anchor drop radius 0").is_err());
+        send_command(&socket, "mob").unwrap();
+        send_command(&socket, "mob clear").unwrap();
+        assert!(send_command(&socket, "mob now").is_err());
+        assert_eq!(received.try_recv(), Ok(Request::Autotune));
+        assert_eq!(received.try_recv(), Ok(Request::StopAutotune));
+        assert_eq!(received.try_recv(), Ok(Request::AcknowledgeAlarms));
+        assert_eq!(received.try_recv(), Ok(Request::SilenceAlarms));
+        assert_eq!(
+            received.try_recv(),
+            Ok(Request::DropAnchor {
+                radius_m: None,
+                rode: None
+            })
+        );
+        assert_eq!(
+            received.try_recv(),
+            Ok(Request::DropAnchor {
+                radius_m: Some(45.0),
+                rode: Some(Rode {
+                    length_m: 30.0,
+                    bearing: 215.0
+                })
+            })
+        );
+        assert_eq!(received.try_recv(), Ok(Request::RaiseAnchor));
+        assert_eq!(received.try_recv(), Ok(Request::ManOverboard));
+        assert_eq!(received.try_recv(), Ok(Request::ClearMob));
+        assert!(received.try_recv().is_err());
+
+        // the status as the control loop last published it
+        assert!(send_command(&socket, "status").is_err());
+        status.publish(AutopilotStatus {
+            mode: Mode::Auto,
+            target_heading: Some(92.0),
+            target_wind_angle: None,
+            mode_duration: Duration::from_secs(75),
+        });
+        assert_eq!(
+            send_command(&socket, "status").unwrap(),
+            "AUTO | Target: 92.0° (E) | for 75s\n"
+        );
+        assert!(send_command(&socket, "status now").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
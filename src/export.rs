@@ -0,0 +1,547 @@
+//! KML and GeoJSON export of track logs and routes, for Google Earth and web maps.
+//!
+//! GPX export goes through [`crate::gpx`]; this module covers the formats that can carry the
+//! per-point autopilot data (speed, headings, rudder angle, mode).
+
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value, json};
+
+use crate::gpx::{Gpx, escape};
+use crate::position::Position;
+use crate::route::Route;
+use crate::track_log::{LogRecord, records_to_track};
+
+/// Icon rotated by the heading for the heading arrows.
+const ARROW_ICON: &str = "http://maps.google.com/mapfiles/kml/shapes/arrow.png";
+const WAYPOINT_ICON: &str = "http://maps.google.com/mapfiles/kml/paddle/blu-circle.png";
+const ALARM_ICON: &str = "http://maps.google.com/mapfiles/kml/shapes/caution.png";
+
+/// Alarms further than this from the nearest logged fix are left out (seconds).
+const ALARM_MAX_GAP_SECS: i64 = 60;
+
+/// A point of interest shown alongside the track, e.g. where an alarm went off.
+#[derive(Clone, Debug)]
+pub struct Marker {
+    pub name: String,
+    pub position: Position,
+    pub time: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+}
+
+/// Export file formats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Gpx,
+    Kml,
+    GeoJson,
+}
+
+impl ExportFormat {
+    /// Usual file extension.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Gpx => "gpx",
+            ExportFormat::Kml => "kml",
+            ExportFormat::GeoJson => "geojson",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gpx" => Ok(ExportFormat::Gpx),
+            "kml" => Ok(ExportFormat::Kml),
+            "geojson" | "json" => Ok(ExportFormat::GeoJson),
+            _ => Err(format!("unknown export format '{}' (gpx, kml, geojson)", s)),
+        }
+    }
+}
+
+/// A trip to export: the logged track, any routes sailed and alarm markers.
+#[derive(Clone, Debug, Default)]
+pub struct Trip {
+    pub name: String,
+    pub records: Vec<LogRecord>,
+    pub routes: Vec<Route>,
+    pub alarms: Vec<Marker>,
+}
+
+impl Trip {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Add a marker for every alarm raised in the text of an alarm file (see
+    /// [`crate::alarm`]), at the logged position nearest in time. Add the records first: alarms
+    /// without a fix within a minute are left out. Returns how many were added.
+    pub fn add_alarms(&mut self, text: &str) -> Result<usize, Box<dyn Error>> {
+        let mut added = 0;
+        for (index, line) in text.lines().enumerate() {
+            let Some((time, message)) = line
+                .split_once(' ')
+                .and_then(|(time, event)| Some((time, event.strip_prefix("ALARM ")?)))
+            else {
+                continue;
+            };
+            let time = DateTime::parse_from_rfc3339(time)
+                .map_err(|e| format!("line {}: {}", index + 1, e))?
+                .with_timezone(&Utc);
+
+            let nearest = self
+                .records
+                .iter()
+                .filter_map(|record| {
+                    Some(((record.time - time).num_seconds().abs(), record.position?))
+                })
+                .min_by_key(|(gap, _)| *gap)
+                .filter(|(gap, _)| *gap <= ALARM_MAX_GAP_SECS);
+            if let Some((_, position)) = nearest {
+                self.alarms.push(Marker {
+                    name: message.to_string(),
+                    position,
+                    time: Some(time),
+                    description: None,
+                });
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Render in the given format.
+    pub fn export(&self, format: ExportFormat) -> Result<String, Box<dyn Error>> {
+        match format {
+            ExportFormat::Gpx => Ok(self.to_gpx().to_xml()),
+            ExportFormat::Kml => Ok(self.to_kml()),
+            ExportFormat::GeoJson => Ok(serde_json::to_string_pretty(&self.to_geojson())?),
+        }
+    }
+
+    pub fn to_gpx(&self) -> Gpx {
+        let track = records_to_track(&self.name, &self.records);
+        Gpx {
+            routes: self.routes.clone(),
+            tracks: if track.segments.is_empty() {
+                Vec::new()
+            } else {
+                vec![track]
+            },
+            ..Default::default()
+        }
+    }
+
+    /// KML document with the track as a styled LineString, a heading arrow for every logged
+    /// point, and placemarks for route waypoints and alarms.
+    pub fn to_kml(&self) -> String {
+        let mut kml = String::new();
+        kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+        let _ = writeln!(kml, "  <name>{}</name>", escape(&self.name));
+        write_styles(&mut kml);
+
+        let positions: Vec<Position> = self.records.iter().filter_map(|r| r.position).collect();
+        if positions.len() >= 2 {
+            kml.push_str("  <Placemark>\n    <name>Track</name>\n");
+            kml.push_str("    <styleUrl>#track</styleUrl>\n");
+            write_line_string(&mut kml, &positions);
+            kml.push_str("  </Placemark>\n");
+        }
+
+        if !positions.is_empty() {
+            kml.push_str("  <Folder>\n    <name>Headings</name>\n");
+            for record in &self.records {
+                write_arrow(&mut kml, record);
+            }
+            kml.push_str("  </Folder>\n");
+        }
+
+        for route in &self.routes {
+            let _ = writeln!(
+                kml,
+                "  <Folder>\n    <name>Route: {}</name>",
+                escape(&route.name)
+            );
+            let positions: Vec<Position> = route.waypoints().iter().map(|w| w.position).collect();
+            if positions.len() >= 2 {
+                kml.push_str("    <Placemark>\n      <styleUrl>#route</styleUrl>\n");
+                write_line_string(&mut kml, &positions);
+                kml.push_str("    </Placemark>\n");
+            }
+            for waypoint in route.waypoints() {
+                write_placemark(
+                    &mut kml,
+                    &waypoint.name,
+                    "waypoint",
+                    waypoint.position,
+                    waypoint.time,
+                    None,
+                );
+            }
+            kml.push_str("  </Folder>\n");
+        }
+
+        if !self.alarms.is_empty() {
+            kml.push_str("  <Folder>\n    <name>Alarms</name>\n");
+            for alarm in &self.alarms {
+                write_placemark(
+                    &mut kml,
+                    &alarm.name,
+                    "alarm",
+                    alarm.position,
+                    alarm.time,
+                    alarm.description.as_deref(),
+                );
+            }
+            kml.push_str("  </Folder>\n");
+        }
+
+        kml.push_str("</Document>\n</kml>\n");
+        kml
+    }
+
+    /// GeoJSON FeatureCollection: the track as a LineString, each logged point with its speed,
+    /// headings, rudder angle and mode, the routes with their waypoints, and the alarms.
+    pub fn to_geojson(&self) -> Value {
+        let mut features = Vec::new();
+
+        let track: Vec<Value> = self
+            .records
+            .iter()
+            .filter_map(|r| r.position)
+            .map(|p| coordinates(&p))
+            .collect();
+        // a LineString needs at least two positions
+        if track.len() >= 2 {
+            features.push(json!({
+                "type": "Feature",
+                "geometry": { "type": "LineString", "coordinates": track },
+                "properties": { "kind": "track", "name": self.name },
+            }));
+        }
+
+        for record in &self.records {
+            let Some(position) = record.position else {
+                continue;
+            };
+            features.push(json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": coordinates(&position) },
+                "properties": {
+                    "kind": "fix",
+                    "time": format_time(record.time),
+                    "speed_kn": record.speed,
+                    "heading": record.gps_heading.or(record.compass_heading),
+                    "gps_heading": record.gps_heading,
+                    "compass_heading": record.compass_heading,
+                    "target_heading": record.target_heading,
+                    "rudder_angle": record.rudder_angle,
+                    "mode": record.mode.to_string(),
+                },
+            }));
+        }
+
+        for route in &self.routes {
+            let line: Vec<Value> = route
+                .waypoints()
+                .iter()
+                .map(|w| coordinates(&w.position))
+                .collect();
+            if line.len() >= 2 {
+                features.push(json!({
+                    "type": "Feature",
+                    "geometry": { "type": "LineString", "coordinates": line },
+                    "properties": { "kind": "route", "name": route.name },
+                }));
+            }
+
+            for waypoint in route.waypoints() {
+                let mut properties = Map::new();
+                properties.insert("kind".into(), json!("waypoint"));
+                properties.insert("name".into(), json!(waypoint.name));
+                properties.insert("route".into(), json!(route.name));
+                if let Some(time) = waypoint.time {
+                    properties.insert("time".into(), json!(format_time(time)));
+                }
+                features.push(point_feature(&waypoint.position, properties));
+            }
+        }
+
+        for alarm in &self.alarms {
+            let mut properties = Map::new();
+            properties.insert("kind".into(), json!("alarm"));
+            properties.insert("name".into(), json!(alarm.name));
+            if let Some(time) = alarm.time {
+                properties.insert("time".into(), json!(format_time(time)));
+            }
+            if let Some(description) = &alarm.description {
+                properties.insert("description".into(), json!(description));
+            }
+            features.push(point_feature(&alarm.position, properties));
+        }
+
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// GeoJSON positions are longitude first.
+fn coordinates(position: &Position) -> Value {
+    json!([position.longitude, position.latitude])
+}
+
+fn point_feature(position: &Position, properties: Map<String, Value>) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": coordinates(position) },
+        "properties": properties,
+    })
+}
+
+fn write_styles(kml: &mut String) {
+    // KML colors are aabbggrr
+    kml.push_str(
+        "  <Style id=\"track\"><LineStyle><color>ff0000ff</color><width>3</width></LineStyle></Style>\n",
+    );
+    kml.push_str(
+        "  <Style id=\"route\"><LineStyle><color>ffff0000</color><width>2</width></LineStyle></Style>\n",
+    );
+    let _ = writeln!(
+        kml,
+        "  <Style id=\"waypoint\"><IconStyle><Icon><href>{}</href></Icon></IconStyle></Style>",
+        WAYPOINT_ICON
+    );
+    let _ = writeln!(
+        kml,
+        "  <Style id=\"alarm\"><IconStyle><color>ff0000ff</color><Icon><href>{}</href></Icon></IconStyle></Style>",
+        ALARM_ICON
+    );
+}
+
+fn write_line_string(kml: &mut String, positions: &[Position]) {
+    kml.push_str("    <LineString><tessellate>1</tessellate><coordinates>\n");
+    for position in positions {
+        let _ = writeln!(
+            kml,
+            "      {:.7},{:.7}",
+            position.longitude, position.latitude
+        );
+    }
+    kml.push_str("    </coordinates></LineString>\n");
+}
+
+fn write_placemark(
+    kml: &mut String,
+    name: &str,
+    style: &str,
+    position: Position,
+    time: Option<DateTime<Utc>>,
+    description: Option<&str>,
+) {
+    let _ = writeln!(kml, "    <Placemark>\n      <name>{}</name>", escape(name));
+    if let Some(description) = description {
+        let _ = writeln!(
+            kml,
+            "      <description>{}</description>",
+            escape(description)
+        );
+    }
+    if let Some(time) = time {
+        let _ = writeln!(
+            kml,
+            "      <TimeStamp><when>{}</when></TimeStamp>",
+            format_time(time)
+        );
+    }
+    let _ = writeln!(kml, "      <styleUrl>#{}</styleUrl>", style);
+    let _ = writeln!(
+        kml,
+        "      <Point><coordinates>{:.7},{:.7}</coordinates></Point>\n    </Placemark>",
+        position.longitude, position.latitude
+    );
+}
+
+/// A heading arrow, rotated to the GPS heading (compass if there is none).
+fn write_arrow(kml: &mut String, record: &LogRecord) {
+    let (Some(position), Some(heading)) = (
+        record.position,
+        record.gps_heading.or(record.compass_heading),
+    ) else {
+        return;
+    };
+
+    let mut description = format!("{} | heading {:.0}°", record.mode, heading);
+    if let Some(speed) = record.speed {
+        let _ = write!(description, " | {:.1} kn", speed);
+    }
+    if let Some(rudder) = record.rudder_angle {
+        let _ = write!(description, " | rudder {:+.0}°", rudder);
+    }
+
+    kml.push_str("    <Placemark>\n");
+    let _ = writeln!(
+        kml,
+        "      <description>{}</description>",
+        escape(&description)
+    );
+    let _ = writeln!(
+        kml,
+        "      <TimeStamp><when>{}</when></TimeStamp>",
+        format_time(record.time)
+    );
+    let _ = writeln!(
+        kml,
+        "      <Style><IconStyle><scale>0.6</scale><heading>{:.1}</heading><Icon><href>{}</href></Icon></IconStyle></Style>",
+        heading, ARROW_ICON
+    );
+    let _ = writeln!(
+        kml,
+        "      <Point><coordinates>{:.7},{:.7}</coordinates></Point>\n    </Placemark>",
+        position.longitude, position.latitude
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autopilot::Mode;
+    use crate::route::Waypoint;
+    use chrono::TimeZone;
+
+    fn trip() -> Trip {
+        let time = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let record = |lat: f64, seconds: i64| LogRecord {
+            time: time + chrono::Duration::seconds(seconds),
+            position: Some(Position::new(lat, -123.0)),
+            speed: Some(5.0),
+            gps_heading: Some(0.0),
+            compass_heading: Some(2.0),
+            target_heading: Some(1.0),
+            rudder_angle: Some(-2.0),
+            mode: Mode::Auto,
+        };
+
+        Trip {
+            name: "Harbour & back".to_string(),
+            records: vec![
+                record(48.0, 0),
+                LogRecord::new(Mode::Standby),
+                record(48.001, 10),
+            ],
+            routes: vec![Route::with_waypoints(
+                "out",
+                vec![
+                    Waypoint::new("A", Position::new(48.0, -123.0)),
+                    Waypoint::new("B", Position::new(48.1, -123.0)),
+                ],
+            )],
+            alarms: vec![Marker {
+                name: "Off course".to_string(),
+                position: Position::new(48.001, -123.0),
+                time: Some(time),
+                description: Some("25° off".to_string()),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_kml() {
+        let kml = trip().to_kml();
+
+        assert!(kml.contains("<name>Harbour &amp; back</name>"));
+        assert!(kml.contains("<styleUrl>#track</styleUrl>"));
+        assert!(kml.contains("-123.0000000,48.0010000"));
+        // one arrow per point with a position
+        assert_eq!(kml.matches("<heading>0.0</heading>").count(), 2);
+        assert!(kml.contains("<name>A</name>"));
+        assert!(kml.contains("<name>Off course</name>"));
+        assert!(kml.contains("rudder -2°"));
+    }
+
+    #[test]
+    fn test_geojson() {
+        let geojson = trip().to_geojson();
+        let features = geojson["features"].as_array().unwrap();
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        assert_eq!(features[0]["geometry"]["type"], "LineString");
+        assert_eq!(features[0]["geometry"]["coordinates"][1][1], 48.001);
+
+        let fixes: Vec<_> = features
+            .iter()
+            .filter(|f| f["properties"]["kind"] == "fix")
+            .collect();
+        assert_eq!(fixes.len(), 2);
+        assert_eq!(fixes[0]["properties"]["speed_kn"], 5.0);
+        assert_eq!(fixes[0]["properties"]["heading"], 0.0);
+        assert_eq!(fixes[0]["properties"]["rudder_angle"], -2.0);
+        assert_eq!(fixes[0]["properties"]["mode"], "AUTO");
+
+        assert!(
+            features
+                .iter()
+                .any(|f| f["properties"]["kind"] == "alarm"
+                    && f["properties"]["name"] == "Off course")
+        );
+        assert_eq!(
+            features
+                .iter()
+                .filter(|f| f["properties"]["kind"] == "waypoint")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_export_formats() {
+        assert_eq!("GeoJSON".parse::<ExportFormat>(), Ok(ExportFormat::GeoJson));
+        assert!("shp".parse::<ExportFormat>().is_err());
+
+        let gpx = trip().export(ExportFormat::Gpx).unwrap();
+        let parsed = Gpx::parse(&gpx).unwrap();
+        assert_eq!(parsed.tracks[0].segments[0].len(), 2);
+        assert_eq!(parsed.routes.len(), 1);
+
+        let json = trip().export(ExportFormat::GeoJson).unwrap();
+        assert!(serde_json::from_str::<Value>(&json).is_ok());
+    }
+
+    #[test]
+    fn test_add_alarms() {
+        let mut trip = Trip {
+            alarms: Vec::new(),
+            ..trip()
+        };
+        let text = "2024-06-01T12:00:08Z ALARM Off course: 25° off\n\
+                    2024-06-01T12:00:20Z ACKNOWLEDGED 1 alarm(s)\n\
+                    2024-06-01T12:00:30Z CLEARED Off course\n\
+                    2024-06-01T14:00:00Z ALARM GPS fix lost\n";
+
+        // the one within a minute of the track, at the nearest fix
+        assert_eq!(trip.add_alarms(text).unwrap(), 1);
+        assert_eq!(trip.alarms.len(), 1);
+        assert_eq!(trip.alarms[0].name, "Off course: 25° off");
+        assert_eq!(trip.alarms[0].position, Position::new(48.001, -123.0));
+        assert!(trip.to_kml().contains("<name>Off course: 25° off</name>"));
+
+        assert!(trip.add_alarms("yesterday ALARM Off course\n").is_err());
+    }
+}
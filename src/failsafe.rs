@@ -0,0 +1,243 @@
+//! What happens when steering can't go on.
+//!
+//! While engaged, [`Failsafe`] watches for a valid heading. After `failsafe.heading_timeout_secs`
+//! without one the rudder is held, centered or released and the alarm sounds until the heading
+//! is back. A jammed rudder trips it at once until standby. [`Shutdown`] turns SIGINT, SIGTERM and panics into a request to stop the main loop,
+//! so the servo controller is dropped and leaves the rudder as `failsafe.shutdown_action` says.
+
+use std::error::Error;
+use std::panic;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::alarm::AlarmOutput;
+use crate::config::{FailsafeAction, FailsafeSettings};
+
+/// A change in the failsafe state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailsafeEvent {
+    /// No heading for the timeout, or the rudder jammed: the rudder is to be left as the action
+    /// says.
+    Tripped(FailsafeAction),
+    /// The heading is back.
+    Recovered,
+}
+
+/// Heading loss timer.
+pub struct Failsafe {
+    settings: FailsafeSettings,
+    /// When the last valid heading arrived, or the timer started without one.
+    last_heading: Option<Instant>,
+    tripped: bool,
+    /// Tripped by a jammed rudder, which only a reset clears.
+    jammed: bool,
+}
+
+impl Failsafe {
+    pub fn new(settings: &FailsafeSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            last_heading: None,
+            tripped: false,
+            jammed: false,
+        }
+    }
+
+    pub fn configure(&mut self, settings: &FailsafeSettings) {
+        self.settings = settings.clone();
+    }
+
+    pub fn settings(&self) -> &FailsafeSettings {
+        &self.settings
+    }
+
+    /// Whether the rudder is in its failsafe state, for lack of a heading or jammed.
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Whether it was tripped by a jammed rudder.
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    /// Note that the rudder is jammed: trip at once and stay tripped until reset, heading or
+    /// not, so steering doesn't keep pushing against whatever holds it. Returns what changed.
+    pub fn rudder_jammed(&mut self) -> Option<FailsafeEvent> {
+        self.jammed = true;
+        (!std::mem::replace(&mut self.tripped, true))
+            .then_some(FailsafeEvent::Tripped(self.settings.action))
+    }
+
+    /// Note whether a steering cycle at `now` had a heading to steer by. Returns what changed.
+    pub fn update(&mut self, has_heading: bool, now: Instant) -> Option<FailsafeEvent> {
+        if has_heading {
+            self.last_heading = Some(now);
+            if self.jammed {
+                return None;
+            }
+            return std::mem::take(&mut self.tripped).then_some(FailsafeEvent::Recovered);
+        }
+
+        let since = *self.last_heading.get_or_insert(now);
+        let timeout = Duration::from_secs_f64(self.settings.heading_timeout_secs);
+        if !self.tripped && now.duration_since(since) >= timeout {
+            self.tripped = true;
+            return Some(FailsafeEvent::Tripped(self.settings.action));
+        }
+        None
+    }
+
+    /// Start over, e.g. in standby: the timeout counts from the next update. Returns whether it
+    /// was tripped.
+    pub fn reset(&mut self) -> bool {
+        self.last_heading = None;
+        self.jammed = false;
+        std::mem::take(&mut self.tripped)
+    }
+}
+
+/// Shutdown requested by SIGINT or SIGTERM, or by a panic in any thread. Clones share the flags.
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    panicked: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Catch SIGINT and SIGTERM, and install a panic hook that sounds `alarm`. A second SIGINT
+    /// or SIGTERM exits at once.
+    pub fn install(alarm: &AlarmOutput) -> Result<Self, Box<dyn Error>> {
+        let shutdown = Self {
+            requested: Arc::new(AtomicBool::new(false)),
+            panicked: Arc::new(AtomicBool::new(false)),
+        };
+
+        for signal in [SIGINT, SIGTERM] {
+            // the first one only sets the flag, the next one finds it set and exits
+            signal_hook::flag::register_conditional_shutdown(
+                signal,
+                1,
+                Arc::clone(&shutdown.requested),
+            )?;
+            signal_hook::flag::register(signal, Arc::clone(&shutdown.requested))?;
+        }
+
+        let previous = panic::take_hook();
+        let alarm = alarm.clone();
+        let hook = shutdown.clone();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            crate::error!("  ⚠ ALARM: Internal error - stopping, rudder to its failsafe state");
+            alarm.set(true);
+            hook.panicked.store(true, Ordering::Relaxed);
+            hook.requested.store(true, Ordering::Relaxed);
+        }));
+
+        Ok(shutdown)
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Whether the shutdown is because a thread panicked.
+    pub fn panicked(&self) -> bool {
+        self.panicked.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gps_tracker::GpsTracker;
+
+    #[test]
+    fn test_heading_timeout() {
+        let settings = FailsafeSettings::default();
+        let mut failsafe = Failsafe::new(&settings);
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        assert_eq!(failsafe.update(true, at(0.0)), None);
+        // heading lost: counts from the last one
+        assert_eq!(failsafe.update(false, at(1.0)), None);
+        assert_eq!(failsafe.update(false, at(4.9)), None);
+        assert_eq!(
+            failsafe.update(false, at(5.0)),
+            Some(FailsafeEvent::Tripped(FailsafeAction::Center))
+        );
+        assert!(failsafe.is_tripped());
+        // reported once
+        assert_eq!(failsafe.update(false, at(6.0)), None);
+
+        assert_eq!(
+            failsafe.update(true, at(7.0)),
+            Some(FailsafeEvent::Recovered)
+        );
+        assert!(!failsafe.is_tripped());
+
+        // engaged without a heading: counts from the first cycle
+        assert!(!failsafe.reset());
+        assert_eq!(failsafe.update(false, at(100.0)), None);
+        assert_eq!(
+            failsafe.update(false, at(105.0)),
+            Some(FailsafeEvent::Tripped(FailsafeAction::Center))
+        );
+        assert!(failsafe.reset());
+        assert!(!failsafe.is_tripped());
+    }
+
+    #[test]
+    fn test_rudder_jammed() {
+        let mut failsafe = Failsafe::new(&FailsafeSettings::default());
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        assert_eq!(failsafe.update(true, at(0.0)), None);
+        assert_eq!(
+            failsafe.rudder_jammed(),
+            Some(FailsafeEvent::Tripped(FailsafeAction::Center))
+        );
+        assert!(failsafe.is_tripped() && failsafe.is_jammed());
+        assert_eq!(failsafe.rudder_jammed(), None);
+        // a heading doesn't free the rudder
+        assert_eq!(failsafe.update(true, at(1.0)), None);
+        assert!(failsafe.is_tripped());
+
+        // already tripped for lack of a heading: no second event, but held until reset
+        assert!(failsafe.reset());
+        assert!(!failsafe.is_jammed());
+        assert_eq!(failsafe.update(false, at(10.0)), None);
+        assert!(failsafe.update(false, at(15.0)).is_some());
+        assert_eq!(failsafe.rudder_jammed(), None);
+        assert_eq!(failsafe.update(true, at(16.0)), None);
+        assert!(failsafe.is_jammed());
+    }
+
+    #[test]
+    fn test_gps_stops() {
+        let mut failsafe = Failsafe::new(&FailsafeSettings::default());
+        let mut tracker = GpsTracker::new();
+        tracker.update_heading(245.0);
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        // the last course is steered by for a moment, then no longer
+        let mut tripped = None;
+        for tenths in 0..200 {
+            let now = at(tenths as f64 / 10.0);
+            if let Some(event) = failsafe.update(tracker.heading_at(now).is_some(), now) {
+                tripped = Some((event, tenths));
+                break;
+            }
+        }
+        let (event, tenths) = tripped.expect("the failsafe never tripped");
+        assert_eq!(event, FailsafeEvent::Tripped(FailsafeAction::Center));
+        // stale after 3 s, tripped 5 s after the last heading steered by
+        assert!((78..=80).contains(&tenths), "{}", tenths);
+    }
+}
@@ -1,77 +1,203 @@
 use crate::GpsTracker;
+use crate::config::GpsSettings;
+use crate::nmea_capture::{CaptureReader, NmeaCapture};
+use chrono::{DateTime, Utc};
+use nmea::Nmea;
+use std::error::Error;
 use std::fs::File;
 use std::io;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 // TODO: add NMEA sentence validation
 // pub fn validate_sentence() {}
 
-pub fn fetch_with_tracker(tracker: Arc<Mutex<GpsTracker>>) -> io::Result<()> {
-    use nmea::Nmea;
+pub fn fetch_with_tracker(tracker: Arc<Mutex<GpsTracker>>, gps: &GpsSettings) -> io::Result<()> {
+    fetch_with_capture(tracker, NmeaCapture::new(), gps)
+}
+
+/// Read the configured GPS serial port into the tracker, teeing every raw line into `capture`
+/// while it is active.
+pub fn fetch_with_capture(
+    tracker: Arc<Mutex<GpsTracker>>,
+    capture: NmeaCapture,
+    gps: &GpsSettings,
+) -> io::Result<()> {
+    fetch_from_device(&gps.serial_device, tracker, capture)
+}
 
-    println!("Opening /dev/serial0...");
+/// Read the GPS on `device` into the tracker, teeing every raw line into `capture` while it is
+/// active.
+pub fn fetch_from_device(
+    device: &Path,
+    tracker: Arc<Mutex<GpsTracker>>,
+    capture: NmeaCapture,
+) -> io::Result<()> {
+    crate::info!("Opening {}...", device.display());
 
-    let file = match File::open("/dev/serial0") {
+    let file = match File::open(device) {
         Ok(f) => {
-            println!("✓ Serial port opened successfully");
+            crate::info!("✓ Serial port opened successfully");
             f
         }
         Err(e) => {
-            eprintln!("❌ Failed to open /dev/serial0: {}", e);
-            eprintln!("  • Check if GPS is connected");
-            eprintln!("  • Verify user is in 'dialout' group: sudo usermod -a -G dialout $USER");
-            eprintln!("  • Check permissions: ls -l /dev/serial0");
+            crate::error!("❌ Failed to open {}: {}", device.display(), e);
+            crate::error!("  • Check if GPS is connected");
+            crate::error!(
+                "  • Verify user is in 'dialout' group: sudo usermod -a -G dialout $USER"
+            );
+            crate::error!("  • Check permissions: ls -l {}", device.display());
             return Err(e);
         }
     };
 
-    let reader = BufReader::new(file);
+    fetch_from_reader(BufReader::new(file), &tracker, &capture)
+}
+
+/// Parse NMEA lines from any reader into the tracker.
+pub fn fetch_from_reader<R: BufRead>(
+    mut reader: R,
+    tracker: &Arc<Mutex<GpsTracker>>,
+    capture: &NmeaCapture,
+) -> io::Result<()> {
     let mut nmea = Nmea::default();
     let mut sentence_count = 0;
+    let mut raw = Vec::new();
 
-    for line in reader.lines() {
-        match line {
-            Ok(content) => {
-                let trimmed = content.trim();
-                if trimmed.is_empty() {
-                    continue;
-                }
+    loop {
+        raw.clear();
+        match reader.read_until(b'\n', &mut raw) {
+            Ok(0) => break,
+            Ok(_) => {
+                // captured before any validation so bad lines are kept too
+                capture.record(&raw);
 
-                if nmea.parse(trimmed).is_ok()
-                    && let Ok(mut tracker_lock) = tracker.lock()
-                {
+                if process_line(&mut nmea, tracker, &raw) {
                     sentence_count += 1;
 
                     // log first valid sentence
                     if sentence_count == 1 {
-                        println!("✓ Receiving GPS data");
+                        crate::info!("✓ Receiving GPS data");
                     }
+                }
+            }
+            Err(e) => crate::warn!("Error reading line: {}", e),
+        }
+    }
 
-                    if let (Some(lat), Some(lon)) = (nmea.latitude, nmea.longitude) {
-                        tracker_lock.update_position(lat, lon);
-                    }
+    Ok(())
+}
 
-                    if let Some(heading) = nmea.true_course {
-                        tracker_lock.update_heading(heading.into());
-                    }
+/// Play a raw NMEA capture back into the tracker. With `realtime` the original spacing between
+/// lines is kept, otherwise lines are fed as fast as they can be parsed.
+pub fn replay_capture(
+    path: &Path,
+    tracker: &Arc<Mutex<GpsTracker>>,
+    realtime: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let mut nmea = Nmea::default();
+    let mut previous: Option<DateTime<Utc>> = None;
+    let mut sentence_count = 0;
 
-                    if let Some(num_sats) = nmea.num_of_fix_satellites {
-                        tracker_lock.update_satellites(num_sats.try_into().unwrap_or(0));
-                    }
+    for line in CaptureReader::open(path)? {
+        let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
 
-                    if let Some(hdop) = nmea.hdop {
-                        tracker_lock.update_hdop(hdop);
-                    }
+        if realtime
+            && let Some(previous) = previous
+            && let Ok(gap) = (line.time - previous).to_std()
+        {
+            thread::sleep(gap);
+        }
+        previous = Some(line.time);
 
-                    if let Some(speed) = nmea.speed_over_ground {
-                        tracker_lock.update_speed(speed.into());
-                    }
-                }
-            }
-            Err(e) => eprintln!("Error reading line: {}", e),
+        if process_line(&mut nmea, tracker, &line.raw) {
+            sentence_count += 1;
         }
     }
 
-    Ok(())
+    Ok(sentence_count)
+}
+
+/// Parse one raw line and copy the fix into the tracker. Returns whether it was a valid sentence.
+fn process_line(nmea: &mut Nmea, tracker: &Arc<Mutex<GpsTracker>>, raw: &[u8]) -> bool {
+    let Ok(content) = std::str::from_utf8(raw) else {
+        return false;
+    };
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return false;
+    }
+
+    if nmea.parse(trimmed).is_ok()
+        && let Ok(mut tracker_lock) = tracker.lock()
+    {
+        tracker_lock.record_sentence();
+
+        if let (Some(lat), Some(lon)) = (nmea.latitude, nmea.longitude) {
+            tracker_lock.update_position(lat, lon);
+        }
+
+        if let Some(heading) = nmea.true_course {
+            tracker_lock.update_heading(heading.into());
+        }
+
+        if let Some(num_sats) = nmea.num_of_fix_satellites {
+            tracker_lock.update_satellites(num_sats.try_into().unwrap_or(0));
+        }
+
+        if let Some(hdop) = nmea.hdop {
+            tracker_lock.update_hdop(hdop);
+        }
+
+        if let Some(speed) = nmea.speed_over_ground {
+            tracker_lock.update_speed(speed.into());
+        }
+
+        return true;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_tees_raw_lines() {
+        let path = std::env::temp_dir().join(format!("geo-rs-fetch-{}.nmea", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let input: &[u8] =
+            b"$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76\r\n\
+                             not nmea \xff\n";
+        let tracker = Arc::new(Mutex::new(GpsTracker::new()));
+        let capture = NmeaCapture::new();
+        capture.start(&path).unwrap();
+
+        fetch_from_reader(input, &tracker, &capture).unwrap();
+        capture.stop();
+
+        let position = tracker.lock().unwrap().get_current_position().unwrap();
+        assert!((position.latitude - 53.361337).abs() < 1e-5);
+        assert_eq!(tracker.lock().unwrap().get_num_satellites(), Some(8));
+
+        let lines: Vec<_> = CaptureReader::open(&path)
+            .unwrap()
+            .map(|line| line.unwrap().raw)
+            .collect();
+        assert_eq!(lines.concat(), input);
+
+        // replaying the capture gives the same fix
+        let replayed = Arc::new(Mutex::new(GpsTracker::new()));
+        assert_eq!(replay_capture(&path, &replayed, false).unwrap(), 1);
+        assert_eq!(
+            replayed.lock().unwrap().get_current_position(),
+            Some(position)
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -1,10 +1,8 @@
 use std::error::Error;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::config::{
-    ADJUST_LEFT_DEGREES, ADJUST_RIGHT_DEGREES, GPIO_TOGGLE_LEFT, GPIO_TOGGLE_RIGHT, SERVO_MAX_ANGLE,
-};
+use crate::config::GpioSettings;
 
 // Use rppal in production
 #[cfg(not(test))]
@@ -25,34 +23,69 @@ pub enum SwitchPosition {
 pub struct UserInterface {
     toggle_left: InputPin,
     toggle_right: InputPin,
+    engage_button: InputPin,
+    mob_button: Option<InputPin>,
     heading_offset: f64,      // offset from GPS heading (default 0°)
     gps_heading: Option<f64>, // current GPS heading for range limiting
     last_toggle_position: SwitchPosition,
+    engage_was_pressed: bool,
+    /// When the engage button went down, while it is held with long-press MOB on.
+    engage_down_since: Option<Instant>,
+    engage_event: bool,
+    mob_was_pressed: bool,
+    mob_event: bool,
+    mob_long_press_secs: f64,
+    adjust_left_degrees: f64,
+    adjust_right_degrees: f64,
+    max_offset: f64, // offset limit, the servo's maximum angle
 }
 
 /// Provides methods for interacting with GPIO supported physical hardware.
 impl UserInterface {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        Self::with_pins(GPIO_TOGGLE_LEFT, GPIO_TOGGLE_RIGHT)
-    }
-
-    pub fn with_pins(toggle_left_pin: u8, toggle_right_pin: u8) -> Result<Self, Box<dyn Error>> {
+    /// Set up the inputs. The heading offset is limited to `max_offset` degrees either side.
+    pub fn new(settings: &GpioSettings, max_offset: f64) -> Result<Self, Box<dyn Error>> {
         let gpio = Gpio::new()?;
 
-        let toggle_left = gpio.get(toggle_left_pin)?.into_input_pullup();
-        let toggle_right = gpio.get(toggle_right_pin)?.into_input_pullup();
+        let toggle_left = gpio.get(settings.toggle_left)?.into_input_pullup();
+        let toggle_right = gpio.get(settings.toggle_right)?.into_input_pullup();
+        let engage_button = gpio.get(settings.engage_button)?.into_input_pullup();
+        let mob_button = if settings.mob_button_enabled {
+            Some(gpio.get(settings.mob_button)?.into_input_pullup())
+        } else {
+            None
+        };
 
         thread::sleep(Duration::from_millis(100));
 
         Ok(Self {
             toggle_left,
             toggle_right,
+            engage_button,
+            mob_button,
             heading_offset: 0.0, // start with no offset (follow GPS)
             gps_heading: None,   // track GPS heading for range limiting
             last_toggle_position: SwitchPosition::Neutral,
+            engage_was_pressed: false,
+            engage_down_since: None,
+            engage_event: false,
+            mob_was_pressed: false,
+            mob_event: false,
+            mob_long_press_secs: settings.mob_long_press_secs,
+            adjust_left_degrees: settings.adjust_left_degrees,
+            adjust_right_degrees: settings.adjust_right_degrees,
+            max_offset,
         })
     }
 
+    /// Use new adjustment steps, long press and offset limit. The pins can't change while
+    /// running.
+    pub fn configure(&mut self, settings: &GpioSettings, max_offset: f64) {
+        self.mob_long_press_secs = settings.mob_long_press_secs;
+        self.adjust_left_degrees = settings.adjust_left_degrees;
+        self.adjust_right_degrees = settings.adjust_right_degrees;
+        self.max_offset = max_offset;
+    }
+
     pub fn read_toggle_switch(&self) -> SwitchPosition {
         let left_active = self.toggle_left.read() == Level::Low;
         let right_active = self.toggle_right.read() == Level::Low;
@@ -66,6 +99,51 @@ impl UserInterface {
         }
     }
 
+    /// Returns true once for each press of the engage button (active low). With long-press MOB
+    /// on, a press counts when the button is released, unless it was held long enough for MOB.
+    pub fn engage_pressed(&mut self) -> bool {
+        self.poll_engage_button(Instant::now());
+        std::mem::take(&mut self.engage_event)
+    }
+
+    /// Returns true once for each press of the MOB button or long press of the engage button.
+    pub fn mob_pressed(&mut self) -> bool {
+        self.poll_engage_button(Instant::now());
+        let mut pressed = std::mem::take(&mut self.mob_event);
+        if let Some(button) = &self.mob_button {
+            let down = button.read() == Level::Low;
+            pressed |= down && !self.mob_was_pressed;
+            self.mob_was_pressed = down;
+        }
+        pressed
+    }
+
+    fn poll_engage_button(&mut self, now: Instant) {
+        let pressed = self.engage_button.read() == Level::Low;
+        if self.mob_long_press_secs <= 0.0 {
+            self.engage_event |= pressed && !self.engage_was_pressed;
+            self.engage_was_pressed = pressed;
+            return;
+        }
+
+        match (pressed, self.engage_down_since) {
+            (true, None) if !self.engage_was_pressed => self.engage_down_since = Some(now),
+            (true, Some(since))
+                if now.duration_since(since).as_secs_f64() >= self.mob_long_press_secs =>
+            {
+                // held for MOB: nothing more until it is released
+                self.engage_down_since = None;
+                self.mob_event = true;
+            }
+            (false, Some(_)) => {
+                self.engage_down_since = None;
+                self.engage_event = true;
+            }
+            _ => {}
+        }
+        self.engage_was_pressed = pressed;
+    }
+
     /// Get the known toggle position.
     pub fn get_toggle_position(&self) -> SwitchPosition {
         self.last_toggle_position
@@ -90,6 +168,11 @@ impl UserInterface {
         self.heading_offset
     }
 
+    /// Clear the heading offset, so the target is the heading again.
+    pub fn reset_offset(&mut self) {
+        self.heading_offset = 0.0;
+    }
+
     /// Check if GPS heading has been received.
     pub fn has_heading(&self) -> bool {
         self.gps_heading.is_some()
@@ -101,9 +184,9 @@ impl UserInterface {
         let new_offset = self.heading_offset + degrees;
 
         // check if new offset exceeds servo limits
-        if new_offset.abs() > SERVO_MAX_ANGLE {
+        if new_offset.abs() > self.max_offset {
             // clamp to servo limit
-            self.heading_offset = new_offset.signum() * SERVO_MAX_ANGLE;
+            self.heading_offset = new_offset.signum() * self.max_offset;
             return false; // was clamped
         }
 
@@ -121,50 +204,55 @@ impl UserInterface {
             if self.gps_heading.is_some() {
                 match position {
                     SwitchPosition::Left => {
-                        let applied = self.adjust_heading_offset(ADJUST_LEFT_DEGREES);
+                        let applied = self.adjust_heading_offset(self.adjust_left_degrees);
                         if let Some(target_heading) = self.get_heading() {
                             if applied {
-                                println!(
+                                crate::info!(
                                     "← Toggle LEFT: Offset: {:.1}° | Target: {:.1}°",
-                                    self.heading_offset, target_heading
+                                    self.heading_offset,
+                                    target_heading
                                 );
                             } else {
-                                println!(
+                                crate::info!(
                                     "← Toggle LEFT: Servo at limit - Offset: {:.1}° | Target: {:.1}°",
-                                    self.heading_offset, target_heading
+                                    self.heading_offset,
+                                    target_heading
                                 );
-                                println!("   ⚠ Wait for boat to turn before adjusting further");
+                                crate::info!("   ⚠ Wait for boat to turn before adjusting further");
                             }
                         }
                     }
                     SwitchPosition::Right => {
-                        let applied = self.adjust_heading_offset(ADJUST_RIGHT_DEGREES);
+                        let applied = self.adjust_heading_offset(self.adjust_right_degrees);
                         if let Some(target_heading) = self.get_heading() {
                             if applied {
-                                println!(
+                                crate::info!(
                                     "→ Toggle RIGHT: Offset: {:.1}° | Target: {:.1}°",
-                                    self.heading_offset, target_heading
+                                    self.heading_offset,
+                                    target_heading
                                 );
                             } else {
-                                println!(
+                                crate::info!(
                                     "→ Toggle RIGHT: Servo at limit - Offset: {:.1}° | Target: {:.1}°",
-                                    self.heading_offset, target_heading
+                                    self.heading_offset,
+                                    target_heading
                                 );
-                                println!("   ⚠ Wait for boat to turn before adjusting further");
+                                crate::info!("   ⚠ Wait for boat to turn before adjusting further");
                             }
                         }
                     }
                     SwitchPosition::Neutral => {
                         if let Some(target_heading) = self.get_heading() {
-                            println!(
+                            crate::info!(
                                 "● Toggle NEUTRAL: Offset: {:.1}° | Target: {:.1}°",
-                                self.heading_offset, target_heading
+                                self.heading_offset,
+                                target_heading
                             );
                         }
                     }
                 }
             } else {
-                println!("⚠ Waiting for GPS heading before adjusting...");
+                crate::info!("⚠ Waiting for GPS heading before adjusting...");
             }
         }
 
@@ -175,8 +263,24 @@ impl UserInterface {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{
+        GPIO_ENGAGE_BUTTON, GPIO_MOB_BUTTON, GPIO_TOGGLE_LEFT, GPIO_TOGGLE_RIGHT, SERVO_MAX_ANGLE,
+    };
     use crate::mocks::mock_gpio;
 
+    fn new_ui() -> Result<UserInterface, Box<dyn Error>> {
+        UserInterface::new(&GpioSettings::default(), SERVO_MAX_ANGLE)
+    }
+
+    /// Engage on press, no long-press MOB.
+    fn new_ui_without_long_press() -> Result<UserInterface, Box<dyn Error>> {
+        let settings = GpioSettings {
+            mob_long_press_secs: 0.0,
+            ..GpioSettings::default()
+        };
+        UserInterface::new(&settings, SERVO_MAX_ANGLE)
+    }
+
     #[test]
     fn test_heading_adjustment() {
         let mut heading = 0.0; // start heading NORTH
@@ -206,7 +310,7 @@ mod tests {
     fn test_ui_starts_without_heading() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let ui = UserInterface::new()?;
+        let ui = new_ui()?;
 
         // should start with no GPS heading and zero offset
         assert!(!ui.has_heading());
@@ -220,7 +324,7 @@ mod tests {
     fn test_toggle_switch_positions() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
         // set initial GPS heading
         ui.update_gps_heading(0.0);
 
@@ -255,11 +359,76 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_engage_button_edge() -> Result<(), Box<dyn Error>> {
+        mock_gpio::reset_mock_pins();
+
+        let mut ui = new_ui_without_long_press()?;
+        assert!(!ui.engage_pressed());
+
+        // a press is reported once, however long the button is held
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::Low);
+        assert!(ui.engage_pressed());
+        assert!(!ui.engage_pressed());
+
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::High);
+        assert!(!ui.engage_pressed());
+
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::Low);
+        assert!(ui.engage_pressed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mob_buttons() -> Result<(), Box<dyn Error>> {
+        mock_gpio::reset_mock_pins();
+        let settings = GpioSettings {
+            mob_button_enabled: true,
+            mob_long_press_secs: 0.1,
+            ..GpioSettings::default()
+        };
+        let mut ui = UserInterface::new(&settings, SERVO_MAX_ANGLE)?;
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+
+        // a short press engages on release
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::Low);
+        ui.poll_engage_button(at(0));
+        ui.poll_engage_button(at(50));
+        assert!(!ui.engage_event);
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::High);
+        ui.poll_engage_button(at(60));
+        assert!(ui.engage_pressed());
+        assert!(!ui.mob_pressed());
+
+        // a long press is MOB, and releasing it doesn't engage
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::Low);
+        ui.poll_engage_button(at(100));
+        ui.poll_engage_button(at(200));
+        assert!(ui.mob_pressed());
+        ui.poll_engage_button(at(300));
+        assert!(!ui.mob_pressed());
+        mock_gpio::set_mock_pin_level(GPIO_ENGAGE_BUTTON, mock_gpio::Level::High);
+        ui.poll_engage_button(at(310));
+        assert!(!ui.engage_pressed());
+        assert!(!ui.mob_pressed());
+
+        // the dedicated button, once per press
+        mock_gpio::set_mock_pin_level(GPIO_MOB_BUTTON, mock_gpio::Level::Low);
+        assert!(ui.mob_pressed());
+        assert!(!ui.mob_pressed());
+        mock_gpio::set_mock_pin_level(GPIO_MOB_BUTTON, mock_gpio::Level::High);
+        assert!(!ui.mob_pressed());
+
+        Ok(())
+    }
+
     #[test]
     fn test_heading_wraparound() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
         ui.update_gps_heading(358.0);
 
         // press RIGHT once, offset = +5°, target = 358° + 5° = 363° = 3°
@@ -276,7 +445,7 @@ mod tests {
     fn test_multiple_adjustments_with_wraparound() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
         ui.update_gps_heading(355.0);
 
         // press RIGHT 3 times: offset = +15°
@@ -301,7 +470,7 @@ mod tests {
     fn test_target_heading_tracks_gps() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
 
         // set initial GPS heading - with no button presses, target should match
         ui.update_gps_heading(45.0);
@@ -325,7 +494,7 @@ mod tests {
     fn test_offset_maintains_with_gps_changes() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
 
         // start at GPS 45°
         ui.update_gps_heading(45.0);
@@ -360,7 +529,7 @@ mod tests {
     fn test_servo_range_limiting() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
 
         // set GPS heading to 0° (North)
         ui.update_gps_heading(0.0);
@@ -394,7 +563,7 @@ mod tests {
     fn test_servo_range_limiting_left() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
 
         // set GPS heading to 180° (South)
         ui.update_gps_heading(180.0);
@@ -428,7 +597,7 @@ mod tests {
     fn test_servo_range_with_wraparound() -> Result<(), Box<dyn Error>> {
         mock_gpio::reset_mock_pins();
 
-        let mut ui = UserInterface::new()?;
+        let mut ui = new_ui()?;
 
         // set GPS heading to 10° (just past North)
         ui.update_gps_heading(10.0);
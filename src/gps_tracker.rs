@@ -1,4 +1,8 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
 use crate::compass_sensor::CompassSensor;
+use crate::config::GpsSettings;
 use crate::position::Position;
 use crate::vector::Vector;
 
@@ -9,6 +13,16 @@ pub struct GpsTracker {
     current_speed: Option<f64>,   // knots
     current_hdop: Option<f32>,
     num_satellites: Option<u8>,
+    last_sentence: Option<Instant>,
+    last_fix: Option<Instant>,
+    last_heading: Option<Instant>,
+    last_speed: Option<Instant>,
+    /// Fixes of the last `gps.fix_history_secs` up to the latest one, oldest first.
+    fix_history: VecDeque<(Instant, Position)>,
+    /// A course or speed older than this is not used.
+    max_age: Duration,
+    /// How long fixes are kept in `fix_history`.
+    history_length: Duration,
 }
 
 impl Default for GpsTracker {
@@ -19,29 +33,90 @@ impl Default for GpsTracker {
 
 impl GpsTracker {
     pub fn new() -> Self {
+        Self::with_settings(&GpsSettings::default())
+    }
+
+    /// Create a tracker that holds a course or speed for `gps.max_age_secs` and keeps fixes for
+    /// `gps.fix_history_secs`.
+    pub fn with_settings(settings: &GpsSettings) -> Self {
         Self {
             current_position: None,
             current_heading: None,
             current_speed: None,
             current_hdop: None,
             num_satellites: None,
+            last_sentence: None,
+            last_fix: None,
+            last_heading: None,
+            last_speed: None,
+            fix_history: VecDeque::new(),
+            max_age: Duration::from_secs_f64(settings.max_age_secs),
+            history_length: Duration::from_secs_f64(settings.fix_history_secs),
         }
     }
 
+    /// Use new GPS settings. The course and speed kept so far are judged by the new age limit;
+    /// a shorter fix history is trimmed at the next fix.
+    pub fn configure(&mut self, settings: &GpsSettings) {
+        self.max_age = Duration::from_secs_f64(settings.max_age_secs);
+        self.history_length = Duration::from_secs_f64(settings.fix_history_secs);
+    }
+
     pub fn get_current_position(&self) -> Option<Position> {
         self.current_position
     }
 
     pub fn update_position(&mut self, lat: f64, lon: f64) {
-        self.current_position = Some(Position::new(lat, lon));
+        self.record_fix(Position::new(lat, lon), Instant::now());
     }
 
+    fn record_fix(&mut self, position: Position, now: Instant) {
+        self.current_position = Some(position);
+        self.last_fix = Some(now);
+
+        while self
+            .fix_history
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > self.history_length)
+        {
+            self.fix_history.pop_front();
+        }
+        self.fix_history.push_back((now, position));
+    }
+
+    /// Where the boat was at `time`, and when that fix came: the last fix at or before it, or
+    /// the oldest fix kept if they are all later. Fixes are kept for `gps.fix_history_secs` up to
+    /// the latest one. `None` before the first fix.
+    pub fn fix_at(&self, time: Instant) -> Option<(Instant, Position)> {
+        self.fix_history
+            .iter()
+            .rev()
+            .find(|(fix_time, _)| *fix_time <= time)
+            .or_else(|| self.fix_history.front())
+            .copied()
+    }
+
+    /// Time since the last position fix, `None` if there never was one. The position is kept
+    /// when the fix is lost, so this tells how stale it is.
+    pub fn fix_age(&self) -> Option<Duration> {
+        self.last_fix.map(|time| time.elapsed())
+    }
+
+    /// The GPS course, `None` once it is older than `gps.max_age_secs`.
     pub fn get_current_heading(&self) -> Option<f64> {
+        self.heading_at(Instant::now())
+    }
+
+    /// The GPS course as it stands at `time`, `None` if it is older than `gps.max_age_secs`
+    /// by then.
+    pub fn heading_at(&self, time: Instant) -> Option<f64> {
         self.current_heading
+            .filter(|_| self.is_recent(self.last_heading, time))
     }
 
     pub fn update_heading(&mut self, heading: f64) {
         self.current_heading = Some(heading);
+        self.last_heading = Some(Instant::now());
     }
 
     pub fn get_num_satellites(&self) -> Option<u8> {
@@ -52,12 +127,21 @@ impl GpsTracker {
         self.num_satellites = Some(num_sats);
     }
 
+    /// Speed over ground, `None` once it is older than `gps.max_age_secs`.
     pub fn get_current_speed(&self) -> Option<f64> {
+        self.speed_at(Instant::now())
+    }
+
+    /// Speed over ground as it stands at `time`, `None` if it is older than `gps.max_age_secs`
+    /// by then.
+    pub fn speed_at(&self, time: Instant) -> Option<f64> {
         self.current_speed
+            .filter(|_| self.is_recent(self.last_speed, time))
     }
 
     pub fn update_speed(&mut self, speed: f64) {
         self.current_speed = Some(speed);
+        self.last_speed = Some(Instant::now());
     }
 
     pub fn get_current_hdop(&self) -> Option<f32> {
@@ -68,6 +152,16 @@ impl GpsTracker {
         self.current_hdop = Some(hdop)
     }
 
+    /// Note that a valid sentence arrived from the receiver, fix or not.
+    pub fn record_sentence(&mut self) {
+        self.last_sentence = Some(Instant::now());
+    }
+
+    /// Time since the receiver last sent a valid sentence, `None` if it never has.
+    pub fn sentence_age(&self) -> Option<Duration> {
+        self.last_sentence.map(|time| time.elapsed())
+    }
+
     /// This is where we're currently heading.
     pub fn get_forward_vector(&self, distance: f64) -> Option<Vector> {
         match (self.current_position, self.current_heading) {
@@ -91,6 +185,11 @@ impl GpsTracker {
         // Fall back to compass when stationary
         compass.read_heading().ok()
     }
+
+    /// Whether something `updated` then is no older than `gps.max_age_secs` at `time`.
+    fn is_recent(&self, updated: Option<Instant>, time: Instant) -> bool {
+        updated.is_some_and(|updated| time.saturating_duration_since(updated) <= self.max_age)
+    }
 }
 
 #[cfg(test)]
@@ -110,8 +209,10 @@ mod tests {
     fn test_gps_tracker_updates() {
         let mut tracker = GpsTracker::new();
 
+        assert!(tracker.fix_age().is_none());
         tracker.update_position(48.0, -123.0);
         assert!(tracker.get_current_position().is_some());
+        assert!(tracker.fix_age().unwrap() < Duration::from_secs(1));
 
         tracker.update_heading(90.0);
         assert_eq!(tracker.get_current_heading(), Some(90.0));
@@ -121,6 +222,62 @@ mod tests {
 
         tracker.update_satellites(8);
         assert_eq!(tracker.get_num_satellites(), Some(8));
+
+        assert!(tracker.sentence_age().is_none());
+        tracker.record_sentence();
+        assert!(tracker.sentence_age().unwrap() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_heading_and_speed_go_stale() {
+        let mut tracker = GpsTracker::new();
+        tracker.update_heading(90.0);
+        tracker.update_speed(6.0);
+        let now = Instant::now();
+        assert_eq!(tracker.heading_at(now), Some(90.0));
+        assert_eq!(tracker.heading_at(now + Duration::from_secs(2)), Some(90.0));
+        assert_eq!(tracker.heading_at(now + Duration::from_secs(4)), None);
+        assert_eq!(tracker.speed_at(now + Duration::from_secs(2)), Some(6.0));
+        assert_eq!(tracker.speed_at(now + Duration::from_secs(4)), None);
+
+        // a longer age limit holds them longer
+        tracker.configure(&GpsSettings {
+            max_age_secs: 5.0,
+            ..GpsSettings::default()
+        });
+        assert_eq!(tracker.heading_at(now + Duration::from_secs(4)), Some(90.0));
+        assert_eq!(tracker.speed_at(now + Duration::from_secs(6)), None);
+    }
+
+    #[test]
+    fn test_fix_history() {
+        let mut tracker = GpsTracker::new();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        assert!(tracker.fix_at(at(0)).is_none());
+
+        for secs in 0..=100 {
+            tracker.record_fix(Position::new(48.0 + secs as f64 * 0.001, -123.0), at(secs));
+        }
+        let fix_95 = Some((at(95), Position::new(48.095, -123.0)));
+        assert_eq!(tracker.fix_at(at(95)), fix_95);
+        // between fixes: the one before
+        assert_eq!(tracker.fix_at(at(95) + Duration::from_millis(500)), fix_95);
+        // only the last minute is kept
+        assert_eq!(tracker.fix_history.len(), 61);
+        assert_eq!(
+            tracker.fix_at(at(10)),
+            Some((at(40), Position::new(48.04, -123.0)))
+        );
+
+        // a shorter history is trimmed at the next fix
+        tracker.configure(&GpsSettings {
+            fix_history_secs: 10.0,
+            ..GpsSettings::default()
+        });
+        tracker.record_fix(Position::new(48.101, -123.0), at(101));
+        assert_eq!(tracker.fix_history.len(), 11);
+        assert_eq!(tracker.fix_at(at(10)).unwrap().0, at(91));
     }
 
     #[test]
@@ -0,0 +1,588 @@
+//! GPX 1.1 import and export.
+//!
+//! Maps `<wpt>` onto [`Waypoint`], `<rte>` onto [`Route`] and `<trk>` onto [`Track`]. Names,
+//! timestamps and elevations are preserved; extensions and other elements are ignored on import.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::position::Position;
+use crate::route::{Route, Waypoint};
+use crate::track::{Track, TrackPoint};
+
+const GPX_NAMESPACE: &str = "http://www.topografix.com/GPX/1/1";
+
+/// Contents of a GPX file.
+#[derive(Clone, Debug, Default)]
+pub struct Gpx {
+    pub waypoints: Vec<Waypoint>,
+    pub routes: Vec<Route>,
+    pub tracks: Vec<Track>,
+}
+
+impl Gpx {
+    /// Read a GPX file.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Parse GPX from a string. Errors carry the line number of the offending element.
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let root = parse_xml(text)?;
+        if root.name != "gpx" {
+            return Err(
+                format!("line {}: expected <gpx>, found <{}>", root.line, root.name).into(),
+            );
+        }
+
+        let mut gpx = Gpx::default();
+        for element in &root.children {
+            match element.name.as_str() {
+                "wpt" => gpx.waypoints.push(parse_waypoint(element)?),
+                "rte" => {
+                    let name = element.child_text("name").unwrap_or_default();
+                    let mut waypoints = Vec::new();
+                    for (index, point) in element.children_named("rtept").enumerate() {
+                        let mut waypoint = parse_waypoint(point)?;
+                        if waypoint.name.is_empty() {
+                            waypoint.name = format!("WP{}", index + 1);
+                        }
+                        waypoints.push(waypoint);
+                    }
+                    gpx.routes.push(Route::with_waypoints(&name, waypoints));
+                }
+                "trk" => {
+                    let mut track = Track::new(element.child_text("name").as_deref());
+                    for segment in element.children_named("trkseg") {
+                        let points = segment
+                            .children_named("trkpt")
+                            .map(parse_track_point)
+                            .collect::<Result<Vec<_>, _>>()?;
+                        track.segments.push(points);
+                    }
+                    gpx.tracks.push(track);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(gpx)
+    }
+
+    /// Serialize as GPX 1.1.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<gpx version=\"1.1\" creator=\"geo-rs\" xmlns=\"{}\">",
+            GPX_NAMESPACE
+        );
+
+        for waypoint in &self.waypoints {
+            write_point(&mut xml, "wpt", 1, waypoint_fields(waypoint));
+        }
+
+        for route in &self.routes {
+            xml.push_str("  <rte>\n");
+            if !route.name.is_empty() {
+                let _ = writeln!(xml, "    <name>{}</name>", escape(&route.name));
+            }
+            for waypoint in route.waypoints() {
+                write_point(&mut xml, "rtept", 2, waypoint_fields(waypoint));
+            }
+            xml.push_str("  </rte>\n");
+        }
+
+        for track in &self.tracks {
+            xml.push_str("  <trk>\n");
+            if let Some(name) = &track.name {
+                let _ = writeln!(xml, "    <name>{}</name>", escape(name));
+            }
+            for segment in &track.segments {
+                xml.push_str("    <trkseg>\n");
+                for point in segment {
+                    write_point(
+                        &mut xml,
+                        "trkpt",
+                        3,
+                        PointFields {
+                            position: point.position,
+                            elevation: point.elevation,
+                            time: point.time,
+                            name: None,
+                        },
+                    );
+                }
+                xml.push_str("    </trkseg>\n");
+            }
+            xml.push_str("  </trk>\n");
+        }
+
+        xml.push_str("</gpx>\n");
+        xml
+    }
+
+    /// Write a GPX file.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_xml())?;
+        Ok(())
+    }
+
+    /// The route to follow from this file: the first `<rte>`, or the waypoints in order if
+    /// there is none.
+    pub fn into_route(mut self, name: &str) -> Option<Route> {
+        if !self.routes.is_empty() {
+            return Some(self.routes.swap_remove(0));
+        }
+        if self.waypoints.is_empty() {
+            return None;
+        }
+        Some(Route::with_waypoints(name, self.waypoints))
+    }
+}
+
+fn parse_waypoint(element: &Element) -> Result<Waypoint, Box<dyn Error>> {
+    let point = parse_track_point(element)?;
+    let mut waypoint = Waypoint::new(
+        &element.child_text("name").unwrap_or_default(),
+        point.position,
+    );
+    waypoint.elevation = point.elevation;
+    waypoint.time = point.time;
+    Ok(waypoint)
+}
+
+fn parse_track_point(element: &Element) -> Result<TrackPoint, Box<dyn Error>> {
+    let lat = element.coordinate("lat", 90.0)?;
+    let lon = element.coordinate("lon", 180.0)?;
+
+    let mut point = TrackPoint::new(Position::new(lat, lon));
+
+    if let Some(ele) = element.child("ele") {
+        point.elevation = Some(
+            ele.text
+                .trim()
+                .parse()
+                .map_err(|_| format!("line {}: invalid elevation '{}'", ele.line, ele.text))?,
+        );
+    }
+
+    if let Some(time) = element.child("time") {
+        point.time = Some(
+            DateTime::parse_from_rfc3339(time.text.trim())
+                .map_err(|_| format!("line {}: invalid time '{}'", time.line, time.text))?
+                .with_timezone(&Utc),
+        );
+    }
+
+    Ok(point)
+}
+
+struct PointFields<'a> {
+    position: Position,
+    elevation: Option<f64>,
+    time: Option<DateTime<Utc>>,
+    name: Option<&'a str>,
+}
+
+fn waypoint_fields(waypoint: &Waypoint) -> PointFields<'_> {
+    PointFields {
+        position: waypoint.position,
+        elevation: waypoint.elevation,
+        time: waypoint.time,
+        name: Some(&waypoint.name),
+    }
+}
+
+fn write_point(xml: &mut String, tag: &str, depth: usize, fields: PointFields) {
+    let indent = "  ".repeat(depth);
+    let _ = write!(
+        xml,
+        "{}<{} lat=\"{:.7}\" lon=\"{:.7}\"",
+        indent, tag, fields.position.latitude, fields.position.longitude
+    );
+
+    let name = fields.name.filter(|name| !name.is_empty());
+    if fields.elevation.is_none() && fields.time.is_none() && name.is_none() {
+        xml.push_str("/>\n");
+        return;
+    }
+
+    xml.push_str(">\n");
+    // GPX 1.1 requires ele, time, name in this order
+    if let Some(elevation) = fields.elevation {
+        let _ = writeln!(xml, "{}  <ele>{:.2}</ele>", indent, elevation);
+    }
+    if let Some(time) = fields.time {
+        let _ = writeln!(
+            xml,
+            "{}  <time>{}</time>",
+            indent,
+            time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        );
+    }
+    if let Some(name) = name {
+        let _ = writeln!(xml, "{}  <name>{}</name>", indent, escape(name));
+    }
+    let _ = writeln!(xml, "{}</{}>", indent, tag);
+}
+
+/// Escape text for use in XML content or attributes.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Minimal XML element tree, enough for GPX.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+    line: usize,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child_text(&self, name: &str) -> Option<String> {
+        self.child(name).map(|child| child.text.trim().to_string())
+    }
+
+    /// Parse a lat/lon attribute and check it is within ±`limit`.
+    fn coordinate(&self, name: &str, limit: f64) -> Result<f64, Box<dyn Error>> {
+        let value = self
+            .attribute(name)
+            .ok_or_else(|| format!("line {}: <{}> missing {}", self.line, self.name, name))?;
+        let coordinate: f64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("line {}: invalid {} '{}'", self.line, name, value))?;
+        if !coordinate.is_finite() || coordinate.abs() > limit {
+            return Err(format!("line {}: {} {} out of range", self.line, name, value).into());
+        }
+        Ok(coordinate)
+    }
+}
+
+/// Parse an XML document into its root element. Namespace prefixes are dropped, comments,
+/// processing instructions and DOCTYPE are skipped.
+fn parse_xml(text: &str) -> Result<Element, Box<dyn Error>> {
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<Element> = None;
+    let mut rest = text;
+    let mut line = 1;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after
+                .find("-->")
+                .ok_or_else(|| format!("line {}: unterminated comment", line))?;
+            line += after[..end].matches('\n').count();
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after
+                .find("]]>")
+                .ok_or_else(|| format!("line {}: unterminated CDATA", line))?;
+            if let Some(element) = stack.last_mut() {
+                element.text.push_str(&after[..end]);
+            }
+            line += after[..end].matches('\n').count();
+            rest = &after[end + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest
+                .find('>')
+                .ok_or_else(|| format!("line {}: unterminated declaration", line))?;
+            line += rest[..end].matches('\n').count();
+            rest = &rest[end + 1..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after
+                .find('>')
+                .ok_or_else(|| format!("line {}: unterminated tag", line))?;
+            let name = local_name(after[..end].trim());
+            let element = stack
+                .pop()
+                .ok_or_else(|| format!("line {}: unexpected </{}>", line, name))?;
+            if element.name != name {
+                return Err(format!(
+                    "line {}: expected </{}>, found </{}>",
+                    line, element.name, name
+                )
+                .into());
+            }
+            close_element(&mut stack, &mut root, element);
+            line += after[..end].matches('\n').count();
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('<') {
+            let end =
+                find_tag_end(after).ok_or_else(|| format!("line {}: unterminated tag", line))?;
+            let tag = &after[..end];
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/');
+
+            let element = parse_start_tag(tag, line)?;
+            line += tag.matches('\n').count();
+            rest = &after[end + 1..];
+
+            if self_closing {
+                close_element(&mut stack, &mut root, element);
+            } else {
+                stack.push(element);
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let content = &rest[..end];
+            if let Some(element) = stack.last_mut() {
+                element.text.push_str(&unescape(content, line)?);
+            } else if !content.trim().is_empty() {
+                return Err(format!("line {}: text outside of the root element", line).into());
+            }
+            line += content.matches('\n').count();
+            rest = &rest[end..];
+        }
+    }
+
+    if let Some(element) = stack.last() {
+        return Err(format!("line {}: <{}> is never closed", element.line, element.name).into());
+    }
+    root.ok_or_else(|| "empty document".into())
+}
+
+fn close_element(stack: &mut [Element], root: &mut Option<Element>, element: Element) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None => *root = Some(element),
+    }
+}
+
+/// Find the closing `>` of a tag, skipping over quoted attribute values.
+fn find_tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_start_tag(tag: &str, line: usize) -> Result<Element, Box<dyn Error>> {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = &tag[..name_end];
+    if name.is_empty() {
+        return Err(format!("line {}: empty tag", line).into());
+    }
+
+    let mut element = Element {
+        name: local_name(name).to_string(),
+        line,
+        ..Default::default()
+    };
+
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| format!("line {}: malformed attribute in <{}>", line, name))?;
+        let key = rest[..eq].trim();
+        let value_part = rest[eq + 1..].trim_start();
+        let quote = value_part
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("line {}: unquoted attribute {} in <{}>", line, key, name))?;
+        let value_end = value_part[1..]
+            .find(quote)
+            .ok_or_else(|| format!("line {}: unterminated attribute {}", line, key))?;
+
+        element.attributes.push((
+            local_name(key).to_string(),
+            unescape(&value_part[1..value_end + 1], line)?,
+        ));
+        rest = value_part[value_end + 2..].trim_start();
+    }
+
+    Ok(element)
+}
+
+/// Strip a namespace prefix (`gpx:wpt` → `wpt`).
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(text: &str, line: usize) -> Result<String, Box<dyn Error>> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| format!("line {}: unterminated entity", line))?;
+        let entity = &rest[start + 1..start + end];
+
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("line {}: unknown entity &{};", line, entity))?,
+        };
+        result.push(c);
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="OpenCPN" xmlns="http://www.topografix.com/GPX/1/1">
+  <!-- planned in OpenCPN -->
+  <wpt lat="48.1" lon="-123.1">
+    <ele>2.5</ele>
+    <time>2024-06-01T12:00:00Z</time>
+    <name>Anchorage &amp; Beach</name>
+  </wpt>
+  <rte>
+    <name>Harbour run</name>
+    <rtept lat="48.0" lon="-123.0"><name>Start</name></rtept>
+    <rtept lat="48.2" lon="-123.2"/>
+  </rte>
+  <trk>
+    <name>Morning</name>
+    <trkseg>
+      <trkpt lat="48.0" lon="-123.0"><time>2024-06-01T08:00:00Z</time></trkpt>
+      <trkpt lat="48.001" lon="-123.0"><time>2024-06-01T08:00:10Z</time></trkpt>
+    </trkseg>
+    <extensions><opencpn:style width="2"/></extensions>
+  </trk>
+</gpx>
+"#;
+
+    #[test]
+    fn test_parse_gpx() {
+        let gpx = Gpx::parse(SAMPLE).unwrap();
+
+        assert_eq!(gpx.waypoints.len(), 1);
+        let wpt = &gpx.waypoints[0];
+        assert_eq!(wpt.name, "Anchorage & Beach");
+        assert_eq!(wpt.elevation, Some(2.5));
+        assert_eq!(
+            wpt.time.unwrap().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2024-06-01T12:00:00Z"
+        );
+
+        assert_eq!(gpx.routes.len(), 1);
+        let route = &gpx.routes[0];
+        assert_eq!(route.name, "Harbour run");
+        assert_eq!(route.waypoints()[0].name, "Start");
+        assert_eq!(route.waypoints()[1].name, "WP2");
+
+        assert_eq!(gpx.tracks.len(), 1);
+        let track = &gpx.tracks[0];
+        assert_eq!(track.name.as_deref(), Some("Morning"));
+        assert_eq!(track.segments[0].len(), 2);
+        assert!((track.distance() - 111.2).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let gpx = Gpx::parse(SAMPLE).unwrap();
+        let reparsed = Gpx::parse(&gpx.to_xml()).unwrap();
+
+        assert_eq!(reparsed.waypoints[0].name, "Anchorage & Beach");
+        assert_eq!(reparsed.waypoints[0].elevation, Some(2.5));
+        assert_eq!(reparsed.waypoints[0].time, gpx.waypoints[0].time);
+        assert_eq!(reparsed.routes[0].waypoints().len(), 2);
+        assert_eq!(
+            reparsed.tracks[0].segments[0][1].time,
+            gpx.tracks[0].segments[0][1].time
+        );
+        assert!((reparsed.tracks[0].segments[0][1].position.latitude - 48.001).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_coordinates_report_line() {
+        let text =
+            "<gpx>\n  <wpt lat=\"48.0\" lon=\"-123.0\"/>\n  <wpt lat=\"91.0\" lon=\"0\"/>\n</gpx>";
+        let err = Gpx::parse(text).unwrap_err().to_string();
+        assert!(err.starts_with("line 3:"), "{}", err);
+
+        let text = "<gpx>\n<rte>\n<rtept lat=\"abc\" lon=\"0\"/>\n</rte>\n</gpx>";
+        let err = Gpx::parse(text).unwrap_err().to_string();
+        assert!(err.starts_with("line 3:"), "{}", err);
+
+        let text = "<gpx>\n<wpt lon=\"0\"/>\n</gpx>";
+        assert!(
+            Gpx::parse(text)
+                .unwrap_err()
+                .to_string()
+                .contains("missing lat")
+        );
+    }
+
+    #[test]
+    fn test_malformed_xml() {
+        assert!(Gpx::parse("<gpx><wpt lat=\"1\" lon=\"1\"></gpx>").is_err());
+        assert!(Gpx::parse("<kml></kml>").is_err());
+        assert!(Gpx::parse("").is_err());
+    }
+
+    #[test]
+    fn test_into_route() {
+        let gpx = Gpx::parse(SAMPLE).unwrap();
+        assert_eq!(gpx.into_route("x").unwrap().name, "Harbour run");
+
+        let waypoints_only =
+            Gpx::parse("<gpx><wpt lat=\"1\" lon=\"1\"/><wpt lat=\"2\" lon=\"2\"/></gpx>").unwrap();
+        let route = waypoints_only.into_route("marks").unwrap();
+        assert_eq!(route.name, "marks");
+        assert_eq!(route.waypoints().len(), 2);
+    }
+}
@@ -0,0 +1,228 @@
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+// Use rppal in production
+#[cfg(not(test))]
+use rppal::i2c::I2c;
+
+#[cfg(test)]
+// This is only used in testing, not compiled in release.
+use crate::mocks::mock_i2c::I2c;
+
+/// LSM6DSL accelerometer/gyroscope on the BerryGPS-IMU.
+const LSM6DSL_ADDR: u16 = 0x6A;
+/// Expected WHO_AM_I response for the LSM6DSL.
+const LSM6DSL_ID: u8 = 0x6A;
+
+// LSM6DSL register addresses.
+const WHO_AM_I: u8 = 0x0F;
+/// Accelerometer data rate and full scale.
+const CTRL1_XL: u8 = 0x10;
+/// Gyroscope data rate and full scale.
+const CTRL2_G: u8 = 0x11;
+/// Block data update, register auto-increment.
+const CTRL3_C: u8 = 0x12;
+const STATUS_REG: u8 = 0x1E;
+const OUTZ_L_G: u8 = 0x26;
+const OUTX_L_XL: u8 = 0x28;
+
+/// CTRL2_G: 104 Hz, ±245 °/s.
+const ODR_104HZ_245DPS: u8 = 0x40;
+/// CTRL1_XL: 104 Hz, ±2 g.
+const XL_ODR_104HZ_2G: u8 = 0x40;
+/// CTRL3_C: output registers are not updated until both bytes have been read.
+const BDU: u8 = 0x40;
+/// CTRL3_C: register address auto-increment for multi-byte reads.
+const IF_INC: u8 = 0x04;
+/// STATUS_REG: new accelerometer data available.
+const STATUS_XLDA: u8 = 0x01;
+/// STATUS_REG: new gyroscope data available.
+const STATUS_GDA: u8 = 0x02;
+
+/// Sensitivity at ±245 °/s from the datasheet (8.75 m°/s per LSB).
+const DPS_PER_LSB: f64 = 0.00875;
+/// Sensitivity at ±2 g from the datasheet (0.061 mg per LSB).
+const G_PER_LSB: f64 = 0.000061;
+
+/// Samples averaged at startup for the zero-rate offset.
+const BIAS_SAMPLES: u32 = 50;
+const BIAS_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Yaw rate from the LSM6DSL gyroscope, with the board mounted flat.
+pub struct GyroSensor {
+    i2c: I2c,
+    /// Zero-rate offset (°/s), measured at startup.
+    bias: f64,
+}
+
+impl GyroSensor {
+    /// Initialize the gyroscope and measure its zero-rate offset. The boat should not be
+    /// turning meanwhile (half a second); rolling and pitching average out.
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let i2c = open_lsm6dsl()?;
+        i2c.smbus_write_byte(CTRL2_G, ODR_104HZ_245DPS)?;
+
+        let mut sensor = Self { i2c, bias: 0.0 };
+        thread::sleep(Duration::from_millis(100));
+
+        let mut total = 0.0;
+        for _ in 0..BIAS_SAMPLES {
+            total += sensor.read_raw_rate()?;
+            thread::sleep(BIAS_SAMPLE_INTERVAL);
+        }
+        sensor.bias = total / BIAS_SAMPLES as f64;
+
+        crate::info!(
+            "✓ Gyro (LSM6DSL) initialized, zero-rate offset {:+.2}°/s",
+            sensor.bias
+        );
+        Ok(sensor)
+    }
+
+    /// Zero-rate offset subtracted from every reading (°/s).
+    pub fn bias(&self) -> f64 {
+        self.bias
+    }
+
+    /// Rate of turn in degrees per second, positive turning to starboard (heading increasing).
+    pub fn read_rate_of_turn(&mut self) -> Result<f64, Box<dyn Error>> {
+        Ok(self.read_raw_rate()? - self.bias)
+    }
+
+    /// Z axis rate, sign flipped: the gyro counts counter-clockwise seen from above as positive.
+    fn read_raw_rate(&mut self) -> Result<f64, Box<dyn Error>> {
+        let status = self.i2c.smbus_read_byte(STATUS_REG)?;
+        if status & STATUS_GDA == 0 {
+            return Err("Gyro data not ready".into());
+        }
+
+        let mut data = [0u8; 2];
+        self.i2c.write_read(&[OUTZ_L_G], &mut data)?;
+        Ok(-(i16::from_le_bytes(data) as f64) * DPS_PER_LSB)
+    }
+}
+
+/// Gravity from the LSM6DSL accelerometer, to level the compass field with. It shares the chip
+/// with [`GyroSensor`].
+pub struct Accelerometer {
+    i2c: I2c,
+}
+
+impl Accelerometer {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let i2c = open_lsm6dsl()?;
+        i2c.smbus_write_byte(CTRL1_XL, XL_ODR_104HZ_2G)?;
+        thread::sleep(Duration::from_millis(20));
+        Ok(Self { i2c })
+    }
+
+    /// Acceleration along the board's X, Y and Z axes (g). At rest it points up, away from the
+    /// earth: (0, 0, 1) with the board flat.
+    pub fn read_up(&mut self) -> Result<[f64; 3], Box<dyn Error>> {
+        let status = self.i2c.smbus_read_byte(STATUS_REG)?;
+        if status & STATUS_XLDA == 0 {
+            return Err("Accelerometer data not ready".into());
+        }
+
+        let mut data = [0u8; 6];
+        self.i2c.write_read(&[OUTX_L_XL], &mut data)?;
+        let axis = |i: usize| i16::from_le_bytes([data[i], data[i + 1]]) as f64 * G_PER_LSB;
+        Ok([axis(0), axis(2), axis(4)])
+    }
+}
+
+/// Check the chip is there and set up multi-byte reads.
+fn open_lsm6dsl() -> Result<I2c, Box<dyn Error>> {
+    let mut i2c = I2c::new()?;
+    i2c.set_slave_address(LSM6DSL_ADDR)?;
+
+    let who_am_i = i2c.smbus_read_byte(WHO_AM_I)?;
+    if who_am_i != LSM6DSL_ID {
+        return Err(format!(
+            "Wrong device ID: 0x{:02X}, expected 0x{:02X}",
+            who_am_i, LSM6DSL_ID
+        )
+        .into());
+    }
+
+    i2c.smbus_write_byte(CTRL3_C, BDU | IF_INC)?;
+    Ok(i2c)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::mocks::mock_i2c;
+
+    /// Set the raw Z rate the mock gyro reports.
+    pub(crate) fn set_raw_rate(raw: i16) {
+        let [low, high] = raw.to_le_bytes();
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, OUTZ_L_G, low);
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, OUTZ_L_G + 1, high);
+    }
+
+    /// A mock gyro with the given raw zero-rate offset.
+    pub(crate) fn mock_gyro(raw_bias: i16) -> GyroSensor {
+        mock_i2c::reset_mock_registers();
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, WHO_AM_I, LSM6DSL_ID);
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, STATUS_REG, STATUS_GDA);
+        set_raw_rate(raw_bias);
+        GyroSensor::new().unwrap()
+    }
+
+    #[test]
+    fn test_configuration_and_bias() {
+        let mut gyro = mock_gyro(100);
+        assert_eq!(
+            mock_i2c::get_mock_register(LSM6DSL_ADDR, CTRL2_G),
+            ODR_104HZ_245DPS
+        );
+        assert_eq!(mock_i2c::get_mock_register(LSM6DSL_ADDR, CTRL3_C), 0x44);
+        assert!((gyro.bias() + 0.875).abs() < 1e-9);
+
+        // 1000 LSB counter-clockwise above the offset is 8.75°/s to port
+        set_raw_rate(1100);
+        assert!((gyro.read_rate_of_turn().unwrap() + 8.75).abs() < 1e-9);
+
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, STATUS_REG, 0x00);
+        assert!(gyro.read_rate_of_turn().is_err());
+    }
+
+    /// A mock accelerometer reporting `up` (g).
+    pub(crate) fn mock_accelerometer(up: [f64; 3]) -> Accelerometer {
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, WHO_AM_I, LSM6DSL_ID);
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, STATUS_REG, STATUS_XLDA | STATUS_GDA);
+        for (i, value) in up.into_iter().enumerate() {
+            let [low, high] = ((value / G_PER_LSB).round() as i16).to_le_bytes();
+            let register = OUTX_L_XL + 2 * i as u8;
+            mock_i2c::set_mock_register(LSM6DSL_ADDR, register, low);
+            mock_i2c::set_mock_register(LSM6DSL_ADDR, register + 1, high);
+        }
+        Accelerometer::new().unwrap()
+    }
+
+    #[test]
+    fn test_accelerometer() {
+        mock_i2c::reset_mock_registers();
+        let mut accelerometer = mock_accelerometer([0.0, -0.5, 0.866]);
+        assert_eq!(
+            mock_i2c::get_mock_register(LSM6DSL_ADDR, CTRL1_XL),
+            XL_ODR_104HZ_2G
+        );
+
+        let up = accelerometer.read_up().unwrap();
+        assert!(up[0].abs() < 1e-3 && (up[1] + 0.5).abs() < 1e-3 && (up[2] - 0.866).abs() < 1e-3);
+
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, STATUS_REG, STATUS_GDA);
+        assert!(accelerometer.read_up().is_err());
+    }
+
+    #[test]
+    fn test_wrong_device_id() {
+        mock_i2c::reset_mock_registers();
+        mock_i2c::set_mock_register(LSM6DSL_ADDR, WHO_AM_I, 0x42);
+        assert!(GyroSensor::new().is_err());
+        assert!(Accelerometer::new().is_err());
+    }
+}
@@ -1,12 +1,34 @@
+pub mod alarm;
+pub mod anchor;
+pub mod autopilot;
+pub mod autotune;
+pub mod calibration;
 pub mod compass;
 pub mod compass_sensor;
 pub mod config;
+pub mod control;
+pub mod export;
+pub mod failsafe;
 pub mod fetch;
 pub mod gpio_input;
 pub mod gps_tracker;
+pub mod gpx;
+pub mod gyro;
+pub mod logging;
+pub mod mob;
+pub mod motor;
+pub mod nmea_capture;
+pub mod params;
+pub mod pid;
 pub mod position;
 pub mod pwm;
+pub mod route;
+pub mod rudder;
+pub mod simulator;
+pub mod track;
+pub mod track_log;
 pub mod vector;
+pub mod watchdog;
 
 #[cfg(test)]
 pub(crate) mod mocks;
@@ -1,79 +1,959 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use geo_rs::GpsTracker;
+use clap::{Parser, Subcommand};
+use geo_rs::alarm::{AlarmInputs, AlarmManager, AlarmOutput};
+use geo_rs::anchor::{AnchorWatch, Rode};
+use geo_rs::autopilot::{Autopilot, Mode};
+use geo_rs::autotune::{AutotuneResult, AutotuneStep};
+use geo_rs::calibration::{
+    run_magnetometer_calibration, run_rudder_calibration, run_servo_calibration,
+    save_autotune_result,
+};
 use geo_rs::compass::heading_to_direction_8point;
-use geo_rs::compass_sensor::CompassSensor;
+use geo_rs::compass_sensor::{CompassReading, CompassSensor, FieldStatus};
 use geo_rs::config::{
-    LOOKAHEAD_DISTANCE_M, SERVO_UPDATE_INTERVAL_SECS, STATUS_UPDATE_INTERVAL_SECS,
+    Actuator, AnchorSettings, CONFIG_FILE, CompassSettings, Config, EngageMode, FailsafeAction,
+    GpioSettings, HeadingSource, NavigationSettings, RateSource, SteeringAlgorithm,
+    TrackLogSettings, list_profiles,
 };
-use geo_rs::fetch::fetch_with_tracker;
+use geo_rs::control::{Request, SharedStatus, send_command, start_control_server};
+use geo_rs::export::{ExportFormat, Trip};
+use geo_rs::failsafe::{Failsafe, FailsafeEvent, Shutdown};
+use geo_rs::fetch::{fetch_from_device, replay_capture};
 use geo_rs::gpio_input::UserInterface;
+use geo_rs::gyro::{Accelerometer, GyroSensor};
+use geo_rs::logging::{self, Level};
+use geo_rs::mob::ManOverboard;
+use geo_rs::nmea_capture::NmeaCapture;
+use geo_rs::params::{Parameters, watch_config_file};
 use geo_rs::pwm::ServoController;
+use geo_rs::route::{Arrival, ArrivalReason, METERS_PER_NM, Route, Waypoint};
+use geo_rs::rudder::{RudderFault, RudderSensor, is_plausible, rudder_angle};
+use geo_rs::simulator::{BoatSimulator, start_simulated_gps};
+use geo_rs::track_log::{LogRecord, TrackLogConfig, TrackLogger, read_log};
+use geo_rs::watchdog::{Stage, Watchdog};
+use geo_rs::{GpsTracker, Position, debug, error, info, warn};
+use signal_hook::consts::{SIGHUP, SIGUSR1};
 
-#[cfg(test)]
-pub(crate) mod mocks;
+/// GPS autopilot for a Raspberry Pi.
+#[derive(Parser)]
+#[command(name = "geo-rs", version)]
+struct Cli {
+    /// Configuration file [default: geo-rs.toml if it exists]
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting GPS Navigation System...");
+    /// Vessel profile from the configuration file [default: GEO_RS_PROFILE or the file's
+    /// `profile` setting]
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
 
-    let tracker = Arc::new(Mutex::new(GpsTracker::new()));
-    let mut ui = UserInterface::new()?;
+    /// Serial device the GPS is connected to, overrides gps.serial_device
+    #[arg(long, global = true, value_name = "DEVICE")]
+    device: Option<PathBuf>,
 
-    // initialize compass
-    let mut compass = match CompassSensor::new() {
-        Ok(c) => Some(c),
-        Err(e) => {
-            eprintln!("Compass not available: {} - GPS heading only", e);
-            None
+    /// Console verbosity: error, warn, info or debug
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "info")]
+    log_level: Level,
+
+    /// Run without hardware: simulated GPS, no compass, servo or GPIO
+    #[arg(long, global = true)]
+    simulate: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the autopilot (default)
+    Run,
+    /// Show position and headings without touching the steering
+    Monitor,
+    /// Calibrate the magnetometer
+    Calibrate {
+        /// Stop after this many seconds instead of waiting for Ctrl+C
+        #[arg(long, value_name = "SECS")]
+        duration: Option<u64>,
+    },
+    /// Calibrate the rudder angle sensor at the end stops and amidships
+    CalibrateRudder,
+    /// Jog the servo to center and each end stop with the toggles and save the pulses
+    CalibrateServo,
+    /// Play a raw NMEA capture back through the GPS parser
+    Replay {
+        file: PathBuf,
+        /// Feed lines as fast as possible instead of at the captured pace
+        #[arg(long)]
+        fast: bool,
+    },
+    /// Export track logs and routes
+    Export {
+        /// gpx, kml or geojson
+        format: ExportFormat,
+        /// Track log files
+        logs: Vec<PathBuf>,
+        /// Route to include (CSV or GPX), may be repeated
+        #[arg(long, value_name = "FILE")]
+        route: Vec<PathBuf>,
+        /// Alarm file to mark the alarms raised along the track from
+        #[arg(long, value_name = "FILE")]
+        alarms: Option<PathBuf>,
+        /// Write to a file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Manage the route followed in TRACK mode
+    Route {
+        #[command(subcommand)]
+        command: RouteCommand,
+    },
+    /// Check the GPS, compass, servo, GPIO and storage
+    Selftest,
+    /// Print the effective configuration (file, profile, environment and defaults) as TOML
+    Config,
+    /// List the vessel profiles defined in the configuration file
+    Profiles,
+    /// Read or change the settings of the running autopilot
+    Param {
+        #[command(subcommand)]
+        command: ParamCommand,
+    },
+    /// Tune the heading PID of the running autopilot with a relay test (engage AUTO first)
+    Autotune {
+        /// Stop a running test and go back to AUTO
+        #[arg(long)]
+        stop: bool,
+    },
+    /// Acknowledge or silence the alarms of the running autopilot
+    Alarm {
+        #[command(subcommand)]
+        command: AlarmCommand,
+    },
+    /// Start or stop the anchor watch of the running autopilot
+    Anchor {
+        #[command(subcommand)]
+        command: AnchorCommand,
+    },
+    /// Mark a man overboard on the running autopilot, where the boat was mob.backdate_secs ago
+    Mob {
+        /// Clear the mark instead
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Show the mode and target of the running autopilot
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ParamCommand {
+    /// Show every setting in use
+    List,
+    /// Show one setting, e.g. `pid.kp`
+    Get { key: String },
+    /// Change a setting; applied between control cycles
+    Set { key: String, value: String },
+    /// Read the configuration file again (same as SIGHUP)
+    Reload,
+}
+
+#[derive(Subcommand)]
+enum AlarmCommand {
+    /// Stop the raised alarms sounding; they stay listed until their condition clears
+    Ack,
+    /// Mute the sounding alarms for alarm.silence_secs
+    Silence,
+}
+
+#[derive(Subcommand)]
+enum AnchorCommand {
+    /// Mark the anchor at the boat's position and start watching
+    Drop {
+        /// Swing radius in meters (default anchor.radius_m)
+        #[arg(long, value_name = "M")]
+        radius: Option<f64>,
+        /// The anchor lies this many meters from the boat...
+        #[arg(long, value_name = "M", requires = "bearing")]
+        rode: Option<f64>,
+        /// ...on this bearing from the boat (degrees)
+        #[arg(long, value_name = "DEG", requires = "rode")]
+        bearing: Option<f64>,
+    },
+    /// Stop the anchor watch
+    Up,
+}
+
+#[derive(Subcommand)]
+enum RouteCommand {
+    /// Check a route file and install it as the startup route
+    Load { file: PathBuf },
+    /// Show a route file's legs (the startup route by default)
+    Show { file: Option<PathBuf> },
+}
+
+/// Options shared by all commands.
+struct Options {
+    config: Config,
+    /// File the configuration was read from, reloaded on SIGHUP or change.
+    config_path: Option<PathBuf>,
+    /// Command line settings that a reload must not undo.
+    overrides: Vec<(String, String)>,
+    simulate: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    logging::set_level(cli.log_level);
+
+    // printed with Display: configuration errors list one problem per line
+    if let Err(e) = run_cli(cli) {
+        error!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run_cli(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let path = match &cli.config {
+        Some(path) => Some(path.as_path()),
+        None => Some(Path::new(CONFIG_FILE)).filter(|path| path.exists()),
+    };
+    let mut config = Config::load(path, cli.profile.as_deref())?;
+    if let Some(path) = path {
+        debug!("Configuration loaded from {}", path.display());
+    }
+    if let Some(profile) = &config.profile {
+        debug!("Vessel profile: {}", profile);
+    }
+    let mut overrides = Vec::new();
+    if let Some(device) = cli.device {
+        overrides.push((
+            "gps.serial_device".to_string(),
+            device.to_string_lossy().into_owned(),
+        ));
+        config.gps.serial_device = device;
+    }
+
+    let options = Options {
+        config,
+        config_path: path.map(Path::to_path_buf),
+        overrides,
+        simulate: cli.simulate,
+    };
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run_command(&options),
+        Command::Monitor => monitor_command(&options),
+        Command::Calibrate { duration } => {
+            let path = options
+                .config_path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+            run_magnetometer_calibration(
+                &options.config,
+                &path,
+                Duration::from_secs(5),
+                duration.map(Duration::from_secs),
+            )?;
+            Ok(())
+        }
+        Command::CalibrateRudder => {
+            run_rudder_calibration(&options.config.rudder, options.config.servo.max_angle)?;
+            Ok(())
+        }
+        Command::CalibrateServo => {
+            let path = options
+                .config_path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+            run_servo_calibration(&options.config, &path)?;
+            Ok(())
+        }
+        Command::Replay { file, fast } => replay_command(&options, &file, fast),
+        Command::Export {
+            format,
+            logs,
+            route,
+            alarms,
+            output,
+        } => export_command(format, &logs, &route, alarms.as_deref(), output.as_deref()),
+        Command::Route { command } => match command {
+            RouteCommand::Load { file } => route_load_command(&file, &options.config.navigation),
+            RouteCommand::Show { file } => {
+                route_show_command(file.as_deref(), &options.config.navigation)
+            }
+        },
+        Command::Selftest => selftest_command(&options),
+        Command::Config => {
+            if let Some(profile) = &options.config.profile {
+                println!("# vessel profile: {}", profile);
+            }
+            print!("{}", options.config.to_toml()?);
+            Ok(())
+        }
+        Command::Profiles => profiles_command(&options),
+        Command::Param { command } => param_command(&options, command),
+        Command::Autotune { stop } => {
+            let command = if stop { "autotune stop" } else { "autotune" };
+            print!("{}", send_command(&options.config.control.socket, command)?);
+            Ok(())
+        }
+        Command::Alarm { command } => {
+            let command = match command {
+                AlarmCommand::Ack => "alarm ack",
+                AlarmCommand::Silence => "alarm silence",
+            };
+            print!("{}", send_command(&options.config.control.socket, command)?);
+            Ok(())
+        }
+        Command::Anchor { command } => {
+            let command = match command {
+                AnchorCommand::Drop {
+                    radius,
+                    rode,
+                    bearing,
+                } => {
+                    let mut command = String::from("anchor drop");
+                    if let Some(radius) = radius {
+                        command += &format!(" radius {}", radius);
+                    }
+                    if let (Some(rode), Some(bearing)) = (rode, bearing) {
+                        command += &format!(" rode {} {}", rode, bearing);
+                    }
+                    command
+                }
+                AnchorCommand::Up => String::from("anchor up"),
+            };
+            print!(
+                "{}",
+                send_command(&options.config.control.socket, &command)?
+            );
+            Ok(())
+        }
+        Command::Mob { clear } => {
+            let command = if clear { "mob clear" } else { "mob" };
+            print!("{}", send_command(&options.config.control.socket, command)?);
+            Ok(())
+        }
+        Command::Status => {
+            print!(
+                "{}",
+                send_command(&options.config.control.socket, "status")?
+            );
+            Ok(())
+        }
+    }
+}
+
+/// List the profiles in the configuration file, marking the one selected.
+fn profiles_command(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = &options.config_path else {
+        return Err(format!("no configuration file ({} not found)", CONFIG_FILE).into());
+    };
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let profiles = list_profiles(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if profiles.is_empty() {
+        println!("No vessel profiles in {}", path.display());
+    }
+
+    for profile in profiles {
+        let marker = if options.config.profile.as_ref() == Some(&profile.name) {
+            "*"
+        } else {
+            " "
+        };
+        match profile.inherits {
+            Some(base) => println!("{} {} (inherits {})", marker, profile.name, base),
+            None => println!("{} {}", marker, profile.name),
+        }
+    }
+    Ok(())
+}
+
+/// Talk to a running instance over its control socket.
+fn param_command(
+    options: &Options,
+    command: ParamCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let command = match command {
+        ParamCommand::List => "list".to_string(),
+        ParamCommand::Get { key } => format!("get {}", key),
+        ParamCommand::Set { key, value } => format!("set {} {}", key, value),
+        ParamCommand::Reload => "reload".to_string(),
+    };
+    print!(
+        "{}",
+        send_command(&options.config.control.socket, &command)?
+    );
+    Ok(())
+}
+
+fn run_command(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Starting GPS Navigation System...");
+
+    let config = &options.config;
+    if let Some(profile) = &config.profile {
+        info!("Vessel profile: {}", profile);
+    }
+    let tracker = Arc::new(Mutex::new(GpsTracker::with_settings(&config.gps)));
+
+    let (mut ui, mut compass, mut servo) = if options.simulate {
+        info!("Simulation: no compass, servo or GPIO");
+        (None, None, None)
+    } else {
+        let ui = UserInterface::new(&config.gpio, config.servo.max_angle)?;
+
+        let compass = open_compass(&config.compass);
+
+        // try to initialize servo, but don't fail if it's unavailable
+        let servo = match open_actuator(config) {
+            Ok(s) => {
+                match config.servo.actuator {
+                    Actuator::Servo => info!(
+                        "Servo controller initialized on GPIO {}",
+                        config.servo.pwm_pin
+                    ),
+                    Actuator::Motor => info!(
+                        "Motor drive initialized: PWM on GPIO {}, direction on GPIO {}",
+                        config.servo.pwm_pin, config.motor.direction_pin
+                    ),
+                }
+                Some(s)
+            }
+            Err(e) => {
+                warn!("Servo not available: {} - running in manual mode only", e);
+                None
+            }
+        };
+        let servo = servo.map(|mut servo| {
+            // rate of turn for the rate-of-turn steering algorithm
+            let gyro = GyroSensor::new()
+                .map_err(|e| warn!("Gyro not available: {} - rate of turn from heading", e))
+                .ok();
+            servo.set_gyro(gyro);
+            if config.rudder.enabled {
+                let sensor = RudderSensor::new(&config.rudder)
+                    .map_err(|e| match config.servo.actuator {
+                        Actuator::Servo => {
+                            warn!("Rudder sensor not available: {} - no rudder feedback", e)
+                        }
+                        Actuator::Motor => {
+                            warn!("Rudder sensor not available: {} - the motor can't steer", e)
+                        }
+                    })
+                    .ok();
+                servo.set_rudder_sensor(sensor, &config.rudder);
+            }
+            servo
+        });
+
+        (Some(ui), compass, servo)
+    };
+
+    // from here on Ctrl+C, SIGTERM and panics leave the rudder in its shutdown state
+    let alarm = if options.simulate {
+        AlarmOutput::default()
+    } else {
+        AlarmOutput::new(&config.alarm)
+            .map_err(|e| warn!("Alarm output not available: {} - console alarms only", e))
+            .unwrap_or_default()
+    };
+    let shutdown = Shutdown::install(&alarm)?;
+
+    let mut route = load_route(&config.navigation);
+
+    initialize_system(&mut servo, ui.is_some().then_some(&config.gpio))?;
+    // raw NMEA tee, can be started and stopped while running
+    let capture = NmeaCapture::new();
+    if config.nmea_capture.on_start {
+        match capture.start_in(&config.nmea_capture.directory) {
+            Ok(path) => info!("✓ Capturing raw NMEA to {}", path.display()),
+            Err(e) => warn!("NMEA capture not started: {}", e),
+        }
+    }
+
+    let controls = start_controls(options, shutdown, alarm);
+    if let Some(s) = &mut servo {
+        s.follow(controls.parameters.clone());
+    }
+
+    start_gps(options, Arc::clone(&tracker), capture.clone());
+    if !wait_for_gps_fix(&tracker, Some(&controls.shutdown))? {
+        info!("Shutting down");
+        return Ok(());
+    }
+
+    info!("Main control loop started.");
+    info!("Press engage to hold the current heading, press again for standby");
+    info!(
+        "Use 3-way toggle to adjust heading ({:+}°/{:+}° increments)",
+        config.gpio.adjust_left_degrees, config.gpio.adjust_right_degrees
+    );
+
+    run(
+        &controls,
+        &tracker,
+        &mut ui,
+        &mut servo,
+        &mut compass,
+        &mut route,
+        &capture,
+    )?;
+
+    Ok(())
+}
+
+/// The compass, with the accelerometer to level its field for the interference check.
+fn open_compass(settings: &CompassSettings) -> Option<CompassSensor> {
+    let mut compass = CompassSensor::new(settings)
+        .map_err(|e| warn!("Compass not available: {} - GPS heading only", e))
+        .ok()?;
+    let accelerometer = Accelerometer::new()
+        .map_err(|e| {
+            warn!(
+                "Accelerometer not available: {} - compass field inclination not checked",
+                e
+            )
+        })
+        .ok();
+    compass.set_accelerometer(accelerometer);
+    Some(compass)
+}
+
+/// Read-only display: GPS and compass, no steering, buttons or logging.
+fn monitor_command(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let config = &options.config;
+    let tracker = Arc::new(Mutex::new(GpsTracker::with_settings(&config.gps)));
+    let mut compass = if options.simulate {
+        None
+    } else {
+        open_compass(&config.compass)
+    };
+    let route = load_route(&config.navigation);
+    let status_interval = Duration::from_secs(config.navigation.status_update_interval_secs);
+    let autopilot = Autopilot::new();
+    // nothing to leave safe, but stop cleanly on Ctrl+C or SIGTERM
+    let shutdown = Shutdown::install(&AlarmOutput::default())?;
+
+    start_gps(options, Arc::clone(&tracker), NmeaCapture::new());
+    if !wait_for_gps_fix(&tracker, Some(&shutdown))? {
+        info!("Shutting down");
+        return Ok(());
+    }
+
+    let watch = Watch::new(AlarmManager::new(&config.alarm, AlarmOutput::default()));
+    let mut last_status_update = std::time::Instant::now();
+    while !shutdown.requested() {
+        if last_status_update.elapsed() >= status_interval {
+            let compass_reading = compass.as_mut().and_then(|c| c.read().ok());
+            display_status_update(
+                &tracker,
+                compass_reading.as_ref(),
+                &autopilot,
+                &None,
+                &route,
+                &watch,
+            );
+            last_status_update = std::time::Instant::now();
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    info!("Shutting down");
+    Ok(())
+}
+
+/// Replay a capture through the parser and show the status as it goes.
+fn replay_command(
+    options: &Options,
+    file: &Path,
+    fast: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let status_interval =
+        Duration::from_secs(options.config.navigation.status_update_interval_secs);
+    let tracker = Arc::new(Mutex::new(GpsTracker::with_settings(&options.config.gps)));
+    let replay = {
+        let tracker = Arc::clone(&tracker);
+        let file = file.to_path_buf();
+        thread::spawn(move || replay_capture(&file, &tracker, !fast).map_err(|e| e.to_string()))
+    };
+
+    let autopilot = Autopilot::new();
+    let watch = Watch::new(AlarmManager::new(
+        &options.config.alarm,
+        AlarmOutput::default(),
+    ));
+    let mut last_status_update = std::time::Instant::now();
+    while !replay.is_finished() {
+        if last_status_update.elapsed() >= status_interval {
+            display_status_update(&tracker, None, &autopilot, &None, &None, &watch);
+            last_status_update = std::time::Instant::now();
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let sentences = replay.join().map_err(|_| "replay thread panicked")??;
+    info!(
+        "✓ Replayed {} valid sentences from {}",
+        sentences,
+        file.display()
+    );
+    Ok(())
+}
+
+fn export_command(
+    format: ExportFormat,
+    logs: &[PathBuf],
+    routes: &[PathBuf],
+    alarms: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if logs.is_empty() && routes.is_empty() {
+        return Err("nothing to export: give track log files and/or --route".into());
+    }
+
+    let name = logs
+        .first()
+        .and_then(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "geo-rs".to_string());
+    let mut trip = Trip::new(&name);
+    for route in routes {
+        trip.routes.push(Route::load(route)?);
+    }
+    for log in logs {
+        trip.records.extend(read_log(log)?);
+    }
+    if let Some(path) = alarms {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        trip.add_alarms(&text)
+            .map_err(|e| format!("{} {}", path.display(), e))?;
+    }
+
+    let text = trip.export(format)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, text)?;
+            info!(
+                "✓ Exported {} points to {}",
+                trip.records.len(),
+                path.display()
+            );
         }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Validate a route and copy it to where `run` picks it up.
+fn route_load_command(
+    file: &Path,
+    navigation: &NavigationSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let route = Route::load(file)?;
+    if route.waypoints().is_empty() {
+        return Err(format!("{}: route has no waypoints", file.display()).into());
+    }
+    print_route(&route);
+
+    let is_gpx = file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gpx"));
+    let (target, other) = if is_gpx {
+        (&navigation.route_gpx_file, &navigation.route_file)
+    } else {
+        (&navigation.route_file, &navigation.route_gpx_file)
+    };
+
+    std::fs::copy(file, target)?;
+    println!("\n✓ Installed as {}", target.display());
+    if !is_gpx && other.exists() {
+        println!(
+            "  ⚠ {} exists and takes precedence - remove it to use this route",
+            other.display()
+        );
+    }
+    Ok(())
+}
+
+fn route_show_command(
+    file: Option<&Path>,
+    navigation: &NavigationSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let route = match file {
+        Some(file) => Route::load(file)?,
+        None => load_route(navigation).ok_or_else(|| {
+            format!(
+                "no route loaded ({} or {})",
+                navigation.route_gpx_file.display(),
+                navigation.route_file.display()
+            )
+        })?,
     };
+    print_route(&route);
+    Ok(())
+}
 
-    // try to initialize servo, but don't fail if it's unavailable
-    let mut servo = match ServoController::new() {
-        Ok(s) => {
-            println!("Servo controller initialized on GPIO 18");
-            Some(s)
+fn print_route(route: &Route) {
+    let waypoints = route.waypoints();
+    println!("Route '{}': {} waypoints", route.name, waypoints.len());
+
+    let mut total = 0.0;
+    for (index, waypoint) in waypoints.iter().enumerate() {
+        match index.checked_sub(1).map(|previous| &waypoints[previous]) {
+            Some(previous) => {
+                let distance = previous.position.distance_to(&waypoint.position);
+                total += distance;
+                println!(
+                    "  {:>2}. {:<16} {}  {:>5.1}°  {:>6.2} nm",
+                    index + 1,
+                    waypoint.name,
+                    waypoint.position,
+                    previous.position.heading_to(&waypoint.position),
+                    distance / METERS_PER_NM
+                );
+            }
+            None => println!(
+                "  {:>2}. {:<16} {}",
+                index + 1,
+                waypoint.name,
+                waypoint.position
+            ),
         }
+    }
+    println!("  Total: {:.2} nm", total / METERS_PER_NM);
+}
+
+/// Check each piece of hardware and the storage, print a summary and fail if anything is broken.
+fn selftest_command(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let config = &options.config;
+    println!("geo-rs self test\n");
+    let mut failures = 0;
+    let mut check = |name: &str, result: Result<String, Box<dyn std::error::Error>>| match result {
+        Ok(detail) => println!("  ✓ {:<10} {}", name, detail),
         Err(e) => {
-            eprintln!("Servo not available: {} - running in manual mode only", e);
-            None
+            println!("  ✗ {:<10} {}", name, e);
+            failures += 1;
         }
     };
 
-    initialize_system(&mut servo)?;
-    start_gps_thread(Arc::clone(&tracker));
-    wait_for_gps_fix(&tracker, &mut ui)?;
+    if options.simulate {
+        println!("  (simulation: hardware checks skipped)");
+    } else {
+        check(
+            "GPIO",
+            UserInterface::new(&config.gpio, config.servo.max_angle).map(|_| {
+                let mut inputs = format!(
+                    "toggles on {}/{}, engage on {}",
+                    config.gpio.toggle_left, config.gpio.toggle_right, config.gpio.engage_button
+                );
+                if config.gpio.mob_button_enabled {
+                    inputs += &format!(", MOB on {}", config.gpio.mob_button);
+                }
+                inputs
+            }),
+        );
+
+        check(
+            "Compass",
+            CompassSensor::new(&config.compass).and_then(|mut compass| {
+                let reading = compass.read()?;
+                let temperature = compass.read_temperature()?;
+                Ok(format!(
+                    "heading {:.1}°, field {:.2} gauss, {:.0}°C",
+                    reading.heading,
+                    reading.field.magnitude(),
+                    temperature
+                ))
+            }),
+        );
+
+        check(
+            "Servo",
+            open_actuator(config).and_then(|mut servo| {
+                servo.center()?;
+                Ok(match config.servo.actuator {
+                    Actuator::Servo => format!("centered on GPIO {}", config.servo.pwm_pin),
+                    Actuator::Motor => format!(
+                        "motor stopped, PWM on GPIO {}, direction on GPIO {}",
+                        config.servo.pwm_pin, config.motor.direction_pin
+                    ),
+                })
+            }),
+        );
+
+        if config.steering.algorithm == SteeringAlgorithm::RateOfTurn
+            && config.rate_of_turn.source == RateSource::Gyro
+        {
+            check(
+                "Gyro",
+                GyroSensor::new().and_then(|mut gyro| {
+                    let rate = gyro.read_rate_of_turn()?;
+                    Ok(format!(
+                        "rate of turn {:+.1}°/s, offset {:+.2}°/s",
+                        rate,
+                        gyro.bias()
+                    ))
+                }),
+            );
+        }
+
+        if config.rudder.enabled {
+            check(
+                "Rudder",
+                RudderSensor::new(&config.rudder).and_then(|mut sensor| {
+                    let raw = sensor.read_raw()?;
+                    if !is_plausible(&config.rudder, raw.into()) {
+                        return Err(format!(
+                            "reading {} is outside the calibrated range - disconnected?",
+                            raw
+                        )
+                        .into());
+                    }
+                    let angle = rudder_angle(&config.rudder, raw.into(), config.servo.max_angle);
+                    Ok(format!("{:+.1}° (reading {})", angle, raw))
+                }),
+            );
+        }
+
+        check("GPS", check_gps(&config.gps.serial_device));
+    }
+
+    check("Storage", check_storage(&config.track_log.directory));
 
-    run(&tracker, &mut ui, &mut servo, &mut compass)?;
+    let navigation = &config.navigation;
+    if let Some(path) = [&navigation.route_gpx_file, &navigation.route_file]
+        .into_iter()
+        .find(|path| path.exists())
+    {
+        check(
+            "Route",
+            Route::load(path)
+                .map(|route| format!("'{}' ({} waypoints)", route.name, route.waypoints().len())),
+        );
+    }
 
+    if failures > 0 {
+        return Err(format!("{} check(s) failed", failures).into());
+    }
+    println!("\nAll checks passed.");
     Ok(())
 }
 
+/// Wait up to 5 s for a valid sentence from the receiver.
+fn check_gps(device: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    std::fs::File::open(device).map_err(|e| format!("{}: {}", device.display(), e))?;
+
+    let tracker = Arc::new(Mutex::new(GpsTracker::new()));
+    {
+        let tracker = Arc::clone(&tracker);
+        let device = device.to_path_buf();
+        thread::spawn(move || fetch_from_device(&device, tracker, NmeaCapture::new()));
+    }
+
+    for _ in 0..50 {
+        thread::sleep(Duration::from_millis(100));
+        if let Ok(tracker_lock) = tracker.lock()
+            && tracker_lock.get_num_satellites().is_some()
+        {
+            return Ok(match tracker_lock.get_current_position() {
+                Some(pos) => format!("fix at {}", pos),
+                None => "receiving, no fix yet".to_string(),
+            });
+        }
+    }
+    Err(format!("no NMEA data from {}", device.display()).into())
+}
+
+fn check_storage(directory: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(directory)?;
+    let probe = directory.join(".selftest");
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)?;
+    Ok(format!("{}/ writable", directory.display()))
+}
+
 fn initialize_system(
     servo: &mut Option<ServoController>,
+    gpio: Option<&GpioSettings>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    println!("GPIO initialized:");
-    println!("  Toggle Left:  GPIO 23");
-    println!("  Toggle Right: GPIO 24");
+    if let Some(gpio) = gpio {
+        info!("GPIO initialized:");
+        info!("  Toggle Left:  GPIO {}", gpio.toggle_left);
+        info!("  Toggle Right: GPIO {}", gpio.toggle_right);
+        info!("  Engage:       GPIO {}", gpio.engage_button);
+        if gpio.mob_button_enabled {
+            info!("  MOB:          GPIO {}", gpio.mob_button);
+        }
+    }
 
     if let Some(s) = servo {
         s.center()?;
-        println!("  Servo PWM:    GPIO 18 ✓");
+        info!("  Servo PWM:    GPIO {} ✓", s.settings().pwm_pin);
     } else {
-        println!("  Servo PWM:    Disabled (manual mode)");
+        info!("  Servo PWM:    Disabled (manual mode)");
     }
 
-    println!("\nHardware initialization complete.");
+    info!("Hardware initialization complete.");
     Ok(())
 }
 
-fn start_gps_thread(tracker: Arc<Mutex<GpsTracker>>) {
+/// Load the route file if there is one (GPX first). Engaging with a route loaded starts TRACK mode.
+fn load_route(navigation: &NavigationSettings) -> Option<Route> {
+    let path = [&navigation.route_gpx_file, &navigation.route_file]
+        .into_iter()
+        .find(|path| path.exists())?;
+
+    match Route::load(path) {
+        Ok(mut route) => {
+            route.configure(navigation);
+            info!(
+                "✓ Route '{}' loaded: {} waypoints",
+                route.name,
+                route.waypoints().len()
+            );
+            Some(route)
+        }
+        Err(e) => {
+            warn!("Route file {} not loaded: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// The servo, or the H-bridge motor drive with `servo.actuator = "motor"`.
+fn open_actuator(config: &Config) -> Result<ServoController, Box<dyn std::error::Error>> {
+    Ok(match config.servo.actuator {
+        Actuator::Servo => ServoController::new(config)?,
+        Actuator::Motor => ServoController::with_motor(config)?,
+    })
+}
+
+/// Start feeding the tracker from the GPS receiver, or from the simulator with `--simulate`.
+fn start_gps(options: &Options, tracker: Arc<Mutex<GpsTracker>>, capture: NmeaCapture) {
+    if options.simulate {
+        let simulator = &options.config.simulator;
+        let start = Position::new(simulator.start_latitude, simulator.start_longitude);
+        info!(
+            "Simulated GPS: {} kn on {:.0}°",
+            simulator.speed_knots, simulator.course
+        );
+        start_simulated_gps(
+            tracker,
+            BoatSimulator::new(start, simulator.course, simulator.speed_knots),
+        );
+        return;
+    }
+
+    let device = options.config.gps.serial_device.clone();
     thread::spawn(move || {
-        if let Err(e) = fetch_with_tracker(tracker) {
-            eprintln!("\n❌ GPS error: {}", e);
-            eprintln!("Check /dev/serial0 connection and permissions");
+        if let Err(e) = fetch_from_device(&device, tracker, capture) {
+            error!("❌ GPS error: {}", e);
+            error!("Check {} connection and permissions", device.display());
         }
     });
 
@@ -81,52 +961,44 @@ fn start_gps_thread(tracker: Arc<Mutex<GpsTracker>>) {
     thread::sleep(Duration::from_millis(100));
 }
 
+/// Returns false if a shutdown was asked for before the fix.
 fn wait_for_gps_fix(
     tracker: &Arc<Mutex<GpsTracker>>,
-    ui: &mut UserInterface,
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting GPS data collection...");
+    shutdown: Option<&Shutdown>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    info!("Starting GPS data collection...");
     thread::sleep(Duration::from_millis(500)); // let serial port open
 
-    println!("Waiting for GPS fix...");
-    println!("  (Make sure GPS antenna has clear view of sky)");
+    info!("Waiting for GPS fix...");
+    info!("  (Make sure GPS antenna has clear view of sky)");
 
     let mut fix_attempts = 0;
     let start_time = std::time::Instant::now();
 
     loop {
         thread::sleep(Duration::from_millis(500));
+        if shutdown.is_some_and(Shutdown::requested) {
+            return Ok(false);
+        }
 
         if let Ok(tracker_lock) = tracker.lock()
             && let Some(pos) = tracker_lock.get_current_position()
         {
             let elapsed = start_time.elapsed().as_secs();
-            println!("\n✓ GPS fix acquired after {}s!", elapsed);
-            println!("  Position: {}", pos);
+            info!("✓ GPS fix acquired after {}s!", elapsed);
+            info!("  Position: {}", pos);
 
-            let gps_heading = tracker_lock.get_current_heading();
-            let gps_speed = tracker_lock.get_current_speed();
             let num_sats = tracker_lock.get_num_satellites();
             let hdop = tracker_lock.get_current_hdop();
 
             drop(tracker_lock);
 
             if let Some(sats) = num_sats {
-                println!("  Satellites: {}", sats);
+                info!("  Satellites: {}", sats);
             }
 
-            // if let Some(heading) = gps_heading {
-            //     let (direction, _) = heading_to_direction_8point(heading);
-            //     println!("  GPS heading: {:.1}° ({})", heading, direction);
-            //     ui.update_gps_heading(heading);
-            // }
-
-            //  if let Some(speed) = gps_speed {
-            //      println!("  Speed: {:.2} knots", speed);
-            //  }
-
             if let Some(hdop) = hdop {
-                println!("  hdop: {:.2} ", hdop);
+                info!("  hdop: {:.2} ", hdop);
             }
 
             break;
@@ -137,57 +1009,605 @@ fn wait_for_gps_fix(
         // show progress every 3 seconds
         if fix_attempts % 6 == 0 {
             let elapsed = start_time.elapsed().as_secs();
-            print!("\r  Waiting for GPS fix... {}s", elapsed);
-            std::io::Write::flush(&mut std::io::stdout()).ok();
+            info!("  Waiting for GPS fix... {}s", elapsed);
         }
 
         // show reminder after 30 seconds
         if fix_attempts == 60 {
-            println!("\n  ⚠ Still waiting for GPS fix...");
-            println!("  • Check antenna connection");
-            println!("  • Ensure clear view of sky");
-            println!("  • Cold start can take 30s-5min");
+            warn!("  ⚠ Still waiting for GPS fix...");
+            warn!("  • Check antenna connection");
+            warn!("  • Ensure clear view of sky");
+            warn!("  • Cold start can take 30s-5min");
         }
     }
 
-    println!("\nMain control loop started.");
-    println!("Use 3-way toggle to adjust heading (±5° increments)\n");
+    Ok(true)
+}
 
-    Ok(())
+/// What `run` takes from outside besides the sensors: the live parameters, the requests sent
+/// over the control socket and the status it answers with, the shutdown request and the alarm
+/// output.
+struct Controls {
+    parameters: Parameters,
+    requests: Receiver<Request>,
+    status: SharedStatus,
+    shutdown: Shutdown,
+    alarm: AlarmOutput,
+}
+
+/// What `run` keeps an eye on besides the steering: the alarms, the anchor watch and the
+/// man-overboard mark.
+struct Watch {
+    alarms: AlarmManager,
+    anchor: Option<AnchorWatch>,
+    mob: Option<ManOverboard>,
+}
+
+impl Watch {
+    fn new(alarms: AlarmManager) -> Self {
+        Self {
+            alarms,
+            anchor: None,
+            mob: None,
+        }
+    }
+}
+
+/// Live parameters for `run`: reloaded on SIGHUP (see `run`) or when the file changes, and
+/// settable over the control socket.
+fn start_controls(options: &Options, shutdown: Shutdown, alarm: AlarmOutput) -> Controls {
+    let config = &options.config;
+    let parameters = Parameters::with_overrides(
+        config.clone(),
+        options.config_path.clone(),
+        options.overrides.clone(),
+    );
+    let (sender, requests) = mpsc::channel();
+    let status = SharedStatus::default();
+
+    match start_control_server(
+        &config.control.socket,
+        parameters.clone(),
+        sender,
+        status.clone(),
+    ) {
+        Ok(()) => info!("✓ Control socket: {}", config.control.socket.display()),
+        Err(e) => warn!("Control socket not available: {} - no runtime tuning", e),
+    }
+    if config.control.watch_config {
+        watch_config_file(parameters.clone(), Duration::from_secs(2));
+    }
+    Controls {
+        parameters,
+        requests,
+        status,
+        shutdown,
+        alarm,
+    }
 }
 
 fn run(
+    controls: &Controls,
     tracker: &Arc<Mutex<GpsTracker>>,
-    ui: &mut UserInterface,
+    ui: &mut Option<UserInterface>,
     servo: &mut Option<ServoController>,
     compass: &mut Option<CompassSensor>,
+    route: &mut Option<Route>,
+    capture: &NmeaCapture,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // `kill -USR1 <pid>` starts or stops the raw NMEA capture
+    let capture_toggle = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGUSR1, Arc::clone(&capture_toggle))?;
+    // `kill -HUP <pid>` reloads the configuration file
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&reload_requested))?;
+    let parameters = &controls.parameters;
+
     let mut last_status_update = std::time::Instant::now();
     let mut last_servo_update = std::time::Instant::now();
     let mut last_correction = 0.0; // track last correction to reduce noise
+    let mut compass_disturbed = false; // interference alarm state
+    let mut heading_error = None; // off the target at the last steering update
+    let mut last_heading = None; // when the steering last had a heading
+    let mut autopilot = Autopilot::with_settings(&parameters.current().steering);
+    let mut track_log = start_track_log(&parameters.current().track_log);
+    let mut failsafe = Failsafe::new(&parameters.current().failsafe);
+    let mut watch = Watch::new(AlarmManager::new(
+        &parameters.current().alarm,
+        controls.alarm.clone(),
+    ));
+    let config = parameters.current();
+    let mut watchdog = Watchdog::start(
+        &config.watchdog,
+        servo.as_ref().map(ServoController::failsafe_handle),
+        config.failsafe.shutdown_action,
+        controls.alarm.clone(),
+    )?;
 
     loop {
-        initialize_heading_if_needed(tracker, ui);
-        handle_toggle_changes(tracker, ui)?;
+        watchdog.stage(Stage::Settings);
+        // settings only change here, between cycles
+        if reload_requested.swap(false, Ordering::Relaxed) {
+            match parameters.reload() {
+                Ok(changes) if changes.is_empty() => {
+                    info!("  ⚙ Configuration reloaded, no changes")
+                }
+                Ok(_) => info!("  ⚙ Configuration reloaded"),
+                Err(e) => warn!("  ⚠ Configuration not reloaded: {}", e),
+            }
+        }
+        if !parameters.apply_pending().is_empty() {
+            let config = parameters.current();
+            apply_parameters(&config, ui, compass, route);
+            if let Ok(mut tracker_lock) = tracker.lock() {
+                tracker_lock.configure(&config.gps);
+            }
+            autopilot.configure(&config.steering);
+            failsafe.configure(&config.failsafe);
+            watch.alarms.configure(&config.alarm);
+            if let Some(anchor_watch) = &mut watch.anchor {
+                anchor_watch.configure(&config.anchor);
+            }
+        }
+        let config = parameters.current();
 
-        // only apply servo correction if servo is available
-        if let Some(s) = servo {
-            apply_servo_correction(
+        // the servo controller, dropped on the way out, leaves the rudder in its shutdown state
+        if controls.shutdown.requested() {
+            if controls.shutdown.panicked() {
+                return Err("stopped after an internal error".into());
+            }
+            controls.alarm.set(false);
+            watchdog.disarm();
+            info!("Shutting down");
+            return Ok(());
+        }
+        let status_interval = Duration::from_secs(config.navigation.status_update_interval_secs);
+
+        // one compass reading per cycle, checked for interference whichever heading is steered by
+        watchdog.stage(Stage::Sensors);
+        let compass_reading = read_compass(compass, &mut compass_disturbed);
+
+        // buttons and toggles, unless running without GPIO
+        watchdog.stage(Stage::Controls);
+        if let Some(ui) = ui {
+            initialize_heading_if_needed(tracker, ui);
+            handle_engage_button(
                 tracker,
-                compass,
+                compass_reading.as_ref(),
                 ui,
+                servo,
+                route,
+                &mut autopilot,
+            );
+            handle_toggle_changes(
+                tracker,
+                ui,
+                &mut autopilot,
+                config.navigation.lookahead_distance_m,
+            )?;
+            if ui.mob_pressed() {
+                mark_mob(&config, tracker, &autopilot, route, &mut watch);
+            }
+        }
+        while let Ok(request) = controls.requests.try_recv() {
+            handle_request(
+                request,
+                &config,
+                tracker,
+                &mut autopilot,
+                servo,
+                route,
+                &mut watch,
+            );
+        }
+        watchdog.stage(Stage::Navigation);
+        if let Some(arrival) = update_route(
+            tracker,
+            compass_reading.as_ref(),
+            route,
+            servo,
+            &mut autopilot,
+        ) {
+            watch
+                .alarms
+                .waypoint_arrived(&arrival.waypoint.name, std::time::Instant::now());
+        }
+        if let Some(anchor_watch) = &mut watch.anchor
+            && let Ok(tracker_lock) = tracker.lock()
+        {
+            anchor_watch.update(
+                tracker_lock.get_current_position(),
+                tracker_lock.fix_age(),
+                std::time::Instant::now(),
+            );
+        }
+
+        // only steer when engaged and a servo is available
+        watchdog.stage(Stage::Steering);
+        if autopilot.mode() == Mode::Autotune
+            && let Some(s) = servo
+        {
+            if let Some(heading) = apply_autotune_step(
+                tracker,
+                compass_reading.as_ref(),
+                parameters,
+                &mut autopilot,
+                s,
+                &mut last_servo_update,
+            )? {
+                update_failsafe(&mut failsafe, heading.is_some(), s);
+                if heading.is_some() {
+                    last_heading = Some(std::time::Instant::now());
+                }
+                // no swinging the rudder about without a heading: back to AUTO, still failsafe
+                if failsafe.is_tripped() {
+                    warn!("  ⚠ Autotune stopped: no heading");
+                    autopilot.stop_autotune(Some(s))?;
+                }
+            }
+        } else if autopilot.is_engaged()
+            && let Some(s) = servo
+            && let Some(heading) = apply_servo_correction(
+                tracker,
+                compass_reading.as_ref(),
+                &autopilot,
+                &failsafe,
                 s,
                 &mut last_servo_update,
                 &mut last_correction,
-            )?;
+            )?
+        {
+            update_failsafe(&mut failsafe, heading.is_some(), s);
+            heading_error = heading.and_then(|heading| autopilot.heading_error(heading));
+            if heading.is_some() {
+                last_heading = Some(std::time::Instant::now());
+            }
+        }
+        if !autopilot.is_engaged() {
+            failsafe.reset();
+            heading_error = None;
+        }
+        let mut inputs = alarm_inputs(
+            tracker,
+            route,
+            servo,
+            &autopilot,
+            &failsafe,
+            compass_disturbed,
+            heading_error,
+        );
+        if let Some(anchor_watch) = &watch.anchor {
+            inputs.anchor_dragging = anchor_watch
+                .is_dragging()
+                .then(|| anchor_watch.distance_m());
+            inputs.anchor_fix_lost = anchor_watch.is_fix_lost();
         }
+        watch.alarms.update(&inputs, std::time::Instant::now());
 
-        display_status_update(tracker, compass, ui, &mut last_status_update);
+        controls.status.publish(autopilot.status());
+
+        watchdog.stage(Stage::Logging);
+        if capture_toggle.swap(false, Ordering::Relaxed) {
+            toggle_nmea_capture(capture, &config.nmea_capture.directory);
+        }
+
+        if let Some(logger) = &mut track_log {
+            log_track(tracker, compass_reading.as_ref(), servo, &autopilot, logger);
+        }
+
+        watchdog.stage(Stage::Display);
+        if last_status_update.elapsed() >= status_interval {
+            display_status_update(
+                tracker,
+                compass_reading.as_ref(),
+                &autopilot,
+                servo,
+                route,
+                &watch,
+            );
+            last_status_update = std::time::Instant::now();
+        }
+
+        // while steering, checking in only with a heading from whichever sensor it comes from;
+        // otherwise nothing depends on the sensors, a cycle is all it takes
+        let sensor_timeout = Duration::from_secs_f64(config.watchdog.sensor_timeout_secs);
+        let steering = autopilot.is_engaged() && servo.is_some();
+        let fresh = !steering || last_heading.is_some_and(|time| time.elapsed() < sensor_timeout);
+        watchdog.cycle_done(fresh);
 
         thread::sleep(Duration::from_millis(20));
     }
 }
 
+/// Carry out a request from the control socket.
+fn handle_request(
+    request: Request,
+    config: &Config,
+    tracker: &Arc<Mutex<GpsTracker>>,
+    autopilot: &mut Autopilot,
+    servo: &mut Option<ServoController>,
+    route: &mut Option<Route>,
+    watch: &mut Watch,
+) {
+    let result = match request {
+        Request::Autotune => {
+            if servo.is_none() {
+                Err("no servo to run the autotune with".into())
+            } else {
+                if !config.gain_schedule.points.is_empty() {
+                    warn!("  ⚠ Autotune: the gain schedule overrides [pid] while it is active");
+                }
+                autopilot.start_autotune(&config.autotune, config.servo.max_angle, servo.as_mut())
+            }
+        }
+        Request::StopAutotune => autopilot.stop_autotune(servo.as_mut()),
+        Request::AcknowledgeAlarms => {
+            if watch.alarms.acknowledge() == 0 {
+                info!("  No alarms to acknowledge");
+            }
+            return;
+        }
+        Request::SilenceAlarms => {
+            if watch.alarms.silence(std::time::Instant::now()) == 0 {
+                info!("  No alarms sounding");
+            }
+            return;
+        }
+        Request::DropAnchor { radius_m, rode } => {
+            if autopilot.is_engaged() {
+                warn!("  ⚠ Anchor watch: put the autopilot in standby first");
+            } else {
+                match start_anchor_watch(tracker, &config.anchor, radius_m, rode) {
+                    Ok(anchor_watch) => watch.anchor = Some(anchor_watch),
+                    Err(e) => warn!("  ⚠ Anchor watch: {}", e),
+                }
+            }
+            return;
+        }
+        Request::RaiseAnchor => {
+            match watch.anchor.take() {
+                Some(anchor_watch) => info!(
+                    "  ⚓ Anchor watch stopped, furthest from the anchor {:.0} m",
+                    anchor_watch.max_distance_m()
+                ),
+                None => info!("  No anchor watch running"),
+            }
+            return;
+        }
+        Request::ManOverboard => {
+            mark_mob(config, tracker, autopilot, route, watch);
+            return;
+        }
+        Request::ClearMob => {
+            match watch.mob.take() {
+                Some(mob) => info!("  MOB mark at {} cleared", mob),
+                None => info!("  No MOB mark set"),
+            }
+            return;
+        }
+    };
+
+    match result {
+        Ok(()) => info!("  Autopilot: {}", autopilot.status()),
+        Err(e) => warn!("  ⚠ {}", e),
+    }
+}
+
+/// Mark the anchor at the current fix, off by the rode if given, and start watching.
+fn start_anchor_watch(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    settings: &AnchorSettings,
+    radius_m: Option<f64>,
+    rode: Option<Rode>,
+) -> Result<AnchorWatch, Box<dyn std::error::Error>> {
+    let fix_lost_after = Duration::from_secs_f64(settings.fix_lost_secs);
+    let boat = tracker
+        .lock()
+        .ok()
+        .filter(|tracker_lock| {
+            tracker_lock
+                .fix_age()
+                .is_some_and(|age| age < fix_lost_after)
+        })
+        .and_then(|tracker_lock| tracker_lock.get_current_position())
+        .ok_or("no GPS fix to mark the anchor with")?;
+
+    let watch = AnchorWatch::start(settings, boat, radius_m, rode, std::time::Instant::now());
+    info!(
+        "  ⚓ Anchor watch: anchor at {}, radius {:.0} m",
+        watch.anchor(),
+        watch.radius_m()
+    );
+    if let Some(path) = watch.path() {
+        info!("  ⚓ Recording the swing pattern to {}", path.display());
+    }
+    Ok(watch)
+}
+
+/// Mark a man overboard where the boat was `mob.backdate_secs` ago and show the way back to it.
+/// With `mob.set_waypoint` the mark replaces the route; in TRACK the autopilot turns for it at
+/// once, otherwise engaging TRACK does.
+fn mark_mob(
+    config: &Config,
+    tracker: &Arc<Mutex<GpsTracker>>,
+    autopilot: &Autopilot,
+    route: &mut Option<Route>,
+    watch: &mut Watch,
+) {
+    let now = std::time::Instant::now();
+    let (mob, boat) = match tracker.lock() {
+        Ok(tracker_lock) => (
+            ManOverboard::mark(
+                &tracker_lock,
+                Duration::from_secs_f64(config.mob.backdate_secs),
+                now,
+            ),
+            tracker_lock.get_current_position(),
+        ),
+        Err(_) => (None, None),
+    };
+    let Some(mob) = mob else {
+        watch
+            .alarms
+            .man_overboard("MAN OVERBOARD - no GPS fix to mark".to_string(), now);
+        return;
+    };
+    watch
+        .alarms
+        .man_overboard(format!("MAN OVERBOARD at {}", mob), now);
+    if let Some(boat) = boat {
+        info!("  MOB: {}", mob.from(&boat, now));
+    }
+    watch.mob = Some(mob);
+
+    if config.mob.set_waypoint {
+        let mut mob_route = Route::homing("MOB", Waypoint::new("MOB", mob.position));
+        mob_route.configure(&config.navigation);
+        mob_route.activate(boat.unwrap_or(mob.position));
+        if let Some(replaced) = route.replace(mob_route)
+            && replaced.name != "MOB"
+        {
+            info!("  Route '{}' replaced by the MOB waypoint", replaced.name);
+        }
+        if autopilot.mode() == Mode::Track {
+            info!("  ⚑ Steering for the MOB waypoint");
+        } else {
+            info!("  ⚑ MOB is the active waypoint, engage TRACK to steer for it");
+        }
+    }
+}
+
+/// Hand changed settings to the parts of the loop that keep their own copy. The servo follows
+/// the parameters itself.
+fn apply_parameters(
+    config: &Config,
+    ui: &mut Option<UserInterface>,
+    compass: &mut Option<CompassSensor>,
+    route: &mut Option<Route>,
+) {
+    if let Some(ui) = ui {
+        ui.configure(&config.gpio, config.servo.max_angle);
+    }
+    if let Some(compass) = compass {
+        compass.set_calibration(&config.compass);
+    }
+    if let Some(route) = route {
+        route.configure(&config.navigation);
+    }
+}
+
+/// Toggle between standby and steering on each press of the engage button. Engaging follows the
+/// route if one is loaded and the engage mode is `track`, otherwise holds the current heading.
+fn handle_engage_button(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    compass: Option<&CompassReading>,
+    ui: &mut UserInterface,
+    servo: &mut Option<ServoController>,
+    route: &mut Option<Route>,
+    autopilot: &mut Autopilot,
+) {
+    if !ui.engage_pressed() {
+        return;
+    }
+
+    let result = if autopilot.is_engaged() {
+        autopilot.standby(servo.as_mut())
+    } else {
+        let steering = autopilot.steering().clone();
+        let heading = current_heading(tracker, compass, steering.heading_source);
+
+        let track_route = route
+            .as_mut()
+            .filter(|r| steering.engage_mode == EngageMode::Track && r.has_remaining());
+        match track_route {
+            Some(route) => {
+                // the first leg starts where we are now
+                let position = tracker
+                    .lock()
+                    .ok()
+                    .and_then(|tracker_lock| tracker_lock.get_current_position());
+                if !route.is_active()
+                    && let Some(position) = position
+                {
+                    route.activate(position);
+                }
+                autopilot.engage_track(heading, route.is_active(), servo.as_mut())
+            }
+            None => autopilot.engage_auto(heading, servo.as_mut()),
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            // toggle adjustments are relative to the captured heading
+            if let Some(target) = autopilot.target_heading() {
+                ui.update_gps_heading(target);
+                ui.reset_offset();
+            }
+            info!("  Autopilot: {}", autopilot.status());
+        }
+        Err(e) => warn!("  ⚠ {}", e),
+    }
+}
+
+fn toggle_nmea_capture(capture: &NmeaCapture, directory: &Path) {
+    if let Some(path) = capture.stop() {
+        info!("  ■ NMEA capture stopped: {}", path.display());
+        return;
+    }
+
+    match capture.start_in(directory) {
+        Ok(path) => info!("  ● Capturing raw NMEA to {}", path.display()),
+        Err(e) => warn!("  ⚠ NMEA capture not started: {}", e),
+    }
+}
+
+fn start_track_log(settings: &TrackLogSettings) -> Option<TrackLogger> {
+    let config = TrackLogConfig::from(settings);
+    match TrackLogger::start(config.clone()) {
+        Ok(logger) => {
+            info!("✓ Track log: {}/", config.directory.display());
+            Some(logger)
+        }
+        Err(e) => {
+            warn!("Track log not available: {} - not recording", e);
+            None
+        }
+    }
+}
+
+/// Queue a track log record when one is due.
+fn log_track(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    compass: Option<&CompassReading>,
+    servo: &Option<ServoController>,
+    autopilot: &Autopilot,
+    logger: &mut TrackLogger,
+) {
+    let Ok(tracker_lock) = tracker.lock() else {
+        return;
+    };
+    let position = tracker_lock.get_current_position();
+    if !logger.is_due(position.as_ref(), autopilot.mode()) {
+        return;
+    }
+
+    let record = LogRecord {
+        position,
+        speed: tracker_lock.get_current_speed(),
+        gps_heading: tracker_lock.get_current_heading(),
+        compass_heading: compass
+            .filter(|reading| !reading.status.is_disturbed())
+            .map(|reading| reading.heading),
+        target_heading: autopilot.target_heading(),
+        rudder_angle: servo.as_ref().map(|s| s.get_angle()),
+        ..LogRecord::new(autopilot.mode())
+    };
+    drop(tracker_lock);
+
+    logger.log(record);
+}
+
 fn initialize_heading_if_needed(tracker: &Arc<Mutex<GpsTracker>>, ui: &mut UserInterface) {
     if !ui.has_heading()
         && let Ok(tracker_lock) = tracker.lock()
@@ -195,7 +1615,7 @@ fn initialize_heading_if_needed(tracker: &Arc<Mutex<GpsTracker>>, ui: &mut UserI
     {
         let (direction, _) = heading_to_direction_8point(gps_heading);
         ui.update_gps_heading(gps_heading);
-        println!(
+        info!(
             "✓ GPS heading acquired: {:.1}° ({}) | Offset: 0.0° (following GPS)",
             gps_heading, direction
         );
@@ -205,27 +1625,42 @@ fn initialize_heading_if_needed(tracker: &Arc<Mutex<GpsTracker>>, ui: &mut UserI
 fn handle_toggle_changes(
     tracker: &Arc<Mutex<GpsTracker>>,
     ui: &mut UserInterface,
+    autopilot: &mut Autopilot,
+    lookahead_m: f64,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // in standby the toggle offset is relative to the heading we're on
+    if !autopilot.is_engaged()
+        && let Ok(tracker_lock) = tracker.lock()
+        && let Some(gps_heading) = tracker_lock.get_current_heading()
+    {
+        ui.update_gps_heading(gps_heading);
+    }
+
     if ui.update()?
         && let Some(target_heading) = ui.get_heading()
     {
+        // in TRACK the route owns the target
+        if autopilot.mode() == Mode::Auto {
+            autopilot.set_target_heading(target_heading);
+        }
+
         let (direction, _) = heading_to_direction_8point(target_heading);
 
         if let Ok(tracker_lock) = tracker.lock() {
             if let Some(_pos) = tracker_lock.get_current_position()
                 && let Some(vector) =
-                    tracker_lock.get_vector_to_direction(target_heading, LOOKAHEAD_DISTANCE_M)
+                    tracker_lock.get_vector_to_direction(target_heading, lookahead_m)
             {
                 let target = vector.end_position();
-                println!("  → Target heading: {:.1}° ({})", target_heading, direction);
-                println!("     {}m ahead: {}", LOOKAHEAD_DISTANCE_M, target);
+                info!("  → Target heading: {:.1}° ({})", target_heading, direction);
+                info!("     {}m ahead: {}", lookahead_m, target);
             }
 
             if let Some(gps_heading) = tracker_lock.get_current_heading() {
                 let (gps_direction, _) = heading_to_direction_8point(gps_heading);
-                println!("  → GPS heading: {:.1}° ({})", gps_heading, gps_direction);
+                info!("  → GPS heading: {:.1}° ({})", gps_heading, gps_direction);
             } else {
-                println!("  → GPS heading: N/A (speed too low)");
+                info!("  → GPS heading: N/A (speed too low)");
             }
         }
     }
@@ -235,108 +1670,493 @@ fn handle_toggle_changes(
 
 fn display_status_update(
     tracker: &Arc<Mutex<GpsTracker>>,
-    compass: &mut Option<CompassSensor>,
-    ui: &mut UserInterface,
-    last_status_update: &mut std::time::Instant,
+    compass: Option<&CompassReading>,
+    autopilot: &Autopilot,
+    servo: &Option<ServoController>,
+    route: &Option<Route>,
+    watch: &Watch,
 ) {
-    if last_status_update.elapsed() >= Duration::from_secs(STATUS_UPDATE_INTERVAL_SECS) {
-        if let Ok(tracker_lock) = tracker.lock()
-            && let Some(pos) = tracker_lock.get_current_position()
-        {
-            println!("\n[Status Update]");
-            println!("  Position: {}", pos);
-
-            // if let Some(target_heading) = ui.get_heading() {
-            //     let (target_direction, _) = heading_to_direction_8point(target_heading);
-            //     let offset = ui.get_heading_offset();
-            //     println!(
-            //         "  Target heading: {:.1}° ({}) [Offset: {:.1}°]",
-            //         target_heading, target_direction, offset
-            //     );
-            // } else {
-            //     println!("  Target heading: Waiting for GPS...");
-            // }
-
-            if let Some(num_sats) = tracker_lock.get_num_satellites() {
-                println!("  Satellites: {}", num_sats);
-            } else {
-                println!("  Satellites: N/A");
-            }
+    if !logging::enabled(Level::Info) {
+        return;
+    }
 
-            if let Some(hdop) = tracker_lock.get_current_hdop() {
-                println!("  HDOP: {:.2}", hdop);
-            } else {
-                println!("  HDOP: N/A");
+    if let Ok(tracker_lock) = tracker.lock()
+        && let Some(pos) = tracker_lock.get_current_position()
+    {
+        println!("\n[Status Update]");
+        // first, where the one in the water is
+        if let Some(mob) = &watch.mob {
+            println!("  MOB: ⚠ {}", mob.from(&pos, std::time::Instant::now()));
+        }
+        println!("  Autopilot: {}", autopilot.status());
+        for active in watch.alarms.active() {
+            println!("  Alarm: ⚠ {}", active);
+        }
+        if let Some(anchor_watch) = &watch.anchor {
+            println!("  Anchor: {}", anchor_watch);
+        }
+        if let Some(servo) = servo {
+            match servo.algorithm() {
+                SteeringAlgorithm::Pid => println!("  Gains: {}", servo.active_gains()),
+                SteeringAlgorithm::RateOfTurn => println!("  Steering: rate of turn"),
+            }
+            if let Some(fault) = servo.motor_fault() {
+                println!("  Motor: ⚠ {}", fault);
+            }
+            if let Some(fault) = servo.rudder_fault() {
+                println!("  Rudder: ⚠ {}", fault);
+            } else if let Some(angle) = servo.rudder_angle() {
+                println!(
+                    "  Rudder: {:+.1}° (commanded {:+.1}°)",
+                    angle,
+                    servo.get_angle()
+                );
             }
+        }
+        println!("  Position: {}", pos);
 
-            // show both GPS and compass headings
-            let gps_heading = tracker_lock.get_current_heading();
-            let compass_heading = compass.as_mut().and_then(|c| c.read_heading().ok());
+        if let Some(route) = route
+            && let Some(leg) = route.leg_status(&pos, tracker_lock.get_current_speed())
+        {
+            println!(
+                "  Route '{}': leg {}/{} {}",
+                route.name,
+                leg.leg + 1,
+                route.waypoints().len(),
+                leg
+            );
+        }
 
-            if let Some(heading) = gps_heading {
-                let (gps_direction, _) = heading_to_direction_8point(heading);
-                println!("  GPS heading: {:.1}° ({})", heading, gps_direction);
-            } else {
-                println!("  GPS heading: N/A (speed too low)");
-            }
+        // if let Some(target_heading) = ui.get_heading() {
+        //     let (target_direction, _) = heading_to_direction_8point(target_heading);
+        //     let offset = ui.get_heading_offset();
+        //     println!(
+        //         "  Target heading: {:.1}° ({}) [Offset: {:.1}°]",
+        //         target_heading, target_direction, offset
+        //     );
+        // } else {
+        //     println!("  Target heading: Waiting for GPS...");
+        // }
 
-            if let Some(heading) = compass_heading {
-                let (comp_direction, _) = heading_to_direction_8point(heading);
-                println!("  Compass heading: {:.1}° ({})", heading, comp_direction);
-            } else {
-                println!("  Compass heading: N/A");
-            }
+        if let Some(num_sats) = tracker_lock.get_num_satellites() {
+            println!("  Satellites: {}", num_sats);
+        } else {
+            println!("  Satellites: N/A");
+        }
 
-            //  if let Some(speed) = tracker_lock.get_current_speed() {
-            //      println!("  Speed: {:.2} knots", speed);
-            //  }
+        if let Some(hdop) = tracker_lock.get_current_hdop() {
+            println!("  HDOP: {:.2}", hdop);
+        } else {
+            println!("  HDOP: N/A");
+        }
+
+        // show both GPS and compass headings
+        let gps_heading = tracker_lock.get_current_heading();
+
+        if let Some(heading) = gps_heading {
+            let (gps_direction, _) = heading_to_direction_8point(heading);
+            println!("  GPS heading: {:.1}° ({})", heading, gps_direction);
+        } else {
+            println!("  GPS heading: N/A (speed too low)");
+        }
 
-            println!();
+        if let Some(reading) = compass {
+            let (comp_direction, _) = heading_to_direction_8point(reading.heading);
+            let flag = match reading.status {
+                FieldStatus::Learning => " [learning field baseline]",
+                FieldStatus::Normal => "",
+                FieldStatus::Disturbed { .. } => " [DISTURBED]",
+            };
+            println!(
+                "  Compass heading: {:.1}° ({}){}",
+                reading.heading, comp_direction, flag
+            );
+        } else {
+            println!("  Compass heading: N/A");
         }
-        *last_status_update = std::time::Instant::now();
+
+        //  if let Some(speed) = tracker_lock.get_current_speed() {
+        //      println!("  Speed: {:.2} knots", speed);
+        //  }
+
+        println!();
     }
 }
 
+/// Steer towards the target heading, once per servo update interval, unless the failsafe holds
+/// a jammed rudder. Returns the heading steered by, if there was one, or `None` between updates.
 fn apply_servo_correction(
     tracker: &Arc<Mutex<GpsTracker>>,
-    compass: &mut Option<CompassSensor>,
-    ui: &mut UserInterface,
+    compass: Option<&CompassReading>,
+    autopilot: &Autopilot,
+    failsafe: &Failsafe,
     servo: &mut ServoController,
     last_servo_update: &mut std::time::Instant,
     last_correction: &mut f64,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Option<Option<f64>>, Box<dyn std::error::Error>> {
     let dt = last_servo_update.elapsed().as_secs_f64();
+    if dt < servo.settings().update_interval_secs {
+        return Ok(None);
+    }
 
-    if dt >= SERVO_UPDATE_INTERVAL_SECS {
-        if let Some(target_heading) = ui.get_heading()
-            && let Ok(tracker_lock) = tracker.lock()
-        {
-            // try GPS heading first, fall back to compass
-            let current_heading = tracker_lock
-                .get_current_heading()
-                .or_else(|| compass.as_mut().and_then(|c| c.read_heading().ok()));
-
-            if let Some(heading) = current_heading {
-                // update UI with current GPS heading for servo range limiting
-                ui.update_gps_heading(heading);
-
-                match servo.auto_steer(target_heading, heading, dt) {
-                    Ok(correction) => {
-                        // only print if correction changed by more than 0.5°
-                        let correction_change = (correction - *last_correction).abs();
-                        if correction_change > 0.5 {
-                            if correction.abs() > 0.1 {
-                                println!("  ⚙ Steering correction: {:.1}°", correction);
-                            }
-                            *last_correction = correction;
-                        }
+    let heading = current_heading(tracker, compass, autopilot.steering().heading_source);
+    if !failsafe.is_jammed()
+        && let Some(target_heading) = autopilot.target_heading()
+        && let Some(heading) = heading
+    {
+        // gain scheduling by speed over ground, the [pid] gains once it is stale
+        servo.set_speed(
+            tracker
+                .lock()
+                .ok()
+                .and_then(|tracker_lock| tracker_lock.get_current_speed()),
+        );
+        match servo.auto_steer(target_heading, heading, dt) {
+            Ok(correction) => {
+                debug!(
+                    "  target {:.1}° heading {:.1}° rudder {:+.1}° (dt {:.3}s)",
+                    target_heading,
+                    heading,
+                    servo.get_angle(),
+                    dt
+                );
+                // only print if correction changed by more than 0.5°
+                let correction_change = (correction - *last_correction).abs();
+                if correction_change > 0.5 {
+                    if correction.abs() > 0.1 {
+                        info!("  ⚙ Steering correction: {:.1}°", correction);
                     }
-                    Err(e) => eprintln!("Servo error: {}", e),
+                    *last_correction = correction;
                 }
             }
+            Err(e) => error!("Servo error: {}", e),
         }
-        *last_servo_update = std::time::Instant::now();
     }
+    *last_servo_update = std::time::Instant::now();
 
-    Ok(())
+    Ok(Some(heading))
+}
+
+/// Hold, center or release the rudder while there is no heading to steer by, and steer again
+/// once it is back. A jammed rudder is left the same way until standby. The alarm manager sounds
+/// the alarm while the failsafe is tripped.
+fn update_failsafe(failsafe: &mut Failsafe, has_heading: bool, servo: &mut ServoController) {
+    let event = if servo.rudder_fault() == Some(RudderFault::Jammed) {
+        failsafe.rudder_jammed()
+    } else {
+        failsafe.update(has_heading, std::time::Instant::now())
+    };
+    match event {
+        Some(FailsafeEvent::Tripped(action)) => {
+            if failsafe.is_jammed() {
+                warn!("  ⚠ Failsafe: rudder jammed - {} until standby", action);
+            } else {
+                warn!(
+                    "  ⚠ Failsafe: no heading for {:.0}s - {}",
+                    failsafe.settings().heading_timeout_secs,
+                    action
+                );
+            }
+            if action == FailsafeAction::Release
+                && let Err(e) = servo.release()
+            {
+                error!("Servo error: {}", e);
+            }
+        }
+        Some(FailsafeEvent::Recovered) => {
+            info!("  ✓ Heading back - steering resumed");
+            // continue from where the rudder was left
+            servo.reset();
+        }
+        None => {}
+    }
+
+    // amidships at the servo's own rate
+    if failsafe.is_tripped()
+        && failsafe.settings().action == FailsafeAction::Center
+        && let Err(e) = servo.steer_to(0.0, servo.settings().update_interval_secs)
+    {
+        error!("Servo error: {}", e);
+    }
+}
+
+/// Drive the relay autotune: swing the rudder from side to side until the oscillation is
+/// measured, then stage the proposed gains and go back to AUTO. Returns the heading (or `None`
+/// without one) when the step was due, for the failsafe.
+fn apply_autotune_step(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    compass: Option<&CompassReading>,
+    parameters: &Parameters,
+    autopilot: &mut Autopilot,
+    servo: &mut ServoController,
+    last_servo_update: &mut std::time::Instant,
+) -> Result<Option<Option<f64>>, Box<dyn std::error::Error>> {
+    let dt = last_servo_update.elapsed().as_secs_f64();
+    if dt < servo.settings().update_interval_secs {
+        return Ok(None);
+    }
+    *last_servo_update = std::time::Instant::now();
+
+    let heading = current_heading(tracker, compass, autopilot.steering().heading_source);
+
+    match autopilot.autotune_step(heading, std::time::Instant::now()) {
+        Some(AutotuneStep::Rudder(angle)) => {
+            if let Err(e) = servo.steer_to(angle, dt) {
+                error!("Servo error: {}", e);
+            }
+        }
+        Some(AutotuneStep::Done(result)) => {
+            info!("  ✓ Autotune: {}", result);
+            save_tuned_gains(parameters, &result);
+            autopilot.stop_autotune(Some(servo))?;
+        }
+        Some(AutotuneStep::Aborted(reason)) => {
+            warn!("  ⚠ Autotune stopped: {}", reason);
+            autopilot.stop_autotune(Some(servo))?;
+        }
+        None => {}
+    }
+    Ok(Some(heading))
+}
+
+/// Write the tuned gains to the configuration file's `[autotune.result]` for review. The
+/// gains in use are left alone until the operator copies them into `[pid]`.
+fn save_tuned_gains(parameters: &Parameters, result: &AutotuneResult) {
+    let Some(path) = parameters.path() else {
+        warn!("  ⚠ Autotune: no configuration file, the result is not saved");
+        return;
+    };
+    let profile = parameters.current().profile.clone();
+    match save_autotune_result(path, profile.as_deref(), result) {
+        Ok(()) => info!(
+            "  Autotune result written to [autotune.result] in {}: copy the gains into [pid] to use them",
+            path.display()
+        ),
+        Err(e) => warn!("  ⚠ Autotune: result not saved: {}", e),
+    }
+}
+
+/// In TRACK mode, steer for the next waypoint and advance legs on arrival. When the last
+/// waypoint is reached the autopilot drops back to AUTO on the final leg's heading, or on the
+/// current heading if no leg was steered yet, and to standby without either. Returns the
+/// arrival, if a waypoint was reached.
+fn update_route(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    compass: Option<&CompassReading>,
+    route: &mut Option<Route>,
+    servo: &mut Option<ServoController>,
+    autopilot: &mut Autopilot,
+) -> Option<Arrival> {
+    if autopilot.mode() != Mode::Track {
+        return None;
+    }
+    let route = route.as_mut()?;
+    let position = tracker
+        .lock()
+        .ok()
+        .and_then(|tracker_lock| tracker_lock.get_current_position())?;
+
+    let arrival = route.update(&position);
+    if let Some(reached) = &arrival {
+        let how = match reached.reason {
+            ArrivalReason::ArrivalCircle => "arrival circle",
+            ArrivalReason::PerpendicularPassed => "perpendicular passed",
+        };
+        info!("  ⚑ Waypoint {} reached ({})", reached.waypoint.name, how);
+
+        if reached.route_complete {
+            info!("  ⚑ Route '{}' complete", route.name);
+            // engaged inside the last arrival circle there is no leg heading yet
+            let heading = autopilot
+                .target_heading()
+                .or_else(|| current_heading(tracker, compass, autopilot.steering().heading_source));
+            if let Err(e) = autopilot.engage_auto(heading, servo.as_mut()) {
+                warn!("  ⚠ {} - standby", e);
+                if let Err(e) = autopilot.standby(servo.as_mut()) {
+                    error!("Servo error: {}", e);
+                }
+            }
+            return arrival;
+        }
+    }
+
+    if let Some(heading) = route.steering_heading(&position) {
+        autopilot.set_target_heading(heading);
+    }
+    arrival
+}
+
+/// Gather what the alarms are checked against this cycle.
+fn alarm_inputs(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    route: &Option<Route>,
+    servo: &Option<ServoController>,
+    autopilot: &Autopilot,
+    failsafe: &Failsafe,
+    compass_disturbed: bool,
+    heading_error: Option<f64>,
+) -> AlarmInputs {
+    let mut inputs = AlarmInputs {
+        no_heading: failsafe.is_tripped() && !failsafe.is_jammed(),
+        heading_error,
+        compass_disturbed,
+        ..AlarmInputs::default()
+    };
+    if let Ok(tracker_lock) = tracker.lock() {
+        inputs.fix_age = tracker_lock.fix_age();
+        inputs.hdop = tracker_lock.get_current_hdop().map(f64::from);
+        if autopilot.mode() == Mode::Track
+            && let Some(route) = route
+            && let Some(position) = tracker_lock.get_current_position()
+        {
+            inputs.cross_track_m = route
+                .leg_status(&position, None)
+                .map(|leg| leg.cross_track_m);
+        }
+    }
+    // within a degree of the end stop, measured if there is a rudder sensor
+    if autopilot.is_engaged()
+        && let Some(servo) = servo
+    {
+        let angle = servo.rudder_angle().unwrap_or_else(|| servo.get_angle());
+        inputs.rudder_at_limit = angle.abs() >= servo.settings().max_angle - 1.0;
+        // a jam holds the failsafe even once the rudder gets free
+        inputs.rudder_fault = servo
+            .rudder_fault()
+            .or(failsafe.is_jammed().then_some(RudderFault::Jammed));
+        inputs.motor_fault = servo.motor_fault();
+    }
+    inputs
+}
+
+/// Current heading for steering from the preferred source, falling back to the other. The
+/// compass counts as unavailable while it is disturbed.
+fn current_heading(
+    tracker: &Arc<Mutex<GpsTracker>>,
+    compass: Option<&CompassReading>,
+    source: HeadingSource,
+) -> Option<f64> {
+    let gps_heading = || {
+        tracker
+            .lock()
+            .ok()
+            .and_then(|tracker_lock| tracker_lock.get_current_heading())
+    };
+    let compass_heading = compass
+        .filter(|reading| !reading.status.is_disturbed())
+        .map(|reading| reading.heading);
+
+    match source {
+        HeadingSource::Gps => gps_heading().or(compass_heading),
+        HeadingSource::Compass => compass_heading.or_else(gps_heading),
+    }
+}
+
+/// Read the compass for this cycle and track whether its field is disturbed, for the alarm and
+/// the heading choice. The disturbed state only changes with a reading.
+fn read_compass(
+    compass: &mut Option<CompassSensor>,
+    compass_disturbed: &mut bool,
+) -> Option<CompassReading> {
+    let reading = compass.as_mut()?.read().ok()?;
+    let disturbed = reading.status.is_disturbed();
+
+    if disturbed != *compass_disturbed {
+        if let FieldStatus::Disturbed {
+            magnitude_deviation,
+            inclination_deviation,
+        } = reading.status
+        {
+            warn!(
+                "  ⚠ Magnetic interference (field {:+.0}%, dip {:+.1}°) - steering on GPS course",
+                magnitude_deviation * 100.0,
+                inclination_deviation
+            );
+        } else {
+            info!("  ✓ Magnetic interference cleared - compass heading back in use");
+        }
+        *compass_disturbed = disturbed;
+    }
+
+    Some(reading)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_rs::track_log::LOG_HEADER;
+
+    #[test]
+    fn test_export_alarms() {
+        let directory = std::env::temp_dir().join(format!("geo-rs-export-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = |name: &str| directory.join(name).to_string_lossy().into_owned();
+
+        let record = |time: &str, latitude: f64| LogRecord {
+            time: time.parse().unwrap(),
+            position: Some(Position::new(latitude, -123.0)),
+            ..LogRecord::new(Mode::Auto)
+        };
+        let log = [
+            LOG_HEADER.to_string(),
+            record("2024-06-01T12:00:00Z", 48.0).to_csv(),
+            record("2024-06-01T12:00:10Z", 48.001).to_csv(),
+        ];
+        std::fs::write(file("config.toml"), "").unwrap();
+        std::fs::write(file("track.csv"), log.join("\n") + "\n").unwrap();
+        std::fs::write(
+            file("alarms.log"),
+            "2024-06-01T12:00:08Z ALARM Off course: 25° off\n",
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from([
+            "geo-rs",
+            "--config",
+            &file("config.toml"),
+            "export",
+            "kml",
+            &file("track.csv"),
+            "--alarms",
+            &file("alarms.log"),
+            "--output",
+            &file("trip.kml"),
+        ])
+        .unwrap();
+        run_cli(cli).unwrap();
+
+        let kml = std::fs::read_to_string(file("trip.kml")).unwrap();
+        assert!(kml.contains("<name>Off course: 25° off</name>"), "{}", kml);
+        assert!(kml.contains("-123.0000000,48.0010000"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_route_complete_without_leg_heading() {
+        let mark = Position::new(48.0, -123.0);
+        let single = || {
+            let mut route = Route::with_waypoints("mark", vec![Waypoint::new("A", mark)]);
+            route.activate(mark);
+            Some(route)
+        };
+        let tracker = Arc::new(Mutex::new(GpsTracker::new()));
+        tracker.lock().unwrap().update_position(48.0, -123.0);
+        tracker.lock().unwrap().update_heading(135.0);
+
+        // engaged inside the arrival circle: AUTO on the heading we're on
+        let mut autopilot = Autopilot::new();
+        autopilot.engage_track(Some(135.0), true, None).unwrap();
+        let arrival = update_route(&tracker, None, &mut single(), &mut None, &mut autopilot);
+        assert!(arrival.unwrap().route_complete);
+        assert_eq!(autopilot.mode(), Mode::Auto);
+        assert_eq!(autopilot.target_heading(), Some(135.0));
+
+        // and without a heading either: standby
+        let tracker = Arc::new(Mutex::new(GpsTracker::new()));
+        tracker.lock().unwrap().update_position(48.0, -123.0);
+        let mut autopilot = Autopilot::new();
+        autopilot.engage_track(Some(135.0), true, None).unwrap();
+        update_route(&tracker, None, &mut single(), &mut None, &mut autopilot).unwrap();
+        assert_eq!(autopilot.mode(), Mode::Standby);
+    }
 }
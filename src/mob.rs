@@ -0,0 +1,118 @@
+//! Man overboard.
+//!
+//! A MOB mark is where the boat was `mob.backdate_secs` before the button was pressed, taken
+//! from the GPS fix history: by the time someone reaches the button the boat has moved on. It
+//! needs nothing but a GPS fix. If the fix was lost, the last one known is used and its time
+//! recorded.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::gps_tracker::GpsTracker;
+use crate::position::Position;
+use crate::route::METERS_PER_NM;
+
+/// A man-overboard mark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ManOverboard {
+    pub position: Position,
+    /// Time of the fix the position came from.
+    pub time: DateTime<Utc>,
+    /// When the button was pressed.
+    pub marked: Instant,
+}
+
+impl ManOverboard {
+    /// Mark where the boat was `backdate` before `now`. `None` if there has never been a fix.
+    pub fn mark(tracker: &GpsTracker, backdate: Duration, now: Instant) -> Option<Self> {
+        let (fix_time, position) = tracker.fix_at(now.checked_sub(backdate).unwrap_or(now))?;
+        let age = chrono::Duration::from_std(now.saturating_duration_since(fix_time)).ok()?;
+        Some(Self {
+            position,
+            time: Utc::now() - age,
+            marked: now,
+        })
+    }
+
+    /// Range and bearing from `boat` back to the mark at `now`.
+    pub fn from(&self, boat: &Position, now: Instant) -> MobBearing {
+        MobBearing {
+            range_m: boat.distance_to(&self.position),
+            bearing: boat.heading_to(&self.position),
+            elapsed: now.saturating_duration_since(self.marked),
+        }
+    }
+}
+
+impl fmt::Display for ManOverboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}",
+            self.position,
+            self.time.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )
+    }
+}
+
+/// Where the MOB mark lies from the boat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MobBearing {
+    pub range_m: f64,
+    pub bearing: f64,
+    /// Time since the mark.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for MobBearing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // meters while close, where it matters
+        if self.range_m < 1000.0 {
+            write!(f, "{:.0} m", self.range_m)?;
+        } else {
+            write!(f, "{:.2} nm", self.range_m / METERS_PER_NM)?;
+        }
+        let secs = self.elapsed.as_secs();
+        write!(
+            f,
+            " bearing {:03.0}° | {}m {:02}s ago",
+            self.bearing,
+            secs / 60,
+            secs % 60
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_back_dated() {
+        let mut tracker = GpsTracker::new();
+        assert!(ManOverboard::mark(&tracker, Duration::from_secs(5), Instant::now()).is_none());
+
+        tracker.update_position(48.0, -123.0);
+        let now = Instant::now();
+        let mob = ManOverboard::mark(&tracker, Duration::from_secs(5), now).unwrap();
+        // only one fix so far: that's where it was
+        assert_eq!(mob.position, Position::new(48.0, -123.0));
+        assert!(Utc::now() - mob.time < chrono::Duration::seconds(1));
+
+        // 85 m further on
+        let boat = mob.position.project(30.0, 85.0);
+        let bearing = mob.from(&boat, now + Duration::from_secs(130));
+        assert!((bearing.range_m - 85.0).abs() < 0.1);
+        assert!((bearing.bearing - 210.0).abs() < 0.1);
+        assert_eq!(bearing.to_string(), "85 m bearing 210° | 2m 10s ago");
+
+        let far = MobBearing {
+            range_m: 2500.0,
+            bearing: 5.0,
+            elapsed: Duration::from_secs(601),
+        };
+        assert_eq!(far.to_string(), "1.35 nm bearing 005° | 10m 01s ago");
+    }
+}
@@ -1,2 +1,3 @@
 pub(crate) mod mock_gpio;
+pub(crate) mod mock_i2c;
 pub(crate) mod mock_pwm;
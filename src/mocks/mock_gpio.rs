@@ -23,6 +23,22 @@ impl InputPin {
     }
 }
 
+pub struct OutputPin {
+    pin: u8,
+}
+
+impl OutputPin {
+    pub fn set_high(&mut self) {
+        set_mock_pin_level(self.pin, Level::High);
+    }
+
+    pub fn set_low(&mut self) {
+        set_mock_pin_level(self.pin, Level::Low);
+    }
+
+    pub fn set_reset_on_drop(&mut self, _reset_on_drop: bool) {}
+}
+
 pub struct Gpio;
 
 impl Gpio {
@@ -46,6 +62,11 @@ impl Pin {
         });
         InputPin { pin: self.pin }
     }
+
+    pub fn into_output_low(self) -> OutputPin {
+        set_mock_pin_level(self.pin, Level::Low);
+        OutputPin { pin: self.pin }
+    }
 }
 
 // test helper function to set pin levels
@@ -55,6 +76,11 @@ pub fn set_mock_pin_level(pin: u8, level: Level) {
     });
 }
 
+// test helper to inspect a pin level
+pub fn get_mock_pin_level(pin: u8) -> Level {
+    MOCK_PINS.with(|pins| *pins.borrow().get(&pin).unwrap_or(&Level::Low))
+}
+
 // test helper to reset all pins
 pub fn reset_mock_pins() {
     MOCK_PINS.with(|pins| {
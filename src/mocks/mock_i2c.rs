@@ -0,0 +1,99 @@
+// This file is only compiled during tests
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static MOCK_REGISTERS: RefCell<HashMap<(u16, u8), u8>> = RefCell::new(HashMap::new());
+    // 16-bit registers, for devices like the ADS1115 that have no byte registers
+    static MOCK_WORDS: RefCell<HashMap<(u16, u8), u16>> = RefCell::new(HashMap::new());
+}
+
+pub struct I2c {
+    address: u16,
+}
+
+impl I2c {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(I2c { address: 0 })
+    }
+
+    pub fn set_slave_address(&mut self, address: u16) -> Result<(), Box<dyn std::error::Error>> {
+        self.address = address;
+        Ok(())
+    }
+
+    pub fn smbus_read_byte(&self, register: u8) -> Result<u8, Box<dyn std::error::Error>> {
+        Ok(get_mock_register(self.address, register))
+    }
+
+    pub fn smbus_write_byte(
+        &self,
+        register: u8,
+        value: u8,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        set_mock_register(self.address, register, value);
+        Ok(())
+    }
+
+    pub fn smbus_read_word_swapped(&self, register: u8) -> Result<u16, Box<dyn std::error::Error>> {
+        Ok(get_mock_word(self.address, register))
+    }
+
+    pub fn smbus_write_word_swapped(
+        &self,
+        register: u8,
+        value: u16,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        set_mock_word(self.address, register, value);
+        Ok(())
+    }
+
+    /// Burst read starting at the register in `write_buffer[0]`; the auto-increment bit (0x80) is
+    /// masked off and consecutive registers are returned.
+    pub fn write_read(
+        &self,
+        write_buffer: &[u8],
+        read_buffer: &mut [u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let start = write_buffer.first().copied().unwrap_or(0) & 0x7F;
+        for (i, byte) in read_buffer.iter_mut().enumerate() {
+            *byte = get_mock_register(self.address, start + i as u8);
+        }
+        Ok(())
+    }
+}
+
+// test helper to preload a device register
+pub fn set_mock_register(address: u16, register: u8, value: u8) {
+    MOCK_REGISTERS.with(|regs| {
+        regs.borrow_mut().insert((address, register), value);
+    });
+}
+
+// test helper to inspect a device register
+pub fn get_mock_register(address: u16, register: u8) -> u8 {
+    MOCK_REGISTERS.with(|regs| *regs.borrow().get(&(address, register)).unwrap_or(&0))
+}
+
+// test helper to preload a 16-bit register
+pub fn set_mock_word(address: u16, register: u8, value: u16) {
+    MOCK_WORDS.with(|words| {
+        words.borrow_mut().insert((address, register), value);
+    });
+}
+
+// test helper to inspect a 16-bit register
+pub fn get_mock_word(address: u16, register: u8) -> u16 {
+    MOCK_WORDS.with(|words| *words.borrow().get(&(address, register)).unwrap_or(&0))
+}
+
+// test helper to reset all registers
+pub fn reset_mock_registers() {
+    MOCK_REGISTERS.with(|regs| {
+        regs.borrow_mut().clear();
+    });
+    MOCK_WORDS.with(|words| {
+        words.borrow_mut().clear();
+    });
+}
@@ -2,7 +2,8 @@
 
 use std::cell::RefCell;
 thread_local! {
-    static MOCK_PWM_DUTY: RefCell<f64> = const { RefCell::new(0.5) }
+    static MOCK_PWM_DUTY: RefCell<f64> = const { RefCell::new(0.5) };
+    static MOCK_PWM_ENABLED: RefCell<bool> = const { RefCell::new(true) };
 }
 
 pub struct Pwm {
@@ -11,6 +12,7 @@ pub struct Pwm {
 
 impl Pwm {
     pub fn new(pin: u8) -> Result<Self, Box<dyn std::error::Error>> {
+        MOCK_PWM_ENABLED.with(|enabled| *enabled.borrow_mut() = true);
         Ok(Pwm { pin })
     }
 
@@ -24,7 +26,14 @@ impl Pwm {
         );
     }
 
+    pub fn enable(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        MOCK_PWM_ENABLED.with(|enabled| *enabled.borrow_mut() = true);
+        println!("[Mock PWM {}] Enabled", self.pin);
+        Ok(())
+    }
+
     pub fn disable(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        MOCK_PWM_ENABLED.with(|enabled| *enabled.borrow_mut() = false);
         println!("[Mock PWM {}] Disabled", self.pin);
         Ok(())
     }
@@ -34,3 +43,8 @@ impl Pwm {
 pub fn get_mock_duty_cycle() -> f64 {
     MOCK_PWM_DUTY.with(|duty| *duty.borrow())
 }
+
+// Test helper
+pub fn is_mock_enabled() -> bool {
+    MOCK_PWM_ENABLED.with(|enabled| *enabled.borrow())
+}
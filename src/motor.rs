@@ -0,0 +1,309 @@
+//! DC motor rudder drive through an H-bridge: PWM sets the speed, a GPIO sets the direction.
+//!
+//! A motor has no position of its own, so the rudder angle sensor closes the loop: the motor
+//! runs towards the commanded angle, proportionally slower as it gets there, and stops inside
+//! the deadband. Starts, stops and reversals are ramped, the end stops are never driven into,
+//! and a motor that runs without moving the rudder is stopped for a while.
+
+use std::error::Error;
+use std::fmt;
+
+// Use rppal in production
+#[cfg(not(test))]
+use rppal::gpio::{Gpio, OutputPin};
+#[cfg(not(test))]
+use rppal::pwm::{Polarity, Pwm};
+
+#[cfg(test)]
+// This is only used in testing, not compiled in release.
+use crate::mocks::mock_gpio::{Gpio, OutputPin};
+#[cfg(test)]
+use crate::mocks::mock_pwm::Pwm;
+
+use crate::config::MotorSettings;
+
+/// Why the motor isn't driving the rudder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MotorFault {
+    /// No rudder angle to steer by.
+    NoFeedback,
+    /// The rudder didn't move while the motor was driven.
+    Stalled,
+}
+
+impl fmt::Display for MotorFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            MotorFault::NoFeedback => "no rudder feedback",
+            MotorFault::Stalled => "stalled",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+pub struct MotorDrive {
+    pwm: Pwm,
+    direction: OutputPin,
+    settings: MotorSettings,
+    /// Duty cycle applied, positive driving to starboard.
+    duty: f64,
+    last_angle: Option<f64>,
+    /// How long the motor has been driven without the rudder moving (seconds).
+    stalled_for: f64,
+    /// Time left before driving again after a stall (seconds).
+    retry_in: f64,
+    fault: Option<MotorFault>,
+}
+
+impl MotorDrive {
+    /// Set up the H-bridge with the speed on hardware PWM pin `pwm_pin`, stopped.
+    pub fn new(pwm_pin: u8, settings: &MotorSettings) -> Result<Self, Box<dyn Error>> {
+        #[cfg(not(test))]
+        let pwm = Pwm::with_frequency(
+            crate::pwm::pwm_channel(pwm_pin)?,
+            settings.frequency_hz,
+            0.0, // stopped
+            Polarity::Normal,
+            true, // enabled
+        )?;
+
+        #[cfg(test)]
+        let pwm = Pwm::new(pwm_pin)?;
+
+        let direction = Gpio::new()?.get(settings.direction_pin)?.into_output_low();
+
+        let mut motor = Self {
+            pwm,
+            direction,
+            settings: settings.clone(),
+            duty: 0.0,
+            last_angle: None,
+            stalled_for: 0.0,
+            retry_in: 0.0,
+            fault: None,
+        };
+        motor.apply()?;
+        Ok(motor)
+    }
+
+    /// Change the gains and limits. The pins and PWM frequency stay as created.
+    pub fn configure(&mut self, settings: &MotorSettings) {
+        self.settings = MotorSettings {
+            direction_pin: self.settings.direction_pin,
+            frequency_hz: self.settings.frequency_hz,
+            ..settings.clone()
+        };
+    }
+
+    /// Duty cycle applied, positive driving to starboard.
+    pub fn duty(&self) -> f64 {
+        self.duty
+    }
+
+    pub fn fault(&self) -> Option<MotorFault> {
+        self.fault
+    }
+
+    /// Run the motor towards `target` given the measured rudder `angle` (degrees), `dt`
+    /// seconds after the last update. The end stops are `max_angle` either side.
+    pub fn drive(
+        &mut self,
+        target: f64,
+        angle: Option<f64>,
+        max_angle: f64,
+        dt: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        let dt = if dt.is_finite() { dt.max(0.0) } else { 0.0 };
+        let Some(angle) = angle else {
+            self.set_fault(Some(MotorFault::NoFeedback));
+            self.last_angle = None;
+            return self.stop();
+        };
+        let last_angle = self.last_angle.replace(angle);
+
+        if self.retry_in > 0.0 {
+            self.retry_in -= dt;
+            return self.stop();
+        }
+
+        // did the rudder move with what was applied since the last update?
+        match last_angle {
+            Some(last) if dt > 0.0 && self.duty.abs() >= self.settings.min_duty => {
+                if (angle - last).abs() / dt < self.settings.stall_rate_dps {
+                    self.stalled_for += dt;
+                } else {
+                    self.stalled_for = 0.0;
+                }
+            }
+            _ => self.stalled_for = 0.0,
+        }
+        if self.stalled_for >= self.settings.stall_timeout_secs {
+            self.set_fault(Some(MotorFault::Stalled));
+            self.retry_in = self.settings.stall_retry_secs;
+            return self.stop();
+        }
+        self.set_fault(None);
+
+        let error = target - angle;
+        let mut wanted = if error.abs() < self.settings.deadband_deg {
+            0.0
+        } else {
+            error.signum()
+                * (self.settings.kp * error.abs())
+                    .clamp(self.settings.min_duty, self.settings.max_duty)
+        };
+        // never drive into the end stops
+        if (angle >= max_angle && wanted > 0.0) || (angle <= -max_angle && wanted < 0.0) {
+            wanted = 0.0;
+        }
+
+        // soft start, stop and reversal
+        let step = if self.settings.ramp_secs > 0.0 {
+            dt / self.settings.ramp_secs
+        } else {
+            f64::INFINITY
+        };
+        self.duty += (wanted - self.duty).clamp(-step, step);
+        self.apply()
+    }
+
+    /// Stop the motor at once, leaving the helm free.
+    pub fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.duty = 0.0;
+        self.stalled_for = 0.0;
+        self.apply()
+    }
+
+    /// Stop the motor and disable the PWM output.
+    pub fn disable(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stop()?;
+        self.pwm.disable()?;
+        Ok(())
+    }
+
+    fn apply(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.duty > 0.0 {
+            self.direction.set_high();
+        } else {
+            self.direction.set_low();
+        }
+
+        #[cfg(not(test))]
+        self.pwm.set_duty_cycle(self.duty.abs())?;
+
+        #[cfg(test)]
+        self.pwm.set_duty_cycle(self.duty.abs());
+
+        Ok(())
+    }
+
+    fn set_fault(&mut self, fault: Option<MotorFault>) {
+        if fault == self.fault {
+            return;
+        }
+        match (fault, self.fault) {
+            (Some(fault), _) => crate::warn!("  ⚠ Rudder motor {} - stopped", fault),
+            (None, Some(fault)) => {
+                crate::info!("  ✓ Rudder motor running again ({} cleared)", fault)
+            }
+            (None, None) => {}
+        }
+        self.fault = fault;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocks::mock_gpio::{self, Level};
+    use crate::mocks::mock_pwm;
+
+    fn motor(settings: &MotorSettings) -> MotorDrive {
+        mock_gpio::reset_mock_pins();
+        MotorDrive::new(18, settings).unwrap()
+    }
+
+    #[test]
+    fn test_drives_towards_target() {
+        let settings = MotorSettings::default();
+        let mut motor = motor(&settings);
+        assert_eq!(mock_pwm::get_mock_duty_cycle(), 0.0);
+
+        // ramped up: 0.1 s of a 0.5 s ramp
+        motor.drive(20.0, Some(0.0), 30.0, 0.1).unwrap();
+        assert!((motor.duty() - 0.2).abs() < 1e-9);
+        assert_eq!(
+            mock_gpio::get_mock_pin_level(settings.direction_pin),
+            Level::High
+        );
+        assert!((mock_pwm::get_mock_duty_cycle() - 0.2).abs() < 1e-9);
+
+        // 20° of error wants full speed, reached at the end of the ramp
+        for step in 1..=5 {
+            motor.drive(20.0, Some(step as f64), 30.0, 0.1).unwrap();
+        }
+        assert!((motor.duty() - 1.0).abs() < 1e-9);
+
+        // slower close in, never below the minimum duty
+        motor.drive(20.0, Some(19.0), 30.0, 0.5).unwrap();
+        assert!((motor.duty() - settings.min_duty).abs() < 1e-9);
+        // on target: ramps down and stops
+        motor.drive(20.0, Some(20.0), 30.0, 0.5).unwrap();
+        assert_eq!(motor.duty(), 0.0);
+
+        // the other way
+        motor.drive(-20.0, Some(20.0), 30.0, 0.5).unwrap();
+        assert_eq!(motor.duty(), -1.0);
+        assert_eq!(
+            mock_gpio::get_mock_pin_level(settings.direction_pin),
+            Level::Low
+        );
+        assert_eq!(motor.fault(), None);
+    }
+
+    #[test]
+    fn test_end_stops_and_feedback_loss() {
+        let settings = MotorSettings {
+            ramp_secs: 0.0,
+            ..MotorSettings::default()
+        };
+        let mut motor = motor(&settings);
+
+        // the commanded angle is past the end stop the rudder is already at
+        motor.drive(40.0, Some(30.0), 30.0, 0.1).unwrap();
+        assert_eq!(motor.duty(), 0.0);
+        motor.drive(0.0, Some(30.0), 30.0, 0.1).unwrap();
+        assert!(motor.duty() < 0.0);
+
+        // no rudder angle: stop at once, ramp or not
+        motor.drive(0.0, None, 30.0, 0.1).unwrap();
+        assert_eq!(motor.duty(), 0.0);
+        assert_eq!(motor.fault(), Some(MotorFault::NoFeedback));
+    }
+
+    #[test]
+    fn test_stall() {
+        let settings = MotorSettings {
+            ramp_secs: 0.0,
+            ..MotorSettings::default()
+        };
+        let mut motor = motor(&settings);
+
+        // driven hard, but the rudder stays at 5°
+        for _ in 0..5 {
+            assert_eq!(motor.fault(), None);
+            motor.drive(20.0, Some(5.0), 30.0, 0.25).unwrap();
+        }
+        assert_eq!(motor.fault(), Some(MotorFault::Stalled));
+        assert_eq!(motor.duty(), 0.0);
+
+        // rests for the retry time, then tries again
+        for _ in 0..5 {
+            motor.drive(20.0, Some(5.0), 30.0, 1.0).unwrap();
+            assert_eq!(motor.duty(), 0.0);
+        }
+        motor.drive(20.0, Some(5.0), 30.0, 0.25).unwrap();
+        assert_eq!(motor.duty(), 1.0);
+        assert_eq!(motor.fault(), None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Raw NMEA capture and replay.
+//!
+//! Every line read from the receiver is written exactly as received, valid or not, prefixed with
+//! its receive time and length:
+//!
+//! ```text
+//! 2024-06-01T12:00:00.123456Z 36 $GPGLL,4807.038,N,01131.000,E*1D\r\n
+//! ```
+//!
+//! The length counts the raw bytes including their line ending, so lines with odd endings or
+//! invalid UTF-8 play back byte-for-byte.
+
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// One captured line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedLine {
+    pub time: DateTime<Utc>,
+    /// Raw bytes as received, including the line ending.
+    pub raw: Vec<u8>,
+}
+
+/// Handle to the capture tee. Clones share the same file, so the GPS thread can record while the
+/// control loop starts and stops the capture.
+#[derive(Clone, Default)]
+pub struct NmeaCapture {
+    file: Arc<Mutex<Option<(PathBuf, File)>>>,
+}
+
+impl NmeaCapture {
+    /// An inactive capture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start capturing to `path`, appending if it exists. Replaces a running capture.
+    pub fn start(&self, path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        if let Ok(mut current) = self.file.lock() {
+            *current = Some((path.to_path_buf(), file));
+        }
+        Ok(())
+    }
+
+    /// Start capturing to a new timestamped file (`nmea-YYYYMMDD-HHMMSS.nmea`) in `directory`.
+    pub fn start_in(&self, directory: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(format!("nmea-{}.nmea", Utc::now().format("%Y%m%d-%H%M%S")));
+        self.start(&path)?;
+        Ok(path)
+    }
+
+    /// Stop capturing. Returns the file that was being written, if any.
+    pub fn stop(&self) -> Option<PathBuf> {
+        let (path, file) = self.file.lock().ok()?.take()?;
+        if let Err(e) = file.sync_all() {
+            crate::warn!("NMEA capture sync failed: {}", e);
+        }
+        Some(path)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.file.lock().map(|file| file.is_some()).unwrap_or(false)
+    }
+
+    /// File currently being written.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.file
+            .lock()
+            .ok()?
+            .as_ref()
+            .map(|(path, _)| path.clone())
+    }
+
+    /// Record a raw line stamped with the current time. Does nothing when inactive; a write
+    /// error stops the capture rather than the GPS reader.
+    pub fn record(&self, raw: &[u8]) {
+        self.record_at(Utc::now(), raw);
+    }
+
+    fn record_at(&self, time: DateTime<Utc>, raw: &[u8]) {
+        let Ok(mut current) = self.file.lock() else {
+            return;
+        };
+        let Some((path, file)) = current.as_mut() else {
+            return;
+        };
+
+        let mut entry = format!(
+            "{} {} ",
+            time.to_rfc3339_opts(SecondsFormat::Micros, true),
+            raw.len()
+        )
+        .into_bytes();
+        entry.extend_from_slice(raw);
+
+        if let Err(e) = file.write_all(&entry) {
+            crate::warn!("NMEA capture to {} stopped: {}", path.display(), e);
+            *current = None;
+        }
+    }
+}
+
+/// Reads a capture file back line by line.
+pub struct CaptureReader<R> {
+    reader: R,
+    entry: usize,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> CaptureReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, entry: 0 }
+    }
+
+    fn read_entry(&mut self) -> Result<Option<CapturedLine>, Box<dyn Error>> {
+        let time = match self.read_field()? {
+            Some(time) => time,
+            None => return Ok(None),
+        };
+        let time = DateTime::parse_from_rfc3339(&time)
+            .map_err(|_| format!("invalid time '{}'", time))?
+            .with_timezone(&Utc);
+
+        let len = self.read_field()?.ok_or("missing length")?;
+        let len: usize = len
+            .parse()
+            .map_err(|_| format!("invalid length '{}'", len))?;
+
+        let mut raw = vec![0; len];
+        self.reader
+            .read_exact(&mut raw)
+            .map_err(|_| "truncated line")?;
+
+        Ok(Some(CapturedLine { time, raw }))
+    }
+
+    /// Read up to the next space. `None` at a clean end of file.
+    fn read_field(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let mut field = Vec::new();
+        self.reader.read_until(b' ', &mut field)?;
+        if field.is_empty() {
+            return Ok(None);
+        }
+        if field.pop() != Some(b' ') {
+            return Err("truncated header".into());
+        }
+        Ok(Some(String::from_utf8(field)?))
+    }
+}
+
+impl<R: BufRead> Iterator for CaptureReader<R> {
+    type Item = Result<CapturedLine, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entry += 1;
+        self.read_entry()
+            .map_err(|e| format!("entry {}: {}", self.entry, e).into())
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_capture_round_trip() {
+        let path = std::env::temp_dir().join(format!("geo-rs-capture-{}.nmea", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let capture = NmeaCapture::new();
+        capture.record(b"ignored while inactive\n");
+
+        capture.start(&path).unwrap();
+        assert!(capture.is_active());
+
+        let time = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let lines: [&[u8]; 4] = [
+            b"$GPGLL,4807.038,N,01131.000,E*1D\r\n",
+            b"garbage \xff\xfe with spaces\n",
+            b"\r\n",
+            b"$GPGGA,partial",
+        ];
+        for line in lines {
+            capture.record_at(time, line);
+        }
+
+        assert_eq!(capture.stop(), Some(path.clone()));
+        assert!(!capture.is_active());
+        capture.record(b"ignored after stop\n");
+
+        let replayed: Vec<CapturedLine> = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(replayed.len(), lines.len());
+        for (captured, line) in replayed.iter().zip(lines) {
+            assert_eq!(captured.raw, line);
+            assert_eq!(captured.time, time);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_capture() {
+        let data = b"2024-06-01T12:00:00.000000Z 40 $GPGLL\r\n";
+        let mut reader = CaptureReader::new(&data[..]);
+        let err = reader.next().unwrap().unwrap_err().to_string();
+        assert!(err.starts_with("entry 1:"), "{}", err);
+
+        let mut empty = CaptureReader::new(&b""[..]);
+        assert!(empty.next().is_none());
+    }
+}
@@ -0,0 +1,287 @@
+//! Live parameters: the configuration in use, reloadable and tunable while running.
+//!
+//! Changes from a reload, a file change or the control socket are validated and staged, then
+//! swapped in as a whole by the control loop between cycles with [`Parameters::apply_pending`],
+//! so a cycle never sees half of a change. Readers take a snapshot with [`Parameters::current`]
+//! and can cheaply check [`Parameters::generation`] to see whether it changed.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::config::{Change, Config};
+
+/// Settings that are only read at startup. They can still be changed, but the change is only
+/// picked up after a restart.
+const RESTART_REQUIRED: &[&str] = &[
+    "gps.serial_device",
+    "gpio.toggle_left",
+    "gpio.toggle_right",
+    "gpio.engage_button",
+    "gpio.mob_button_enabled",
+    "gpio.mob_button",
+    "servo.pwm_pin",
+    "servo.actuator",
+    "servo.frequency_hz",
+    "motor.direction_pin",
+    "motor.frequency_hz",
+    "rudder.enabled",
+    "rudder.i2c_address",
+    "rudder.channel",
+    "alarm.output_enabled",
+    "alarm.output_pin",
+    "watchdog.",
+    "track_log.",
+    "simulator.",
+    "control.",
+];
+
+/// Whether a change to `key` only takes effect after a restart.
+pub fn needs_restart(key: &str) -> bool {
+    RESTART_REQUIRED
+        .iter()
+        .any(|prefix| key == *prefix || (prefix.ends_with('.') && key.starts_with(prefix)))
+}
+
+/// Shared handle to the live configuration. Clones refer to the same parameters.
+#[derive(Clone)]
+pub struct Parameters {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// File reloads read from, `None` for defaults and environment only.
+    path: Option<PathBuf>,
+    /// Command line settings, re-applied on top of every reload.
+    overrides: Vec<(String, String)>,
+    active: RwLock<Arc<Config>>,
+    pending: Mutex<Option<Config>>,
+    generation: AtomicU64,
+}
+
+impl Parameters {
+    pub fn new(config: Config, path: Option<PathBuf>) -> Self {
+        Self::with_overrides(config, path, Vec::new())
+    }
+
+    /// Parameters with `section.setting` overrides (e.g. from the command line) that survive a
+    /// reload. They must already be applied to `config`.
+    pub fn with_overrides(
+        config: Config,
+        path: Option<PathBuf>,
+        overrides: Vec<(String, String)>,
+    ) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                path,
+                overrides,
+                active: RwLock::new(Arc::new(config)),
+                pending: Mutex::new(None),
+                generation: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The configuration in use.
+    pub fn current(&self) -> Arc<Config> {
+        Arc::clone(&self.shared.active.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Incremented each time changes are applied.
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.load(Ordering::Acquire)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.shared.path.as_deref()
+    }
+
+    /// Change one setting. The new configuration is validated, then staged for the next cycle.
+    pub fn set(&self, key: &str, value: &str) -> Result<Change, Box<dyn Error>> {
+        let mut pending = self.lock_pending();
+        let old = pending.clone().unwrap_or_else(|| (*self.current()).clone());
+
+        let mut new = old.clone();
+        new.set(key, value)?;
+        new.validate()?;
+
+        let (Some(old_value), Some(new_value)) = (old.get(key), new.get(key)) else {
+            return Err(format!("{}: no such setting", key).into());
+        };
+        *pending = Some(new);
+        let change = Change {
+            key: key.to_string(),
+            old: old_value,
+            new: new_value,
+        };
+        Ok(change)
+    }
+
+    /// Read the configuration file (and environment) again and stage it for the next cycle. The
+    /// vessel profile selected at startup stays selected. Settings changed with
+    /// [`Parameters::set`] are replaced. On error nothing changes.
+    pub fn reload(&self) -> Result<Vec<Change>, Box<dyn Error>> {
+        let profile = self.current().profile.clone();
+        let mut new = Config::load(self.path(), profile.as_deref())?;
+        for (key, value) in &self.shared.overrides {
+            new.set(key, value)?;
+        }
+        new.validate()?;
+
+        let mut pending = self.lock_pending();
+        let old = pending.clone().unwrap_or_else(|| (*self.current()).clone());
+        let changes = old.changes(&new);
+        if !changes.is_empty() {
+            *pending = Some(new);
+        }
+        Ok(changes)
+    }
+
+    /// Swap in the staged changes, if any, and log them. Called by the control loop between
+    /// cycles.
+    pub fn apply_pending(&self) -> Vec<Change> {
+        let Some(new) = self.lock_pending().take() else {
+            return Vec::new();
+        };
+
+        let mut active = self
+            .shared
+            .active
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        let changes = active.changes(&new);
+        *active = Arc::new(new);
+        self.shared.generation.fetch_add(1, Ordering::AcqRel);
+        drop(active);
+
+        for change in &changes {
+            if needs_restart(&change.key) {
+                crate::warn!("  ⚙ {} (takes effect after a restart)", change);
+            } else {
+                crate::info!("  ⚙ {}", change);
+            }
+        }
+        changes
+    }
+
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, Option<Config>> {
+        self.shared
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Reload the configuration file whenever its modification time changes. A file that doesn't
+/// parse or validate is reported and the previous settings stay in use.
+pub fn watch_config_file(parameters: Parameters, interval: Duration) {
+    let Some(path) = parameters.path().map(Path::to_path_buf) else {
+        return;
+    };
+    let modified = |path: &Path| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+
+    thread::spawn(move || {
+        let mut last_modified = modified(&path);
+        loop {
+            thread::sleep(interval);
+
+            let current = modified(&path);
+            if current.is_none() || current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match parameters.reload() {
+                Ok(changes) if changes.is_empty() => {
+                    crate::debug!("{} changed, no settings differ", path.display())
+                }
+                Ok(_) => crate::info!("{} changed, reloading", path.display()),
+                Err(e) => crate::warn!("{} not reloaded: {}", path.display(), e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_is_staged_until_applied() {
+        let parameters = Parameters::new(Config::default(), None);
+        let before = parameters.generation();
+
+        let change = parameters.set("pid.kp", "2.5").unwrap();
+        assert_eq!(change.key, "pid.kp");
+        assert_eq!(change.new, toml::Value::Float(2.5));
+
+        // staged changes accumulate and are applied together
+        parameters.set("pid.deadband", "3").unwrap();
+        assert_eq!(parameters.current().pid.kp, Config::default().pid.kp);
+
+        let changes = parameters.apply_pending();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(parameters.current().pid.kp, 2.5);
+        assert_eq!(parameters.current().pid.deadband, 3.0);
+        assert_eq!(parameters.generation(), before + 1);
+
+        assert!(parameters.apply_pending().is_empty());
+        assert_eq!(parameters.generation(), before + 1);
+    }
+
+    #[test]
+    fn test_invalid_set_is_rejected() {
+        let parameters = Parameters::new(Config::default(), None);
+
+        assert!(parameters.set("servo.min_pulse_us", "2500").is_err());
+        assert!(parameters.set("pid.kp", "fast").is_err());
+        assert!(parameters.set("pid.kq", "1").is_err());
+        assert!(parameters.apply_pending().is_empty());
+    }
+
+    #[test]
+    fn test_reload_from_file() {
+        let dir = std::env::temp_dir().join(format!("geo-rs-params-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("geo-rs.toml");
+        fs::write(&path, "[pid]\nkp = 1.5\n").unwrap();
+
+        let mut config = Config::load(Some(&path), None).unwrap();
+        config.set("gps.serial_device", "/dev/ttyUSB0").unwrap();
+        let parameters = Parameters::with_overrides(
+            config,
+            Some(path.clone()),
+            vec![("gps.serial_device".to_string(), "/dev/ttyUSB0".to_string())],
+        );
+
+        fs::write(&path, "[pid]\nkp = 2.0\n").unwrap();
+        let changes = parameters.reload().unwrap();
+        assert_eq!(changes.len(), 1, "{:?}", changes);
+        parameters.apply_pending();
+        assert_eq!(parameters.current().pid.kp, 2.0);
+        assert_eq!(
+            parameters.current().gps.serial_device,
+            Path::new("/dev/ttyUSB0")
+        );
+
+        // a broken file keeps the current settings
+        fs::write(&path, "[servo]\nmin_pulse_us = 2500.0\n").unwrap();
+        assert!(parameters.reload().is_err());
+        assert!(parameters.apply_pending().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restart_required() {
+        assert!(needs_restart("servo.pwm_pin"));
+        assert!(needs_restart("track_log.directory"));
+        assert!(!needs_restart("servo.max_rate"));
+        assert!(!needs_restart("pid.kp"));
+    }
+}
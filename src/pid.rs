@@ -0,0 +1,481 @@
+//! PID controller used by the steering loops.
+//!
+//! Compared to the textbook form it:
+//! - keeps the integral term within `integral_limit` and stops it winding up while the output
+//!   is saturated (clamping or back-calculation, see [`AntiWindup`])
+//! - differentiates the measurement rather than the error, so a setpoint change (a toggle press,
+//!   a new leg) doesn't kick the output, and low-pass filters it
+//! - weights the setpoint in the proportional term (`setpoint_weight`, b): P acts on
+//!   `b·setpoint - measurement`, so with b below 1 a setpoint step only steps the output by that
+//!   share and the integral does the rest. The setpoint is counted from where it was when the
+//!   measurement last settled inside the deadband, so the integral doesn't carry the offset of
+//!   every course change.
+//! - stores the integral in output units, so changing the gains doesn't make the output jump
+//! - can be reset to a given output for a bumpless start, which the integral holds: without
+//!   integral action (`ki` = 0) the output goes back to the P and D terms on the next update
+//! - ignores updates with a zero or negative time step instead of dividing by zero
+
+use std::fmt;
+
+use crate::config::{AntiWindup, GainPoint, PidSettings};
+
+/// Proportional, integral and derivative gains.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+}
+
+impl Gains {
+    pub fn of(settings: &PidSettings) -> Self {
+        Self {
+            kp: settings.kp,
+            ki: settings.ki,
+            kd: settings.kd,
+        }
+    }
+
+    /// `settings` with these gains.
+    pub fn apply_to(&self, settings: &PidSettings) -> PidSettings {
+        PidSettings {
+            kp: self.kp,
+            ki: self.ki,
+            kd: self.kd,
+            ..settings.clone()
+        }
+    }
+}
+
+impl fmt::Display for Gains {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "kp {:.2} ki {:.3} kd {:.2}", self.kp, self.ki, self.kd)
+    }
+}
+
+/// Gains for `speed_knots` from a schedule sorted by speed: interpolated linearly between
+/// points, the nearest point's beyond the ends. `None` for an empty schedule.
+pub fn scheduled_gains(points: &[GainPoint], speed_knots: f64) -> Option<Gains> {
+    let gains = |point: &GainPoint| Gains {
+        kp: point.kp,
+        ki: point.ki,
+        kd: point.kd,
+    };
+    let first = points.first()?;
+    let last = points.last()?;
+    if speed_knots <= first.speed_knots {
+        return Some(gains(first));
+    }
+    if speed_knots >= last.speed_knots {
+        return Some(gains(last));
+    }
+
+    let upper = points.iter().position(|p| p.speed_knots > speed_knots)?;
+    let (a, b) = (&points[upper - 1], &points[upper]);
+    let t = (speed_knots - a.speed_knots) / (b.speed_knots - a.speed_knots);
+    let lerp = |from: f64, to: f64| from + (to - from) * t;
+    Some(Gains {
+        kp: lerp(a.kp, b.kp),
+        ki: lerp(a.ki, b.ki),
+        kd: lerp(a.kd, b.kd),
+    })
+}
+
+pub struct Pid {
+    settings: PidSettings,
+    output_limit: f64,
+    /// Measurements and setpoints are headings: differences wrap at ±180°.
+    circular: bool,
+    /// Integral term, in output units.
+    integral: f64,
+    /// Filtered rate of change of the measurement (units per second).
+    derivative: f64,
+    last_measurement: Option<f64>,
+    /// Setpoint the weighted part of the proportional term is counted from.
+    setpoint_reference: Option<f64>,
+    /// Weighted error the proportional term acted on last.
+    last_error: f64,
+    output: f64,
+}
+
+impl Pid {
+    /// Controller for a linear quantity, output limited to ±`output_limit`.
+    pub fn new(settings: &PidSettings, output_limit: f64) -> Self {
+        Self {
+            settings: settings.clone(),
+            output_limit,
+            circular: false,
+            integral: 0.0,
+            derivative: 0.0,
+            last_measurement: None,
+            setpoint_reference: None,
+            last_error: 0.0,
+            output: 0.0,
+        }
+    }
+
+    /// Controller for a heading in degrees, where 359° and 1° are 2° apart.
+    pub fn angular(settings: &PidSettings, output_limit: f64) -> Self {
+        Self {
+            circular: true,
+            ..Self::new(settings, output_limit)
+        }
+    }
+
+    /// Use new gains and limits. A change of `kp` is offset in the integral so the output
+    /// continues from where it was.
+    pub fn configure(&mut self, settings: &PidSettings, output_limit: f64) {
+        if settings.ki > 0.0 {
+            let offset = (self.settings.kp - settings.kp) * self.last_error;
+            self.integral =
+                (self.integral + offset).clamp(-settings.integral_limit, settings.integral_limit);
+        }
+        self.settings = settings.clone();
+        self.output_limit = output_limit;
+    }
+
+    pub fn settings(&self) -> &PidSettings {
+        &self.settings
+    }
+
+    /// Last output.
+    pub fn output(&self) -> f64 {
+        self.output
+    }
+
+    /// Integral term (output units).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Forget the history and continue from `output`, e.g. the rudder angle already applied
+    /// when the autopilot engages. The integral holds it, so this is only bumpless with integral
+    /// action: with `ki` = 0 the next update drops back to the P and D terms. The derivative and
+    /// the setpoint weighting start from the next update.
+    pub fn reset(&mut self, output: f64) {
+        self.integral = if self.settings.ki > 0.0 {
+            output.clamp(-self.settings.integral_limit, self.settings.integral_limit)
+        } else {
+            0.0
+        };
+        self.derivative = 0.0;
+        self.last_measurement = None;
+        self.setpoint_reference = None;
+        self.last_error = 0.0;
+        self.output = output.clamp(-self.output_limit, self.output_limit);
+    }
+
+    /// Update with a new measurement `dt` seconds after the last one and return the output,
+    /// which acts on `setpoint - measurement`. With no time elapsed the previous output is
+    /// returned unchanged.
+    ///
+    /// Inside the deadband the output is the integral, which holds, so a standing trim (weather
+    /// helm) stays on. The measurement is still tracked so the derivative is current when the
+    /// error leaves the deadband, and the setpoint becomes the new reference for the weighting:
+    /// the integral gives back what it took up for the last setpoint change.
+    pub fn update(&mut self, setpoint: f64, measurement: f64, dt: f64) -> f64 {
+        if !(dt > 0.0 && dt.is_finite()) {
+            return self.output;
+        }
+
+        let error = self.difference(setpoint, measurement);
+        // the share of the setpoint change the proportional term leaves to the integral
+        let reference = *self.setpoint_reference.get_or_insert(setpoint);
+        let held_back =
+            (1.0 - self.settings.setpoint_weight) * self.difference(setpoint, reference);
+
+        // derivative on measurement, through a first order low-pass filter
+        if let Some(last) = self.last_measurement {
+            let rate = self.difference(measurement, last) / dt;
+            let alpha = dt / (self.settings.derivative_filter_secs + dt);
+            self.derivative += alpha * (rate - self.derivative);
+        }
+        self.last_measurement = Some(measurement);
+
+        let PidSettings { kp, ki, kd, .. } = self.settings;
+        if error.abs() < self.settings.deadband {
+            // settled on the setpoint: the standing trim is what the integral has beyond the
+            // share of the setpoint change it took up
+            self.integral = (self.integral - kp * held_back)
+                .clamp(-self.settings.integral_limit, self.settings.integral_limit);
+            self.setpoint_reference = Some(setpoint);
+            self.last_error = error;
+            self.output = self.integral.clamp(-self.output_limit, self.output_limit);
+            return self.output;
+        }
+
+        let weighted_error = error - held_back;
+        self.last_error = weighted_error;
+        let limit = self.output_limit;
+        let p_term = kp * weighted_error;
+        let d_term = -kd * self.derivative;
+        // the integral carries the share of the setpoint change on top of the trim
+        let integral_limit = self.settings.integral_limit + (kp * held_back).abs();
+
+        if ki > 0.0 {
+            let step = ki * error * dt;
+            let unsaturated = p_term + self.integral + step + d_term;
+            let saturated = unsaturated.clamp(-limit, limit);
+
+            match self.settings.anti_windup {
+                AntiWindup::Clamp => {
+                    // only integrate when it doesn't push further into the limit
+                    if saturated == unsaturated || step.signum() != unsaturated.signum() {
+                        self.integral += step;
+                    }
+                }
+                AntiWindup::BackCalculation => {
+                    let tracking_secs = self.tracking_time();
+                    self.integral +=
+                        step + (saturated - unsaturated) * (dt / tracking_secs).min(1.0);
+                }
+            }
+            self.integral = self.integral.clamp(-integral_limit, integral_limit);
+        } else {
+            self.integral = 0.0;
+        }
+
+        self.output = (p_term + self.integral + d_term).clamp(-limit, limit);
+        self.output
+    }
+
+    /// Time constant the integral tracks the output limit with in back-calculation: the
+    /// geometric mean of the integral and derivative times, or the integral time without D.
+    fn tracking_time(&self) -> f64 {
+        let PidSettings { kp, ki, kd, .. } = self.settings;
+        if kp <= 0.0 {
+            return 1.0 / ki;
+        }
+        let integral_time = kp / ki;
+        if kd > 0.0 {
+            (integral_time * kd / kp).sqrt()
+        } else {
+            integral_time
+        }
+    }
+
+    /// `a - b`, the short way round for headings.
+    fn difference(&self, a: f64, b: f64) -> f64 {
+        let difference = a - b;
+        if self.circular {
+            (difference + 180.0).rem_euclid(360.0) - 180.0
+        } else {
+            difference
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(kp: f64, ki: f64, kd: f64) -> PidSettings {
+        PidSettings {
+            kp,
+            ki,
+            kd,
+            deadband: 0.0,
+            derivative_filter_secs: 0.0,
+            ..PidSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_zero_dt_keeps_output() {
+        let mut pid = Pid::new(&settings(1.0, 0.5, 0.5), 30.0);
+        let output = pid.update(10.0, 0.0, 0.1);
+        assert!(output > 0.0);
+
+        assert_eq!(pid.update(10.0, 5.0, 0.0), output);
+        assert_eq!(pid.update(10.0, 5.0, -1.0), output);
+        assert_eq!(pid.update(10.0, 5.0, f64::NAN), output);
+        assert!(pid.update(10.0, 5.0, 0.1).is_finite());
+    }
+
+    #[test]
+    fn test_no_derivative_kick_on_setpoint_change() {
+        let mut pid = Pid::new(&settings(0.0, 0.0, 2.0), 30.0);
+        pid.update(0.0, 0.0, 0.1);
+        // a setpoint step with a steady measurement leaves the D term at zero
+        assert_eq!(pid.update(20.0, 0.0, 0.1), 0.0);
+        // a moving measurement is opposed
+        assert!(pid.update(20.0, 1.0, 0.1) < 0.0);
+    }
+
+    #[test]
+    fn test_derivative_filter() {
+        let unfiltered = settings(0.0, 0.0, 1.0);
+        let filtered = PidSettings {
+            derivative_filter_secs: 1.0,
+            ..unfiltered.clone()
+        };
+        let mut fast = Pid::new(&unfiltered, 100.0);
+        let mut slow = Pid::new(&filtered, 100.0);
+        for pid in [&mut fast, &mut slow] {
+            pid.update(0.0, 0.0, 0.1);
+        }
+
+        // a one-sample spike of 5 units moves the filtered output far less
+        let fast_spike = fast.update(0.0, 5.0, 0.1).abs();
+        let slow_spike = slow.update(0.0, 5.0, 0.1).abs();
+        assert_eq!(fast_spike, 50.0);
+        assert!(slow_spike < fast_spike / 5.0, "{}", slow_spike);
+    }
+
+    #[test]
+    fn test_anti_windup() {
+        for anti_windup in [AntiWindup::Clamp, AntiWindup::BackCalculation] {
+            let settings = PidSettings {
+                anti_windup,
+                integral_limit: 100.0,
+                ..settings(1.0, 1.0, 0.0)
+            };
+            let mut pid = Pid::new(&settings, 10.0);
+
+            // held far from the setpoint with the output saturated for a minute
+            for _ in 0..600 {
+                assert_eq!(pid.update(50.0, 0.0, 0.1), 10.0);
+            }
+            assert!(
+                pid.integral() <= 10.0,
+                "{:?}: {}",
+                anti_windup,
+                pid.integral()
+            );
+
+            // once past the setpoint the output reverses within a few seconds
+            let mut steps = 0;
+            while pid.update(50.0, 52.0, 0.1) > 0.0 {
+                steps += 1;
+                assert!(steps < 60, "{:?} wound up", anti_windup);
+            }
+        }
+    }
+
+    #[test]
+    fn test_integral_limit() {
+        let settings = PidSettings {
+            integral_limit: 5.0,
+            ..settings(0.0, 1.0, 0.0)
+        };
+        let mut pid = Pid::new(&settings, 30.0);
+        for _ in 0..100 {
+            pid.update(3.0, 0.0, 0.1);
+        }
+        assert_eq!(pid.integral(), 5.0);
+        assert_eq!(pid.output(), 5.0);
+    }
+
+    #[test]
+    fn test_deadband_tracks_measurement() {
+        let settings = PidSettings {
+            deadband: 2.0,
+            ..settings(0.0, 0.0, 1.0)
+        };
+        let mut pid = Pid::new(&settings, 100.0);
+
+        pid.update(0.0, 0.0, 0.1);
+        assert_eq!(pid.update(0.0, 1.0, 0.1), 0.0);
+        // the derivative is from 1 → 3 in this step, not 0 → 3 across the deadband
+        let output = pid.update(0.0, 3.0, 0.1);
+        assert!((output + 20.0).abs() < 1e-9, "{}", output);
+    }
+
+    #[test]
+    fn test_deadband_keeps_trim() {
+        let settings = PidSettings {
+            deadband: 2.0,
+            ..settings(1.0, 1.0, 0.0)
+        };
+        let mut pid = Pid::new(&settings, 5.0);
+
+        // weather helm: 4° of rudder to hold the heading
+        pid.reset(4.0);
+        for _ in 0..10 {
+            assert!((pid.update(0.0, 1.0, 0.1) - 4.0).abs() < 1e-9);
+        }
+        assert_eq!(pid.integral(), 4.0);
+
+        // held within the output limit
+        pid.configure(&settings, 3.0);
+        assert_eq!(pid.update(0.0, -1.0, 0.1), 3.0);
+    }
+
+    #[test]
+    fn test_bumpless_reset_and_gain_change() {
+        let mut pid = Pid::new(&settings(1.0, 0.1, 0.0), 30.0);
+
+        // engaging with the rudder already at 8° continues from there
+        pid.reset(8.0);
+        let output = pid.update(0.0, 0.0, 0.1);
+        assert!((output - 8.0).abs() < 1e-9, "{}", output);
+
+        // a gain change with an error standing doesn't step the output
+        let before = pid.update(5.0, 0.0, 0.1);
+        pid.configure(&settings(2.0, 0.1, 0.0), 30.0);
+        let after = pid.update(5.0, 0.0, 0.1);
+        assert!((after - before).abs() < 0.1, "{} → {}", before, after);
+    }
+
+    #[test]
+    fn test_setpoint_weight() {
+        let weighted = PidSettings {
+            setpoint_weight: 0.0,
+            deadband: 1.0,
+            ..settings(1.0, 0.5, 0.0)
+        };
+        let mut pid = Pid::angular(&weighted, 30.0);
+        let mut heading = 90.0;
+        assert_eq!(pid.update(90.0, heading, 0.1), 0.0);
+
+        // a 10° toggle press doesn't step the rudder, only the integral starts
+        let output = pid.update(100.0, heading, 0.1);
+        assert!((output - 0.5).abs() < 1e-9, "{}", output);
+        // with the full weight it would
+        let mut full = Pid::angular(&settings(1.0, 0.5, 0.0), 30.0);
+        full.update(90.0, heading, 0.1);
+        assert!(full.update(100.0, heading, 0.1) > 10.0);
+
+        // a boat turning at the rudder angle in °/s gets there without overshooting much
+        let mut furthest: f64 = 0.0;
+        for _ in 0..600 {
+            heading += pid.update(100.0, heading, 0.1) * 0.1;
+            furthest = furthest.max(heading);
+        }
+        assert!((heading - 100.0).abs() < 1.0, "{}", heading);
+        assert!(furthest < 103.0, "{}", furthest);
+        // and the integral gave back the course change once settled: no trim is needed
+        assert!(pid.integral().abs() < 1.0, "{}", pid.integral());
+    }
+
+    #[test]
+    fn test_gain_schedule() {
+        let point = |speed_knots, kp, ki, kd| GainPoint {
+            speed_knots,
+            kp,
+            ki,
+            kd,
+        };
+        let points = [point(2.0, 2.0, 0.1, 1.0), point(6.0, 1.0, 0.05, 0.5)];
+
+        assert_eq!(scheduled_gains(&[], 4.0), None);
+        // held beyond the ends
+        assert_eq!(scheduled_gains(&points, 0.0).unwrap().kp, 2.0);
+        assert_eq!(scheduled_gains(&points, 9.0).unwrap().kp, 1.0);
+        // interpolated between
+        let gains = scheduled_gains(&points, 5.0).unwrap();
+        assert!((gains.kp - 1.25).abs() < 1e-9);
+        assert!((gains.ki - 0.0625).abs() < 1e-9);
+        assert!((gains.kd - 0.625).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_angular_wraparound() {
+        let mut pid = Pid::angular(&settings(1.0, 0.0, 1.0), 90.0);
+        // 5° is 10° clockwise of 355°
+        assert_eq!(pid.update(5.0, 355.0, 0.1), 10.0);
+        // crossing north is a 2° change, not 358°
+        let output = pid.update(5.0, 357.0, 0.1);
+        assert!((output - (8.0 - 20.0)).abs() < 1e-9, "{}", output);
+    }
+}
@@ -3,7 +3,7 @@ use std::fmt;
 /// Earth's radius in meters.
 const EARTH_RADIUS: f64 = 6371000.0;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Position {
     pub latitude: f64,
     pub longitude: f64,
@@ -55,6 +55,39 @@ impl Position {
         EARTH_RADIUS * c
     }
 
+    /// Calculate the cross-track distance from this position to the great circle path from
+    /// `start` to `end`. Returns meters, positive when this position is right of the track
+    /// (looking from `start` towards `end`), negative when left.
+    /// See: [Cross-track distance](https://www.movable-type.co.uk/scripts/latlong.html#cross-track)
+    pub fn cross_track_distance(&self, start: &Position, end: &Position) -> f64 {
+        let angular_distance = start.distance_to(self) / EARTH_RADIUS;
+        let bearing_to_self = start.heading_to(self).to_radians();
+        let bearing_to_end = start.heading_to(end).to_radians();
+
+        (angular_distance.sin() * (bearing_to_self - bearing_to_end).sin()).asin() * EARTH_RADIUS
+    }
+
+    /// Calculate the along-track distance: how far along the path from `start` to `end` the
+    /// point closest to this position lies. Returns meters, negative when behind `start`.
+    pub fn along_track_distance(&self, start: &Position, end: &Position) -> f64 {
+        let angular_distance = start.distance_to(self) / EARTH_RADIUS;
+        let bearing_to_self = start.heading_to(self).to_radians();
+        let bearing_to_end = start.heading_to(end).to_radians();
+        let cross_track = self.cross_track_distance(start, end) / EARTH_RADIUS;
+
+        let along_track = (angular_distance.cos() / cross_track.cos())
+            .clamp(-1.0, 1.0)
+            .acos()
+            * EARTH_RADIUS;
+
+        // the point is behind the start when it's more than 90° off the track bearing
+        if (bearing_to_self - bearing_to_end).cos() < 0.0 {
+            -along_track
+        } else {
+            along_track
+        }
+    }
+
     /// Project a position forward by a given distance and heading.
     pub fn project(&self, heading: f64, distance: f64) -> Position {
         let lat1 = self.latitude.to_radians();
@@ -128,4 +161,42 @@ mod tests {
         assert!((lat_rad.to_degrees() - 48.057440).abs() < 1e-10);
         assert!((lon_rad.to_degrees() - (-123.119625)).abs() < 1e-10);
     }
+
+    #[test]
+    fn test_cross_track_distance() {
+        // track due north along 123°W
+        let start = Position::new(48.0, -123.0);
+        let end = Position::new(49.0, -123.0);
+
+        // on the track
+        let on_track = Position::new(48.5, -123.0);
+        assert!(on_track.cross_track_distance(&start, &end).abs() < 1e-6);
+
+        // east of a northbound track is to the right
+        let east = start.project(90.0, 500.0).project(0.0, 1000.0);
+        let xte = east.cross_track_distance(&start, &end);
+        assert!((xte - 500.0).abs() < 1.0, "expected +500 m, got {:.1}", xte);
+
+        let west = start.project(270.0, 500.0).project(0.0, 1000.0);
+        let xte = west.cross_track_distance(&start, &end);
+        assert!((xte + 500.0).abs() < 1.0, "expected -500 m, got {:.1}", xte);
+    }
+
+    #[test]
+    fn test_along_track_distance() {
+        let start = Position::new(48.0, -123.0);
+        let end = Position::new(49.0, -123.0);
+
+        let ahead = start.project(0.0, 1000.0).project(90.0, 200.0);
+        let atd = ahead.along_track_distance(&start, &end);
+        assert!(
+            (atd - 1000.0).abs() < 1.0,
+            "expected 1000 m, got {:.1}",
+            atd
+        );
+
+        let behind = start.project(180.0, 300.0);
+        let atd = behind.along_track_distance(&start, &end);
+        assert!((atd + 300.0).abs() < 1.0, "expected -300 m, got {:.1}", atd);
+    }
 }
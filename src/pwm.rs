@@ -1,72 +1,257 @@
 use std::error::Error;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // Use rppal in production
 #[cfg(not(test))]
 use rppal::pwm::{Channel, Polarity, Pwm};
 
-// Mock PWM for testing
+use std::fmt;
+
 use crate::config::{
-    HEADING_ERROR_DEADBAND, KD, KI, KP, MAX_SERVO_RATE, SERVO_CENTER_PULSE_US, SERVO_FREQUENCY_HZ,
-    SERVO_MAX_ANGLE, SERVO_MAX_PULSE_US, SERVO_MIN_PULSE_US, SERVO_PWM_PIN,
+    Actuator, Config, FailsafeAction, GainScheduleSettings, PidSettings, RateOfTurnSettings,
+    RateSource, RudderSettings, ServoSettings, SteeringAlgorithm,
 };
+use crate::gyro::GyroSensor;
+use crate::motor::{MotorDrive, MotorFault};
+use crate::params::Parameters;
+use crate::pid::{Gains, Pid, scheduled_gains};
+use crate::rudder::{RudderFault, RudderLoop, RudderSensor};
 
+// Mock PWM for testing
 #[cfg(test)]
 use crate::mocks::mock_pwm::Pwm;
 
-pub struct ServoController {
-    pwm: Pwm,
-    integral: f64,
-    last_error: f64,
-    current_angle: f64, // track current servo position for rate limiting
+/// Time for the servo to get amidships on shutdown before its output is switched off.
+#[cfg(not(test))]
+const CENTER_SETTLE: Duration = Duration::from_millis(800);
+#[cfg(test)]
+const CENTER_SETTLE: Duration = Duration::ZERO;
+
+/// Gains the heading PID is using and where they came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActiveGains {
+    pub gains: Gains,
+    /// Speed the gains were scheduled for; `None` if there is no schedule or no speed.
+    pub speed_knots: Option<f64>,
+    /// Whether a gain schedule is configured.
+    pub scheduled: bool,
 }
 
-impl ServoController {
-    /// Create a new servo controller.
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        Self::with_pin(SERVO_PWM_PIN)
+impl fmt::Display for ActiveGains {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.gains)?;
+        match (self.scheduled, self.speed_knots) {
+            (false, _) => Ok(()),
+            (true, Some(speed)) => write!(f, " (scheduled for {:.1} kn)", speed),
+            (true, None) => write!(f, " (no speed, [pid] gains)"),
+        }
     }
+}
 
-    /// Create a servo controller with a custom GPIO pin.
-    pub fn with_pin(pin: u8) -> Result<Self, Box<dyn Error>> {
-        #[cfg(not(test))]
-        let channel = match pin {
-            12 | 18 => Channel::Pwm0,
-            13 | 19 => Channel::Pwm1,
-            _ => return Err("Invalid PWM pin. Use 12, 13, 18, or 19".into()),
+/// What turns the rudder.
+enum Output {
+    /// Hobby servo: the pulse width sets the position.
+    Servo {
+        pwm: Pwm,
+        /// Duty cycle that puts the rudder amidships, for [`FailsafeHandle`].
+        center_duty: f64,
+        /// Pulses switched off by a release until the next one is set.
+        released: bool,
+    },
+    /// DC motor through an H-bridge, positioned with the rudder sensor.
+    Motor(MotorDrive),
+}
+
+impl Output {
+    /// Set the servo duty cycle, switching the pulses back on if they were released.
+    fn set_servo_duty(&mut self, duty_cycle: f64) -> Result<(), Box<dyn Error>> {
+        let Output::Servo { pwm, released, .. } = self else {
+            return Ok(());
         };
 
+        #[cfg(not(test))]
+        pwm.set_duty_cycle(duty_cycle)?;
+
+        #[cfg(test)]
+        pwm.set_duty_cycle(duty_cycle);
+
+        if *released {
+            pwm.enable()?;
+            *released = false;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Output::Servo { pwm, released, .. } => {
+                pwm.disable()?;
+                *released = true;
+            }
+            Output::Motor(motor) => motor.stop()?,
+        }
+        Ok(())
+    }
+
+    /// Leave the rudder as `action` says without the steering loop: a servo is held, centered
+    /// or released, a motor is stopped as it can't be positioned.
+    fn apply_failsafe(&mut self, action: FailsafeAction) -> Result<(), Box<dyn Error>> {
+        match (action, &*self) {
+            (_, Output::Motor(_)) | (FailsafeAction::Release, _) => self.release(),
+            (FailsafeAction::Center, Output::Servo { center_duty, .. }) => {
+                let duty_cycle = *center_duty;
+                self.set_servo_duty(duty_cycle)
+            }
+            (FailsafeAction::Hold, Output::Servo { .. }) => Ok(()),
+        }
+    }
+}
+
+/// Puts the actuator in a failsafe state from another thread, e.g. the watchdog while the
+/// control loop is stuck. Clones share the output with the [`ServoController`] it came from.
+#[derive(Clone)]
+pub struct FailsafeHandle {
+    output: Arc<Mutex<Output>>,
+}
+
+impl FailsafeHandle {
+    /// How long to wait for the output if the control loop is using it.
+    const WAIT: Duration = Duration::from_millis(200);
+
+    /// Hold, center or release the rudder (a motor is stopped). Fails if the output stays busy,
+    /// which means the control loop is stuck while driving it.
+    pub fn apply(&self, action: FailsafeAction) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        loop {
+            match self.output.try_lock() {
+                Ok(mut output) => return output.apply_failsafe(action),
+                Err(std::sync::TryLockError::Poisoned(e)) => {
+                    return e.into_inner().apply_failsafe(action);
+                }
+                Err(std::sync::TryLockError::WouldBlock) if started.elapsed() < Self::WAIT => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(std::sync::TryLockError::WouldBlock) => {
+                    return Err("the actuator output is busy".into());
+                }
+            }
+        }
+    }
+}
+
+/// Hardware PWM channel of a GPIO pin.
+#[cfg(not(test))]
+pub(crate) fn pwm_channel(pin: u8) -> Result<Channel, Box<dyn Error>> {
+    match pin {
+        12 | 18 => Ok(Channel::Pwm0),
+        13 | 19 => Ok(Channel::Pwm1),
+        _ => Err("Invalid PWM pin. Use 12, 13, 18, or 19".into()),
+    }
+}
+
+/// Steers the rudder to heading corrections, with a servo or, with `servo.actuator = "motor"`,
+/// an H-bridge motor drive.
+pub struct ServoController {
+    output: Arc<Mutex<Output>>,
+    settings: ServoSettings,
+    /// Configured PID settings, before gain scheduling.
+    pid_settings: PidSettings,
+    gain_schedule: GainScheduleSettings,
+    /// Speed over ground for gain scheduling (knots).
+    speed_knots: Option<f64>,
+    pid: Pid,
+    algorithm: SteeringAlgorithm,
+    rate_of_turn: RateOfTurnController,
+    gyro: Option<GyroSensor>,
+    rudder_sensor: Option<RudderSensor>,
+    rudder: RudderLoop,
+    last_output: Instant,
+    /// Where the rudder is left when the controller is dropped.
+    shutdown_action: FailsafeAction,
+    parameters: Option<(Parameters, u64)>, // live settings and the generation last read
+    current_angle: f64,                    // track current servo position for rate limiting
+}
+
+impl ServoController {
+    /// Create a new servo controller with the servo, gains, steering and shutdown settings of
+    /// `config`.
+    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let settings = &config.servo;
         #[cfg(not(test))]
         let pwm = Pwm::with_frequency(
-            channel,
-            SERVO_FREQUENCY_HZ,
+            pwm_channel(settings.pwm_pin)?,
+            settings.frequency_hz,
             0.5, // 50% duty cycle (centered)
             Polarity::Normal,
             true, // enabled
         )?;
 
         #[cfg(test)]
-        let pwm = Pwm::new(pin)?;
+        let pwm = Pwm::new(settings.pwm_pin)?;
 
-        Ok(Self {
+        let output = Output::Servo {
             pwm,
-            integral: 0.0,
-            last_error: 0.0,
+            center_duty: center_duty(settings),
+            released: false,
+        };
+        Ok(Self::with_output(output, config))
+    }
+
+    /// Create a controller driving an H-bridge motor with its speed on `servo.pwm_pin`, with the
+    /// settings of `config` as for [`ServoController::new`]. It needs a rudder sensor, see
+    /// [`ServoController::set_rudder_sensor`]; without one the motor stays stopped.
+    pub fn with_motor(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let motor = MotorDrive::new(config.servo.pwm_pin, &config.motor)?;
+        Ok(Self::with_output(Output::Motor(motor), config))
+    }
+
+    fn with_output(output: Output, config: &Config) -> Self {
+        let settings = &config.servo;
+        let pid = &config.pid;
+        Self {
+            output: Arc::new(Mutex::new(output)),
+            settings: settings.clone(),
+            pid_settings: pid.clone(),
+            gain_schedule: config.gain_schedule.clone(),
+            speed_knots: None,
+            pid: Pid::angular(pid, settings.max_angle),
+            algorithm: config.steering.algorithm,
+            rate_of_turn: RateOfTurnController::new(&config.rate_of_turn, pid, settings.max_angle),
+            gyro: None,
+            rudder_sensor: None,
+            rudder: RudderLoop::new(&config.rudder, settings.max_angle),
+            last_output: Instant::now(),
+            shutdown_action: config.failsafe.shutdown_action,
+            parameters: None,
             current_angle: 0.0, // start at center position
-        })
+        }
     }
 
-    /// Set servo position based on angle (-SERVO_MAX_ANGLE to +SERVO_MAX_ANGLE)
+    /// Set servo position based on angle (-max_angle to +max_angle)
     /// Negative = left, Positive = right, 0 = center
+    ///
+    /// With a rudder sensor the servo is driven until the measured rudder angle gets there. A
+    /// motor runs towards the angle until the next call.
     pub fn set_angle(&mut self, angle: f64) -> Result<(), Box<dyn Error>> {
-        // clamp angle to valid range
-        let clamped_angle = angle.clamp(-SERVO_MAX_ANGLE, SERVO_MAX_ANGLE);
+        self.refresh_settings();
 
-        // map angle to pulse width
-        // -90° → 1000μs, 0° → 1500μs, +90° → 2000μs
-        let pulse_us = SERVO_CENTER_PULSE_US
-            + (clamped_angle / SERVO_MAX_ANGLE) * (SERVO_MAX_PULSE_US - SERVO_CENTER_PULSE_US);
+        // clamp angle to valid range
+        let max_angle = self.settings.max_angle;
+        let clamped_angle = angle.clamp(-max_angle, max_angle);
+        let dt = self.last_output.elapsed().as_secs_f64();
+        self.last_output = Instant::now();
+        let command = self
+            .rudder_command(clamped_angle, dt)
+            .clamp(-max_angle, max_angle);
 
-        self.set_pulse_width_us(pulse_us)?;
+        let rudder_angle = self.rudder_sensor.as_ref().and(self.rudder.angle());
+        if let Output::Motor(motor) = &mut *self.output() {
+            motor.drive(clamped_angle, rudder_angle, max_angle, dt)?;
+        } else {
+            self.set_pulse_width_us(pulse_for_angle(&self.settings, command))?;
+        }
 
         // track current position
         self.current_angle = clamped_angle;
@@ -74,28 +259,206 @@ impl ServoController {
         Ok(())
     }
 
-    /// Set servo to center position (neutral).
+    /// Angle to command for the rudder to reach `target`, from the position loop if there is a
+    /// rudder sensor.
+    fn rudder_command(&mut self, target: f64, dt: f64) -> f64 {
+        let Some(sensor) = &mut self.rudder_sensor else {
+            return target;
+        };
+        let raw = sensor
+            .read_raw()
+            .map_err(|e| crate::debug!("Rudder sensor read failed: {}", e))
+            .ok();
+        self.rudder.update(target, raw, dt)
+    }
+
+    /// Servo limits and timing this controller was created with.
+    pub fn settings(&self) -> &ServoSettings {
+        &self.settings
+    }
+
+    /// Select the steering algorithm `auto_steer` uses.
+    pub fn use_algorithm(&mut self, algorithm: SteeringAlgorithm, settings: &RateOfTurnSettings) {
+        self.algorithm = algorithm;
+        self.rate_of_turn
+            .configure(settings, &self.pid_settings, self.settings.max_angle);
+    }
+
+    pub fn algorithm(&self) -> SteeringAlgorithm {
+        self.algorithm
+    }
+
+    /// Schedule the heading PID gains by speed over ground.
+    pub fn set_gain_schedule(&mut self, schedule: &GainScheduleSettings) {
+        self.gain_schedule = schedule.clone();
+    }
+
+    /// Current speed over ground (knots) for the gain schedule, `None` if unknown. Without a
+    /// speed the `[pid]` gains are used.
+    pub fn set_speed(&mut self, speed_knots: Option<f64>) {
+        self.speed_knots = speed_knots;
+    }
+
+    /// Gains the heading PID uses at the current speed.
+    pub fn active_gains(&self) -> ActiveGains {
+        let scheduled = self
+            .speed_knots
+            .and_then(|speed| Some((scheduled_gains(&self.gain_schedule.points, speed)?, speed)));
+        ActiveGains {
+            gains: scheduled.map_or_else(|| Gains::of(&self.pid_settings), |(gains, _)| gains),
+            speed_knots: scheduled.map(|(_, speed)| speed),
+            scheduled: !self.gain_schedule.points.is_empty(),
+        }
+    }
+
+    /// Gyro the rate-of-turn controller reads the rate of turn from.
+    pub fn set_gyro(&mut self, gyro: Option<GyroSensor>) {
+        self.gyro = gyro;
+    }
+
+    /// Rudder angle sensor to close the position loop on, and its calibration.
+    pub fn set_rudder_sensor(&mut self, sensor: Option<RudderSensor>, settings: &RudderSettings) {
+        self.rudder_sensor = sensor;
+        self.rudder.configure(settings, self.settings.max_angle);
+        self.last_output = Instant::now();
+    }
+
+    /// Measured rudder angle (degrees), `None` without a working rudder sensor.
+    pub fn rudder_angle(&self) -> Option<f64> {
+        self.rudder_sensor.as_ref().and(self.rudder.angle())
+    }
+
+    /// Fault in the rudder feedback, if there is a rudder sensor.
+    pub fn rudder_fault(&self) -> Option<RudderFault> {
+        self.rudder_sensor.as_ref().and(self.rudder.fault())
+    }
+
+    /// What turns the rudder.
+    pub fn actuator(&self) -> Actuator {
+        match &*self.output() {
+            Output::Servo { .. } => Actuator::Servo,
+            Output::Motor(_) => Actuator::Motor,
+        }
+    }
+
+    /// Why the motor is stopped, with a motor drive.
+    pub fn motor_fault(&self) -> Option<MotorFault> {
+        match &*self.output() {
+            Output::Motor(motor) => motor.fault(),
+            Output::Servo { .. } => None,
+        }
+    }
+
+    /// Handle for putting the actuator in a failsafe state from another thread.
+    pub fn failsafe_handle(&self) -> FailsafeHandle {
+        FailsafeHandle {
+            output: Arc::clone(&self.output),
+        }
+    }
+
+    fn output(&self) -> MutexGuard<'_, Output> {
+        self.output.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take the limits, steering algorithm and gains from the live parameters from now on, so
+    /// changes apply without a restart. The PWM pin and frequency stay as created.
+    pub fn follow(&mut self, parameters: Parameters) {
+        self.parameters = Some((parameters, u64::MAX));
+        self.refresh_settings();
+    }
+
+    /// Pick up the live parameters if they changed since the last read.
+    fn refresh_settings(&mut self) {
+        let Some((parameters, generation)) = &mut self.parameters else {
+            return;
+        };
+        if parameters.generation() == *generation {
+            return;
+        }
+        *generation = parameters.generation();
+
+        let config = parameters.current();
+        self.settings = ServoSettings {
+            pwm_pin: self.settings.pwm_pin,
+            frequency_hz: self.settings.frequency_hz,
+            ..config.servo.clone()
+        };
+        self.pid_settings = config.pid.clone();
+        self.gain_schedule = config.gain_schedule.clone();
+        self.algorithm = config.steering.algorithm;
+        self.rate_of_turn
+            .configure(&config.rate_of_turn, &config.pid, self.settings.max_angle);
+        self.rudder
+            .configure(&config.rudder, self.settings.max_angle);
+        match &mut *self.output() {
+            Output::Servo {
+                center_duty: duty, ..
+            } => *duty = center_duty(&self.settings),
+            Output::Motor(motor) => motor.configure(&config.motor),
+        }
+        self.shutdown_action = config.failsafe.shutdown_action;
+    }
+
+    /// Last commanded servo angle (degrees).
+    pub fn get_angle(&self) -> f64 {
+        self.current_angle
+    }
+
+    /// Clear the PID state, e.g. when the autopilot engages. Steering continues from the
+    /// current rudder angle.
+    pub fn reset(&mut self) {
+        // the PID output is the negated correction, see `calculate_correction`
+        self.pid.reset(-self.current_angle);
+        self.rate_of_turn.reset(self.current_angle);
+    }
+
+    /// Set servo to center position (neutral). A motor is stopped instead, leaving the helm
+    /// free where it is.
     pub fn center(&mut self) -> Result<(), Box<dyn Error>> {
-        self.set_angle(0.0)
+        if self.actuator() == Actuator::Servo {
+            return self.set_angle(0.0);
+        }
+        self.output().release()?;
+        // engaging again continues from where the rudder is
+        self.current_angle = self.rudder_angle().unwrap_or(0.0);
+        Ok(())
     }
 
-    /// Set servo pulse width directly (microseconds).
-    fn set_pulse_width_us(&mut self, pulse_us: f64) -> Result<(), Box<dyn Error>> {
+    /// Set servo pulse width directly (microseconds), within the configured pulse range. For
+    /// calibration; steering goes through [`ServoController::set_angle`].
+    pub fn set_pulse_width_us(&mut self, pulse_us: f64) -> Result<(), Box<dyn Error>> {
         // clamp to valid range
-        let clamped_pulse = pulse_us.clamp(SERVO_MIN_PULSE_US, SERVO_MAX_PULSE_US);
+        let clamped_pulse = pulse_us.clamp(self.settings.min_pulse_us, self.settings.max_pulse_us);
 
         // convert to duty cycle
         // period = 1/50Hz = 20ms = 20000μs
-        let period_us = 1_000_000.0 / SERVO_FREQUENCY_HZ;
+        let period_us = 1_000_000.0 / self.settings.frequency_hz;
         let duty_cycle = clamped_pulse / period_us;
 
-        #[cfg(not(test))]
-        self.pwm.set_duty_cycle(duty_cycle)?;
+        self.output().set_servo_duty(duty_cycle)
+    }
 
-        #[cfg(test)]
-        self.pwm.set_duty_cycle(duty_cycle);
+    /// Stop driving the rudder, leaving it free to move: the servo gets no pulses, the motor is
+    /// stopped. The next angle set drives it again.
+    pub fn release(&mut self) -> Result<(), Box<dyn Error>> {
+        self.output().release()
+    }
 
-        Ok(())
+    /// Whether the servo output is released, see [`ServoController::release`].
+    pub fn is_released(&self) -> bool {
+        matches!(*self.output(), Output::Servo { released: true, .. })
+    }
+
+    /// Leave the rudder as the shutdown action says and switch the output off. A servo is
+    /// centered first with [`FailsafeAction::Center`]; a motor is always stopped, as it can't
+    /// be positioned once the program is gone.
+    pub fn shut_down(&mut self) -> Result<(), Box<dyn Error>> {
+        self.refresh_settings();
+        if self.shutdown_action == FailsafeAction::Center && self.actuator() == Actuator::Servo {
+            self.set_angle(0.0)?;
+            thread::sleep(CENTER_SETTLE);
+        }
+        self.disable()
     }
 
     /// Calculate steering correction using PID controller
@@ -106,50 +469,26 @@ impl ServoController {
     /// * `dt` - Time delta since last update (seconds)
     ///
     /// # Returns
-    /// Servo angle correction (-SERVO_MAX_ANGLE to +SERVO_MAX_ANGLE)
+    /// Servo angle correction (-max_angle to +max_angle)
     pub fn calculate_correction(
         &mut self,
         target_heading: f64,
         current_heading: f64,
         dt: f64,
     ) -> f64 {
-        // calculate heading error (accounting for wraparound)
+        self.refresh_settings();
+        let settings = self.active_gains().gains.apply_to(&self.pid_settings);
+        self.pid.configure(&settings, self.settings.max_angle);
+
         // NOTE: For boat rudder control, the error sign is inverted.
         // when heading is too far right, we need positive rudder angle (rudder right)
-        // to push the stern right and turn the bow left.
-        let mut error = current_heading - target_heading;
-
-        // normalize error to -180 to +180 range
-        if error > 180.0 {
-            error -= 360.0;
-        } else if error < -180.0 {
-            error += 360.0;
-        }
-
-        // apply deadband - don't correct small errors
-        if error.abs() < HEADING_ERROR_DEADBAND {
-            return 0.0;
-        }
-
-        // PID calculations
-        let p_term = KP * error;
-
-        self.integral += error * dt;
-        let i_term = KI * self.integral;
-
-        let derivative = (error - self.last_error) / dt;
-        let d_term = KD * derivative;
-
-        self.last_error = error;
-
-        // calculate correction angle
-        let correction = p_term + i_term + d_term;
-
-        // clamp to servo limits
-        correction.clamp(-SERVO_MAX_ANGLE, SERVO_MAX_ANGLE)
+        // to push the stern right and turn the bow left. The PID acts on target - current
+        // (wrapped to ±180°, clamped to the servo limits), so its output is negated.
+        -self.pid.update(target_heading, current_heading, dt)
     }
 
-    /// Apply automatic heading correction with rate limiting
+    /// Apply automatic heading correction with rate limiting, using the selected steering
+    /// algorithm.
     ///
     /// # Arguments
     /// * `target_heading` - Desired heading (degrees)
@@ -161,18 +500,43 @@ impl ServoController {
         current_heading: f64,
         dt: f64,
     ) -> Result<f64, Box<dyn Error>> {
-        let desired_correction = self.calculate_correction(target_heading, current_heading, dt);
+        self.refresh_settings();
+
+        let desired_correction = match self.algorithm {
+            SteeringAlgorithm::Pid => {
+                self.calculate_correction(target_heading, current_heading, dt)
+            }
+            SteeringAlgorithm::RateOfTurn => {
+                let gyro_rate = match (&mut self.gyro, self.rate_of_turn.settings.source) {
+                    (Some(gyro), RateSource::Gyro) => gyro
+                        .read_rate_of_turn()
+                        .map_err(|e| crate::debug!("Gyro read failed: {}", e))
+                        .ok(),
+                    _ => None,
+                };
+                self.rate_of_turn
+                    .update(target_heading, current_heading, gyro_rate, dt)
+            }
+        };
+
+        self.steer_to(desired_correction, dt)
+    }
+
+    /// Move towards `angle` no faster than `max_rate`, `dt` seconds after the last move.
+    /// Returns the angle set.
+    pub fn steer_to(&mut self, angle: f64, dt: f64) -> Result<f64, Box<dyn Error>> {
+        self.refresh_settings();
 
         // apply rate limiting to prevent violent movements
-        let max_change = MAX_SERVO_RATE * dt;
-        let angle_diff = desired_correction - self.current_angle;
+        let max_change = self.settings.max_rate * dt;
+        let angle_diff = angle - self.current_angle;
 
         let actual_correction = if angle_diff.abs() > max_change {
             // limit the change to maximum rate
             self.current_angle + angle_diff.signum() * max_change
         } else {
             // small change, apply directly
-            desired_correction
+            angle
         };
 
         self.set_angle(actual_correction)?;
@@ -181,26 +545,172 @@ impl ServoController {
 
     /// Disable PWM output
     pub fn disable(&mut self) -> Result<(), Box<dyn Error>> {
-        #[cfg(not(test))]
-        self.pwm.disable()?;
+        match &mut *self.output() {
+            Output::Servo { pwm, .. } => pwm.disable()?,
+            Output::Motor(motor) => motor.disable()?,
+        }
 
         Ok(())
     }
 }
 
+/// Cascaded heading controller. The outer loop turns heading error into the rate of turn to
+/// steer at, limited to `max_rate_dps`; the inner loop turns the rate error into rudder. Holding
+/// a rate rather than chasing the heading stops the overshoot and snaking of a plain heading
+/// PID on boats with a lot of yaw inertia.
+pub struct RateOfTurnController {
+    settings: RateOfTurnSettings,
+    /// Heading errors below this are treated as on course (degrees).
+    deadband: f64,
+    /// Inner loop: rate of turn to rudder.
+    inner: Pid,
+    last_heading: Option<f64>,
+    /// Rate of turn differentiated from the heading, filtered (°/s).
+    heading_rate: f64,
+}
+
+impl RateOfTurnController {
+    /// `pid` supplies the heading deadband and the anti-windup and derivative filter of the
+    /// inner loop.
+    pub fn new(settings: &RateOfTurnSettings, pid: &PidSettings, max_angle: f64) -> Self {
+        Self {
+            settings: settings.clone(),
+            deadband: pid.deadband,
+            inner: Pid::new(&Self::inner_settings(settings, pid), max_angle),
+            last_heading: None,
+            heading_rate: 0.0,
+        }
+    }
+
+    pub fn configure(&mut self, settings: &RateOfTurnSettings, pid: &PidSettings, max_angle: f64) {
+        self.settings = settings.clone();
+        self.deadband = pid.deadband;
+        self.inner
+            .configure(&Self::inner_settings(settings, pid), max_angle);
+    }
+
+    fn inner_settings(settings: &RateOfTurnSettings, pid: &PidSettings) -> PidSettings {
+        PidSettings {
+            kp: settings.kp,
+            ki: settings.ki,
+            kd: settings.kd,
+            deadband: 0.0,
+            // the setpoint is the rate of turn the outer loop asks for, not the target heading
+            setpoint_weight: 1.0,
+            ..pid.clone()
+        }
+    }
+
+    /// Forget the history and continue from the rudder angle `correction`.
+    pub fn reset(&mut self, correction: f64) {
+        self.inner.reset(-correction);
+        self.last_heading = None;
+        self.heading_rate = 0.0;
+    }
+
+    /// Rate of turn taken from the heading (°/s, positive to starboard).
+    pub fn heading_rate(&self) -> f64 {
+        self.heading_rate
+    }
+
+    /// Servo angle correction for the heading, in the same sense as
+    /// [`ServoController::calculate_correction`]. `gyro_rate` is the measured rate of turn
+    /// (°/s, positive to starboard); without it the rate is differentiated from the heading.
+    pub fn update(
+        &mut self,
+        target_heading: f64,
+        current_heading: f64,
+        gyro_rate: Option<f64>,
+        dt: f64,
+    ) -> f64 {
+        if !(dt > 0.0 && dt.is_finite()) {
+            return -self.inner.output();
+        }
+
+        // keep the heading rate current even while the gyro is in use, for a clean fallback
+        if let Some(last) = self.last_heading {
+            let rate = normalize_angle(current_heading - last) / dt;
+            let alpha = dt / (self.settings.heading_filter_secs + dt);
+            self.heading_rate += alpha * (rate - self.heading_rate);
+        }
+        self.last_heading = Some(current_heading);
+        let rate = gyro_rate.unwrap_or(self.heading_rate);
+
+        // outer loop: heading error to the rate of turn wanted
+        let error = normalize_angle(target_heading - current_heading);
+        let max_rate = self.settings.max_rate_dps;
+        let desired_rate = if error.abs() < self.deadband {
+            0.0
+        } else {
+            (self.settings.heading_gain * error).clamp(-max_rate, max_rate)
+        };
+
+        // inner loop: turning to starboard takes negative (left) rudder, see
+        // `calculate_correction`
+        -self.inner.update(desired_rate, rate, dt)
+    }
+}
+
+/// Servo pulse width for a rudder angle (microseconds). Without a curve, port angles map onto
+/// `min_pulse_us..center_pulse_us` and starboard onto `center_pulse_us..max_pulse_us`, or the
+/// other way round if `reversed`: -90° → 1000μs, 0° → 1500μs, +90° → 2000μs by default. A
+/// curve is interpolated instead, after `reversed` is applied.
+pub fn pulse_for_angle(settings: &ServoSettings, angle: f64) -> f64 {
+    let angle = if settings.reversed { -angle } else { angle };
+
+    let curve = &settings.curve;
+    if let (Some(first), Some(last)) = (curve.first(), curve.last()) {
+        if angle <= first.angle_deg {
+            return first.pulse_us;
+        }
+        if angle >= last.angle_deg {
+            return last.pulse_us;
+        }
+        let i = curve.partition_point(|point| point.angle_deg <= angle);
+        let (low, high) = (curve[i - 1], curve[i]);
+        let fraction = (angle - low.angle_deg) / (high.angle_deg - low.angle_deg);
+        return low.pulse_us + fraction * (high.pulse_us - low.pulse_us);
+    }
+
+    let center = settings.center_pulse_us;
+    let span = if angle >= 0.0 {
+        settings.max_pulse_us - center
+    } else {
+        center - settings.min_pulse_us
+    };
+    center + angle / settings.max_angle * span
+}
+
+/// Wrap an angle difference to -180..180 degrees.
+fn normalize_angle(angle: f64) -> f64 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Servo duty cycle for the rudder amidships.
+fn center_duty(settings: &ServoSettings) -> f64 {
+    pulse_for_angle(settings, 0.0) * settings.frequency_hz / 1_000_000.0
+}
+
 impl Drop for ServoController {
     fn drop(&mut self) {
-        let _ = self.disable();
+        // also on the way out of a panic
+        let _ = self.shut_down();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{FailsafeSettings, MAX_RATE_OF_TURN, MAX_SERVO_RATE, MotorSettings};
+    use crate::mocks::mock_pwm;
+
+    fn new_controller() -> ServoController {
+        ServoController::new(&Config::default()).unwrap()
+    }
 
     #[test]
     fn test_heading_error_calculation() {
-        let mut controller = ServoController::new().unwrap();
+        let mut controller = new_controller();
 
         // test simple error: current heading is 85° (too far left), target is 90°
         // rudder should move LEFT (negative) to turn bow right toward target
@@ -220,7 +730,7 @@ mod tests {
 
     #[test]
     fn test_deadband() {
-        let mut controller = ServoController::new().unwrap();
+        let mut controller = new_controller();
 
         // small error within deadband - should return 0
         let correction = controller.calculate_correction(90.0, 89.0, 0.1);
@@ -231,19 +741,95 @@ mod tests {
         assert!(correction != 0.0);
     }
 
+    #[test]
+    fn test_toggle_press_does_not_kick() {
+        let pid = PidSettings {
+            kd: 5.0,
+            derivative_filter_secs: 0.0,
+            ..PidSettings::default()
+        };
+        let config = Config {
+            pid: pid.clone(),
+            ..Config::default()
+        };
+        let mut controller = ServoController::new(&config).unwrap();
+
+        let before = controller.calculate_correction(90.0, 85.0, 0.1);
+        // a 5° toggle press on a steady heading only changes the proportional part
+        let after = controller.calculate_correction(95.0, 85.0, 0.1);
+        assert!(
+            (after - before - (-5.0 * pid.kp)).abs() < 1e-9,
+            "{} → {}",
+            before,
+            after
+        );
+
+        // no time elapsed: no division by zero, the correction stands
+        assert_eq!(controller.calculate_correction(95.0, 80.0, 0.0), after);
+    }
+
     #[test]
     fn test_servo_angle_clamping() {
-        let mut controller = ServoController::new().unwrap();
+        let mut controller = new_controller();
 
         // test angle clamping
         assert!(controller.set_angle(100.0).is_ok()); // should clamp to max
         assert!(controller.set_angle(-100.0).is_ok()); // should clamp to min
         assert!(controller.set_angle(0.0).is_ok()); // center
+
+        // center = 1500μs of a 20ms period
+        assert!((mock_pwm::get_mock_duty_cycle() - 0.075).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pulse_calibration() {
+        // asymmetric linkage: 400μs to port, 300μs to starboard
+        let mut settings = ServoSettings {
+            min_pulse_us: 1100.0,
+            center_pulse_us: 1500.0,
+            max_pulse_us: 1800.0,
+            max_angle: 30.0,
+            ..ServoSettings::default()
+        };
+        assert_eq!(pulse_for_angle(&settings, 0.0), 1500.0);
+        assert_eq!(pulse_for_angle(&settings, -30.0), 1100.0);
+        assert_eq!(pulse_for_angle(&settings, 15.0), 1650.0);
+
+        // mounted the other way round
+        settings.reversed = true;
+        assert_eq!(pulse_for_angle(&settings, -30.0), 1800.0);
+        assert_eq!(pulse_for_angle(&settings, 15.0), 1300.0);
+
+        // a linkage that moves the rudder faster near center
+        settings.reversed = false;
+        let point = |angle_deg, pulse_us| crate::config::CurvePoint {
+            angle_deg,
+            pulse_us,
+        };
+        settings.curve = vec![
+            point(-30.0, 1100.0),
+            point(-10.0, 1400.0),
+            point(10.0, 1550.0),
+            point(30.0, 1800.0),
+        ];
+        assert_eq!(pulse_for_angle(&settings, -20.0), 1250.0);
+        assert_eq!(pulse_for_angle(&settings, 0.0), 1475.0);
+        assert_eq!(pulse_for_angle(&settings, 10.0), 1550.0);
+        assert_eq!(pulse_for_angle(&settings, 45.0), 1800.0);
+        assert_eq!(pulse_for_angle(&settings, -45.0), 1100.0);
+
+        let config = Config {
+            servo: settings,
+            ..Config::default()
+        };
+        let mut controller = ServoController::new(&config).unwrap();
+        controller.set_angle(-20.0).unwrap();
+        assert!((mock_pwm::get_mock_duty_cycle() - 1250.0 / 20000.0).abs() < 1e-9);
     }
 
     #[test]
     fn test_boat_rudder_steering_logic() {
-        let mut controller = ServoController::new().unwrap();
+        let mut controller = new_controller();
 
         // Scenario 1: Boat heading too far RIGHT (100°), need to go back to 90°
         // Error = 100° - 90° = +10° (positive error)
@@ -272,28 +858,31 @@ mod tests {
 
     #[test]
     fn test_servo_rate_limiting() {
-        let mut controller = ServoController::new().unwrap();
+        let mut controller = new_controller();
 
         // start at center (0°)
         assert_eq!(controller.current_angle, 0.0);
 
         // large correction needed: heading 0°, target 90° → error = -90°
-        // with dt=0.1s and MAX_SERVO_RATE=20°/s, max change = 2°
+        // with dt=0.1s, max change = MAX_SERVO_RATE * 0.1
+        let max_step = MAX_SERVO_RATE * 0.1;
         let correction = controller.auto_steer(90.0, 0.0, 0.1).unwrap();
 
-        // should move toward -90° but limited to 2° change
+        // should move toward -90° but limited to one step
         assert!(
-            (correction - (-2.0)).abs() < 0.1,
-            "First step should be limited to -2°, got {:.1}°",
+            (correction - (-max_step)).abs() < 0.1,
+            "First step should be limited to {:.1}°, got {:.1}°",
+            -max_step,
             correction
         );
         assert_eq!(controller.current_angle, correction);
 
-        // next update: should move another 2°
+        // next update: should move another step
         let correction = controller.auto_steer(90.0, 0.0, 0.1).unwrap();
         assert!(
-            (correction - (-4.0)).abs() < 0.1,
-            "Second step should be -4° total, got {:.1}°",
+            (correction - (-2.0 * max_step)).abs() < 0.1,
+            "Second step should be {:.1}° total, got {:.1}°",
+            -2.0 * max_step,
             correction
         );
 
@@ -307,4 +896,245 @@ mod tests {
             "Small corrections should apply directly"
         );
     }
+
+    fn rate_controller() -> RateOfTurnController {
+        let settings = RateOfTurnSettings {
+            ki: 0.0,
+            ..RateOfTurnSettings::default()
+        };
+        RateOfTurnController::new(&settings, &PidSettings::default(), 30.0)
+    }
+
+    #[test]
+    fn test_rate_of_turn_cascade() {
+        let mut controller = rate_controller();
+
+        // 90° to starboard: asks for the maximum rate, so at rest it's left (negative) rudder
+        let at_rest = controller.update(90.0, 0.0, Some(0.0), 0.1);
+        assert!(at_rest < 0.0);
+        // already turning at the maximum rate: nothing more to do
+        let turning = controller.update(90.0, 0.0, Some(MAX_RATE_OF_TURN), 0.1);
+        assert!(turning.abs() < 1e-9, "{}", turning);
+        // turning too fast: the rudder counters even though the heading is still short
+        assert!(controller.update(90.0, 0.0, Some(2.0 * MAX_RATE_OF_TURN), 0.1) > 0.0);
+        // on course and steady: no rudder
+        assert_eq!(controller.update(90.0, 90.0, Some(0.0), 0.1), 0.0);
+    }
+
+    #[test]
+    fn test_rate_of_turn_from_heading() {
+        let mut controller = rate_controller();
+
+        // without a gyro, a swing to port at 2°/s (across north) is seen in the heading and
+        // damped with left rudder, which turns the bow to starboard
+        let mut correction = 0.0;
+        for step in 0..100 {
+            let heading = (360.0 + 5.0 - 0.2 * step as f64) % 360.0;
+            correction = controller.update(heading, heading, None, 0.1);
+        }
+        assert!((controller.heading_rate() + 2.0).abs() < 0.1);
+        assert!(correction < 0.0);
+    }
+
+    #[test]
+    fn test_auto_steer_selects_algorithm() {
+        let mut controller = new_controller();
+        assert_eq!(controller.algorithm(), SteeringAlgorithm::Pid);
+        controller.set_gyro(Some(crate::gyro::tests::mock_gyro(0)));
+
+        let settings = RateOfTurnSettings {
+            ki: 0.0,
+            ..RateOfTurnSettings::default()
+        };
+        controller.use_algorithm(SteeringAlgorithm::RateOfTurn, &settings);
+        // the gyro reports the boat turning to starboard at the maximum rate (counter-clockwise
+        // is positive on the chip), so a heading still 90° short needs no rudder
+        crate::gyro::tests::set_raw_rate((-MAX_RATE_OF_TURN / 0.00875).round() as i16);
+        let correction = controller.auto_steer(90.0, 0.0, 0.1).unwrap();
+        assert!(correction.abs() < 0.1, "{}", correction);
+
+        // the PID would have gone hard over
+        controller.use_algorithm(SteeringAlgorithm::Pid, &settings);
+        controller.reset();
+        assert!(controller.auto_steer(90.0, 0.0, 0.1).unwrap() < -1.0);
+    }
+
+    #[test]
+    fn test_gain_schedule_by_speed() {
+        let mut controller = new_controller();
+        let point = |speed_knots, kp| crate::config::GainPoint {
+            speed_knots,
+            kp,
+            ki: 0.0,
+            kd: 0.0,
+        };
+        controller.set_gain_schedule(&GainScheduleSettings {
+            points: vec![point(2.0, 2.0), point(7.0, 0.5)],
+        });
+
+        // no speed: the [pid] gains
+        let active = controller.active_gains();
+        assert_eq!(active.gains.kp, PidSettings::default().kp);
+        assert_eq!(
+            active.to_string(),
+            "kp 1.00 ki 0.000 kd 0.00 (no speed, [pid] gains)"
+        );
+        let default = controller.calculate_correction(90.0, 100.0, 0.1);
+
+        controller.set_speed(Some(2.0));
+        let slow = controller.calculate_correction(90.0, 100.0, 0.1);
+        controller.set_speed(Some(7.0));
+        let fast = controller.calculate_correction(90.0, 100.0, 0.1);
+        assert_eq!(slow, 2.0 * default);
+        assert_eq!(fast, 0.5 * default);
+        assert_eq!(
+            controller.active_gains().to_string(),
+            "kp 0.50 ki 0.000 kd 0.00 (scheduled for 7.0 kn)"
+        );
+
+        // the speed is lost: back to [pid]
+        controller.set_speed(None);
+        assert_eq!(controller.calculate_correction(90.0, 100.0, 0.1), default);
+    }
+
+    #[test]
+    fn test_closes_loop_on_rudder_sensor() {
+        let mut controller = new_controller();
+        assert_eq!(controller.rudder_angle(), None);
+        let settings = RudderSettings::default();
+        crate::mocks::mock_i2c::reset_mock_registers();
+        let sensor = RudderSensor::new(&settings).unwrap();
+        controller.set_rudder_sensor(Some(sensor), &settings);
+        let duty = |angle: f64| (1500.0 + angle / 90.0 * 500.0) / 20000.0;
+
+        // the rudder lags 10° behind the servo: driven further than commanded
+        crate::rudder::tests::set_raw(&settings, 13933);
+        controller.set_angle(30.0).unwrap();
+        assert_eq!(controller.get_angle(), 30.0);
+        assert!((controller.rudder_angle().unwrap() - 20.0).abs() < 0.1);
+        assert!(mock_pwm::get_mock_duty_cycle() > duty(34.9));
+        assert_eq!(controller.rudder_fault(), None);
+
+        // without a plausible reading the servo is driven open loop
+        crate::rudder::tests::set_raw(&settings, 0);
+        controller.set_angle(30.0).unwrap();
+        assert_eq!(controller.rudder_fault(), Some(RudderFault::Disconnected));
+        assert!((mock_pwm::get_mock_duty_cycle() - duty(30.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_motor_drive() {
+        let settings = ServoSettings {
+            max_angle: 30.0,
+            ..ServoSettings::default()
+        };
+        let motor = MotorSettings {
+            ramp_secs: 0.0,
+            ..MotorSettings::default()
+        };
+        let config = Config {
+            servo: settings,
+            motor: motor.clone(),
+            ..Config::default()
+        };
+        let mut controller = ServoController::with_motor(&config).unwrap();
+        assert_eq!(controller.actuator(), Actuator::Motor);
+
+        // no rudder angle, no driving
+        controller.set_angle(20.0).unwrap();
+        assert_eq!(controller.motor_fault(), Some(MotorFault::NoFeedback));
+        assert_eq!(mock_pwm::get_mock_duty_cycle(), 0.0);
+
+        let rudder = RudderSettings::default();
+        crate::mocks::mock_i2c::reset_mock_registers();
+        let sensor = RudderSensor::new(&rudder).unwrap();
+        controller.set_rudder_sensor(Some(sensor), &rudder);
+        // rudder at 10° to port, 30° short of the command: full speed to starboard
+        crate::rudder::tests::set_raw(&rudder, 12100);
+        controller.set_angle(20.0).unwrap();
+        assert_eq!(controller.motor_fault(), None);
+        assert_eq!(mock_pwm::get_mock_duty_cycle(), motor.max_duty);
+
+        // standby lets go of the helm where it is
+        controller.center().unwrap();
+        assert_eq!(mock_pwm::get_mock_duty_cycle(), 0.0);
+        assert!((controller.get_angle() + 10.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_release_and_shutdown() {
+        let settings = ServoSettings::default();
+        let duty = |angle: f64| pulse_for_angle(&settings, angle) / 20000.0;
+        let mut controller = new_controller();
+
+        controller.set_angle(20.0).unwrap();
+        controller.release().unwrap();
+        assert!(controller.is_released());
+        assert!(!mock_pwm::is_mock_enabled());
+        // steering again switches the pulses back on
+        controller.set_angle(10.0).unwrap();
+        assert!(!controller.is_released());
+        assert!(mock_pwm::is_mock_enabled());
+
+        // dropped (shutdown, panic): centered, then switched off
+        controller.set_angle(30.0).unwrap();
+        drop(controller);
+        assert!((mock_pwm::get_mock_duty_cycle() - duty(0.0)).abs() < 1e-9);
+        assert!(!mock_pwm::is_mock_enabled());
+
+        // or left where it is, as configured
+        let config = Config {
+            failsafe: FailsafeSettings {
+                shutdown_action: FailsafeAction::Release,
+                ..FailsafeSettings::default()
+            },
+            ..Config::default()
+        };
+        let mut controller = ServoController::new(&config).unwrap();
+        controller.set_angle(30.0).unwrap();
+        drop(controller);
+        assert!((mock_pwm::get_mock_duty_cycle() - duty(30.0)).abs() < 1e-9);
+        assert!(!mock_pwm::is_mock_enabled());
+    }
+
+    #[test]
+    fn test_failsafe_handle() {
+        let settings = ServoSettings::default();
+        let duty = |angle: f64| pulse_for_angle(&settings, angle) / 20000.0;
+        let mut controller = new_controller();
+        let handle = controller.failsafe_handle();
+
+        controller.set_angle(30.0).unwrap();
+        handle.apply(FailsafeAction::Hold).unwrap();
+        assert!((mock_pwm::get_mock_duty_cycle() - duty(30.0)).abs() < 1e-9);
+        handle.apply(FailsafeAction::Center).unwrap();
+        assert!((mock_pwm::get_mock_duty_cycle() - duty(0.0)).abs() < 1e-9);
+        handle.apply(FailsafeAction::Release).unwrap();
+        assert!(controller.is_released());
+
+        // the control loop stuck while driving the output
+        let output = controller.output();
+        assert!(handle.apply(FailsafeAction::Center).is_err());
+        drop(output);
+    }
+
+    #[test]
+    fn test_follows_live_parameters() {
+        let parameters = Parameters::new(Default::default(), None);
+        let mut controller = new_controller();
+        controller.follow(parameters.clone());
+
+        // 3° is outside the default deadband
+        assert!(controller.calculate_correction(90.0, 93.0, 0.1) > 0.0);
+
+        parameters.set("pid.deadband", "5").unwrap();
+        parameters.set("servo.frequency_hz", "100").unwrap();
+        // staged only
+        assert!(controller.calculate_correction(90.0, 93.0, 0.1) > 0.0);
+
+        parameters.apply_pending();
+        assert_eq!(controller.calculate_correction(90.0, 93.0, 0.1), 0.0);
+        // the PWM frequency can't change while running
+        assert_eq!(controller.settings().frequency_hz, 50.0);
+    }
 }
@@ -0,0 +1,580 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::config::NavigationSettings;
+use crate::gpx::Gpx;
+use crate::position::Position;
+
+/// Meters in a nautical mile.
+pub const METERS_PER_NM: f64 = 1852.0;
+/// Knots to meters per second.
+pub const KNOTS_TO_MPS: f64 = METERS_PER_NM / 3600.0;
+/// Below this speed the ETA is meaningless.
+const MIN_ETA_SPEED_KNOTS: f64 = 0.1;
+
+/// A named point on a route.
+#[derive(Clone, Debug)]
+pub struct Waypoint {
+    pub name: String,
+    pub position: Position,
+    /// Meters above mean sea level (from GPX).
+    pub elevation: Option<f64>,
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl Waypoint {
+    pub fn new(name: &str, position: Position) -> Self {
+        Self {
+            name: name.to_string(),
+            position,
+            elevation: None,
+            time: None,
+        }
+    }
+}
+
+impl fmt::Display for Waypoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.position)
+    }
+}
+
+/// How the boat reached a waypoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArrivalReason {
+    /// Inside the arrival circle.
+    ArrivalCircle,
+    /// Crossed the line through the waypoint perpendicular to the leg.
+    PerpendicularPassed,
+}
+
+/// Emitted when a waypoint is reached.
+#[derive(Clone, Debug)]
+pub struct Arrival {
+    pub waypoint: Waypoint,
+    pub reason: ArrivalReason,
+    /// True when this was the last waypoint of the route.
+    pub route_complete: bool,
+}
+
+/// Navigation data for the active leg.
+#[derive(Clone, Debug)]
+pub struct LegStatus {
+    /// Zero-based leg number (leg 0 ends at the first waypoint).
+    pub leg: usize,
+    pub waypoint: Waypoint,
+    /// Bearing from the boat to the waypoint (degrees).
+    pub bearing: f64,
+    /// Distance from the boat to the waypoint (meters).
+    pub distance_m: f64,
+    /// Cross-track error from the leg (meters, positive = right of track).
+    pub cross_track_m: f64,
+    /// Time to the waypoint at the current speed over ground.
+    pub eta: Option<Duration>,
+}
+
+impl fmt::Display for LegStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "→ {} | {:.1}° | {:.2} nm | XTE {:.0} m {}",
+            self.waypoint.name,
+            self.bearing,
+            self.distance_m / METERS_PER_NM,
+            self.cross_track_m.abs(),
+            if self.cross_track_m >= 0.0 { "R" } else { "L" }
+        )?;
+
+        match self.eta {
+            Some(eta) => {
+                let secs = eta.as_secs();
+                write!(
+                    f,
+                    " | ETA {:02}:{:02}:{:02}",
+                    secs / 3600,
+                    (secs / 60) % 60,
+                    secs % 60
+                )
+            }
+            None => write!(f, " | ETA N/A"),
+        }
+    }
+}
+
+/// Track-keeping controller: steers to get back onto the leg instead of straight at the
+/// waypoint, so a cross current doesn't push the boat along a curved path.
+///
+/// The cross-track error is turned into an intercept angle towards the track,
+/// `atan(xte / lookahead)`, capped at `max_intercept_deg`. Close to the track this behaves like
+/// a proportional controller; far off it the boat converges at the maximum angle.
+#[derive(Clone, Copy, Debug)]
+pub struct TrackController {
+    /// Distance ahead along the track that the boat aims for (meters).
+    pub lookahead_m: f64,
+    /// Maximum intercept angle (degrees).
+    pub max_intercept_deg: f64,
+}
+
+impl Default for TrackController {
+    fn default() -> Self {
+        Self::new(&NavigationSettings::default())
+    }
+}
+
+impl TrackController {
+    pub fn new(settings: &NavigationSettings) -> Self {
+        Self {
+            lookahead_m: settings.lookahead_distance_m,
+            max_intercept_deg: settings.max_intercept_angle_deg,
+        }
+    }
+
+    /// Heading correction for a cross-track error (degrees, negative = turn left).
+    pub fn intercept_angle(&self, cross_track_m: f64) -> f64 {
+        -(cross_track_m.atan2(self.lookahead_m).to_degrees())
+            .clamp(-self.max_intercept_deg, self.max_intercept_deg)
+    }
+
+    /// Heading to steer to follow the leg from `start` to `end`.
+    pub fn heading(&self, start: &Position, end: &Position, position: &Position) -> f64 {
+        let along_track = position.along_track_distance(start, end);
+        let cross_track = position.cross_track_distance(start, end);
+
+        // bearing of the track at the point abeam of the boat
+        let leg_bearing = start.heading_to(end);
+        let abeam = start.project(leg_bearing, along_track);
+        let track_bearing = if abeam.distance_to(end) > f64::EPSILON {
+            abeam.heading_to(end)
+        } else {
+            leg_bearing
+        };
+
+        (track_bearing + self.intercept_angle(cross_track)).rem_euclid(360.0)
+    }
+}
+
+/// An ordered list of waypoints with an active leg.
+///
+/// The first leg starts wherever the boat was when the route was activated; each following leg
+/// runs from the previous waypoint to the next. A waypoint counts as reached when the boat enters
+/// its arrival circle or passes the perpendicular through it, and the route then advances to the
+/// next leg on its own. A [`Route::homing`] route is different, see there.
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub name: String,
+    waypoints: Vec<Waypoint>,
+    /// Index of the waypoint we're heading for.
+    active: usize,
+    /// Start of the active leg.
+    leg_start: Option<Position>,
+    arrival_radius_m: f64,
+    track_controller: TrackController,
+    /// Arrive only by coming back into the circle, see [`Route::homing`].
+    homing: bool,
+    /// The boat has been outside the arrival circle since the route was activated.
+    left_circle: bool,
+}
+
+impl Route {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            waypoints: Vec::new(),
+            active: 0,
+            leg_start: None,
+            arrival_radius_m: NavigationSettings::default().arrival_radius_m,
+            track_controller: TrackController::default(),
+            homing: false,
+            left_circle: false,
+        }
+    }
+
+    /// A route back to a single mark the boat may still be next to, e.g. a man overboard. The
+    /// mark counts as reached only on coming back into its arrival circle after having left it,
+    /// never by passing the perpendicular.
+    pub fn homing(name: &str, waypoint: Waypoint) -> Self {
+        Self {
+            homing: true,
+            ..Self::with_waypoints(name, vec![waypoint])
+        }
+    }
+
+    /// Create a route from a list of waypoints.
+    pub fn with_waypoints(name: &str, waypoints: Vec<Waypoint>) -> Self {
+        Self {
+            waypoints,
+            ..Self::new(name)
+        }
+    }
+
+    /// Load a route from a text file with one `name,latitude,longitude` waypoint per line.
+    /// Blank lines and lines starting with `#` are ignored. Files ending in `.gpx` are read as
+    /// GPX instead (first `<rte>`, or the `<wpt>` list if there is no route).
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gpx"))
+        {
+            return Gpx::read(path)?
+                .into_route(&name)
+                .ok_or_else(|| format!("{}: no route or waypoints", path.display()).into());
+        }
+
+        let text = fs::read_to_string(path)?;
+        Self::parse(&name, &text)
+    }
+
+    /// Parse `name,latitude,longitude` lines (see [`Route::load`]).
+    pub fn parse(name: &str, text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut route = Self::new(name);
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [wp_name, lat, lon] = fields[..] else {
+                return Err(format!("line {}: expected name,latitude,longitude", index + 1).into());
+            };
+
+            let lat: f64 = lat
+                .parse()
+                .map_err(|_| format!("line {}: invalid latitude '{}'", index + 1, lat))?;
+            let lon: f64 = lon
+                .parse()
+                .map_err(|_| format!("line {}: invalid longitude '{}'", index + 1, lon))?;
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                return Err(format!("line {}: coordinates out of range", index + 1).into());
+            }
+
+            route.add_waypoint(wp_name, Position::new(lat, lon));
+        }
+
+        Ok(route)
+    }
+
+    pub fn add_waypoint(&mut self, name: &str, position: Position) {
+        self.waypoints.push(Waypoint::new(name, position));
+    }
+
+    pub fn waypoints(&self) -> &[Waypoint] {
+        &self.waypoints
+    }
+
+    /// Use the arrival radius and track-keeping settings from the configuration.
+    pub fn configure(&mut self, settings: &NavigationSettings) {
+        self.arrival_radius_m = settings.arrival_radius_m;
+        self.track_controller = TrackController::new(settings);
+    }
+
+    /// Set the arrival circle radius (meters).
+    pub fn set_arrival_radius(&mut self, radius_m: f64) {
+        self.arrival_radius_m = radius_m;
+    }
+
+    /// Replace the track-keeping controller parameters.
+    pub fn set_track_controller(&mut self, controller: TrackController) {
+        self.track_controller = controller;
+    }
+
+    /// Start (or restart) the route at the first waypoint, with the first leg beginning at
+    /// `position`.
+    pub fn activate(&mut self, position: Position) {
+        self.active = 0;
+        self.leg_start = Some(position);
+        self.left_circle = false;
+    }
+
+    /// Skip ahead to the given waypoint, starting the leg at `position`.
+    pub fn activate_waypoint(
+        &mut self,
+        index: usize,
+        position: Position,
+    ) -> Result<(), Box<dyn Error>> {
+        if index >= self.waypoints.len() {
+            return Err(format!("Route has no waypoint {}", index + 1).into());
+        }
+        self.active = index;
+        self.leg_start = Some(position);
+        Ok(())
+    }
+
+    /// Whether the route is activated and has a waypoint left to reach.
+    pub fn is_active(&self) -> bool {
+        self.leg_start.is_some() && self.active < self.waypoints.len()
+    }
+
+    /// Whether the route still has waypoints to reach (activated or not).
+    pub fn has_remaining(&self) -> bool {
+        self.active < self.waypoints.len()
+    }
+
+    /// Whether all waypoints have been reached.
+    pub fn is_complete(&self) -> bool {
+        !self.waypoints.is_empty() && self.active >= self.waypoints.len()
+    }
+
+    /// Waypoint we're heading for.
+    pub fn next_waypoint(&self) -> Option<&Waypoint> {
+        self.waypoints.get(self.active)
+    }
+
+    /// Start and end of the active leg.
+    pub fn active_leg(&self) -> Option<(Position, &Waypoint)> {
+        if !self.is_active() {
+            return None;
+        }
+        Some((self.leg_start?, self.next_waypoint()?))
+    }
+
+    /// Bearing, distance and ETA to the next waypoint.
+    pub fn leg_status(&self, position: &Position, speed_knots: Option<f64>) -> Option<LegStatus> {
+        let (leg_start, waypoint) = self.active_leg()?;
+        let distance_m = position.distance_to(&waypoint.position);
+
+        let eta = speed_knots
+            .filter(|speed| *speed > MIN_ETA_SPEED_KNOTS)
+            .map(|speed| Duration::from_secs_f64(distance_m / (speed * KNOTS_TO_MPS)));
+
+        Some(LegStatus {
+            leg: self.active,
+            waypoint: waypoint.clone(),
+            bearing: position.heading_to(&waypoint.position),
+            distance_m,
+            cross_track_m: position.cross_track_distance(&leg_start, &waypoint.position),
+            eta,
+        })
+    }
+
+    /// Heading to steer to follow the active leg, correcting for cross-track error.
+    pub fn steering_heading(&self, position: &Position) -> Option<f64> {
+        let (leg_start, waypoint) = self.active_leg()?;
+        Some(
+            self.track_controller
+                .heading(&leg_start, &waypoint.position, position),
+        )
+    }
+
+    /// Check for arrival at the next waypoint and advance to the following leg.
+    pub fn update(&mut self, position: &Position) -> Option<Arrival> {
+        let (leg_start, waypoint) = self.active_leg()?;
+
+        let inside = position.distance_to(&waypoint.position) <= self.arrival_radius_m;
+        if self.homing && !inside {
+            self.left_circle = true;
+            return None;
+        }
+        let reason = if inside {
+            if self.homing && !self.left_circle {
+                return None;
+            }
+            ArrivalReason::ArrivalCircle
+        } else if perpendicular_passed(&leg_start, &waypoint.position, position) {
+            ArrivalReason::PerpendicularPassed
+        } else {
+            return None;
+        };
+
+        let waypoint = waypoint.clone();
+        self.leg_start = Some(waypoint.position);
+        self.active += 1;
+
+        Some(Arrival {
+            waypoint,
+            reason,
+            route_complete: self.is_complete(),
+        })
+    }
+}
+
+/// True once `position` is beyond the line through `to` that is perpendicular to the leg.
+fn perpendicular_passed(from: &Position, to: &Position, position: &Position) -> bool {
+    if from.distance_to(to) < f64::EPSILON {
+        return true;
+    }
+
+    // direction of travel as the leg arrives at the waypoint, compared with the bearing from
+    // the waypoint to the boat: less than 90° apart means we're past it
+    let arrival_bearing = (to.heading_to(from) + 180.0) % 360.0;
+    let to_boat = to.heading_to(position);
+
+    let mut diff = (to_boat - arrival_bearing).abs() % 360.0;
+    if diff > 180.0 {
+        diff = 360.0 - diff;
+    }
+    diff < 90.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MAX_INTERCEPT_ANGLE_DEG;
+
+    fn test_route() -> Route {
+        let mut route = Route::new("test");
+        route.add_waypoint("A", Position::new(48.0, -123.0));
+        route.add_waypoint("B", Position::new(48.1, -123.0));
+        route.add_waypoint("C", Position::new(48.1, -122.9));
+        route
+    }
+
+    #[test]
+    fn test_parse_route() {
+        let text = "# harbour run\nA, 48.0, -123.0\n\nB,48.1,-123.0\n";
+        let route = Route::parse("harbour", text).unwrap();
+        assert_eq!(route.waypoints().len(), 2);
+        assert_eq!(route.waypoints()[1].name, "B");
+
+        assert!(Route::parse("bad", "A,48.0").is_err());
+        assert!(Route::parse("bad", "A,95.0,0.0").is_err());
+        let err = Route::parse("bad", "A,48.0,0\nB,x,0").unwrap_err();
+        assert!(err.to_string().starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_inactive_until_activated() {
+        let route = test_route();
+        assert!(!route.is_active());
+        assert!(
+            route
+                .leg_status(&Position::new(47.9, -123.0), None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_leg_status() {
+        let mut route = test_route();
+        let start = Position::new(47.99, -123.0);
+        route.activate(start);
+
+        let status = route.leg_status(&start, Some(5.0)).unwrap();
+        assert_eq!(status.leg, 0);
+        assert_eq!(status.waypoint.name, "A");
+        assert!(!(1.0..=359.0).contains(&status.bearing)); // due north
+        assert!((status.distance_m - 1112.0).abs() < 5.0);
+
+        // 1112 m at 5 knots (2.57 m/s) ≈ 432 s
+        let eta = status.eta.unwrap().as_secs_f64();
+        assert!((eta - 432.0).abs() < 5.0);
+
+        assert!(route.leg_status(&start, Some(0.0)).unwrap().eta.is_none());
+        assert!(route.leg_status(&start, None).unwrap().eta.is_none());
+    }
+
+    #[test]
+    fn test_arrival_circle_advances_leg() {
+        let mut route = test_route();
+        route.activate(Position::new(47.99, -123.0));
+
+        assert!(route.update(&Position::new(47.995, -123.0)).is_none());
+
+        // 20 m south of A
+        let arrival = route.update(&Position::new(47.99982, -123.0)).unwrap();
+        assert_eq!(arrival.waypoint.name, "A");
+        assert_eq!(arrival.reason, ArrivalReason::ArrivalCircle);
+        assert!(!arrival.route_complete);
+        assert_eq!(route.next_waypoint().unwrap().name, "B");
+
+        // leg A → B starts at A
+        let (leg_start, _) = route.active_leg().unwrap();
+        assert_eq!(leg_start.latitude, 48.0);
+    }
+
+    #[test]
+    fn test_perpendicular_passed() {
+        let mut route = test_route();
+        route.activate(Position::new(47.99, -123.0));
+
+        // passed A 500 m to the east, well outside the arrival circle
+        let arrival = route.update(&Position::new(48.0005, -122.993)).unwrap();
+        assert_eq!(arrival.reason, ArrivalReason::PerpendicularPassed);
+    }
+
+    #[test]
+    fn test_homing_arrives_after_leaving_the_circle() {
+        let mark = Position::new(48.0, -123.0);
+        let mut route = Route::homing("MOB", Waypoint::new("MOB", mark));
+        // activated 15 m past the mark, inside the 50 m circle
+        route.activate(mark.project(0.0, 15.0));
+
+        assert!(route.update(&mark.project(0.0, 15.0)).is_none());
+        assert!(route.update(&mark.project(0.0, 40.0)).is_none());
+        // well past the perpendicular, turning back
+        assert!(route.update(&mark.project(10.0, 120.0)).is_none());
+        assert!(route.is_active());
+
+        let arrival = route.update(&mark.project(10.0, 30.0)).unwrap();
+        assert_eq!(arrival.reason, ArrivalReason::ArrivalCircle);
+        assert!(arrival.route_complete);
+    }
+
+    #[test]
+    fn test_intercept_angle() {
+        let controller = TrackController {
+            lookahead_m: 100.0,
+            max_intercept_deg: 30.0,
+        };
+
+        assert_eq!(controller.intercept_angle(0.0), 0.0);
+        // right of track: turn left, 45° would be atan(1) but is capped
+        assert_eq!(controller.intercept_angle(100.0), -30.0);
+        assert_eq!(controller.intercept_angle(-100.0), 30.0);
+        // small errors give a proportional correction
+        let angle = controller.intercept_angle(20.0);
+        assert!((angle + 11.3).abs() < 0.1, "got {:.2}", angle);
+    }
+
+    #[test]
+    fn test_track_heading_corrects_xte() {
+        let controller = TrackController::default();
+        let start = Position::new(48.0, -123.0);
+        let end = Position::new(48.1, -123.0);
+
+        // on the track: steer the leg bearing
+        let on_track = start.project(0.0, 2000.0);
+        let heading = controller.heading(&start, &end, &on_track);
+        assert!(!(0.5..=359.5).contains(&heading), "got {:.1}", heading);
+
+        // pushed 50 m east (right) by the current: steer left of north
+        let right = on_track.project(90.0, 50.0);
+        let heading = controller.heading(&start, &end, &right);
+        assert!(heading > 330.0 && heading < 359.0, "got {:.1}", heading);
+
+        // 1 km west (left): converge at the maximum intercept angle
+        let far_left = on_track.project(270.0, 1000.0);
+        let heading = controller.heading(&start, &end, &far_left);
+        assert!(
+            (heading - MAX_INTERCEPT_ANGLE_DEG).abs() < 0.5,
+            "got {:.1}",
+            heading
+        );
+    }
+
+    #[test]
+    fn test_route_complete() {
+        let mut route = test_route();
+        route.activate(Position::new(47.99, -123.0));
+
+        route.update(&Position::new(48.0, -123.0)).unwrap();
+        route.update(&Position::new(48.1, -123.0)).unwrap();
+        let arrival = route.update(&Position::new(48.1, -122.9)).unwrap();
+
+        assert!(arrival.route_complete);
+        assert!(route.is_complete());
+        assert!(!route.is_active());
+        assert!(route.update(&Position::new(48.1, -122.9)).is_none());
+    }
+}
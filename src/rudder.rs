@@ -0,0 +1,331 @@
+//! Rudder angle feedback from a potentiometer on an ADS1115 ADC.
+//!
+//! The pot reading is turned into a rudder angle with the calibrated port end stop, center and
+//! starboard end stop readings, each side on its own scale so an off-center linkage still reads
+//! 0° amidships. [`RudderLoop`] closes a position loop on the measured angle and watches for a
+//! jammed rudder or a lost sensor.
+
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+// Use rppal in production
+#[cfg(not(test))]
+use rppal::i2c::I2c;
+
+#[cfg(test)]
+// This is only used in testing, not compiled in release.
+use crate::mocks::mock_i2c::I2c;
+
+use crate::config::{AntiWindup, PidSettings, RudderSettings};
+use crate::pid::Pid;
+
+// ADS1115 register addresses (16 bits, most significant byte first).
+const CONVERSION: u8 = 0x00;
+const CONFIG: u8 = 0x01;
+
+/// CONFIG: start a conversion / conversion in progress, read back either way.
+const OS: u16 = 0x8000;
+/// CONFIG: single ended input AIN0, the channel is added to it.
+const MUX_SINGLE_AIN0: u16 = 0x4000;
+const MUX_SHIFT: u16 = 12;
+/// CONFIG: ±4.096 V full scale, enough for a pot across 3.3 V.
+const PGA_4_096V: u16 = 0x0200;
+/// CONFIG: continuous conversion (MODE bit clear), 128 samples per second.
+const MODE_CONTINUOUS: u16 = 0x0000;
+const DR_128SPS: u16 = 0x0080;
+/// CONFIG: comparator off, ALERT/RDY pin high impedance.
+const COMP_QUE_DISABLE: u16 = 0x0003;
+
+/// Potentiometer on one ADS1115 input, converting continuously.
+pub struct RudderSensor {
+    i2c: I2c,
+}
+
+impl RudderSensor {
+    pub fn new(settings: &RudderSettings) -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(settings.i2c_address)?;
+
+        let config = MUX_SINGLE_AIN0
+            | (settings.channel as u16) << MUX_SHIFT
+            | PGA_4_096V
+            | MODE_CONTINUOUS
+            | DR_128SPS
+            | COMP_QUE_DISABLE;
+        i2c.smbus_write_word_swapped(CONFIG, config)?;
+
+        // the ADS1115 has no ID register: check the configuration reads back
+        let readback = i2c.smbus_read_word_swapped(CONFIG)?;
+        if readback & !OS != config {
+            return Err(format!(
+                "No ADS1115 at 0x{:02X}: configuration reads back 0x{:04X}",
+                settings.i2c_address, readback
+            )
+            .into());
+        }
+        // first conversion at 128 SPS
+        thread::sleep(Duration::from_millis(10));
+
+        crate::info!(
+            "✓ Rudder sensor (ADS1115 at 0x{:02X}, AIN{}) initialized",
+            settings.i2c_address,
+            settings.channel
+        );
+        Ok(Self { i2c })
+    }
+
+    /// Latest conversion, in counts of 125 µV.
+    pub fn read_raw(&mut self) -> Result<i16, Box<dyn Error>> {
+        Ok(self.i2c.smbus_read_word_swapped(CONVERSION)? as i16)
+    }
+}
+
+/// Rudder angle for a raw reading (degrees, positive to starboard). The end stops are
+/// `max_angle` either side of center.
+pub fn rudder_angle(settings: &RudderSettings, raw: f64, max_angle: f64) -> f64 {
+    let from_center = raw - settings.center_raw;
+    // the port side is the one the port reading is on, whichever way the pot is wired
+    if from_center * (settings.port_raw - settings.center_raw) > 0.0 {
+        -max_angle * from_center / (settings.port_raw - settings.center_raw)
+    } else {
+        max_angle * from_center / (settings.starboard_raw - settings.center_raw)
+    }
+}
+
+/// Whether a raw reading is one the pot can give. A broken wire or a pot slipped on its shaft
+/// reads well outside the calibrated range.
+pub fn is_plausible(settings: &RudderSettings, raw: f64) -> bool {
+    let low = settings.port_raw.min(settings.starboard_raw);
+    let high = settings.port_raw.max(settings.starboard_raw);
+    let margin = (high - low) * settings.disconnect_margin;
+    (low - margin..=high + margin).contains(&raw)
+}
+
+/// Why the rudder feedback can't be trusted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RudderFault {
+    /// No reading, or a reading outside the calibrated range.
+    Disconnected,
+    /// The rudder stays away from the commanded angle.
+    Jammed,
+}
+
+impl fmt::Display for RudderFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            RudderFault::Disconnected => "sensor disconnected",
+            RudderFault::Jammed => "jammed",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Position loop on the measured rudder angle. It adds a trim to the commanded angle until the
+/// rudder gets there, taking out linkage slop and the load on the blade. While the feedback is
+/// faulty the command goes out unchanged.
+pub struct RudderLoop {
+    settings: RudderSettings,
+    max_angle: f64,
+    trim: Pid,
+    angle: Option<f64>,
+    /// How long the rudder has been further than the jam tolerance from the target (seconds).
+    off_target_secs: f64,
+    fault: Option<RudderFault>,
+}
+
+impl RudderLoop {
+    pub fn new(settings: &RudderSettings, max_angle: f64) -> Self {
+        Self {
+            settings: settings.clone(),
+            max_angle,
+            trim: Pid::new(&trim_settings(settings), settings.max_trim_deg),
+            angle: None,
+            off_target_secs: 0.0,
+            fault: None,
+        }
+    }
+
+    /// Change the settings, keeping the trim built up so far.
+    pub fn configure(&mut self, settings: &RudderSettings, max_angle: f64) {
+        self.settings = settings.clone();
+        self.max_angle = max_angle;
+        self.trim
+            .configure(&trim_settings(settings), settings.max_trim_deg);
+    }
+
+    /// Last measured rudder angle (degrees), `None` without a plausible reading.
+    pub fn angle(&self) -> Option<f64> {
+        self.angle
+    }
+
+    pub fn fault(&self) -> Option<RudderFault> {
+        self.fault
+    }
+
+    /// Take the rudder to `target` (degrees) given the latest reading, `dt` seconds after the
+    /// last update. Returns the angle to command.
+    pub fn update(&mut self, target: f64, raw: Option<i16>, dt: f64) -> f64 {
+        let angle = raw
+            .map(f64::from)
+            .filter(|&raw| is_plausible(&self.settings, raw))
+            .map(|raw| rudder_angle(&self.settings, raw, self.max_angle));
+        self.angle = angle;
+
+        let Some(angle) = angle else {
+            self.set_fault(Some(RudderFault::Disconnected));
+            self.restart();
+            return target;
+        };
+
+        let error = target - angle;
+        if error.abs() > self.settings.jam_tolerance_deg {
+            self.off_target_secs += dt.max(0.0);
+        } else {
+            self.off_target_secs = 0.0;
+        }
+        if self.off_target_secs >= self.settings.jam_timeout_secs {
+            // don't push harder against whatever is holding it
+            self.set_fault(Some(RudderFault::Jammed));
+            self.trim.reset(0.0);
+            return target;
+        }
+        self.set_fault(None);
+
+        // inside the deadband the trim holds what it has
+        let measurement = if error.abs() < self.settings.deadband_deg {
+            target
+        } else {
+            angle
+        };
+        target + self.trim.update(target, measurement, dt)
+    }
+
+    /// Forget the trim and the jam timer.
+    fn restart(&mut self) {
+        self.trim.reset(0.0);
+        self.off_target_secs = 0.0;
+    }
+
+    fn set_fault(&mut self, fault: Option<RudderFault>) {
+        if fault == self.fault {
+            return;
+        }
+        match (fault, self.fault) {
+            (Some(RudderFault::Jammed), _) => {
+                crate::warn!("  ⚠ Rudder jammed - not pushing harder")
+            }
+            (Some(fault), _) => crate::warn!("  ⚠ Rudder {} - steering without feedback", fault),
+            (None, Some(fault)) => crate::info!("  ✓ Rudder feedback back ({} cleared)", fault),
+            (None, None) => {}
+        }
+        self.fault = fault;
+    }
+}
+
+/// The trim is a PI loop on the rudder angle; the deadband is applied by [`RudderLoop`] so the
+/// trim isn't dropped on target.
+fn trim_settings(settings: &RudderSettings) -> PidSettings {
+    PidSettings {
+        kp: settings.position_kp,
+        ki: settings.position_ki,
+        kd: 0.0,
+        deadband: 0.0,
+        integral_limit: settings.max_trim_deg,
+        anti_windup: AntiWindup::Clamp,
+        derivative_filter_secs: 0.0,
+        setpoint_weight: 1.0,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::mocks::mock_i2c;
+
+    /// Set the raw reading the mock ADC reports.
+    pub(crate) fn set_raw(settings: &RudderSettings, raw: i16) {
+        mock_i2c::set_mock_word(settings.i2c_address, CONVERSION, raw as u16);
+    }
+
+    #[test]
+    fn test_sensor_configuration() {
+        mock_i2c::reset_mock_registers();
+        let settings = RudderSettings {
+            channel: 2,
+            ..RudderSettings::default()
+        };
+        let mut sensor = RudderSensor::new(&settings).unwrap();
+        // AIN2 single ended, ±4.096 V, continuous, 128 SPS, comparator off
+        assert_eq!(mock_i2c::get_mock_word(0x48, CONFIG), 0x6283);
+
+        set_raw(&settings, 12345);
+        assert_eq!(sensor.read_raw().unwrap(), 12345);
+    }
+
+    #[test]
+    fn test_angle_from_calibration() {
+        // reversed pot, with more counts to starboard than to port
+        let settings = RudderSettings {
+            port_raw: 16000.0,
+            center_raw: 13000.0,
+            starboard_raw: 9000.0,
+            ..RudderSettings::default()
+        };
+        assert_eq!(rudder_angle(&settings, 13000.0, 40.0), 0.0);
+        assert_eq!(rudder_angle(&settings, 16000.0, 40.0), -40.0);
+        assert_eq!(rudder_angle(&settings, 9000.0, 40.0), 40.0);
+        assert_eq!(rudder_angle(&settings, 14500.0, 40.0), -20.0);
+        assert_eq!(rudder_angle(&settings, 11000.0, 40.0), 20.0);
+
+        // 10% of the 7000 count range either side
+        assert!(is_plausible(&settings, 16600.0));
+        assert!(!is_plausible(&settings, 16800.0));
+        assert!(!is_plausible(&settings, 0.0));
+    }
+
+    #[test]
+    fn test_position_loop() {
+        let settings = RudderSettings::default();
+        let mut rudder = RudderLoop::new(&settings, 30.0);
+        let raw = |angle: f64| (settings.center_raw + angle / 30.0 * 3300.0) as i16;
+
+        // rudder short of the target: pushed further
+        let command = rudder.update(10.0, Some(raw(6.0)), 0.1);
+        assert!((rudder.angle().unwrap() - 6.0).abs() < 0.01);
+        assert!(command > 10.0 + 0.5 * 4.0 - 0.1, "{}", command);
+        assert_eq!(rudder.fault(), None);
+
+        // on target the trim is held, not dropped
+        let trim = command - 10.0;
+        let command = rudder.update(10.0, Some(raw(10.0)), 0.1);
+        assert!(command > 10.0 && command < 10.0 + trim, "{}", command);
+    }
+
+    #[test]
+    fn test_jam_and_disconnect() {
+        let settings = RudderSettings::default();
+        let mut rudder = RudderLoop::new(&settings, 30.0);
+        let center = Some(settings.center_raw as i16);
+
+        // stuck amidships while 20° is commanded
+        for _ in 0..29 {
+            rudder.update(20.0, center, 0.1);
+        }
+        assert_eq!(rudder.fault(), None);
+        assert_eq!(rudder.update(20.0, center, 0.1), 20.0);
+        assert_eq!(rudder.fault(), Some(RudderFault::Jammed));
+
+        // it frees up
+        rudder.update(0.0, center, 0.1);
+        assert_eq!(rudder.fault(), None);
+
+        // wiper floating to a rail, or no reading at all
+        assert_eq!(rudder.update(5.0, Some(i16::MAX), 0.1), 5.0);
+        assert_eq!(rudder.fault(), Some(RudderFault::Disconnected));
+        assert_eq!(rudder.angle(), None);
+        rudder.update(5.0, None, 0.1);
+        assert_eq!(rudder.fault(), Some(RudderFault::Disconnected));
+    }
+}
@@ -0,0 +1,107 @@
+//! Simulated GPS for running without hardware (`--simulate`).
+//!
+//! The boat sails a constant course at constant speed with a slow wave-induced yaw, which is
+//! enough to exercise the display, logging and route code on a desk.
+
+use std::f64::consts::TAU;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::GpsTracker;
+use crate::position::Position;
+use crate::route::KNOTS_TO_MPS;
+
+/// Yaw amplitude (degrees) and period of the simulated swell.
+const YAW_AMPLITUDE_DEG: f64 = 4.0;
+const YAW_PERIOD_SECS: f64 = 8.0;
+
+pub struct BoatSimulator {
+    position: Position,
+    course: f64,
+    speed_knots: f64,
+    elapsed: f64,
+}
+
+impl BoatSimulator {
+    pub fn new(start: Position, course: f64, speed_knots: f64) -> Self {
+        Self {
+            position: start,
+            course,
+            speed_knots,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Heading including the yaw from the swell.
+    pub fn heading(&self) -> f64 {
+        let yaw = YAW_AMPLITUDE_DEG * (TAU * self.elapsed / YAW_PERIOD_SECS).sin();
+        (self.course + yaw).rem_euclid(360.0)
+    }
+
+    pub fn set_course(&mut self, course: f64) {
+        self.course = course.rem_euclid(360.0);
+    }
+
+    /// Advance the boat by `dt` along its current heading.
+    pub fn step(&mut self, dt: Duration) {
+        let distance = self.speed_knots * KNOTS_TO_MPS * dt.as_secs_f64();
+        self.position = self.position.project(self.heading(), distance);
+        self.elapsed += dt.as_secs_f64();
+    }
+
+    /// Publish the current state as a GPS fix.
+    pub fn update_tracker(&self, tracker: &mut GpsTracker) {
+        tracker.update_position(self.position.latitude, self.position.longitude);
+        tracker.update_heading(self.heading());
+        tracker.update_speed(self.speed_knots);
+        tracker.update_satellites(10);
+        tracker.update_hdop(0.9);
+        tracker.record_sentence();
+    }
+}
+
+/// Feed simulated fixes into the tracker at 1 Hz, like a typical receiver.
+pub fn start_simulated_gps(tracker: Arc<Mutex<GpsTracker>>, mut simulator: BoatSimulator) {
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    thread::spawn(move || {
+        loop {
+            if let Ok(mut tracker_lock) = tracker.lock() {
+                simulator.update_tracker(&mut tracker_lock);
+            }
+            thread::sleep(INTERVAL);
+            simulator.step(INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_boat_moves_along_course() {
+        let start = Position::new(48.0, -123.0);
+        let mut simulator = BoatSimulator::new(start, 90.0, 6.0);
+
+        // whole yaw periods cancel out
+        for _ in 0..16 {
+            simulator.step(Duration::from_millis(500));
+        }
+
+        let distance = start.distance_to(&simulator.position());
+        let expected = 6.0 * KNOTS_TO_MPS * 8.0;
+        assert!((distance - expected).abs() < 0.5, "{}", distance);
+        assert!((start.heading_to(&simulator.position()) - 90.0).abs() < 1.0);
+
+        let mut tracker = GpsTracker::new();
+        simulator.update_tracker(&mut tracker);
+        assert_eq!(tracker.get_current_speed(), Some(6.0));
+        assert!(tracker.get_current_position().is_some());
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+
+use crate::position::Position;
+
+/// A recorded fix.
+#[derive(Clone, Debug)]
+pub struct TrackPoint {
+    pub position: Position,
+    pub time: Option<DateTime<Utc>>,
+    /// Meters above mean sea level.
+    pub elevation: Option<f64>,
+}
+
+impl TrackPoint {
+    pub fn new(position: Position) -> Self {
+        Self {
+            position,
+            time: None,
+            elevation: None,
+        }
+    }
+}
+
+/// A recorded track, split into segments wherever recording was interrupted.
+#[derive(Clone, Debug, Default)]
+pub struct Track {
+    pub name: Option<String>,
+    pub segments: Vec<Vec<TrackPoint>>,
+}
+
+impl Track {
+    pub fn new(name: Option<&str>) -> Self {
+        Self {
+            name: name.map(str::to_string),
+            segments: Vec::new(),
+        }
+    }
+
+    /// All points of all segments in order.
+    pub fn points(&self) -> impl Iterator<Item = &TrackPoint> {
+        self.segments.iter().flatten()
+    }
+
+    /// Total distance along the track (meters). Gaps between segments are not counted.
+    pub fn distance(&self) -> f64 {
+        self.segments
+            .iter()
+            .map(|segment| {
+                segment
+                    .windows(2)
+                    .map(|pair| pair[0].position.distance_to(&pair[1].position))
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+}
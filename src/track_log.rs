@@ -0,0 +1,515 @@
+//! Background track logger.
+//!
+//! The control loop hands [`LogRecord`]s to a [`TrackLogger`], which decides whether they are due
+//! (by time or distance) and passes them to a writer thread. The writer appends one CSV line per
+//! record to `track-YYYYMMDD-HHMMSS.csv`, flushing each line as it is written, and starts a new
+//! file when the current one grows too large or the UTC day changes.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+
+use crate::autopilot::Mode;
+use crate::config::TrackLogSettings;
+use crate::position::Position;
+use crate::track::{Track, TrackPoint};
+
+/// Column header written at the top of every log file.
+pub const LOG_HEADER: &str =
+    "time,latitude,longitude,speed_kn,gps_heading,compass_heading,target_heading,rudder_angle,mode";
+
+/// One line of the track log. Missing values are written as empty fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRecord {
+    pub time: DateTime<Utc>,
+    pub position: Option<Position>,
+    /// Speed over ground (knots).
+    pub speed: Option<f64>,
+    pub gps_heading: Option<f64>,
+    pub compass_heading: Option<f64>,
+    pub target_heading: Option<f64>,
+    /// Commanded rudder angle (degrees, positive = starboard).
+    pub rudder_angle: Option<f64>,
+    pub mode: Mode,
+}
+
+impl LogRecord {
+    /// A record stamped now with everything but the mode unset.
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            time: Utc::now(),
+            position: None,
+            speed: None,
+            gps_heading: None,
+            compass_heading: None,
+            target_heading: None,
+            rudder_angle: None,
+            mode,
+        }
+    }
+
+    /// Format as a CSV line (without the newline).
+    pub fn to_csv(&self) -> String {
+        let mut line = self.time.to_rfc3339_opts(SecondsFormat::Millis, true);
+        let (lat, lon) = match self.position {
+            Some(pos) => (Some(pos.latitude), Some(pos.longitude)),
+            None => (None, None),
+        };
+
+        write_field(&mut line, lat, 7);
+        write_field(&mut line, lon, 7);
+        write_field(&mut line, self.speed, 2);
+        write_field(&mut line, self.gps_heading, 1);
+        write_field(&mut line, self.compass_heading, 1);
+        write_field(&mut line, self.target_heading, 1);
+        write_field(&mut line, self.rudder_angle, 1);
+        let _ = write!(line, ",{}", self.mode);
+        line
+    }
+
+    /// Parse a line written by [`LogRecord::to_csv`].
+    pub fn parse(line: &str) -> Result<Self, Box<dyn Error>> {
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        let [time, lat, lon, speed, gps, compass, target, rudder, mode] = fields[..] else {
+            return Err(format!("expected 9 fields, found {}", fields.len()).into());
+        };
+
+        let position = match (parse_field(lat)?, parse_field(lon)?) {
+            (Some(lat), Some(lon)) => Some(Position::new(lat, lon)),
+            _ => None,
+        };
+
+        Ok(Self {
+            time: DateTime::parse_from_rfc3339(time)
+                .map_err(|_| format!("invalid time '{}'", time))?
+                .with_timezone(&Utc),
+            position,
+            speed: parse_field(speed)?,
+            gps_heading: parse_field(gps)?,
+            compass_heading: parse_field(compass)?,
+            target_heading: parse_field(target)?,
+            rudder_angle: parse_field(rudder)?,
+            mode: mode.parse()?,
+        })
+    }
+}
+
+fn write_field(line: &mut String, value: Option<f64>, precision: usize) {
+    line.push(',');
+    if let Some(value) = value {
+        let _ = write!(line, "{:.*}", precision, value);
+    }
+}
+
+fn parse_field(field: &str) -> Result<Option<f64>, Box<dyn Error>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("invalid number '{}'", field).into())
+}
+
+/// Read all records from a log file. Errors carry the line number.
+pub fn read_log(path: &Path) -> Result<Vec<LogRecord>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with(LOG_HEADER) {
+            continue;
+        }
+        let record = LogRecord::parse(&line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), index + 1, e))?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Turn log records into a track. Records without a position are skipped.
+pub fn records_to_track(name: &str, records: &[LogRecord]) -> Track {
+    let mut track = Track::new(Some(name));
+    let points = records
+        .iter()
+        .filter_map(|record| {
+            record.position.map(|position| TrackPoint {
+                time: Some(record.time),
+                ..TrackPoint::new(position)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if !points.is_empty() {
+        track.segments.push(points);
+    }
+    track
+}
+
+/// When to log and where.
+#[derive(Clone, Debug)]
+pub struct TrackLogConfig {
+    pub directory: PathBuf,
+    /// Log at least this often (`None` = only by distance).
+    pub interval: Option<Duration>,
+    /// Log whenever the boat has moved this far (meters, `None` = only by time).
+    pub min_distance_m: Option<f64>,
+    /// Rotate once a file reaches this size (bytes, `None` = no limit).
+    pub max_file_bytes: Option<u64>,
+    /// Rotate at midnight UTC.
+    pub rotate_daily: bool,
+    /// How often the file is synced to disk.
+    pub sync_interval: Duration,
+}
+
+impl Default for TrackLogConfig {
+    fn default() -> Self {
+        Self::from(&TrackLogSettings::default())
+    }
+}
+
+impl From<&TrackLogSettings> for TrackLogConfig {
+    fn from(settings: &TrackLogSettings) -> Self {
+        Self {
+            directory: settings.directory.clone(),
+            interval: Some(Duration::from_secs_f64(settings.interval_secs))
+                .filter(|interval| !interval.is_zero()),
+            min_distance_m: Some(settings.min_distance_m).filter(|distance| *distance > 0.0),
+            max_file_bytes: Some(settings.max_file_bytes).filter(|bytes| *bytes > 0),
+            rotate_daily: settings.rotate_daily,
+            sync_interval: Duration::from_secs(settings.sync_interval_secs),
+        }
+    }
+}
+
+/// Front end used by the control loop. Records are written on a background thread so a slow
+/// SD card never stalls steering.
+pub struct TrackLogger {
+    interval: Option<Duration>,
+    min_distance_m: Option<f64>,
+    last_time: Option<Instant>,
+    last_position: Option<Position>,
+    last_mode: Option<Mode>,
+    sender: Option<Sender<LogRecord>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl TrackLogger {
+    /// Create the log directory and start the writer thread.
+    pub fn start(config: TrackLogConfig) -> Result<Self, Box<dyn Error>> {
+        let mut writer = LogWriter::new(&config)?;
+        let (sender, receiver) = mpsc::channel::<LogRecord>();
+
+        let handle = thread::spawn(move || {
+            for record in receiver {
+                if let Err(e) = writer.write(&record) {
+                    crate::warn!("Track log write failed: {}", e);
+                }
+            }
+            if let Err(e) = writer.sync() {
+                crate::warn!("Track log sync failed: {}", e);
+            }
+        });
+
+        Ok(Self {
+            interval: config.interval,
+            min_distance_m: config.min_distance_m,
+            last_time: None,
+            last_position: None,
+            last_mode: None,
+            sender: Some(sender),
+            writer: Some(handle),
+        })
+    }
+
+    /// Whether a record is due: the interval has passed, the boat has moved far enough, or the
+    /// mode changed. Check this before gathering a record to avoid needless sensor reads.
+    pub fn is_due(&self, position: Option<&Position>, mode: Mode) -> bool {
+        let Some(last_time) = self.last_time else {
+            return true;
+        };
+
+        if self.last_mode != Some(mode) {
+            return true;
+        }
+
+        if let Some(interval) = self.interval
+            && last_time.elapsed() >= interval
+        {
+            return true;
+        }
+
+        match (self.min_distance_m, position, self.last_position) {
+            (Some(min_distance), Some(position), Some(last)) => {
+                position.distance_to(&last) >= min_distance
+            }
+            // first fix after logging without one
+            (Some(_), Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Queue a record for writing.
+    pub fn log(&mut self, record: LogRecord) {
+        self.last_time = Some(Instant::now());
+        self.last_mode = Some(record.mode);
+        if record.position.is_some() {
+            self.last_position = record.position;
+        }
+
+        if let Some(sender) = &self.sender
+            && sender.send(record).is_err()
+        {
+            crate::warn!("Track log writer has stopped, logging disabled");
+            self.sender = None;
+        }
+    }
+}
+
+impl Drop for TrackLogger {
+    fn drop(&mut self) {
+        // closing the channel lets the writer drain, sync and exit
+        self.sender = None;
+        if let Some(handle) = self.writer.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Append-only log file with rotation.
+struct LogWriter {
+    directory: PathBuf,
+    max_file_bytes: Option<u64>,
+    rotate_daily: bool,
+    sync_interval: Duration,
+    file: Option<File>,
+    file_bytes: u64,
+    file_date: Option<NaiveDate>,
+    last_sync: Instant,
+}
+
+impl LogWriter {
+    fn new(config: &TrackLogConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        Ok(Self {
+            directory: config.directory.clone(),
+            max_file_bytes: config.max_file_bytes,
+            rotate_daily: config.rotate_daily,
+            sync_interval: config.sync_interval,
+            file: None,
+            file_bytes: 0,
+            file_date: None,
+            last_sync: Instant::now(),
+        })
+    }
+
+    fn write(&mut self, record: &LogRecord) -> io::Result<()> {
+        let line = format!("{}\n", record.to_csv());
+
+        if self.needs_rotation(record, line.len() as u64) {
+            self.rotate(record.time)?;
+        }
+
+        if let Some(file) = &mut self.file {
+            // one write per line so a crash leaves at most a partial last line
+            file.write_all(line.as_bytes())?;
+            self.file_bytes += line.len() as u64;
+        }
+
+        if self.last_sync.elapsed() >= self.sync_interval {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn needs_rotation(&self, record: &LogRecord, line_bytes: u64) -> bool {
+        if self.file.is_none() {
+            return true;
+        }
+        if self.rotate_daily && self.file_date != Some(record.time.date_naive()) {
+            return true;
+        }
+        match self.max_file_bytes {
+            // a header-only file takes the line regardless
+            Some(max) => self.file_bytes + line_bytes > max && self.file_bytes > header_bytes(),
+            None => false,
+        }
+    }
+
+    fn rotate(&mut self, time: DateTime<Utc>) -> io::Result<()> {
+        self.sync()?;
+
+        let path = self
+            .directory
+            .join(format!("track-{}.csv", time.format("%Y%m%d-%H%M%S")));
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        self.file_bytes = file.metadata()?.len();
+        if self.file_bytes == 0 {
+            file.write_all(format!("{}\n", LOG_HEADER).as_bytes())?;
+            self.file_bytes = header_bytes();
+        }
+
+        self.file = Some(file);
+        self.file_date = Some(time.date_naive());
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.last_sync = Instant::now();
+        match &self.file {
+            Some(file) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn header_bytes() -> u64 {
+    LOG_HEADER.len() as u64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("geo-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn config(directory: PathBuf) -> TrackLogConfig {
+        TrackLogConfig {
+            directory,
+            interval: Some(Duration::from_secs(60)),
+            min_distance_m: Some(10.0),
+            max_file_bytes: None,
+            rotate_daily: true,
+            sync_interval: Duration::ZERO,
+        }
+    }
+
+    fn record(time: DateTime<Utc>) -> LogRecord {
+        LogRecord {
+            time,
+            position: Some(Position::new(48.1, -123.1)),
+            speed: Some(5.25),
+            gps_heading: Some(90.0),
+            compass_heading: None,
+            target_heading: Some(92.5),
+            rudder_angle: Some(-3.0),
+            mode: Mode::Auto,
+        }
+    }
+
+    fn log_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let record = record(Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap());
+        let line = record.to_csv();
+        assert_eq!(
+            line,
+            "2024-06-01T12:00:00.000Z,48.1000000,-123.1000000,5.25,90.0,,92.5,-3.0,AUTO"
+        );
+        assert_eq!(LogRecord::parse(&line).unwrap(), record);
+        assert!(LogRecord::parse("2024-06-01T12:00:00Z,1,2").is_err());
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let dir = temp_dir("thresholds");
+        let mut logger = TrackLogger::start(config(dir.clone())).unwrap();
+        let start = Position::new(48.0, -123.0);
+
+        assert!(logger.is_due(Some(&start), Mode::Standby));
+        logger.log(LogRecord {
+            position: Some(start),
+            ..LogRecord::new(Mode::Standby)
+        });
+
+        // ~5 m moved, interval not reached
+        assert!(!logger.is_due(Some(&Position::new(48.00005, -123.0)), Mode::Standby));
+        // ~11 m moved
+        assert!(logger.is_due(Some(&Position::new(48.0001, -123.0)), Mode::Standby));
+        // mode change is always logged
+        assert!(logger.is_due(Some(&start), Mode::Auto));
+
+        drop(logger);
+        let records = read_log(&log_files(&dir)[0]).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].position, Some(start));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_by_day() {
+        let dir = temp_dir("rotate-day");
+        let mut writer = LogWriter::new(&config(dir.clone())).unwrap();
+
+        writer
+            .write(&record(
+                Utc.with_ymd_and_hms(2024, 6, 1, 23, 59, 0).unwrap(),
+            ))
+            .unwrap();
+        writer
+            .write(&record(Utc.with_ymd_and_hms(2024, 6, 2, 0, 1, 0).unwrap()))
+            .unwrap();
+
+        let files = log_files(&dir);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("track-20240601-235900.csv"));
+        assert_eq!(read_log(&files[1]).unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = temp_dir("rotate-size");
+        let time = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let line_bytes = record(time).to_csv().len() as u64 + 1;
+        let mut writer = LogWriter::new(&TrackLogConfig {
+            max_file_bytes: Some(header_bytes() + 2 * line_bytes),
+            ..config(dir.clone())
+        })
+        .unwrap();
+
+        for seconds in 0..3 {
+            writer
+                .write(&record(time + chrono::Duration::seconds(seconds)))
+                .unwrap();
+        }
+
+        let files = log_files(&dir);
+        assert_eq!(files.len(), 2);
+        assert_eq!(read_log(&files[0]).unwrap().len(), 2);
+        assert_eq!(read_log(&files[1]).unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_records_to_track() {
+        let time = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let records = vec![record(time), LogRecord::new(Mode::Standby)];
+        let track = records_to_track("trip", &records);
+        assert_eq!(track.segments[0].len(), 1);
+        assert_eq!(track.segments[0][0].time, Some(time));
+    }
+}
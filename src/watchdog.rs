@@ -0,0 +1,459 @@
+//! Control loop watchdog.
+//!
+//! The main loop marks each [`Stage`] it enters and checks in at the end of every cycle that had
+//! fresh sensor data: while steering, a heading within `watchdog.sensor_timeout_secs`, otherwise
+//! any cycle. A thread watches the check-ins and, while they keep coming, pets the
+//! hardware watchdog if there is one. When they stop for `watchdog.timeout_secs` it logs the
+//! stage the loop is stuck in, puts the rudder in its shutdown state through a
+//! [`FailsafeHandle`], sounds the alarm and stops petting, so the hardware resets the system.
+//! If the loop recovers first, petting and steering carry on.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::alarm::AlarmOutput;
+use crate::config::{FailsafeAction, WatchdogMode, WatchdogSettings};
+use crate::pwm::FailsafeHandle;
+
+/// How often the watchdog thread looks at the check-ins.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Written to the hardware watchdog before closing it to disarm it.
+const MAGIC_CLOSE: &[u8] = b"V";
+
+/// Part of the control loop, for telling which one overran.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Reloading and applying settings.
+    Settings,
+    /// Reading the compass and checking it for interference.
+    Sensors,
+    /// Buttons, toggles and control socket requests.
+    Controls,
+    /// Route and waypoint updates.
+    Navigation,
+    /// Choosing the heading and driving the rudder.
+    Steering,
+    /// NMEA capture and track log.
+    Logging,
+    /// Status display.
+    Display,
+    /// Sleeping between cycles.
+    Idle,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Settings => "settings",
+            Stage::Sensors => "sensors",
+            Stage::Controls => "controls",
+            Stage::Navigation => "navigation",
+            Stage::Steering => "steering",
+            Stage::Logging => "logging",
+            Stage::Display => "status display",
+            Stage::Idle => "idle",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A cycle that took longer than the deadline, and the stage that took longest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overrun {
+    pub cycle: Duration,
+    pub stage: Stage,
+    pub stage_time: Duration,
+}
+
+/// What the main loop last told the watchdog thread.
+#[derive(Clone, Copy)]
+struct Heartbeat {
+    stage: Stage,
+    stage_since: Instant,
+    last_checkin: Instant,
+    stop: Stop,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Running,
+    /// Clean shutdown: disarm the hardware watchdog.
+    Disarm,
+    /// Gone without a clean shutdown: leave the hardware watchdog to reset the system.
+    Abandon,
+}
+
+/// A change seen by the watchdog thread.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WatchdogEvent {
+    /// No check-in for the timeout, stuck in `stage` if it hasn't changed since.
+    Tripped {
+        stage: Option<Stage>,
+        silent: Duration,
+    },
+    Recovered,
+}
+
+/// The watchdog thread's decision: trip after the timeout without a check-in.
+struct Monitor {
+    timeout: Duration,
+    tripped: bool,
+}
+
+impl Monitor {
+    fn check(&mut self, heartbeat: &Heartbeat, now: Instant) -> Option<WatchdogEvent> {
+        let silent = now.duration_since(heartbeat.last_checkin);
+        if silent < self.timeout {
+            return std::mem::take(&mut self.tripped).then_some(WatchdogEvent::Recovered);
+        }
+        if self.tripped {
+            return None;
+        }
+        self.tripped = true;
+        // the loop may still be cycling, only without fresh data
+        let stuck = now.duration_since(heartbeat.stage_since) >= self.timeout;
+        Some(WatchdogEvent::Tripped {
+            stage: stuck.then_some(heartbeat.stage),
+            silent,
+        })
+    }
+}
+
+/// The main loop's side of the watchdog.
+pub struct Watchdog {
+    settings: WatchdogSettings,
+    heartbeat: Arc<Mutex<Heartbeat>>,
+    stage: Stage,
+    stage_since: Instant,
+    /// Time spent in each stage this cycle.
+    stage_times: Vec<(Stage, Duration)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start watching, with the thread unless `settings.mode` is off. When the loop is stuck the
+    /// actuator behind `failsafe` is left as `action` says. A hardware watchdog that can't be
+    /// opened is logged and the software watchdog carries on alone.
+    pub fn start(
+        settings: &WatchdogSettings,
+        failsafe: Option<FailsafeHandle>,
+        action: FailsafeAction,
+        alarm: AlarmOutput,
+    ) -> Result<Self, Box<dyn Error>> {
+        let now = Instant::now();
+        let heartbeat = Arc::new(Mutex::new(Heartbeat {
+            stage: Stage::Idle,
+            stage_since: now,
+            last_checkin: now,
+            stop: Stop::Running,
+        }));
+
+        let device = match settings.mode {
+            WatchdogMode::Hardware => match OpenOptions::new().write(true).open(&settings.device) {
+                Ok(device) => {
+                    crate::info!("✓ Hardware watchdog: {}", settings.device.display());
+                    Some(device)
+                }
+                Err(e) => {
+                    crate::warn!(
+                        "Hardware watchdog not available: {}: {} - software watchdog only",
+                        settings.device.display(),
+                        e
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let thread = if settings.mode == WatchdogMode::Off {
+            None
+        } else {
+            let monitor = Monitor {
+                timeout: Duration::from_secs_f64(settings.timeout_secs),
+                tripped: false,
+            };
+            let heartbeat = Arc::clone(&heartbeat);
+            Some(
+                thread::Builder::new()
+                    .name("watchdog".to_string())
+                    .spawn(move || watch(monitor, heartbeat, device, failsafe, action, alarm))?,
+            )
+        };
+
+        Ok(Self {
+            settings: settings.clone(),
+            heartbeat,
+            stage: Stage::Idle,
+            stage_since: now,
+            stage_times: Vec::new(),
+            thread,
+        })
+    }
+
+    /// Mark the start of a stage of the cycle.
+    pub fn stage(&mut self, stage: Stage) {
+        let now = Instant::now();
+        if self.stage != Stage::Idle {
+            self.stage_times
+                .push((self.stage, now.duration_since(self.stage_since)));
+        }
+        self.stage = stage;
+        self.stage_since = now;
+
+        let mut heartbeat = self.lock();
+        heartbeat.stage = stage;
+        heartbeat.stage_since = now;
+    }
+
+    /// End the cycle, checking in if it had fresh sensor data. A cycle over the deadline is
+    /// logged and returned.
+    pub fn cycle_done(&mut self, fresh: bool) -> Option<Overrun> {
+        self.stage(Stage::Idle);
+        if fresh {
+            self.lock().last_checkin = Instant::now();
+        }
+
+        let cycle: Duration = self.stage_times.iter().map(|(_, time)| *time).sum();
+        let slowest = self
+            .stage_times
+            .iter()
+            .copied()
+            .max_by_key(|(_, time)| *time);
+        self.stage_times.clear();
+
+        let deadline = Duration::from_secs_f64(self.settings.cycle_deadline_secs);
+        let (stage, stage_time) = slowest.filter(|_| cycle > deadline)?;
+        crate::warn!(
+            "  ⚠ Control cycle took {} ms (deadline {} ms): {} {} ms",
+            cycle.as_millis(),
+            deadline.as_millis(),
+            stage,
+            stage_time.as_millis()
+        );
+        Some(Overrun {
+            cycle,
+            stage,
+            stage_time,
+        })
+    }
+
+    /// Stop watching on a clean shutdown, disarming the hardware watchdog. Dropping the
+    /// watchdog without this leaves the hardware watchdog to reset the system.
+    pub fn disarm(mut self) {
+        self.stop(Stop::Disarm);
+    }
+
+    fn stop(&mut self, stop: Stop) {
+        self.lock().stop = stop;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Heartbeat> {
+        self.heartbeat.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.stop(Stop::Abandon);
+        }
+    }
+}
+
+/// The watchdog thread: pet the hardware while the loop checks in, trip when it doesn't.
+fn watch(
+    mut monitor: Monitor,
+    heartbeat: Arc<Mutex<Heartbeat>>,
+    mut device: Option<File>,
+    failsafe: Option<FailsafeHandle>,
+    action: FailsafeAction,
+    alarm: AlarmOutput,
+) {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let heartbeat = *heartbeat.lock().unwrap_or_else(|e| e.into_inner());
+
+        match heartbeat.stop {
+            Stop::Running => {}
+            Stop::Disarm => {
+                if let Some(device) = &mut device
+                    && let Err(e) = device.write_all(MAGIC_CLOSE)
+                {
+                    crate::warn!("  ⚠ Hardware watchdog not disarmed: {}", e);
+                }
+                return;
+            }
+            Stop::Abandon => return,
+        }
+
+        match monitor.check(&heartbeat, Instant::now()) {
+            Some(WatchdogEvent::Tripped { stage, silent }) => {
+                match stage {
+                    Some(stage) => crate::error!(
+                        "  ⚠ WATCHDOG: Control loop stuck in {} for {:.1}s",
+                        stage,
+                        silent.as_secs_f64()
+                    ),
+                    None => crate::error!(
+                        "  ⚠ WATCHDOG: No fresh sensor data for {:.1}s",
+                        silent.as_secs_f64()
+                    ),
+                }
+                if let Some(failsafe) = &failsafe {
+                    match failsafe.apply(action) {
+                        Ok(()) => crate::error!("  ⚠ WATCHDOG: {}", action),
+                        Err(e) => crate::error!("  ⚠ WATCHDOG: rudder not made safe: {}", e),
+                    }
+                }
+                alarm.set(true);
+                if device.is_some() {
+                    crate::error!("  ⚠ WATCHDOG: System reset unless the loop recovers");
+                }
+            }
+            Some(WatchdogEvent::Recovered) => {
+                crate::info!("  ✓ Control loop running again");
+                alarm.set(false);
+            }
+            None => {}
+        }
+
+        if !monitor.tripped
+            && let Some(device) = &mut device
+            && let Err(e) = device.write_all(b"\0")
+        {
+            crate::warn!("  ⚠ Hardware watchdog not petted: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_monitor() {
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let mut monitor = Monitor {
+            timeout: Duration::from_secs(3),
+            tripped: false,
+        };
+        let mut heartbeat = Heartbeat {
+            stage: Stage::Steering,
+            stage_since: start,
+            last_checkin: start,
+            stop: Stop::Running,
+        };
+
+        assert_eq!(monitor.check(&heartbeat, at(2.9)), None);
+        // stuck in one stage
+        assert_eq!(
+            monitor.check(&heartbeat, at(3.0)),
+            Some(WatchdogEvent::Tripped {
+                stage: Some(Stage::Steering),
+                silent: Duration::from_secs(3),
+            })
+        );
+        assert_eq!(monitor.check(&heartbeat, at(4.0)), None);
+        assert!(monitor.tripped);
+
+        heartbeat.last_checkin = at(5.0);
+        assert_eq!(
+            monitor.check(&heartbeat, at(5.1)),
+            Some(WatchdogEvent::Recovered)
+        );
+
+        // cycling, but without fresh data
+        heartbeat.stage_since = at(7.9);
+        assert_eq!(
+            monitor.check(&heartbeat, at(8.0)),
+            Some(WatchdogEvent::Tripped {
+                stage: None,
+                silent: Duration::from_secs(3),
+            })
+        );
+    }
+
+    #[test]
+    fn test_cycle_overrun() {
+        let settings = WatchdogSettings {
+            mode: WatchdogMode::Off,
+            cycle_deadline_secs: 0.02,
+            ..WatchdogSettings::default()
+        };
+        let mut watchdog = Watchdog::start(
+            &settings,
+            None,
+            FailsafeAction::Center,
+            AlarmOutput::default(),
+        )
+        .unwrap();
+
+        watchdog.stage(Stage::Settings);
+        watchdog.stage(Stage::Steering);
+        assert_eq!(watchdog.cycle_done(true), None);
+
+        watchdog.stage(Stage::Settings);
+        watchdog.stage(Stage::Steering);
+        thread::sleep(Duration::from_millis(30));
+        watchdog.stage(Stage::Display);
+        let overrun = watchdog.cycle_done(true).unwrap();
+        assert_eq!(overrun.stage, Stage::Steering);
+        assert!(overrun.stage_time >= Duration::from_millis(30));
+        assert!(overrun.cycle >= overrun.stage_time);
+    }
+
+    #[test]
+    fn test_hardware_petting() {
+        let device = std::env::temp_dir().join(format!("geo-rs-watchdog-{}", std::process::id()));
+        fs::write(&device, "").unwrap();
+        let settings = WatchdogSettings {
+            mode: WatchdogMode::Hardware,
+            device: device.clone(),
+            timeout_secs: 0.3,
+            ..WatchdogSettings::default()
+        };
+        let mut watchdog = Watchdog::start(
+            &settings,
+            None,
+            FailsafeAction::Center,
+            AlarmOutput::default(),
+        )
+        .unwrap();
+
+        // petted while the loop checks in
+        for _ in 0..15 {
+            watchdog.stage(Stage::Steering);
+            watchdog.cycle_done(true);
+            thread::sleep(Duration::from_millis(20));
+        }
+        let petted = fs::read(&device).unwrap().len();
+        assert!(petted > 0);
+
+        // cycles without fresh data: petting stops
+        for _ in 0..40 {
+            watchdog.stage(Stage::Steering);
+            watchdog.cycle_done(false);
+            thread::sleep(Duration::from_millis(20));
+        }
+        let stopped = fs::read(&device).unwrap().len();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(fs::read(&device).unwrap().len(), stopped);
+
+        // a clean shutdown disarms it
+        watchdog.disarm();
+        assert!(fs::read(&device).unwrap().ends_with(MAGIC_CLOSE));
+        fs::remove_file(&device).unwrap();
+    }
+}